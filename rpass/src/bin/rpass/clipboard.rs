@@ -0,0 +1,162 @@
+use std::{sync::Mutex, thread, time::Duration};
+
+/// Clipboard result type
+pub type Result<T> = std::result::Result<T, arboard::Error>;
+
+/// System clipboard operations
+pub trait Clipboard: Send + 'static {
+    /// Gets current clipboard text
+    fn get_text(&mut self) -> Result<String>;
+
+    /// Replaces clipboard content with `text`
+    fn set_text(&mut self, text: &str) -> Result<()>;
+
+    /// Removes clipboard content
+    fn clear(&mut self) -> Result<()>;
+}
+
+impl Clipboard for arboard::Clipboard {
+    fn get_text(&mut self) -> Result<String> {
+        arboard::Clipboard::get_text(self)
+    }
+
+    fn set_text(&mut self, text: &str) -> Result<()> {
+        arboard::Clipboard::set_text(self, text)
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        arboard::Clipboard::clear(self)
+    }
+}
+
+/// Threads restoring clipboard content, which [`wait_restores()`] waits for
+static PENDING_RESTORES: Mutex<Vec<thread::JoinHandle<()>>> = Mutex::new(Vec::new());
+
+/// Copies `text` to the system clipboard.
+/// If `clear_after` is set, previous content is restored after that time,
+/// see [`copy_with_clear()`]. Call [`wait_restores()`] before exit for that to happen
+///
+/// # Errors
+///
+/// Any error if clipboard is unavailable, e.g. on headless systems
+pub fn copy(text: &str, clear_after: Option<Duration>) -> Result<()> {
+    let mut clipboard = arboard::Clipboard::new()?;
+    match clear_after {
+        Some(clear_after) => {
+            let handle = copy_with_clear(clipboard, text, clear_after)?;
+            let mut pending = PENDING_RESTORES.lock().unwrap();
+            pending.retain(|handle| !handle.is_finished());
+            pending.push(handle);
+            Ok(())
+        }
+        None => clipboard.set_text(text),
+    }
+}
+
+/// Waits until clipboard content changed by [`copy()`] is restored
+pub fn wait_restores() {
+    let handles = std::mem::take(&mut *PENDING_RESTORES.lock().unwrap());
+    for handle in handles {
+        let _ = handle.join();
+    }
+}
+
+/// Copies `text` to `clipboard` and spawns a thread, which restores previous
+/// clipboard content (or clears it, if there was no text) after `clear_after`.
+/// Clipboard isn't touched if its content was changed in the meantime.
+/// Nothing is restored if the process exits sooner
+///
+/// # Errors
+///
+/// Any error during copying `text`
+pub fn copy_with_clear<C: Clipboard>(
+    mut clipboard: C,
+    text: &str,
+    clear_after: Duration,
+) -> Result<thread::JoinHandle<()>> {
+    let previous = clipboard.get_text().ok();
+    clipboard.set_text(text)?;
+
+    let text = text.to_owned();
+    Ok(thread::spawn(move || {
+        thread::sleep(clear_after);
+        if !clipboard.get_text().is_ok_and(|current| current == text) {
+            return;
+        }
+        let res = match previous {
+            Some(previous) => clipboard.set_text(&previous),
+            None => clipboard.clear(),
+        };
+        if let Err(err) = res {
+            eprintln!("Warning: can't clear clipboard ({err})");
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// In-memory clipboard shared between clones
+    #[derive(Clone, Default)]
+    struct MemoryClipboard(Arc<Mutex<Option<String>>>);
+
+    impl Clipboard for MemoryClipboard {
+        fn get_text(&mut self) -> Result<String> {
+            self.0
+                .lock()
+                .unwrap()
+                .clone()
+                .ok_or(arboard::Error::ContentNotAvailable)
+        }
+
+        fn set_text(&mut self, text: &str) -> Result<()> {
+            *self.0.lock().unwrap() = Some(text.to_owned());
+            Ok(())
+        }
+
+        fn clear(&mut self) -> Result<()> {
+            *self.0.lock().unwrap() = None;
+            Ok(())
+        }
+    }
+
+    const CLEAR_AFTER: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn test_restores_previous() {
+        let mut clipboard = MemoryClipboard::default();
+        clipboard.set_text("previous").unwrap();
+
+        let handle = copy_with_clear(clipboard.clone(), "secret", CLEAR_AFTER).unwrap();
+        assert_eq!(clipboard.get_text().unwrap(), "secret");
+
+        handle.join().unwrap();
+        assert_eq!(clipboard.get_text().unwrap(), "previous");
+    }
+
+    #[test]
+    fn test_clears_if_no_previous() {
+        let mut clipboard = MemoryClipboard::default();
+
+        copy_with_clear(clipboard.clone(), "secret", CLEAR_AFTER)
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(clipboard.get_text().is_err());
+    }
+
+    #[test]
+    fn test_keeps_newer_content() {
+        let mut clipboard = MemoryClipboard::default();
+        clipboard.set_text("previous").unwrap();
+
+        let handle =
+            copy_with_clear(clipboard.clone(), "secret", Duration::from_millis(100)).unwrap();
+        clipboard.set_text("newer").unwrap();
+
+        handle.join().unwrap();
+        assert_eq!(clipboard.get_text().unwrap(), "newer");
+    }
+}
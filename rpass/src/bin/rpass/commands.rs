@@ -1,33 +1,248 @@
-use eyre::Result;
-use clap::Args;
-use rpass::{key::Key, record::Record};
+use std::{
+    fs,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{Args, Subcommand};
+use eyre::{bail, eyre, Result, WrapErr};
+use rpass::{
+    key::Key,
+    record::{generate_password, password_strength, PasswordOptions, Record, StrengthLevel},
+};
+
+use crate::clipboard;
+#[mockall_double::double]
+use crate::session::Authorized;
+
+/// Environment variable with password for `add` command
+const PASSWORD_ENV: &str = "RPASS_PASSWORD";
 
 /// Trait to identify executable commands
 pub trait Execute {
-    /// Execute command with `username` and `key`
-    fn execute(&self, username: &str, key: &Key) -> Result<()>;
+    /// Execute command within authorized `session` reading user answers from `input` and
+    /// writing results to `out`
+    async fn execute(
+        &self,
+        session: &mut Authorized,
+        input: &mut dyn BufRead,
+        out: &mut dyn Write,
+    ) -> Result<()>;
+}
+
+/// Commands which require user to be logged in
+#[derive(Debug, Subcommand)]
+pub enum AuthorizedCommand {
+    /// Add record to database
+    Add(Add),
+
+    /// Delete record from database
+    #[clap(visible_alias = "rm")]
+    Delete(Delete),
+
+    /// Export all records to the file
+    Export(Export),
+
+    /// Get record info
+    Get(Get),
+
+    /// Import records from the file made by `export` command
+    Import(Import),
+
+    /// List all user records
+    Ls(Ls),
+
+    /// Check if server is alive
+    Ping(Ping),
+}
+
+impl Execute for AuthorizedCommand {
+    async fn execute(
+        &self,
+        session: &mut Authorized,
+        input: &mut dyn BufRead,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        match self {
+            Self::Add(command) => command.execute(session, input, out).await,
+            Self::Delete(command) => command.execute(session, input, out).await,
+            Self::Export(command) => command.execute(session, input, out).await,
+            Self::Get(command) => command.execute(session, input, out).await,
+            Self::Import(command) => command.execute(session, input, out).await,
+            Self::Ls(command) => command.execute(session, input, out).await,
+            Self::Ping(command) => command.execute(session, input, out).await,
+        }
+    }
+}
+
+impl AuthorizedCommand {
+    /// Makes command print results in JSON format if it supports it
+    pub fn set_json(&mut self) {
+        match self {
+            Self::Get(command) => command.json = true,
+            Self::Ls(command) => command.json = true,
+            Self::Add(_) | Self::Delete(_) | Self::Export(_) | Self::Import(_) | Self::Ping(_) => {}
+        }
+    }
+
+    /// Checks if command reads the whole input, so it can't be used in
+    /// interactive session
+    pub fn reads_whole_input(&self) -> bool {
+        matches!(self, Self::Add(command) if command.password_stdin)
+    }
 }
 
 /// Register new user
 #[derive(Debug, Args)]
 pub struct Register;
 
-impl Execute for Register {
-    fn execute(&self, _username: &str, _key: &Key) -> Result<()> {
+impl Register {
+    /// Register user with `username` and `key`
+    pub fn execute(&self, _username: &str, _key: &Key) -> Result<()> {
         todo!("`Register` isn't implemented yet")
     }
 }
 
 /// Add record to database
+///
+/// Password is taken from the first available source: `--generate`,
+/// `--password-stdin`, `RPASS_PASSWORD` environment variable.
+/// It will be asked interactively otherwise
 #[derive(Debug, Args)]
 pub struct Add {
     /// Record name
-    record: Record,
+    resource: String,
+
+    /// Additional notes
+    #[clap(short, long, default_value = "")]
+    notes: String,
+
+    /// Generate password instead of asking it and copy it to the clipboard
+    #[clap(short, long)]
+    generate: bool,
+
+    /// Read password from the whole standard input. Single trailing newline is removed.
+    /// Not available in interactive session
+    #[clap(long, conflicts_with = "generate")]
+    password_stdin: bool,
+
+    /// Password from `RPASS_PASSWORD` environment variable
+    #[clap(skip = std::env::var(PASSWORD_ENV).ok())]
+    password_env: Option<String>,
 }
 
 impl Execute for Add {
-    fn execute(&self, _username: &str, _key: &Key) -> Result<()> {
-        todo!("`Add` isn't implemented yet")
+    async fn execute(
+        &self,
+        session: &mut Authorized,
+        input: &mut dyn BufRead,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        let password = if self.generate {
+            generate_password(&PasswordOptions::default())?
+        } else if self.password_stdin {
+            let mut password = String::new();
+            input.read_to_string(&mut password)?;
+            let password = password
+                .strip_suffix('\n')
+                .map(|password| password.strip_suffix('\r').unwrap_or(password))
+                .unwrap_or(&password);
+            Self::check_password(password)?
+        } else if let Some(password) = &self.password_env {
+            Self::check_password(password)?
+        } else {
+            Self::read_password(input, out)?
+        };
+
+        let record = Record {
+            resource: self.resource.clone(),
+            password,
+            notes: self.notes.clone(),
+            encrypted_notes: false,
+        };
+        session.add_record(&record).await?;
+
+        if self.generate {
+            if let Err(err) = clipboard::copy(&record.password, None) {
+                eprintln!("Warning: can't use clipboard ({err}), printing password instead");
+                writeln!(out, "{}", record.password)?;
+            } else {
+                eprintln!("Generated password copied to the clipboard");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Add {
+    /// Asks user for password reading it from `input`, see [`Add::check_password()`]
+    ///
+    /// # Errors
+    ///
+    /// Any error during reading or writing or if password is invalid
+    fn read_password(input: &mut dyn BufRead, out: &mut dyn Write) -> Result<String> {
+        write!(out, "Password: ")?;
+        out.flush()?;
+
+        let mut password = String::new();
+        input.read_line(&mut password)?;
+        Self::check_password(password.trim_end_matches(['\r', '\n']))
+    }
+
+    /// Checks that `password` isn't empty. Warns if password is weak
+    ///
+    /// # Errors
+    ///
+    /// If password is empty
+    fn check_password(password: &str) -> Result<String> {
+        if password.is_empty() {
+            bail!("password can't be empty");
+        }
+        let strength = password_strength(password);
+        if strength.level == StrengthLevel::Weak {
+            eprintln!(
+                "Warning: password is {} (about {:.0} bits of entropy)",
+                strength.level, strength.entropy
+            );
+        }
+
+        Ok(password.to_owned())
+    }
+}
+
+/// Generate random password
+#[derive(Debug, Args)]
+pub struct Gen {
+    /// Password length
+    #[clap(short, long, default_value_t = PasswordOptions::default().length)]
+    length: usize,
+
+    /// Exclude uppercase letters
+    #[clap(long)]
+    no_uppercase: bool,
+
+    /// Exclude digits
+    #[clap(long)]
+    no_digits: bool,
+
+    /// Exclude symbols
+    #[clap(long)]
+    no_symbols: bool,
+}
+
+impl Gen {
+    /// Generates password and writes it to `out`
+    pub fn execute(&self, out: &mut dyn Write) -> Result<()> {
+        let opts = PasswordOptions {
+            length: self.length,
+            lowercase: true,
+            uppercase: !self.no_uppercase,
+            digits: !self.no_digits,
+            symbols: !self.no_symbols,
+        };
+        writeln!(out, "{}", generate_password(&opts)?)?;
+        Ok(())
     }
 }
 
@@ -36,44 +251,1170 @@ impl Execute for Add {
 pub struct Delete {
     /// Name of the record to delete
     record_name: String,
+
+    /// Do not ask for confirmation
+    #[clap(short, long)]
+    yes: bool,
 }
 
 impl Execute for Delete {
-    fn execute(&self, _username: &str, _key: &Key) -> Result<()> {
-        todo!("`Delete` isn't implemented yet")
+    async fn execute(
+        &self,
+        session: &mut Authorized,
+        input: &mut dyn BufRead,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        let question = format!("Delete record `{}`?", self.record_name);
+        if !self.yes && !confirm(&question, input, out)? {
+            return Ok(());
+        }
+
+        if let Err(err) = session.delete_record(&self.record_name).await {
+            return Err(record_error(session, &self.record_name, err).await);
+        }
+        Ok(())
     }
 }
 
-
 /// Delete user from database
 #[derive(Debug, Args)]
-pub struct DeleteAccount;
+pub struct DeleteAccount {
+    /// Do not ask for confirmation
+    #[clap(short, long)]
+    yes: bool,
+}
+
+impl DeleteAccount {
+    /// Deletes user the `session` is associated with.
+    /// Asks for confirmation reading answer from `input`
+    pub async fn execute(
+        &self,
+        session: Authorized,
+        input: &mut dyn BufRead,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        let question = "All your records will be lost. Delete account?";
+        if !self.yes && !confirm(question, input, out)? {
+            return Ok(());
+        }
+
+        session.delete_me().await.map_err(|err| err.source)?;
+        Ok(())
+    }
+}
+
+/// Export all records to the file
+///
+/// Records are written in JSON format. Note that passwords are stored as is
+#[derive(Debug, Args)]
+pub struct Export {
+    /// File to write records to
+    file: PathBuf,
+}
+
+impl Execute for Export {
+    async fn execute(
+        &self,
+        session: &mut Authorized,
+        _input: &mut dyn BufRead,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        let records = session.export_records().await?;
+        if records.is_empty() {
+            eprintln!("No records yet");
+            return Ok(());
+        }
 
-impl Execute for DeleteAccount {
-    fn execute(&self, _username: &str, _key: &Key) -> Result<()> {
-        todo!("`DeleteAccount` isn't implemented yet")
+        let json = serde_json::to_string_pretty(&records)?;
+        fs::write(&self.file, json)?;
+        writeln!(
+            out,
+            "Exported {} records to {}",
+            records.len(),
+            self.file.display()
+        )?;
+        Ok(())
     }
 }
 
-/// Delete record info
+/// Get record info
+///
+/// Copies password to the clipboard and prints notes
 #[derive(Debug, Args)]
 pub struct Get {
     /// Name of the record to get
     record_name: String,
+
+    /// Print password instead of copying it to the clipboard
+    #[clap(short, long)]
+    show: bool,
+
+    /// Restore previous clipboard content after this number of seconds.
+    /// Single command waits for it before exit, while interactive session
+    /// restores it only if still running. 0 keeps password in the clipboard
+    #[clap(long, value_name = "SECONDS", default_value_t = 20)]
+    clear_after: u64,
+
+    /// Print record as JSON object without touching the clipboard. Password is
+    /// included only with `--show`
+    #[clap(from_global)]
+    json: bool,
 }
 
 impl Execute for Get {
-    fn execute(&self, _username: &str, _key: &Key) -> Result<()> {
-        todo!("`Get` isn't implemented yet")
+    async fn execute(
+        &self,
+        session: &mut Authorized,
+        _input: &mut dyn BufRead,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        let record = match session.get_record(self.record_name.clone()).await {
+            Ok(record) => record,
+            Err(err) => return Err(record_error(session, &self.record_name, err).await),
+        };
+
+        if self.json {
+            return self.write_json(record, out);
+        }
+
+        if self.show {
+            writeln!(out, "{}", record.password)?;
+        } else if let Err(err) = clipboard::copy(&record.password, self.clear_after()) {
+            eprintln!("Warning: can't use clipboard ({err}), printing password instead");
+            writeln!(out, "{}", record.password)?;
+        } else if self.clear_after > 0 {
+            eprintln!(
+                "Password copied to the clipboard for {} seconds",
+                self.clear_after
+            );
+        } else {
+            eprintln!("Password copied to the clipboard");
+        }
+
+        if !record.notes.is_empty() {
+            writeln!(out, "{}", record.notes)?;
+        }
+        Ok(())
+    }
+}
+
+impl Get {
+    /// Writes `record` to `out` as JSON object. Password is omitted if it's
+    /// not requested explicitly. JSON output is meant for scripts, so the
+    /// clipboard is never used
+    ///
+    /// # Errors
+    ///
+    /// Any error during writing
+    fn write_json(&self, record: Record, out: &mut dyn Write) -> Result<()> {
+        let mut json = serde_json::to_value(&record)?;
+        if !self.show {
+            if let Some(object) = json.as_object_mut() {
+                object.remove("password");
+            }
+        }
+
+        writeln!(out, "{json}")?;
+        Ok(())
+    }
+
+    /// Gets time after which password should be removed from the clipboard
+    fn clear_after(&self) -> Option<Duration> {
+        (self.clear_after > 0).then(|| Duration::from_secs(self.clear_after))
+    }
+}
+
+/// Change user key pair
+///
+/// New key pair is written instead of the current one. Old secret key is kept with `.old`
+/// extension, cause already stored records aren't re-encrypted
+#[derive(Debug, Args)]
+pub struct ChangeKey;
+
+impl ChangeKey {
+    /// Changes key pair of the user `session` is associated with. `key_path` is the path to the
+    /// current secret key, new public key is written in the same directory
+    pub async fn execute(
+        &self,
+        session: &mut Authorized,
+        key_path: &Path,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        let (pub_key, sec_key) = Key::generate_pair();
+
+        // New keys are saved before the server is told about them, so that the user can't
+        // end up with a registered key they don't have
+        let new_sec_key_path = key_path.with_extension("sec.new");
+        let new_pub_key_path = key_path.with_extension("pub.new");
+        let written = sec_key
+            .write_to_file(&new_sec_key_path)
+            .and_then(|()| pub_key.write_to_file(&new_pub_key_path));
+        if let Err(err) = written {
+            Self::remove_new_keys(&new_sec_key_path, &new_pub_key_path);
+            return Err(err).wrap_err("can't save new key pair");
+        }
+
+        if let Err(err) = session.change_pub_key(&pub_key, &sec_key).await {
+            Self::remove_new_keys(&new_sec_key_path, &new_pub_key_path);
+            return Err(err.into());
+        }
+
+        let old_key_path = key_path.with_extension("sec.old");
+        fs::rename(key_path, &old_key_path)
+            .and_then(|()| fs::rename(&new_sec_key_path, key_path))
+            .and_then(|()| fs::rename(&new_pub_key_path, key_path.with_extension("pub")))
+            .wrap_err_with(|| {
+                format!(
+                    "key changed, but can't move new key pair into place. New secret key is \
+                     saved to {}",
+                    new_sec_key_path.display()
+                )
+            })?;
+
+        writeln!(
+            out,
+            "Key changed. Old secret key is saved to {}",
+            old_key_path.display()
+        )?;
+        Ok(())
+    }
+
+    /// Removes new key pair files which weren't moved into place
+    fn remove_new_keys(sec_key_path: &Path, pub_key_path: &Path) {
+        let _ = fs::remove_file(sec_key_path);
+        let _ = fs::remove_file(pub_key_path);
+    }
+}
+
+/// Import records from the file made by `export` command
+#[derive(Debug, Args)]
+pub struct Import {
+    /// File to read records from
+    file: PathBuf,
+
+    /// Overwrite records which already exist
+    #[clap(long)]
+    overwrite: bool,
+}
+
+impl Execute for Import {
+    async fn execute(
+        &self,
+        session: &mut Authorized,
+        _input: &mut dyn BufRead,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        let content = fs::read_to_string(&self.file)?;
+        let records: Vec<Record> = serde_json::from_str(&content)
+            .wrap_err_with(|| format!("malformed export file {}", self.file.display()))?;
+
+        let summary = session.import_records(&records, self.overwrite).await?;
+        for resource in &summary.skipped {
+            writeln!(out, "Skipped existing record `{resource}`")?;
+        }
+        for (resource, err) in &summary.failed {
+            writeln!(out, "Failed to import record `{resource}`: {err}")?;
+        }
+        writeln!(
+            out,
+            "Added: {}, skipped: {}, failed: {}",
+            summary.added.len(),
+            summary.skipped.len(),
+            summary.failed.len()
+        )?;
+        Ok(())
     }
 }
 
 /// List all user records
 #[derive(Debug, Args)]
-pub struct Ls;
+pub struct Ls {
+    /// Use a long listing format
+    #[clap(short, long)]
+    long: bool,
+
+    /// Print records names as JSON array. With `--long` they are printed as
+    /// *records* field of JSON object with their number in *total* field
+    #[clap(from_global)]
+    json: bool,
+}
 
 impl Execute for Ls {
-    fn execute(&self, _username: &str, _key: &Key) -> Result<()> {
-        todo!("`Ls` isn't implemented yet")
+    async fn execute(
+        &self,
+        session: &mut Authorized,
+        _input: &mut dyn BufRead,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        let records = session.get_records_list().await?;
+        if self.json {
+            let json = match self.long {
+                true => serde_json::json!({ "total": records.len(), "records": records }),
+                false => serde_json::json!(records),
+            };
+            writeln!(out, "{json}")?;
+            return Ok(());
+        }
+        if records.is_empty() {
+            eprintln!("No records yet");
+            return Ok(());
+        }
+
+        if self.long {
+            writeln!(out, "total {}", records.len())?;
+        }
+        for record in records {
+            writeln!(out, "{record}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Check if server is alive
+#[derive(Debug, Args)]
+pub struct Ping;
+
+impl Execute for Ping {
+    async fn execute(
+        &self,
+        session: &mut Authorized,
+        _input: &mut dyn BufRead,
+        out: &mut dyn Write,
+    ) -> Result<()> {
+        session.ping().await?;
+        writeln!(out, "pong")?;
+        Ok(())
+    }
+}
+
+/// Asks user `question` writing it to `out` and reads answer from `input`
+///
+/// Returns `true` only if answer is *y* or *yes*
+fn confirm(question: &str, input: &mut dyn BufRead, out: &mut dyn Write) -> Result<bool> {
+    write!(out, "{question} [y/N] ")?;
+    out.flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Converts `err` occurred while accessing `record_name` record into user-friendly error
+///
+/// Server doesn't report missing records explicitly, so records list is checked to
+/// distinguish them from other server errors
+async fn record_error(session: &Authorized, record_name: &str, err: rpass::Error) -> eyre::Report {
+    if let rpass::Error::Server { .. } = err {
+        match session.get_records_list().await {
+            Ok(records) if !records.iter().any(|record| record == record_name) => {
+                return eyre!("no such record `{record_name}`")
+            }
+            _ => (),
+        }
+    }
+    err.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::MockUnauthorized;
+    use rpass::error::ErrorCode;
+    use std::io;
+
+    /// Tests for `Get::execute()`
+    mod get {
+        use super::*;
+        use mockall::predicate::*;
+
+        #[tokio::test]
+        async fn test_no_such_record() {
+            let mut session = Authorized::default();
+            session
+                .expect_get_record()
+                .with(eq(String::from("example.com")))
+                .times(1)
+                .returning(|_| {
+                    Err(rpass::Error::Server {
+                        code: ErrorCode::Io,
+                        mes: String::from("callback error: storage error"),
+                    })
+                });
+            session
+                .expect_get_records_list()
+                .times(1)
+                .returning(|| Ok(vec![String::from("test.com")]));
+
+            let get = Get {
+                record_name: String::from("example.com"),
+                show: false,
+                clear_after: 0,
+                json: false,
+            };
+            let mut out = vec![];
+            let err = get
+                .execute(&mut session, &mut io::empty(), &mut out)
+                .await
+                .unwrap_err();
+
+            assert_eq!(err.to_string(), "no such record `example.com`");
+            assert!(out.is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_show() {
+            let mut session = Authorized::default();
+            session.expect_get_record().times(1).returning(|resource| {
+                Ok(Record {
+                    resource,
+                    password: String::from("secret"),
+                    notes: String::from("notes"),
+                    encrypted_notes: false,
+                })
+            });
+
+            let get = Get {
+                record_name: String::from("example.com"),
+                show: true,
+                clear_after: 0,
+                json: false,
+            };
+            let mut out = vec![];
+            get.execute(&mut session, &mut io::empty(), &mut out)
+                .await
+                .unwrap();
+
+            assert_eq!(String::from_utf8(out).unwrap(), "secret\nnotes\n");
+        }
+
+        #[tokio::test]
+        async fn test_json_show() {
+            let mut session = build_session();
+
+            let get = Get {
+                record_name: String::from("example.com"),
+                show: true,
+                clear_after: 0,
+                json: true,
+            };
+            let mut out = vec![];
+            get.execute(&mut session, &mut io::empty(), &mut out)
+                .await
+                .unwrap();
+
+            let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+            assert_eq!(
+                json,
+                serde_json::json!({
+                    "resource": "example.com",
+                    "password": "secret",
+                    "notes": "notes",
+                })
+            );
+        }
+
+        #[tokio::test]
+        async fn test_json_without_password() {
+            let mut session = build_session();
+
+            let get = Get {
+                record_name: String::from("example.com"),
+                show: false,
+                clear_after: 0,
+                json: true,
+            };
+            let mut out = vec![];
+            get.execute(&mut session, &mut io::empty(), &mut out)
+                .await
+                .unwrap();
+
+            let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+            assert_eq!(
+                json,
+                serde_json::json!({
+                    "resource": "example.com",
+                    "notes": "notes",
+                })
+            );
+        }
+
+        /// Builds session which returns record with *"secret"* password and *"notes"* notes
+        fn build_session() -> Authorized {
+            let mut session = Authorized::default();
+            session.expect_get_record().times(1).returning(|resource| {
+                Ok(Record {
+                    resource,
+                    password: String::from("secret"),
+                    notes: String::from("notes"),
+                    encrypted_notes: false,
+                })
+            });
+            session
+        }
+    }
+
+    /// Tests for `Add::execute()`
+    mod add {
+        use super::*;
+        use mockall::predicate::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let expected_record = Record {
+                resource: String::from("example.com"),
+                password: String::from("secret"),
+                notes: String::from("notes"),
+                encrypted_notes: false,
+            };
+
+            let mut session = Authorized::default();
+            session
+                .expect_add_record()
+                .with(eq(expected_record))
+                .times(1)
+                .returning(|_| Ok(()));
+
+            let add = Add {
+                resource: String::from("example.com"),
+                notes: String::from("notes"),
+                generate: false,
+                password_stdin: false,
+                password_env: None,
+            };
+            let mut out = vec![];
+            add.execute(&mut session, &mut "secret\n".as_bytes(), &mut out)
+                .await
+                .unwrap();
+
+            assert_eq!(String::from_utf8(out).unwrap(), "Password: ");
+        }
+
+        #[tokio::test]
+        async fn test_empty_password() {
+            let mut session = Authorized::default();
+            session.expect_add_record().never();
+
+            let add = Add {
+                resource: String::from("example.com"),
+                notes: String::default(),
+                generate: false,
+                password_stdin: false,
+                password_env: None,
+            };
+            let mut out = vec![];
+            assert!(add
+                .execute(&mut session, &mut "\n".as_bytes(), &mut out)
+                .await
+                .is_err());
+        }
+
+        #[tokio::test]
+        async fn test_generate() {
+            let mut session = Authorized::default();
+            session
+                .expect_add_record()
+                .withf(|record| {
+                    record.resource == "example.com"
+                        && record.password.len() == PasswordOptions::default().length
+                })
+                .times(1)
+                .returning(|_| Ok(()));
+
+            let add = Add {
+                resource: String::from("example.com"),
+                notes: String::default(),
+                generate: true,
+                password_stdin: false,
+                password_env: None,
+            };
+            let mut out = vec![];
+            add.execute(&mut session, &mut "".as_bytes(), &mut out)
+                .await
+                .unwrap();
+
+            assert!(!String::from_utf8(out).unwrap().starts_with("Password: "));
+        }
+
+        #[tokio::test]
+        async fn test_password_stdin() {
+            for (input, password) in [
+                (" secret \n", " secret "),
+                ("secret\r\n", "secret"),
+                ("secret\n\n", "secret\n"),
+                ("secret", "secret"),
+            ] {
+                let mut session = Authorized::default();
+                session
+                    .expect_add_record()
+                    .with(eq(Record {
+                        resource: String::from("example.com"),
+                        password: String::from(password),
+                        notes: String::default(),
+                        encrypted_notes: false,
+                    }))
+                    .times(1)
+                    .returning(|_| Ok(()));
+
+                let add = Add {
+                    resource: String::from("example.com"),
+                    notes: String::default(),
+                    generate: false,
+                    password_stdin: true,
+                    password_env: Some(String::from("ignored")),
+                };
+                let mut out = vec![];
+                add.execute(&mut session, &mut input.as_bytes(), &mut out)
+                    .await
+                    .unwrap();
+
+                assert!(out.is_empty());
+            }
+        }
+
+        #[tokio::test]
+        async fn test_password_stdin_empty() {
+            let add = Add {
+                resource: String::from("example.com"),
+                notes: String::default(),
+                generate: false,
+                password_stdin: true,
+                password_env: None,
+            };
+            let mut out = vec![];
+            assert!(add
+                .execute(&mut Authorized::default(), &mut "\n".as_bytes(), &mut out)
+                .await
+                .is_err());
+        }
+
+        #[tokio::test]
+        async fn test_password_env() {
+            let mut session = Authorized::default();
+            session
+                .expect_add_record()
+                .with(eq(Record {
+                    resource: String::from("example.com"),
+                    password: String::from("secret"),
+                    notes: String::default(),
+                    encrypted_notes: false,
+                }))
+                .times(1)
+                .returning(|_| Ok(()));
+
+            let add = Add {
+                resource: String::from("example.com"),
+                notes: String::default(),
+                generate: false,
+                password_stdin: false,
+                password_env: Some(String::from("secret")),
+            };
+            let mut out = vec![];
+            add.execute(&mut session, &mut "".as_bytes(), &mut out)
+                .await
+                .unwrap();
+
+            assert!(out.is_empty());
+        }
+    }
+
+    /// Tests for `Gen::execute()`
+    mod gen {
+        use super::*;
+
+        #[test]
+        fn test_ok() {
+            let gen = Gen {
+                length: 32,
+                no_uppercase: true,
+                no_digits: false,
+                no_symbols: true,
+            };
+            let mut out = vec![];
+            gen.execute(&mut out).unwrap();
+
+            let password = String::from_utf8(out).unwrap();
+            let password = password.strip_suffix('\n').unwrap();
+            assert_eq!(password.len(), 32);
+            assert!(password
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()));
+        }
+
+        #[test]
+        fn test_too_short() {
+            let gen = Gen {
+                length: 1,
+                no_uppercase: false,
+                no_digits: false,
+                no_symbols: false,
+            };
+            assert!(gen.execute(&mut vec![]).is_err());
+        }
+    }
+
+    /// Tests for `Delete::execute()`
+    mod delete {
+        use super::*;
+        use mockall::predicate::*;
+
+        #[tokio::test]
+        async fn test_confirmed() {
+            let mut session = Authorized::default();
+            session
+                .expect_delete_record()
+                .with(eq("example.com"))
+                .times(1)
+                .returning(|_| Ok(()));
+
+            let mut out = vec![];
+            build_delete(false)
+                .execute(&mut session, &mut "y\n".as_bytes(), &mut out)
+                .await
+                .unwrap();
+
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "Delete record `example.com`? [y/N] "
+            );
+        }
+
+        #[tokio::test]
+        async fn test_declined() {
+            let mut session = Authorized::default();
+            session.expect_delete_record().never();
+
+            let mut out = vec![];
+            build_delete(false)
+                .execute(&mut session, &mut "n\n".as_bytes(), &mut out)
+                .await
+                .unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_empty_answer() {
+            let mut session = Authorized::default();
+            session.expect_delete_record().never();
+
+            let mut out = vec![];
+            build_delete(false)
+                .execute(&mut session, &mut io::empty(), &mut out)
+                .await
+                .unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_yes_flag() {
+            let mut session = Authorized::default();
+            session
+                .expect_delete_record()
+                .with(eq("example.com"))
+                .times(1)
+                .returning(|_| Ok(()));
+
+            let mut out = vec![];
+            build_delete(true)
+                .execute(&mut session, &mut io::empty(), &mut out)
+                .await
+                .unwrap();
+
+            assert!(out.is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_no_such_record() {
+            let mut session = Authorized::default();
+            session.expect_delete_record().times(1).returning(|_| {
+                Err(rpass::Error::Server {
+                    code: ErrorCode::Io,
+                    mes: String::from("callback error: storage error"),
+                })
+            });
+            session
+                .expect_get_records_list()
+                .times(1)
+                .returning(|| Ok(vec![]));
+
+            let mut out = vec![];
+            let err = build_delete(true)
+                .execute(&mut session, &mut io::empty(), &mut out)
+                .await
+                .unwrap_err();
+
+            assert_eq!(err.to_string(), "no such record `example.com`");
+        }
+
+        /// Builds `Delete` command for *example.com* record
+        fn build_delete(yes: bool) -> Delete {
+            Delete {
+                record_name: String::from("example.com"),
+                yes,
+            }
+        }
+    }
+
+    /// Tests for `DeleteAccount::execute()`
+    #[allow(clippy::result_large_err)]
+    mod delete_account {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_confirmed() {
+            let mut session = Authorized::default();
+            session
+                .expect_delete_me()
+                .times(1)
+                .return_once(|| Ok(MockUnauthorized::default()));
+
+            let mut out = vec![];
+            DeleteAccount { yes: false }
+                .execute(session, &mut "yes\n".as_bytes(), &mut out)
+                .await
+                .unwrap();
+
+            assert!(String::from_utf8(out)
+                .unwrap()
+                .contains("All your records will be lost"));
+        }
+
+        #[tokio::test]
+        async fn test_declined() {
+            let mut session = Authorized::default();
+            session.expect_delete_me().never();
+
+            let mut out = vec![];
+            DeleteAccount { yes: false }
+                .execute(session, &mut "\n".as_bytes(), &mut out)
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Tests for `ChangeKey::execute()`
+    mod change_key {
+        use super::*;
+        use std::{env, path::PathBuf};
+
+        const OLD_KEY: &str = "11:11";
+
+        #[tokio::test]
+        async fn test_ok() {
+            let dir = build_key_dir("change_key_ok");
+            let key_path = dir.join("key.sec");
+
+            let mut session = Authorized::default();
+            session
+                .expect_change_pub_key()
+                .times(1)
+                .returning(|_, _| Ok(()));
+
+            let mut out = vec![];
+            ChangeKey
+                .execute(&mut session, &key_path, &mut out)
+                .await
+                .unwrap();
+
+            assert_eq!(
+                fs::read_to_string(dir.join("key.sec.old")).unwrap(),
+                OLD_KEY
+            );
+            let sec_key = Key::from_file(&key_path).unwrap();
+            let pub_key = Key::from_file(dir.join("key.pub")).unwrap();
+            assert_ne!(sec_key.to_string(), OLD_KEY);
+            assert_ne!(sec_key, pub_key);
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_server_error() {
+            let dir = build_key_dir("change_key_server_error");
+            let key_path = dir.join("key.sec");
+
+            let mut session = Authorized::default();
+            session.expect_change_pub_key().times(1).returning(|_, _| {
+                Err(rpass::Error::CantConnectToTheServer {
+                    addr: String::from("127.0.0.1:3747"),
+                    source: std::io::ErrorKind::ConnectionRefused.into(),
+                })
+            });
+
+            assert!(ChangeKey
+                .execute(&mut session, &key_path, &mut io::sink())
+                .await
+                .is_err());
+            assert_eq!(fs::read_to_string(&key_path).unwrap(), OLD_KEY);
+            assert!(!dir.join("key.sec.old").exists());
+            assert!(!dir.join("key.sec.new").exists());
+            assert!(!dir.join("key.pub.new").exists());
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_write_error() {
+            let dir = build_key_dir("change_key_write_error");
+            let key_path = dir.join("key.sec");
+            // Directory in place of the new public key makes writing it fail
+            fs::create_dir(dir.join("key.pub.new")).unwrap();
+
+            let mut session = Authorized::default();
+            session.expect_change_pub_key().never();
+
+            assert!(ChangeKey
+                .execute(&mut session, &key_path, &mut io::sink())
+                .await
+                .is_err());
+            assert_eq!(fs::read_to_string(&key_path).unwrap(), OLD_KEY);
+            assert!(!dir.join("key.sec.old").exists());
+            assert!(!dir.join("key.sec.new").exists());
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        /// Creates temporary directory named `name` with `OLD_KEY` as *key.sec*
+        fn build_key_dir(name: &str) -> PathBuf {
+            let dir = env::temp_dir().join(format!("rpass_{name}_{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("key.sec"), OLD_KEY).unwrap();
+            dir
+        }
+    }
+
+    /// Tests for `Export::execute()`
+    mod export {
+        use super::*;
+        use std::env;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let file = env::temp_dir().join(format!("rpass_export_ok_{}", std::process::id()));
+            let mut session = Authorized::default();
+            session.expect_export_records().times(1).returning(|| {
+                Ok(vec![Record {
+                    resource: String::from("example.com"),
+                    password: String::from("secret"),
+                    notes: String::from("notes"),
+                    encrypted_notes: false,
+                }])
+            });
+
+            let mut out = vec![];
+            Export { file: file.clone() }
+                .execute(&mut session, &mut io::empty(), &mut out)
+                .await
+                .unwrap();
+
+            let records: Vec<Record> =
+                serde_json::from_str(&fs::read_to_string(&file).unwrap()).unwrap();
+            assert_eq!(records.len(), 1);
+            assert_eq!(records[0].resource, "example.com");
+            assert!(String::from_utf8(out)
+                .unwrap()
+                .starts_with("Exported 1 records"));
+            fs::remove_file(file).unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_no_records() {
+            let file =
+                env::temp_dir().join(format!("rpass_export_no_records_{}", std::process::id()));
+            let mut session = Authorized::default();
+            session
+                .expect_export_records()
+                .times(1)
+                .returning(|| Ok(vec![]));
+
+            let mut out = vec![];
+            Export { file: file.clone() }
+                .execute(&mut session, &mut io::empty(), &mut out)
+                .await
+                .unwrap();
+
+            assert!(out.is_empty());
+            assert!(!file.exists());
+        }
+    }
+
+    /// Tests for `Import::execute()`
+    mod import {
+        use super::*;
+        use rpass::session::ImportSummary;
+        use std::env;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let file = build_file(
+                "ok",
+                r#"[{"resource":"a.com","password":"secret","notes":""}]"#,
+            );
+            let mut session = Authorized::default();
+            session
+                .expect_import_records()
+                .withf(|records, overwrite| {
+                    records.len() == 1 && records[0].resource == "a.com" && *overwrite
+                })
+                .times(1)
+                .returning(|_, _| {
+                    Ok(ImportSummary {
+                        added: vec![String::from("a.com")],
+                        skipped: vec![String::from("b.com")],
+                        failed: vec![(
+                            String::from("c.com"),
+                            rpass::Error::Server {
+                                code: ErrorCode::InvalidResourceName,
+                                mes: String::from("invalid resource name"),
+                            },
+                        )],
+                    })
+                });
+
+            let mut out = vec![];
+            Import {
+                file: file.clone(),
+                overwrite: true,
+            }
+            .execute(&mut session, &mut io::empty(), &mut out)
+            .await
+            .unwrap();
+
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "Skipped existing record `b.com`\n\
+                 Failed to import record `c.com`: server error: invalid resource name\n\
+                 Added: 1, skipped: 1, failed: 1\n"
+            );
+            fs::remove_file(file).unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_malformed_file() {
+            let file = build_file("malformed_file", "not a json");
+            let mut session = Authorized::default();
+            session.expect_import_records().never();
+
+            let err = Import {
+                file: file.clone(),
+                overwrite: false,
+            }
+            .execute(&mut session, &mut io::empty(), &mut io::sink())
+            .await
+            .unwrap_err();
+
+            assert!(err.to_string().starts_with("malformed export file"));
+            fs::remove_file(file).unwrap();
+        }
+
+        /// Writes `content` to the temporary file unique for test `name`
+        fn build_file(name: &str, content: &str) -> PathBuf {
+            let file = env::temp_dir().join(format!("rpass_import_{name}_{}", std::process::id()));
+            fs::write(&file, content).unwrap();
+            file
+        }
+    }
+
+    /// Tests for `Ls::execute()`
+    mod ls {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let mut session = build_session(&["example.com", "test.com"]);
+
+            let mut out = vec![];
+            Ls {
+                long: false,
+                json: false,
+            }
+            .execute(&mut session, &mut io::empty(), &mut out)
+            .await
+            .unwrap();
+
+            assert_eq!(String::from_utf8(out).unwrap(), "example.com\ntest.com\n");
+        }
+
+        #[tokio::test]
+        async fn test_long() {
+            let mut session = build_session(&["example.com", "test.com"]);
+
+            let mut out = vec![];
+            Ls {
+                long: true,
+                json: false,
+            }
+            .execute(&mut session, &mut io::empty(), &mut out)
+            .await
+            .unwrap();
+
+            assert_eq!(
+                String::from_utf8(out).unwrap(),
+                "total 2\nexample.com\ntest.com\n"
+            );
+        }
+
+        #[tokio::test]
+        async fn test_no_records() {
+            let mut session = build_session(&[]);
+
+            let mut out = vec![];
+            Ls {
+                long: true,
+                json: false,
+            }
+            .execute(&mut session, &mut io::empty(), &mut out)
+            .await
+            .unwrap();
+
+            assert!(out.is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_json() {
+            for records in [&["example.com", "test.com"][..], &[]] {
+                let mut session = build_session(records);
+
+                let mut out = vec![];
+                Ls {
+                    long: false,
+                    json: true,
+                }
+                .execute(&mut session, &mut io::empty(), &mut out)
+                .await
+                .unwrap();
+
+                let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+                assert_eq!(json, serde_json::json!(records));
+            }
+        }
+
+        #[tokio::test]
+        async fn test_json_long() {
+            let mut session = build_session(&["example.com", "test.com"]);
+
+            let mut out = vec![];
+            Ls {
+                long: true,
+                json: true,
+            }
+            .execute(&mut session, &mut io::empty(), &mut out)
+            .await
+            .unwrap();
+
+            let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+            assert_eq!(
+                json,
+                serde_json::json!({
+                    "total": 2,
+                    "records": ["example.com", "test.com"],
+                })
+            );
+        }
+
+        /// Builds session which returns `records` as records list
+        fn build_session(records: &[&str]) -> Authorized {
+            let records: Vec<String> = records.iter().map(|&s| s.to_owned()).collect();
+            let mut session = Authorized::default();
+            session
+                .expect_get_records_list()
+                .times(1)
+                .return_once(move || Ok(records));
+            session
+        }
     }
 }
@@ -0,0 +1,117 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use eyre::{Result, WrapErr};
+use serde::Deserialize;
+
+/// Path of the config file relative to the user config directory
+const CONFIG_PATH: &str = "rpass/config.toml";
+
+/// Format of command results
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// JSON, see `--json` flag
+    Json,
+}
+
+/// Defaults for command line arguments read from the config file.
+/// Explicit flags and environment variables take precedence over them
+///
+/// # Example
+///
+/// ```toml
+/// host = "127.0.0.1:3747"
+/// user = "alice"
+/// key = "/home/alice/.rpass/key.sec"
+/// output = "json"
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// rpass_db host address in the same format as `--host` argument
+    pub host: Option<String>,
+    /// Username for database
+    pub user: Option<String>,
+    /// Path to the key.sec file
+    pub key: Option<PathBuf>,
+    /// Default output format
+    pub output: Option<OutputFormat>,
+}
+
+impl Config {
+    /// Gets default path of the config file: *$XDG_CONFIG_HOME/rpass/config.toml*
+    /// or *~/.config/rpass/config.toml*.
+    /// Returns `None` if neither of environment variables is set
+    pub fn default_path() -> Option<PathBuf> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config_dir.join(CONFIG_PATH))
+    }
+
+    /// Reads config from file at `path`. Missing file is treated as an empty config
+    ///
+    /// # Errors
+    ///
+    /// * If can't read file
+    /// * If file content isn't a valid config
+    pub fn from_file(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content)
+                .wrap_err_with(|| format!("invalid config file `{}`", path.display())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => {
+                Err(err).wrap_err_with(|| format!("can't read config file `{}`", path.display()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_file() {
+        let path = std::env::temp_dir().join("rpass_test_config_from_file.toml");
+        fs::write(
+            &path,
+            "host = \"127.0.0.1\"\nuser = \"alice\"\nkey = \"/tmp/key.sec\"\noutput = \"json\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Config::from_file(&path).unwrap(),
+            Config {
+                host: Some(String::from("127.0.0.1")),
+                user: Some(String::from("alice")),
+                key: Some(PathBuf::from("/tmp/key.sec")),
+                output: Some(OutputFormat::Json),
+            }
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_from_missing_file() {
+        let path = std::env::temp_dir().join("rpass_test_config_missing.toml");
+        assert_eq!(Config::from_file(&path).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_from_malformed_file() {
+        let path = std::env::temp_dir().join("rpass_test_config_malformed.toml");
+        for content in ["host = ", "output = \"yaml\"", "hots = \"127.0.0.1\""] {
+            fs::write(&path, content).unwrap();
+            let err = Config::from_file(&path).unwrap_err();
+            assert!(err.to_string().contains("invalid config file"), "{err}");
+        }
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+use rpass::error::ErrorCode;
+
+/// Any failure not covered by other codes
+pub const FAILURE: u8 = 1;
+
+/// Can't connect to the server or connection was lost
+pub const CONNECTION: u8 = 3;
+
+/// Server rejected user identity or rights
+pub const AUTH: u8 = 4;
+
+/// Requested user or record doesn't exist
+pub const NOT_FOUND: u8 = 5;
+
+/// Server responded with something client can't understand
+pub const PROTOCOL: u8 = 6;
+
+/// Maps `err` to process exit code, so scripts can tell failures apart.
+/// Code `2` is left for command line parsing errors
+pub fn from_error(err: &eyre::Report) -> u8 {
+    match err.downcast_ref::<rpass::Error>() {
+        Some(err) => from_client_error(err),
+        None => FAILURE,
+    }
+}
+
+/// Maps client library `err` to process exit code
+fn from_client_error(err: &rpass::Error) -> u8 {
+    use rpass::Error;
+
+    match err {
+        Error::CantConnectToTheServer { .. } | Error::ConnectionClosed | Error::Io(_) => CONNECTION,
+        Error::InvalidResponseEncoding(_)
+        | Error::InvalidKey(_)
+        | Error::InvalidServerKey { .. }
+        | Error::KeyMismatch
+        | Error::ServerKeyChanged { .. }
+        | Error::CantParseRecord(_)
+        | Error::UnexpectedResponse { .. } => PROTOCOL,
+        Error::Server { code, .. } => from_server_code(code),
+        Error::InvalidRequest { .. } | Error::InvalidResource { .. } => FAILURE,
+    }
+}
+
+/// Maps server error `code` to process exit code
+fn from_server_code(code: &ErrorCode) -> u8 {
+    match code {
+        ErrorCode::PermissionDenied
+        | ErrorCode::TooManyRequests
+        | ErrorCode::TooManySessions
+        | ErrorCode::KeyNotAllowed
+        | ErrorCode::SessionExpired
+        | ErrorCode::InvalidConfirmationString
+        | ErrorCode::ConfirmationExpired => AUTH,
+        ErrorCode::UserDoesNotExist | ErrorCode::RecordNotFound => NOT_FOUND,
+        ErrorCode::NoCommandProvided
+        | ErrorCode::UndefinedCommand
+        | ErrorCode::InvalidRequestEncoding
+        | ErrorCode::InvalidBatch => PROTOCOL,
+        _ => FAILURE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rpass::Error;
+    use std::io;
+
+    #[test]
+    fn test_from_error() {
+        let server_error = |code| Error::Server {
+            code,
+            mes: String::new(),
+        };
+
+        for (err, expected) in [
+            (
+                Error::CantConnectToTheServer {
+                    addr: String::from("127.0.0.1:3747"),
+                    source: io::ErrorKind::ConnectionRefused.into(),
+                },
+                CONNECTION,
+            ),
+            (Error::ConnectionClosed, CONNECTION),
+            (server_error(ErrorCode::InvalidConfirmationString), AUTH),
+            (server_error(ErrorCode::PermissionDenied), AUTH),
+            (server_error(ErrorCode::UserDoesNotExist), NOT_FOUND),
+            (server_error(ErrorCode::RecordNotFound), NOT_FOUND),
+            (server_error(ErrorCode::UndefinedCommand), PROTOCOL),
+            (
+                Error::UnexpectedResponse {
+                    command: String::from("ping"),
+                    response: String::from("pang"),
+                },
+                PROTOCOL,
+            ),
+            (
+                Error::InvalidServerKey {
+                    raw: String::from("HTTP/1.1 400 Bad Request"),
+                },
+                PROTOCOL,
+            ),
+            (Error::KeyMismatch, PROTOCOL),
+            (
+                Error::ServerKeyChanged {
+                    host: String::from("127.0.0.1:3747"),
+                },
+                PROTOCOL,
+            ),
+            (server_error(ErrorCode::QuotaExceeded), FAILURE),
+            (server_error(ErrorCode::TooManySessions), AUTH),
+            (server_error(ErrorCode::KeyNotAllowed), AUTH),
+            (Error::InvalidRequest { mes: String::new() }, FAILURE),
+        ] {
+            let report = eyre::Report::from(err);
+            assert_eq!(from_error(&report), expected, "{report}");
+        }
+    }
+
+    #[test]
+    fn test_from_other_error() {
+        assert_eq!(from_error(&eyre::eyre!("some error")), FAILURE);
+        let report = eyre::Report::from(io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(from_error(&report), FAILURE);
+    }
+}
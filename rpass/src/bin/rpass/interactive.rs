@@ -0,0 +1,218 @@
+use std::io::{BufRead, Write};
+
+use clap::Parser;
+use eyre::Result;
+
+use crate::commands::{AuthorizedCommand, Execute};
+#[mockall_double::double]
+use crate::session::Authorized;
+
+/// Prompt printed before every command
+const PROMPT: &str = "> ";
+
+/// Command to end interactive session
+const QUIT_COMMAND: &str = "quit";
+
+/// Error message for commands reading the whole input, which is also the
+/// source of the next commands
+const INTERACTIVE_STDIN_ERROR: &str = "`--password-stdin` can't be used in interactive session";
+
+/// Single line of interactive session
+#[derive(Parser, Debug)]
+#[clap(no_binary_name = true)]
+struct Line {
+    #[clap(subcommand)]
+    command: AuthorizedCommand,
+    /// Print results in JSON format
+    #[clap(long, global = true)]
+    json: bool,
+}
+
+/// Runs interactive session reading commands from `input` and writing results to `out`
+///
+/// Session lasts until *quit* command or end of `input`. Errors of single commands are
+/// written to `out` and don't stop the session. If `json` is `true`, all commands
+/// behave as if `--json` flag is set
+///
+/// # Errors
+///
+/// Any error during reading from `input` or writing to `out`
+pub async fn run(
+    session: &mut Authorized,
+    json: bool,
+    input: &mut dyn BufRead,
+    out: &mut dyn Write,
+) -> Result<()> {
+    loop {
+        write!(out, "{PROMPT}")?;
+        out.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(out)?;
+            return Ok(());
+        }
+
+        let args = split_args(&line);
+        match args.first() {
+            None => continue,
+            Some(command) if command == QUIT_COMMAND => return Ok(()),
+            Some(_) => (),
+        }
+
+        let mut command = match Line::try_parse_from(args) {
+            Ok(line) => line.command,
+            Err(err) => {
+                write!(out, "{err}")?;
+                continue;
+            }
+        };
+        if command.reads_whole_input() {
+            writeln!(out, "Error: {INTERACTIVE_STDIN_ERROR}")?;
+            continue;
+        }
+        if json {
+            command.set_json();
+        }
+
+        if let Err(err) = command.execute(session, input, out).await {
+            writeln!(out, "Error: {err}")?;
+        }
+    }
+}
+
+/// Splits `line` into arguments by whitespaces.
+/// Text in double quotes `"` is treated as a single argument
+fn split_args(line: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut current: Option<String> = None;
+    let mut in_quotes = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !in_quotes => args.extend(current.take()),
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(current);
+
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockall::predicate::*;
+    use rpass::record::Record;
+
+    #[tokio::test]
+    async fn test_commands() {
+        let mut session = Authorized::default();
+        session
+            .expect_get_records_list()
+            .times(1)
+            .returning(|| Ok(vec![String::from("example.com")]));
+        session
+            .expect_get_record()
+            .with(eq(String::from("example.com")))
+            .times(1)
+            .returning(|resource| {
+                Ok(Record {
+                    resource,
+                    password: String::from("secret"),
+                    notes: String::from("notes"),
+                    encrypted_notes: false,
+                })
+            });
+
+        session.expect_ping().times(1).returning(|| Ok(()));
+
+        let mut input = "ls\n\nget example.com --show\nping\nquit\nls\n".as_bytes();
+        let mut out = vec![];
+        run(&mut session, false, &mut input, &mut out)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "> example.com\n> > secret\nnotes\n> pong\n> "
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_does_not_end_session() {
+        let mut session = Authorized::default();
+        session
+            .expect_delete_record()
+            .with(eq("example.com"))
+            .times(1)
+            .returning(|_| {
+                Err(rpass::Error::CantConnectToTheServer {
+                    addr: String::from("127.0.0.1:3747"),
+                    source: std::io::ErrorKind::ConnectionRefused.into(),
+                })
+            });
+        session
+            .expect_get_records_list()
+            .times(1)
+            .returning(|| Ok(vec![]));
+
+        let mut input = "rm -y example.com\nls\n".as_bytes();
+        let mut out = vec![];
+        run(&mut session, false, &mut input, &mut out)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "> Error: can't connect to the server at 127.0.0.1:3747\n> > \n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_command() {
+        let mut session = Authorized::default();
+
+        let mut input = "unknown\n".as_bytes();
+        let mut out = vec![];
+        run(&mut session, false, &mut input, &mut out)
+            .await
+            .unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("unknown"));
+        assert!(out.ends_with("> \n"));
+    }
+
+    #[tokio::test]
+    async fn test_password_stdin_rejected() {
+        let mut session = Authorized::default();
+        session.expect_add_record().times(0);
+        session.expect_ping().times(1).returning(|| Ok(()));
+
+        let mut input = "add example.com --password-stdin\nping\n".as_bytes();
+        let mut out = vec![];
+        run(&mut session, false, &mut input, &mut out)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("> Error: {INTERACTIVE_STDIN_ERROR}\n> pong\n> \n")
+        );
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert!(split_args("  \n").is_empty());
+        assert_eq!(
+            split_args("add  example.com --notes \"very important notes\"\n"),
+            ["add", "example.com", "--notes", "very important notes"]
+        );
+        assert_eq!(split_args("add \"\""), ["add", ""]);
+    }
+}
@@ -1,94 +1,545 @@
 use std::{
-    net::{AddrParseError, IpAddr, SocketAddr},
+    fmt, io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
     path::PathBuf,
+    process::ExitCode,
     str::FromStr,
 };
 
-use eyre::Result;
 use clap::{Parser, Subcommand};
-use rpass::key::Key;
+use eyre::{eyre, Result};
+use rpass::{key::Key, known_servers::KnownServers, session::RetryPolicy};
 
 use commands::Execute;
+use config::{Config, OutputFormat};
+#[mockall_double::double]
+use session::Authorized;
+#[mockall_double::double]
+use session::Unauthorized;
+use session_cache::SessionCache;
 
+mod clipboard;
 mod commands;
+mod config;
+mod exit_code;
+mod interactive;
+mod session;
+mod session_cache;
+
+/// Policy of retrying requests after connection loss. Only read-only requests are retried,
+/// cause it's unknown if the server has applied modification before connection was lost
+const RETRY_POLICY: RetryPolicy = RetryPolicy {
+    max_retries: 1,
+    retry_non_idempotent: false,
+};
+
+/// Path to the key file used if it's specified neither in arguments nor in the config
+const DEFAULT_KEY_PATH: &str = "~/.rpass/key.sec";
 
 /// CLI utility to interact with rpass-db
+///
+/// Host, user and key can be also set in the config file,
+/// *~/.config/rpass/config.toml* by default
 #[derive(Parser, Debug)]
 #[clap(version, about, long_about = None)]
 struct Cli {
-    /// rpass_db host address (<ip>[:<port>]). Default port is 3747
-    #[clap(short, long, parse(try_from_str=parse_host))]
-    host: SocketAddr,
+    /// rpass_db host address (<ip>[:<port>] or unix:<path>). Default port is 3747
+    #[clap(short, long, env = "RPASS_HOST", parse(try_from_str=parse_host))]
+    host: Option<Host>,
     /// Username for database
-    #[clap(short, long)]
-    user: String,
-    /// Path to the key.sec file
-    #[clap(short, long, default_value = "~/.rpass/key.sec")]
-    key: PathBuf,
+    #[clap(short, long, env = "RPASS_USER")]
+    user: Option<String>,
+    /// Path to the key.sec file [default: ~/.rpass/key.sec]
+    #[clap(short, long, env = "RPASS_KEY")]
+    key: Option<PathBuf>,
+    /// Path to the config file [default: ~/.config/rpass/config.toml]
+    #[clap(long, env = "RPASS_CONFIG")]
+    config: Option<PathBuf>,
+    /// Reuse login in the following invocations for a short time.
+    /// Session token is stored in ~/.rpass/session
+    #[clap(long)]
+    cache_session: bool,
+    /// Trust new server key if it differs from the one remembered on the first
+    /// connection in ~/.rpass/known_servers, e.g. after server key rotation
+    #[clap(long)]
+    accept_server_key: bool,
+    /// Print results in JSON format, so they can be processed by scripts
+    #[clap(long, global = true)]
+    json: bool,
     /// Subcommand to run. Interactive session will be started, if no command specified
     #[clap(subcommand)]
     command: Option<Command>,
 }
 
-/// Parse host address from `s` using default port if not provided any
-fn parse_host(s: &str) -> Result<SocketAddr, AddrParseError> {
-    const DEFAULT_PORT: u16 = 3747;
+/// Connection settings resolved from arguments, environment and config file
+#[derive(Debug, PartialEq, Eq)]
+struct Settings {
+    host: Host,
+    user: String,
+    key: PathBuf,
+    output: OutputFormat,
+}
 
-    let addr_res = SocketAddr::from_str(s);
-    match addr_res {
-        Ok(host) => Ok(host),
-        Err(addr_err) => {
-            let ip_res = IpAddr::from_str(s);
-            if let Ok(ip) = ip_res {
-                Ok(SocketAddr::new(ip, DEFAULT_PORT))
-            } else {
-                Err(addr_err)
-            }
+impl Settings {
+    /// Resolves settings with the following precedence: explicit flag, environment
+    /// variable (both are already merged in `args`), `config` and built-in default
+    ///
+    /// # Errors
+    ///
+    /// * If host or user isn't specified anywhere
+    /// * If host from `config` is invalid
+    fn resolve(args: &Cli, config: Config) -> Result<Self> {
+        let host = match (&args.host, config.host) {
+            (Some(host), _) => host.clone(),
+            (None, Some(host)) => parse_host(&host)
+                .map_err(|err| eyre!("invalid host `{host}` in the config file: {err}"))?,
+            (None, None) => eyre::bail!("host isn't specified, use `--host` or the config file"),
+        };
+        let user = args
+            .user
+            .clone()
+            .or(config.user)
+            .ok_or_else(|| eyre!("user isn't specified, use `--user` or the config file"))?;
+        let key = args
+            .key
+            .clone()
+            .or(config.key)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_KEY_PATH));
+        let output = match args.json {
+            true => OutputFormat::Json,
+            false => config.output.unwrap_or_default(),
+        };
+
+        Ok(Settings {
+            host,
+            user,
+            key,
+            output,
+        })
+    }
+}
+
+/// Check of server keys against the ones remembered on the first connection
+struct ServerKeyCheck {
+    known_servers: KnownServers,
+    /// If changed key should be remembered instead of refusing connection
+    accept_changed: bool,
+}
+
+/// Address of rpass_db
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Host {
+    Tcp(SocketAddr),
+    /// Path to the Unix domain socket
+    Unix(PathBuf),
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
         }
     }
 }
 
+/// Parse host address from `s` using default port if not provided any.
+/// Addresses with *unix:* scheme are treated as paths to Unix domain sockets.
+/// Hostnames are resolved, the first resolved address is used
+fn parse_host(s: &str) -> io::Result<Host> {
+    const DEFAULT_PORT: u16 = 3747;
+
+    if let Some(path) = s.strip_prefix("unix:") {
+        return Ok(Host::Unix(PathBuf::from(path)));
+    }
+
+    if let Ok(addr) = SocketAddr::from_str(s) {
+        return Ok(Host::Tcp(addr));
+    }
+    if let Ok(ip) = IpAddr::from_str(s) {
+        return Ok(Host::Tcp(SocketAddr::new(ip, DEFAULT_PORT)));
+    }
+
+    let mut addrs = match s.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => s.to_socket_addrs()?,
+        _ => (s, DEFAULT_PORT).to_socket_addrs()?,
+    };
+    addrs.next().map(Host::Tcp).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no addresses found for `{s}`"),
+        )
+    })
+}
+
 #[derive(Debug, Subcommand)]
 enum Command {
-    /// Register new user
-    Register(commands::Register),
+    /// Generate random password
+    Gen(commands::Gen),
 
-    /// Add record to database
-    Add(commands::Add),
+    #[clap(flatten)]
+    Session(SessionCommand),
+}
 
-    /// Delete record from database
-    Delete(commands::Delete),
+/// Commands which need user settings and key
+#[derive(Debug, Subcommand)]
+enum SessionCommand {
+    /// Register new user
+    Register(commands::Register),
 
     /// Delete user from database
     DeleteAccount(commands::DeleteAccount),
 
-    /// Get record info
-    Get(commands::Get),
+    /// Change user key pair
+    ChangeKey(commands::ChangeKey),
 
-    /// List all user records
-    Ls(commands::Ls),
+    #[clap(flatten)]
+    Authorized(commands::AuthorizedCommand),
 }
 
-impl Execute for Command {
-    fn execute(&self, username: &str, key: &Key) -> Result<()> {
-        match self {
-            Self::Register(command) => command.execute(username, key),
-            Self::Add(command) => command.execute(username, key),
-            Self::Delete(command) => command.execute(username, key),
-            Self::DeleteAccount(command) => command.execute(username, key),
-            Self::Get(command) => command.execute(username, key),
-            Self::Ls(command) => command.execute(username, key),
+/// Runs CLI and reports error if any. Exit code depends on error kind, see [`exit_code`]
+fn main() -> ExitCode {
+    let args = Cli::parse();
+    let res = rpass::blocking::block_on(run(args))
+        .map_err(eyre::Report::from)
+        .and_then(|res| res);
+
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::from(exit_code::from_error(&err))
         }
     }
 }
 
-fn main() -> Result<()> {
-    let args = Cli::parse();
+/// Executes command from `args`
+async fn run(mut args: Cli) -> Result<()> {
+    let mut out = io::stdout();
+    let command = match args.command.take() {
+        Some(Command::Gen(command)) => return command.execute(&mut out),
+        Some(Command::Session(command)) => Some(command),
+        None => None,
+    };
+
+    let config = match args.config.clone().or_else(Config::default_path) {
+        Some(path) => Config::from_file(&path)?,
+        None => Config::default(),
+    };
+    let settings = Settings::resolve(&args, config)?;
+    let json = settings.output == OutputFormat::Json;
+
+    let key = Key::from_file(&settings.key)?;
+    let cache = args
+        .cache_session
+        .then(SessionCache::default_path)
+        .flatten()
+        .map(SessionCache::new);
+    let key_check = KnownServers::default_path().map(|path| ServerKeyCheck {
+        known_servers: KnownServers::new(path),
+        accept_changed: args.accept_server_key,
+    });
+    let mut input = io::stdin().lock();
+
+    let command = match command {
+        Some(command) => command,
+        None => {
+            let mut session = login(&settings, &key, cache.as_ref(), key_check.as_ref()).await?;
+            let res = interactive::run(&mut session, json, &mut input, &mut out).await;
+            quit(session, cache.is_some()).await;
+            return res;
+        }
+    };
+
+    match command {
+        SessionCommand::Register(command) => command.execute(&settings.user, &key),
+        SessionCommand::DeleteAccount(command) => {
+            let session = login(&settings, &key, cache.as_ref(), key_check.as_ref()).await?;
+            command.execute(session, &mut input, &mut out).await
+        }
+        SessionCommand::ChangeKey(command) => {
+            let mut session = login(&settings, &key, cache.as_ref(), key_check.as_ref()).await?;
+            let res = command.execute(&mut session, &settings.key, &mut out).await;
+            quit(session, cache.is_some()).await;
+            res
+        }
+        SessionCommand::Authorized(mut command) => {
+            if json {
+                command.set_json();
+            }
+            let mut session = login(&settings, &key, cache.as_ref(), key_check.as_ref()).await?;
+            let res = command.execute(&mut session, &mut input, &mut out).await;
+            quit(session, cache.is_some()).await;
+            clipboard::wait_restores();
+            res
+        }
+    }
+}
+
+/// Connects to the server on `settings.host` and logs in as `settings.user` with `key`.
+///
+/// If `cache` is provided, session is resumed with the cached token instead of logging in.
+/// New token is cached after logging in, if server supports it.
+/// Server key is checked with `key_check` if provided, see [`connect()`]
+async fn login(
+    settings: &Settings,
+    key: &Key,
+    cache: Option<&SessionCache>,
+    key_check: Option<&ServerKeyCheck>,
+) -> Result<Authorized> {
+    let host = settings.host.to_string();
+    let username = settings.user.as_str();
+
+    let mut session = connect(&settings.host, key_check).await?;
+    let supports_resume = session
+        .server_version()
+        .is_some_and(|version| version.supports("resume"));
+    let cache = cache.filter(|_| supports_resume);
+
+    if let Some((cache, token)) =
+        cache.and_then(|cache| Some((cache, cache.load(&host, username)?)))
+    {
+        match session.resume(&token).await {
+            Ok(mut session) => {
+                session.set_retry_policy(RETRY_POLICY);
+                return Ok(session);
+            }
+            Err(err) => {
+                eprintln!("Warning: can't resume cached session ({err}), logging in again");
+                cache.remove();
+                session = connect(&settings.host, key_check).await?;
+            }
+        }
+    }
+
+    let mut session = session
+        .login(username, key)
+        .await
+        .map_err(|err| err.source)?;
+    session.set_retry_policy(RETRY_POLICY);
+
+    if let Some(cache) = cache {
+        let res = match session.issue_session_token().await {
+            Ok(token) => cache
+                .save(&host, username, &token)
+                .map_err(eyre::Report::from),
+            Err(err) => Err(eyre::Report::from(err)),
+        };
+        if let Err(err) = res {
+            eprintln!("Warning: can't cache session ({err})");
+        }
+    }
+    Ok(session)
+}
+
+/// Connects to the server on `host`. If `key_check` is provided, server key is
+/// checked against the one remembered for `host`
+async fn connect(host: &Host, key_check: Option<&ServerKeyCheck>) -> Result<Unauthorized> {
+    let res = match host {
+        Host::Tcp(addr) => Unauthorized::new(*addr).await,
+        #[cfg(unix)]
+        Host::Unix(path) => Unauthorized::new_unix(path.as_path()).await,
+        #[cfg(not(unix))]
+        Host::Unix(_) => eyre::bail!("Unix domain sockets aren't supported on this platform"),
+    };
+    let session = match res {
+        Ok(session) => session,
+        Err(err @ rpass::Error::InvalidServerKey { .. }) => {
+            return Err(eyre::Report::from(err).wrap_err(format!(
+                "connected to {host} but it doesn't look like an rpass server"
+            )))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if let Some(key_check) = key_check {
+        check_server_key(host, &session, key_check)?;
+    }
+    if session.server_version().is_none() {
+        eprintln!("Warning: server doesn't report its version, some commands may be unsupported");
+    }
+    Ok(session)
+}
+
+/// Checks key of the server on `host` `session` is connected to with `key_check`
+///
+/// # Errors
+///
+/// * If server key has changed and it isn't accepted
+/// * If can't read or write known servers file
+fn check_server_key(host: &Host, session: &Unauthorized, key_check: &ServerKeyCheck) -> Result<()> {
+    let host = host.to_string();
+    let key = session.server_pub_key();
+    let res = match key_check.accept_changed {
+        true => key_check.known_servers.accept(&host, key),
+        false => key_check.known_servers.check(&host, key),
+    };
+    match res {
+        Err(err @ rpass::Error::ServerKeyChanged { .. }) => Err(eyre::Report::from(err)
+            .wrap_err("use `--accept-server-key` if server key was rotated on purpose")),
+        res => res.map_err(Into::into),
+    }
+}
+
+/// Gracefully closes `session` if server supports it. Failure is reported as a warning,
+/// cause all the work is already done
+///
+/// Session isn't closed if it's `cached`, cause closing revokes its token
+async fn quit(session: Authorized, cached: bool) {
+    if cached {
+        return;
+    }
+
+    let supports_quit = session
+        .server_version()
+        .await
+        .is_some_and(|version| version.supports("quit"));
+    if !supports_quit {
+        return;
+    }
+
+    if let Err(err) = session.quit().await {
+        eprintln!("Warning: can't close session gracefully ({err})");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    /// Environment variables `Cli` falls back to
+    const CLI_ENVS: [&str; 4] = ["RPASS_HOST", "RPASS_USER", "RPASS_KEY", "RPASS_CONFIG"];
+
+    /// Parses `args` like [`Cli::parse_from()`], but without falling back to
+    /// environment variables of the user running tests
+    fn parse_args<const N: usize>(args: [&str; N]) -> Cli {
+        static CLEAR_ENVS: std::sync::Once = std::sync::Once::new();
+        CLEAR_ENVS.call_once(|| {
+            for env in CLI_ENVS {
+                std::env::remove_var(env);
+            }
+        });
+        Cli::parse_from(args)
+    }
+
+    #[test]
+    fn test_parse_host() {
+        assert_eq!(
+            parse_host("127.0.0.1").unwrap(),
+            Host::Tcp(SocketAddr::from((Ipv4Addr::LOCALHOST, 3747)))
+        );
+        assert_eq!(
+            parse_host("127.0.0.1:1234").unwrap(),
+            Host::Tcp(SocketAddr::from((Ipv4Addr::LOCALHOST, 1234)))
+        );
+        assert_eq!(
+            parse_host("::1").unwrap(),
+            Host::Tcp(SocketAddr::from((Ipv6Addr::LOCALHOST, 3747)))
+        );
+        assert_eq!(
+            parse_host("[::1]:3747").unwrap(),
+            Host::Tcp(SocketAddr::from((Ipv6Addr::LOCALHOST, 3747)))
+        );
+        assert_eq!(
+            parse_host("unix:/run/rpass.sock").unwrap(),
+            Host::Unix(PathBuf::from("/run/rpass.sock"))
+        );
+        assert!(parse_host("[::1]").is_err());
+        assert!(parse_host("not a host").is_err());
+    }
+
+    #[test]
+    fn test_parse_hostname() {
+        for (host, port) in [("localhost", 3747), ("localhost:1234", 1234)] {
+            match parse_host(host).unwrap() {
+                Host::Tcp(addr) => {
+                    assert!(addr.ip().is_loopback(), "{addr}");
+                    assert_eq!(addr.port(), port);
+                }
+                Host::Unix(path) => panic!("unexpected Unix socket {path:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_settings_from_args() {
+        let args = parse_args([
+            "rpass",
+            "--host",
+            "127.0.0.1",
+            "--user",
+            "bob",
+            "--key",
+            "/tmp/bob.sec",
+            "--json",
+        ]);
+        let config = Config {
+            host: Some(String::from("127.0.0.2:1234")),
+            user: Some(String::from("alice")),
+            key: Some(PathBuf::from("/tmp/alice.sec")),
+            output: Some(OutputFormat::Text),
+        };
+
+        assert_eq!(
+            Settings::resolve(&args, config).unwrap(),
+            Settings {
+                host: Host::Tcp(SocketAddr::from((Ipv4Addr::LOCALHOST, 3747))),
+                user: String::from("bob"),
+                key: PathBuf::from("/tmp/bob.sec"),
+                output: OutputFormat::Json,
+            }
+        );
+    }
+
+    #[test]
+    fn test_settings_from_config() {
+        let args = parse_args(["rpass", "--user", "bob"]);
+        let config = Config {
+            host: Some(String::from("unix:/run/rpass.sock")),
+            user: Some(String::from("alice")),
+            key: Some(PathBuf::from("/tmp/alice.sec")),
+            output: Some(OutputFormat::Json),
+        };
+
+        assert_eq!(
+            Settings::resolve(&args, config).unwrap(),
+            Settings {
+                host: Host::Unix(PathBuf::from("/run/rpass.sock")),
+                user: String::from("bob"),
+                key: PathBuf::from("/tmp/alice.sec"),
+                output: OutputFormat::Json,
+            }
+        );
+    }
+
+    #[test]
+    fn test_settings_defaults() {
+        let args = parse_args(["rpass", "--host", "127.0.0.1", "--user", "bob"]);
+
+        assert_eq!(
+            Settings::resolve(&args, Config::default()).unwrap(),
+            Settings {
+                host: Host::Tcp(SocketAddr::from((Ipv4Addr::LOCALHOST, 3747))),
+                user: String::from("bob"),
+                key: PathBuf::from(DEFAULT_KEY_PATH),
+                output: OutputFormat::Text,
+            }
+        );
+    }
+
+    #[test]
+    fn test_settings_missing() {
+        let args = parse_args(["rpass", "--user", "bob"]);
+        assert!(Settings::resolve(&args, Config::default()).is_err());
+
+        let args = parse_args(["rpass", "--host", "127.0.0.1"]);
+        assert!(Settings::resolve(&args, Config::default()).is_err());
 
-    if let Some(command) = args.command {
-        let key = Key::from_file(args.key)?;
-        command.execute(&args.user, &key)
-    } else {
-        todo!("Interactive mode isn't implemented yet")
+        let config = Config {
+            host: Some(String::from("not a host")),
+            ..Config::default()
+        };
+        let args = parse_args(["rpass", "--user", "bob"]);
+        assert!(Settings::resolve(&args, config).is_err());
     }
 }
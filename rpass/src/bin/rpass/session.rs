@@ -0,0 +1,60 @@
+#[cfg(not(test))]
+pub use rpass::session::{Authorized, Unauthorized};
+
+#[cfg(test)]
+pub use mocks::{MockAuthorized, MockUnauthorized};
+
+/// Mocks of the library sessions to test commands without a server
+#[cfg(test)]
+#[allow(clippy::result_large_err)]
+mod mocks {
+    use rpass::{
+        error::{DeleteMeError, LoginError},
+        key::Key,
+        record::Record,
+        session::{ImportSummary, RetryPolicy, ServerVersion},
+        Result,
+    };
+    use std::net::SocketAddr;
+    use std::path::Path;
+
+    mockall::mock! {
+        pub Unauthorized {
+            pub async fn new(addr: SocketAddr) -> Result<Self>;
+            pub async fn new_unix(path: &Path) -> Result<Self>;
+            pub fn server_version(&self) -> Option<ServerVersion>;
+            pub fn server_pub_key(&self) -> &Key;
+            pub async fn login(
+                self,
+                username: &str,
+                sec_key: &Key,
+            ) -> std::result::Result<MockAuthorized, LoginError>;
+            pub async fn resume(
+                self,
+                token: &str,
+            ) -> std::result::Result<MockAuthorized, LoginError>;
+        }
+    }
+
+    mockall::mock! {
+        pub Authorized {
+            pub async fn server_version(&self) -> Option<ServerVersion>;
+            pub fn set_retry_policy(&mut self, policy: RetryPolicy);
+            pub async fn issue_session_token(&mut self) -> Result<String>;
+            pub async fn ping(&mut self) -> Result<()>;
+            pub async fn add_record(&mut self, record: &Record) -> Result<()>;
+            pub async fn get_record(&self, resource: String) -> Result<Record>;
+            pub async fn get_records_list(&self) -> Result<Vec<String>>;
+            pub async fn export_records(&self) -> Result<Vec<Record>>;
+            pub async fn delete_record(&mut self, resource: &str) -> Result<()>;
+            pub async fn import_records(
+                &mut self,
+                records: &[Record],
+                overwrite: bool,
+            ) -> Result<ImportSummary>;
+            pub async fn change_pub_key(&mut self, new_pub: &Key, new_sec: &Key) -> Result<()>;
+            pub async fn delete_me(self) -> std::result::Result<MockUnauthorized, DeleteMeError>;
+            pub async fn quit(self) -> Result<()>;
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Path of the cache file relative to the home directory
+const CACHE_PATH: &str = ".rpass/session";
+
+/// Session token saved between CLI invocations, so login can be skipped.
+/// Token is bound to the host and user it was issued for
+pub struct SessionCache {
+    path: PathBuf,
+}
+
+impl SessionCache {
+    /// Creates cache stored in file at `path`
+    pub fn new(path: PathBuf) -> Self {
+        SessionCache { path }
+    }
+
+    /// Gets default path of the cache file: *~/.rpass/session*.
+    /// Returns `None` if home directory is unknown
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("HOME").map(|home| Path::new(&home).join(CACHE_PATH))
+    }
+
+    /// Gets token saved for `user` on `host`.
+    /// Returns `None` if there is no token or it was saved for another host or user
+    pub fn load(&self, host: &str, user: &str) -> Option<String> {
+        let content = fs::read_to_string(&self.path).ok()?;
+        let mut lines = content.lines();
+        match (lines.next(), lines.next(), lines.next()) {
+            (Some(saved_host), Some(saved_user), Some(token))
+                if saved_host == host && saved_user == user && !token.is_empty() =>
+            {
+                Some(token.to_owned())
+            }
+            _ => None,
+        }
+    }
+
+    /// Saves `token` for `user` on `host` replacing previous one.
+    /// File is readable and writable only by its owner
+    ///
+    /// # Errors
+    ///
+    /// Any error during writing the file
+    pub fn save(&self, host: &str, user: &str, token: &str) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&self.path)?;
+        // Mode is applied only to new files, so permissions of existing one are fixed
+        #[cfg(unix)]
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+
+        write!(file, "{host}\n{user}\n{token}\n")
+    }
+
+    /// Removes saved token if there is some
+    pub fn remove(&self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOST: &str = "127.0.0.1:3747";
+    const USER: &str = "test_user";
+
+    #[test]
+    fn test_save_load() {
+        let cache = build_cache("save_load");
+
+        assert!(cache.load(HOST, USER).is_none());
+        cache.save(HOST, USER, "first").unwrap();
+        cache.save(HOST, USER, "token").unwrap();
+
+        assert_eq!(cache.load(HOST, USER).unwrap(), "token");
+        assert!(cache.load("127.0.0.1:1234", USER).is_none());
+        assert!(cache.load(HOST, "other_user").is_none());
+
+        cache.remove();
+        assert!(cache.load(HOST, USER).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let cache = build_cache("permissions");
+        fs::write(&cache.path, "").unwrap();
+        fs::set_permissions(&cache.path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        cache.save(HOST, USER, "token").unwrap();
+        let mode = fs::metadata(&cache.path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        cache.remove();
+    }
+
+    fn build_cache(name: &str) -> SessionCache {
+        let path = std::env::temp_dir().join(format!("rpass_test_session_cache_{name}"));
+        let _ = fs::remove_file(&path);
+        SessionCache::new(path)
+    }
+}
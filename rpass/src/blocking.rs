@@ -0,0 +1,443 @@
+//! Synchronous API for users who don't need an async runtime
+//!
+//! Every session owns a current-thread runtime which drives the async session inside.
+//! Available with `blocking` feature
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use std::error::Error;
+//! use rpass::{blocking, key::Key};
+//!
+//! # fn main() -> std::result::Result<(), Box<dyn Error>> {
+//! let sec_key = Key::from_file("~/key.sec")?;
+//! let session = blocking::Unauthorized::new("127.0.0.1:3747")?;
+//! let session = session.login("user", &sec_key)?;
+//! for resource in session.get_records_list()? {
+//!     println!("{}", resource);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    key::Key,
+    record::{PatchRecord, Record, RecordVersion, SortOrder},
+    session::{self, ServerInfo, ServerVersion},
+    Error, Result,
+};
+
+use std::future::Future;
+use tokio::{net::ToSocketAddrs, runtime::Runtime};
+
+/// Error of [`Unauthorized::login()`]
+#[derive(thiserror::Error, Debug)]
+#[error("{source}")]
+pub struct LoginError {
+    pub source: Error,
+    pub unauthorized: Unauthorized,
+}
+
+/// Error of [`Authorized::delete_me()`]
+#[derive(thiserror::Error, Debug)]
+#[error("{source}")]
+pub struct DeleteMeError {
+    pub source: Error,
+    pub authorized: Authorized,
+}
+
+/// Runs `future` to completion on a new current-thread runtime
+///
+/// Must not be called from inside another runtime
+///
+/// # Errors
+///
+/// * `Io` - if can't create runtime
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::error::Error;
+/// use rpass::{blocking, session};
+///
+/// # fn main() -> std::result::Result<(), Box<dyn Error>> {
+/// let version = blocking::block_on(async {
+///     let session = session::Unauthorized::new("127.0.0.1:3747").await?;
+///     rpass::Result::Ok(session.server_version())
+/// })??;
+/// # Ok(())
+/// # }
+/// ```
+pub fn block_on<F: Future>(future: F) -> Result<F::Output> {
+    Ok(new_runtime()?.block_on(future))
+}
+
+/// Blocking version of [`session::Unauthorized`]
+#[derive(Debug)]
+pub struct Unauthorized {
+    runtime: Runtime,
+    inner: session::Unauthorized,
+}
+
+impl Unauthorized {
+    /// Creates new Unauthorized connected to rpass server on `addr`
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't create runtime
+    /// * See [`session::Unauthorized::new()`] for other errors
+    pub fn new<A: ToSocketAddrs + std::fmt::Display>(addr: A) -> Result<Self> {
+        let runtime = new_runtime()?;
+        let inner = runtime.block_on(session::Unauthorized::new(addr))?;
+        Ok(Unauthorized { runtime, inner })
+    }
+
+    /// See [`session::Unauthorized::server_version()`]
+    pub fn server_version(&self) -> Option<ServerVersion> {
+        self.inner.server_version()
+    }
+
+    /// See [`session::Unauthorized::server_info()`]
+    pub fn server_info(&self) -> Option<ServerInfo> {
+        self.inner.server_info()
+    }
+
+    /// See [`session::Unauthorized::ping()`]
+    pub fn ping(&mut self) -> Result<()> {
+        self.runtime.block_on(self.inner.ping())
+    }
+
+    /// See [`session::Unauthorized::help()`]
+    pub fn help(&mut self) -> Result<Vec<String>> {
+        self.runtime.block_on(self.inner.help())
+    }
+
+    /// See [`session::Unauthorized::register()`]
+    pub fn register(&mut self, username: &str, pub_key: &Key) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.register(username, pub_key))
+    }
+
+    /// See [`session::Unauthorized::login()`]
+    #[allow(clippy::result_large_err)]
+    pub fn login(
+        self,
+        username: &str,
+        sec_key: &Key,
+    ) -> std::result::Result<Authorized, LoginError> {
+        let runtime = self.runtime;
+        match runtime.block_on(self.inner.login(username, sec_key)) {
+            Ok(inner) => Ok(Authorized { runtime, inner }),
+            Err(err) => Err(LoginError {
+                source: err.source,
+                unauthorized: Unauthorized {
+                    runtime,
+                    inner: err.unauthorized,
+                },
+            }),
+        }
+    }
+
+    /// See [`session::Unauthorized::resume()`]
+    #[allow(clippy::result_large_err)]
+    pub fn resume(self, token: &str) -> std::result::Result<Authorized, LoginError> {
+        let runtime = self.runtime;
+        match runtime.block_on(self.inner.resume(token)) {
+            Ok(inner) => Ok(Authorized { runtime, inner }),
+            Err(err) => Err(LoginError {
+                source: err.source,
+                unauthorized: Unauthorized {
+                    runtime,
+                    inner: err.unauthorized,
+                },
+            }),
+        }
+    }
+
+    /// See [`session::Unauthorized::quit()`]
+    pub fn quit(self) -> Result<()> {
+        self.runtime.block_on(self.inner.quit())
+    }
+}
+
+/// Blocking version of [`session::RecordNames`]
+#[derive(Debug)]
+pub struct RecordNames<'a> {
+    runtime: &'a Runtime,
+    inner: session::RecordNames<'a>,
+}
+
+impl Iterator for RecordNames<'_> {
+    type Item = Result<String>;
+
+    /// See [`session::RecordNames::next()`]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runtime.block_on(self.inner.next())
+    }
+}
+
+/// Blocking version of [`session::Authorized`]
+#[derive(Debug)]
+pub struct Authorized {
+    runtime: Runtime,
+    inner: session::Authorized,
+}
+
+impl Authorized {
+    /// See [`session::Authorized::server_version()`]
+    pub fn server_version(&self) -> Option<ServerVersion> {
+        self.runtime.block_on(self.inner.server_version())
+    }
+
+    /// See [`session::Authorized::server_info()`]
+    pub fn server_info(&self) -> Option<ServerInfo> {
+        self.runtime.block_on(self.inner.server_info())
+    }
+
+    /// See [`session::Authorized::ping()`]
+    pub fn ping(&mut self) -> Result<()> {
+        self.runtime.block_on(self.inner.ping())
+    }
+
+    /// See [`session::Authorized::help()`]
+    pub fn help(&self) -> Result<Vec<String>> {
+        self.runtime.block_on(self.inner.help())
+    }
+
+    /// See [`session::Authorized::issue_session_token()`]
+    pub fn issue_session_token(&mut self) -> Result<String> {
+        self.runtime.block_on(self.inner.issue_session_token())
+    }
+
+    /// See [`session::Authorized::is_alive()`]
+    pub fn is_alive(&self) -> bool {
+        self.runtime.block_on(self.inner.is_alive())
+    }
+
+    /// See [`session::Authorized::add_record()`]
+    pub fn add_record(&mut self, record: &Record) -> Result<()> {
+        self.runtime.block_on(self.inner.add_record(record))
+    }
+
+    /// See [`session::Authorized::patch_record()`]
+    pub fn patch_record(&mut self, resource: &str, patch: &PatchRecord) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.patch_record(resource, patch))
+    }
+
+    /// See [`session::Authorized::get_record()`]
+    pub fn get_record(&self, resource: String) -> Result<Record> {
+        self.runtime.block_on(self.inner.get_record(resource))
+    }
+
+    /// See [`session::Authorized::get_record_or_none()`]
+    pub fn get_record_or_none(&self, resource: String) -> Result<Option<Record>> {
+        self.runtime
+            .block_on(self.inner.get_record_or_none(resource))
+    }
+
+    /// See [`session::Authorized::exists_record()`]
+    pub fn exists_record(&self, resource: &str) -> Result<bool> {
+        self.runtime.block_on(self.inner.exists_record(resource))
+    }
+
+    /// See [`session::Authorized::list_trash()`]
+    pub fn list_trash(&self) -> Result<Vec<String>> {
+        self.runtime.block_on(self.inner.list_trash())
+    }
+
+    /// See [`session::Authorized::restore_record()`]
+    pub fn restore_record(&mut self, resource: &str) -> Result<()> {
+        self.runtime.block_on(self.inner.restore_record(resource))
+    }
+
+    /// See [`session::Authorized::purge_trash()`]
+    pub fn purge_trash(&mut self) -> Result<()> {
+        self.runtime.block_on(self.inner.purge_trash())
+    }
+
+    /// See [`session::Authorized::get_record_history()`]
+    pub fn get_record_history(&self, resource: &str) -> Result<Vec<RecordVersion>> {
+        self.runtime
+            .block_on(self.inner.get_record_history(resource))
+    }
+
+    /// See [`session::Authorized::restore_record_version()`]
+    pub fn restore_record_version(&mut self, resource: &str, version: u64) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.restore_record_version(resource, version))
+    }
+
+    /// See [`session::Authorized::get_records_list()`]
+    pub fn get_records_list(&self) -> Result<Vec<String>> {
+        self.runtime.block_on(self.inner.get_records_list())
+    }
+
+    /// See [`session::Authorized::get_records_list_sorted()`]
+    pub fn get_records_list_sorted(&self, sort: SortOrder) -> Result<Vec<String>> {
+        self.runtime
+            .block_on(self.inner.get_records_list_sorted(sort))
+    }
+
+    /// See [`session::Authorized::get_records_page()`]
+    pub fn get_records_page(&self, offset: usize, limit: usize) -> Result<(Vec<String>, usize)> {
+        self.runtime
+            .block_on(self.inner.get_records_page(offset, limit))
+    }
+
+    /// See [`session::Authorized::iter_records()`]
+    pub fn iter_records(&self) -> Result<RecordNames<'_>> {
+        let inner = self.runtime.block_on(self.inner.iter_records())?;
+        Ok(RecordNames {
+            runtime: &self.runtime,
+            inner,
+        })
+    }
+
+    /// See [`session::Authorized::count_records()`]
+    pub fn count_records(&self) -> Result<usize> {
+        self.runtime.block_on(self.inner.count_records())
+    }
+
+    /// See [`session::Authorized::delete_record()`]
+    pub fn delete_record(&mut self, resource: &str) -> Result<()> {
+        self.runtime.block_on(self.inner.delete_record(resource))
+    }
+
+    /// See [`session::Authorized::delete_records()`]
+    pub fn delete_records(&mut self, resources: &[String]) -> Result<Vec<(String, Result<()>)>> {
+        self.runtime.block_on(self.inner.delete_records(resources))
+    }
+
+    /// See [`session::Authorized::delete_me()`]
+    #[allow(clippy::result_large_err)]
+    pub fn delete_me(self) -> std::result::Result<Unauthorized, DeleteMeError> {
+        let runtime = self.runtime;
+        match runtime.block_on(self.inner.delete_me()) {
+            Ok(inner) => Ok(Unauthorized { runtime, inner }),
+            Err(err) => Err(DeleteMeError {
+                source: err.source,
+                authorized: Authorized {
+                    runtime,
+                    inner: err.authorized,
+                },
+            }),
+        }
+    }
+
+    /// See [`session::Authorized::quit()`]
+    pub fn quit(self) -> Result<()> {
+        self.runtime.block_on(self.inner.quit())
+    }
+}
+
+/// Creates current-thread runtime to drive async sessions
+///
+/// # Errors
+///
+/// * `Io` - if can't create runtime
+fn new_runtime() -> Result<Runtime> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|err| err.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::Connector;
+    use mockall::{predicate::*, Sequence};
+    use num_bigint::ToBigUint;
+
+    const TEST_USER: &str = "test_user";
+
+    #[test]
+    fn test_block_on() {
+        assert_eq!(block_on(async { 42 }).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_full_cycle() {
+        let server_pub_key = Key(11.to_biguint().unwrap(), 22.to_biguint().unwrap());
+        let pub_key = Key(269.to_biguint().unwrap(), 221.to_biguint().unwrap());
+        let sec_key = Key(5.to_biguint().unwrap(), 221.to_biguint().unwrap());
+        let record = Record::builder()
+            .resource("example.com")
+            .password("secret")
+            .notes("notes")
+            .build();
+
+        let mut seq = Sequence::new();
+        let mut connector = Connector::default();
+        expect_request(
+            &mut connector,
+            &mut seq,
+            eq(format!("register {} {}", TEST_USER, pub_key)),
+            String::from("Ok"),
+        );
+        expect_request(
+            &mut connector,
+            &mut seq,
+            eq(format!("login {}", TEST_USER)),
+            pub_key.encrypt("confirmation"),
+        );
+        connector
+            .expect_server_pub_key()
+            .times(1)
+            .in_sequence(&mut seq)
+            .return_const(server_pub_key);
+        expect_request(
+            &mut connector,
+            &mut seq,
+            function(|request: &String| request.starts_with("confirm_login ")),
+            String::from("Ok"),
+        );
+        expect_request(
+            &mut connector,
+            &mut seq,
+            eq(format!("new_record \"example.com\" \"{}\"", record)),
+            String::from("Ok"),
+        );
+        expect_request(
+            &mut connector,
+            &mut seq,
+            eq(String::from("show_record \"example.com\"")),
+            record.to_string(),
+        );
+
+        let mut unauthorized = Unauthorized {
+            runtime: new_runtime().unwrap(),
+            inner: session::Unauthorized::with_connector(connector),
+        };
+        unauthorized.register(TEST_USER, &pub_key).unwrap();
+        let mut authorized = unauthorized.login(TEST_USER, &sec_key).unwrap();
+        authorized.add_record(&record).unwrap();
+        assert_eq!(
+            authorized.get_record(String::from("example.com")).unwrap(),
+            record
+        );
+    }
+
+    /// Expects `connector` to receive request matching `predicate` and answer with `response`
+    fn expect_request<P>(
+        connector: &mut Connector,
+        seq: &mut Sequence,
+        predicate: P,
+        response: String,
+    ) where
+        P: mockall::Predicate<String> + Send + 'static,
+    {
+        connector
+            .expect_send_request()
+            .with(predicate)
+            .times(1)
+            .in_sequence(seq)
+            .returning(|_| Ok(()));
+        connector
+            .expect_recv_response()
+            .times(1)
+            .in_sequence(seq)
+            .return_once(move || Ok(response));
+    }
+}
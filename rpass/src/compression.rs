@@ -0,0 +1,134 @@
+//! Optional compression of messages between client and server
+//!
+//! Compressed message starts with [`MARKER`] byte followed by deflate stream.
+//! Marker byte never appears in UTF-8 text, so raw messages are told apart
+//! without any prefix. Peers should agree to use compression with
+//! [`COMMAND`] request first, cause old peers can't inflate messages
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::io::{self, Read, Write};
+
+/// Request enabling compression of the following messages in both directions.
+/// Server advertises support in *version* response
+pub const COMMAND: &str = "enable_compression";
+
+/// First byte of the compressed message
+pub const MARKER: u8 = 0xff;
+
+/// Messages shorter than this number of bytes are never compressed
+pub const THRESHOLD: usize = 1024;
+
+/// Compresses `message` if it's at least [`THRESHOLD`] bytes long and
+/// compression actually makes it smaller. Returns `message` as is otherwise
+///
+/// # Example
+///
+/// ```
+/// use rpass::compression;
+///
+/// let large = "notes ".repeat(1000).into_bytes();
+/// let compressed = compression::compress(large.clone());
+/// assert_eq!(compressed[0], compression::MARKER);
+/// assert_eq!(compression::decompress(compressed).unwrap(), large);
+///
+/// assert_eq!(compression::compress(b"pong\r\n".to_vec()), b"pong\r\n");
+/// ```
+pub fn compress(message: Vec<u8>) -> Vec<u8> {
+    if message.len() < THRESHOLD {
+        return message;
+    }
+
+    let mut encoder = DeflateEncoder::new(vec![MARKER], Compression::default());
+    let compressed = encoder
+        .write_all(&message)
+        .and_then(|_| encoder.finish())
+        .expect("writing to vector never fails");
+    match compressed.len() < message.len() {
+        true => compressed,
+        false => message,
+    }
+}
+
+/// Inflates `message` if it starts with [`MARKER`] byte.
+/// Returns `message` as is otherwise
+///
+/// # Errors
+///
+/// Any error during inflating, e.g. if compressed message is corrupted
+pub fn decompress(message: Vec<u8>) -> io::Result<Vec<u8>> {
+    decompress_limited(message, usize::MAX)
+}
+
+/// Same as [`decompress()`], but fails if inflated message is longer than
+/// `limit` bytes. Inflating stops as soon as limit is exceeded, so small
+/// compressed message can't take much memory
+///
+/// # Errors
+///
+/// * `InvalidData` - if inflated message is longer than `limit` bytes
+/// * Any error during inflating, e.g. if compressed message is corrupted
+pub fn decompress_limited(message: Vec<u8>, limit: usize) -> io::Result<Vec<u8>> {
+    match message.split_first() {
+        Some((&MARKER, compressed)) => {
+            let mut decompressed = vec![];
+            DeflateDecoder::new(compressed)
+                .take((limit as u64).saturating_add(1))
+                .read_to_end(&mut decompressed)?;
+            if decompressed.len() > limit {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("decompressed message is larger than {limit} bytes"),
+                ));
+            }
+            Ok(decompressed)
+        }
+        _ => Ok(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_large_message_round_trip() {
+        let message =
+            format!("new_record \"example.com\" {}\r\n", "note ".repeat(500)).into_bytes();
+
+        let compressed = compress(message.clone());
+        assert_eq!(compressed[0], MARKER);
+        assert!(compressed.len() < message.len());
+        assert_eq!(decompress(compressed).unwrap(), message);
+    }
+
+    #[test]
+    fn test_small_message_stays_raw() {
+        let message = b"show_record \"example.com\"\r\n".to_vec();
+        assert_eq!(compress(message.clone()), message);
+        assert_eq!(decompress(message.clone()).unwrap(), message);
+    }
+
+    #[test]
+    fn test_incompressible_message_stays_raw() {
+        let message: Vec<u8> = (0..THRESHOLD * 2).map(|_| rand::random()).collect();
+        assert_eq!(compress(message.clone()), message);
+    }
+
+    #[test]
+    fn test_decompress_limited() {
+        let message = vec![b'a'; THRESHOLD * 4];
+        let compressed = compress(message.clone());
+
+        assert_eq!(
+            decompress_limited(compressed.clone(), message.len()).unwrap(),
+            message
+        );
+        let err = decompress_limited(compressed, message.len() - 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_decompress_corrupted() {
+        assert!(decompress(vec![MARKER, 0xff, 0xff, 0xff]).is_err());
+    }
+}
@@ -3,17 +3,25 @@ use crate::{
     record::Record,
     session::{Authorized, Unauthorized},
 };
+use std::fmt;
 use std::str::FromStr;
 use std::string::FromUtf8Error;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
-    #[error("can't connect to the server")]
-    CantConnectToTheServer,
+    #[error("can't connect to the server at {addr}")]
+    CantConnectToTheServer {
+        addr: String,
+        #[source]
+        source: std::io::Error,
+    },
 
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
 
+    #[error("server closed the connection")]
+    ConnectionClosed,
+
     #[error("invalid response encoding")]
     InvalidResponseEncoding(#[from] FromUtf8Error),
 
@@ -23,6 +31,21 @@ pub enum Error {
     #[error("invalid key")]
     InvalidKey(#[from] <Key as FromStr>::Err),
 
+    /// Server sent something else instead of its pub key, probably it's not an rpass server.
+    /// `raw` is the beginning of the received message
+    #[error("server sent invalid key `{raw}`")]
+    InvalidServerKey { raw: String },
+
+    /// Server sent pub key different from the pinned one, see
+    /// [`crate::session::Unauthorized::new_pinned()`]
+    #[error("server key doesn't match the pinned one")]
+    KeyMismatch,
+
+    /// Server presented key different from the one remembered on the first
+    /// connection, see [`crate::known_servers::KnownServers`]
+    #[error("key of the server {host} has changed, it may be a man-in-the-middle attack")]
+    ServerKeyChanged { host: String },
+
     #[error("invalid resource: {mes}")]
     InvalidResource { mes: String },
 
@@ -30,10 +53,10 @@ pub enum Error {
     CantParseRecord(#[from] <Record as FromStr>::Err),
 
     #[error("server error: {mes}")]
-    Server { mes: String },
+    Server { code: ErrorCode, mes: String },
 
-    #[error("unexpected response: {response}")]
-    UnexpectedResponse { response: String },
+    #[error("unexpected response to `{command}` request: {response}")]
+    UnexpectedResponse { command: String, response: String },
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -49,3 +72,160 @@ pub struct DeleteMeError {
     pub source: Error,
     pub authorized: Authorized,
 }
+
+/// Declares [`ErrorCode`] enum with `codes` and conversions from/to string
+macro_rules! error_codes {
+    ($($(#[$meta:meta])* $code:ident),* $(,)?) => {
+        /// Machine-readable code of the error returned by server
+        ///
+        /// Server sends errors in `Error[<code>]: <message>` format
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub enum ErrorCode {
+            $($(#[$meta])* $code,)*
+            /// Code unknown to this version of the library or no code at all
+            Unknown(String),
+        }
+
+        impl fmt::Display for ErrorCode {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                match self {
+                    $(Self::$code => write!(f, stringify!($code)),)*
+                    Self::Unknown(code) => write!(f, "{code}"),
+                }
+            }
+        }
+
+        impl From<&str> for ErrorCode {
+            fn from(code: &str) -> Self {
+                match code {
+                    $(stringify!($code) => Self::$code,)*
+                    unknown => Self::Unknown(unknown.to_owned()),
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    /// Request doesn't contain command
+    NoCommandProvided,
+    /// Server has no such command
+    UndefinedCommand,
+    /// Request isn't UTF-8 encoded
+    InvalidRequestEncoding,
+    /// Request can't be performed in current session state
+    UnacceptableRequestAtThisState,
+    /// User has no rights to perform request
+    PermissionDenied,
+    /// Too many attempts of request in a short time
+    TooManyRequests,
+    /// Server is in maintenance mode and refuses logins and changes
+    Maintenance,
+    /// Argument of *maintenance* command isn't `on` or `off`
+    InvalidMaintenanceMode,
+    /// User reached the limit of records
+    QuotaExceeded,
+    /// User reached the limit of concurrent sessions
+    TooManySessions,
+    /// Request can't be performed inside a batch
+    InvalidBatch,
+    /// Session was idle for too long and should log in again
+    SessionExpired,
+    EmptyUsername,
+    InvalidUsername,
+    EmptyKey,
+    InvalidKey,
+    /// Key isn't in the server allowlist of keys allowed to register
+    KeyNotAllowed,
+    EmptyConfirmationString,
+    InvalidConfirmationString,
+    /// Confirmation string was issued too long ago
+    ConfirmationExpired,
+    EmptySessionToken,
+    /// Session token wasn't issued or was revoked
+    InvalidSessionToken,
+    /// Session token was issued too long ago
+    SessionTokenExpired,
+    EmptyResourceName,
+    InvalidResourceName,
+    /// Requested record doesn't exist
+    RecordNotFound,
+    EmptyRecordContent,
+    InvalidRecordFormat,
+    /// Record is larger than server allows
+    RecordTooLarge,
+    EmptyRecordPatch,
+    /// Patch contains unknown field or field without value
+    InvalidRecordPatch,
+    EmptyRecordVersion,
+    InvalidRecordVersion,
+    /// Offset or limit of records page isn't a non-negative integer
+    InvalidPagination,
+    /// Server io error
+    Io,
+    StoragePathIsNotADirectory,
+    /// Storage directory is used by another server instance
+    StorageLocked,
+    UserAlreadyExists,
+    UserDoesNotExist,
+    /// Public key is already registered by another user
+    KeyAlreadyInUse,
+    /// Server can't decrypt stored file
+    CantDecrypt,
+    /// Server can't parse stored record
+    CantParseRecord,
+    /// Request can't be performed while user has other active sessions
+    UnsupportedActionForMultiSession,
+    /// Server can't read or parse its own or user key
+    StorageKey,
+    /// Server storage is configured with incompatible options
+    InvalidStorageConfig,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_code_conversions() {
+        for code in [
+            ErrorCode::NoCommandProvided,
+            ErrorCode::UserDoesNotExist,
+            ErrorCode::RecordNotFound,
+            ErrorCode::SessionTokenExpired,
+            ErrorCode::StorageKey,
+        ] {
+            assert_eq!(ErrorCode::from(code.to_string().as_str()), code);
+        }
+        assert_eq!(
+            ErrorCode::UserAlreadyExists.to_string(),
+            "UserAlreadyExists"
+        );
+        assert_eq!(
+            ErrorCode::from("SomeNewCode"),
+            ErrorCode::Unknown(String::from("SomeNewCode"))
+        );
+    }
+
+    #[test]
+    fn test_display_context() {
+        let err = Error::CantConnectToTheServer {
+            addr: String::from("127.0.0.1:3747"),
+            source: std::io::ErrorKind::ConnectionRefused.into(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "can't connect to the server at 127.0.0.1:3747"
+        );
+        assert!(std::error::Error::source(&err).is_some());
+
+        let err = Error::UnexpectedResponse {
+            command: String::from("delete_record"),
+            response: String::from("Deleted"),
+        };
+        assert_eq!(
+            err.to_string(),
+            "unexpected response to `delete_record` request: Deleted"
+        );
+    }
+}
@@ -0,0 +1,255 @@
+//! Splitting byte stream between client and server into messages
+//!
+//! Every message is ended with delimiter byte, [`EOT`] by default. Delimiter
+//! and [`ESC`] bytes inside message are prefixed with [`ESC`] byte, so message
+//! can contain any bytes
+
+use std::io::{self, BufRead};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+/// End of transmission character, ends every message by default
+pub const EOT: u8 = 0x04;
+
+/// Escape character, prefixes delimiter and [`ESC`] bytes inside message
+pub const ESC: u8 = 0x05;
+
+/// Framing of messages with configurable delimiter
+///
+/// Both sides of the connection should use the same framing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Framing {
+    delimiter: u8,
+}
+
+impl Framing {
+    /// Messages are ended with [`EOT`] byte. Used by default
+    pub const EOT: Framing = Framing { delimiter: EOT };
+
+    /// Messages are ended with *"\n"*, which is handy for manual debugging
+    /// with tools like `nc`. Line breaks inside message are escaped
+    pub const TEXT: Framing = Framing { delimiter: b'\n' };
+
+    /// Gets byte ending every message
+    pub fn delimiter(&self) -> u8 {
+        self.delimiter
+    }
+
+    /// Escapes `message` and appends delimiter byte at the end
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rpass::framing::Framing;
+    ///
+    /// assert_eq!(Framing::EOT.frame(b"a\x04b\x05"), b"a\x05\x04b\x05\x05\x04");
+    /// assert_eq!(Framing::TEXT.frame(b"a\nb"), b"a\x05\nb\n");
+    /// ```
+    pub fn frame(&self, message: &[u8]) -> Vec<u8> {
+        let escapes = message.iter().filter(|&&b| self.is_special(b)).count();
+        let mut framed = Vec::with_capacity(message.len() + escapes + 1);
+        for &byte in message {
+            if self.is_special(byte) {
+                framed.push(ESC);
+            }
+            framed.push(byte);
+        }
+        framed.push(self.delimiter);
+        framed
+    }
+
+    /// Checks if `buf` read until delimiter byte is a complete message, i.e.
+    /// its last delimiter byte isn't escaped
+    pub fn is_complete(&self, buf: &[u8]) -> bool {
+        match buf.split_last() {
+            Some((&last, rest)) if last == self.delimiter => {
+                rest.iter().rev().take_while(|&&b| b == ESC).count() % 2 == 0
+            }
+            _ => false,
+        }
+    }
+
+    /// Reverses [`Framing::frame()`]. Trailing delimiter byte is removed if
+    /// there is one
+    ///
+    /// [`ESC`] byte not followed by delimiter or [`ESC`] is left as is, so
+    /// messages from peers not escaping bytes are read without changes
+    pub fn unframe(&self, buf: &[u8]) -> Vec<u8> {
+        let buf = match self.is_complete(buf) {
+            true => &buf[..buf.len() - 1],
+            false => buf,
+        };
+
+        let mut message = Vec::with_capacity(buf.len());
+        let mut iter = buf.iter().peekable();
+        while let Some(&byte) = iter.next() {
+            match (byte, iter.peek()) {
+                (ESC, Some(&&next)) if self.is_special(next) => {
+                    message.push(next);
+                    iter.next();
+                }
+                _ => message.push(byte),
+            }
+        }
+        message
+    }
+
+    /// Reads bytes from `reader` until not escaped delimiter byte is captured.
+    /// Returns unframed message, see [`Framing::unframe()`]
+    ///
+    /// Returns `None` if `reader` reached EOF. Message not ended with delimiter
+    /// byte is discarded in that case
+    ///
+    /// # Errors
+    ///
+    /// Any error during reading from `reader`
+    pub fn read_message<R: BufRead>(&self, mut reader: R) -> io::Result<Option<Vec<u8>>> {
+        let mut buf = vec![];
+        loop {
+            let size = reader.read_until(self.delimiter, &mut buf)?;
+            if self.is_complete(&buf) {
+                return Ok(Some(self.unframe(&buf)));
+            }
+            if size == 0 {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Async version of [`Framing::read_message()`]
+    ///
+    /// # Errors
+    ///
+    /// Any error during reading from `reader`
+    pub async fn read_message_async<R: AsyncBufRead + Unpin>(
+        &self,
+        reader: &mut R,
+    ) -> io::Result<Option<Vec<u8>>> {
+        let buf = self.read_frame_async(reader).await?;
+        match self.is_complete(&buf) {
+            true => Ok(Some(self.unframe(&buf))),
+            false => Ok(None),
+        }
+    }
+
+    /// Reads bytes from `reader` until not escaped delimiter byte is captured
+    /// or EOF is reached. Returns read bytes as is, so incomplete message can
+    /// be told apart with [`Framing::is_complete()`]
+    ///
+    /// # Errors
+    ///
+    /// Any error during reading from `reader`
+    pub async fn read_frame_async<R: AsyncBufRead + Unpin>(
+        &self,
+        reader: &mut R,
+    ) -> io::Result<Vec<u8>> {
+        let mut buf = vec![];
+        loop {
+            let size = reader.read_until(self.delimiter, &mut buf).await?;
+            if size == 0 || self.is_complete(&buf) {
+                return Ok(buf);
+            }
+        }
+    }
+
+    /// Checks if `byte` should be escaped inside message
+    fn is_special(&self, byte: u8) -> bool {
+        byte == self.delimiter || byte == ESC
+    }
+}
+
+impl Default for Framing {
+    fn default() -> Self {
+        Self::EOT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for framing in [Framing::EOT, Framing::TEXT] {
+            for message in [
+                &b""[..],
+                b"ping\r\n",
+                b"\x04",
+                b"\x05",
+                b"\n",
+                b"pass\x04word\x05\x05\x04\r\n",
+                b"secret\nnotes\n\r\n",
+                b"\x05\x04\x04\x05\n",
+            ] {
+                let framed = framing.frame(message);
+                let delimiters =
+                    |bytes: &[u8]| bytes.iter().filter(|&&b| b == framing.delimiter()).count();
+                assert_eq!(framed.last(), Some(&framing.delimiter()));
+                assert_eq!(delimiters(&framed), delimiters(message) + 1);
+                assert!(framing.is_complete(&framed), "{message:?}");
+                assert_eq!(framing.unframe(&framed), message);
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_complete() {
+        assert!(Framing::EOT.is_complete(b"ping\x04"));
+        assert!(Framing::EOT.is_complete(b"\x05\x05\x04"));
+        assert!(!Framing::EOT.is_complete(b"ping\x05\x04"));
+        assert!(!Framing::EOT.is_complete(b"\x05\x05\x05\x04"));
+        assert!(!Framing::EOT.is_complete(b"ping\n"));
+        assert!(!Framing::EOT.is_complete(b"ping"));
+        assert!(!Framing::EOT.is_complete(b""));
+
+        assert!(Framing::TEXT.is_complete(b"ping\n"));
+        assert!(!Framing::TEXT.is_complete(b"ping\x05\n"));
+        assert!(!Framing::TEXT.is_complete(b"ping\x04"));
+    }
+
+    #[test]
+    fn test_unframe_unescaped() {
+        assert_eq!(Framing::EOT.unframe(b"a\x05b\x04"), b"a\x05b");
+        assert_eq!(Framing::EOT.unframe(b"a\x05"), b"a\x05");
+        assert_eq!(Framing::TEXT.unframe(b"a\x05\x04\n"), b"a\x05\x04");
+    }
+
+    #[test]
+    fn test_read_message() {
+        for framing in [Framing::EOT, Framing::TEXT] {
+            let mut bytes = framing.frame(b"login user\r\n");
+            bytes.extend(framing.frame(b"secret\nnotes"));
+            bytes.extend(b"partial");
+
+            let mut reader = &bytes[..];
+            assert_eq!(
+                framing.read_message(&mut reader).unwrap().unwrap(),
+                b"login user\r\n"
+            );
+            assert_eq!(
+                framing.read_message(&mut reader).unwrap().unwrap(),
+                b"secret\nnotes"
+            );
+            assert!(framing.read_message(&mut reader).unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_message_async() {
+        for framing in [Framing::EOT, Framing::TEXT] {
+            let mut bytes = framing.frame(b"login user\r\n");
+            bytes.extend(framing.frame(b"secret\nnotes"));
+            bytes.extend(b"partial");
+
+            let mut reader = &bytes[..];
+            assert_eq!(
+                framing.read_message_async(&mut reader).await.unwrap(),
+                Some(b"login user\r\n".to_vec())
+            );
+            assert_eq!(
+                framing.read_message_async(&mut reader).await.unwrap(),
+                Some(b"secret\nnotes".to_vec())
+            );
+            assert_eq!(framing.read_message_async(&mut reader).await.unwrap(), None);
+        }
+    }
+}
@@ -1,13 +1,26 @@
 pub use num_bigint::{BigUint, ParseBigIntError, ToBigUint};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use num_bigint::RandBigInt;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::fmt;
 use std::fs;
 use std::io::{Read, Write};
 use std::path::Path;
 use std::str::FromStr;
 
+/// Size of the modulus of generated keys in bits
+pub const KEY_BITS: u64 = 1024;
+
+/// Public exponent of generated keys
+const PUBLIC_EXPONENT: u32 = 65537;
+
+/// Number of Miller-Rabin rounds used to check primes of generated keys
+const PRIMALITY_ROUNDS: usize = 32;
+
 /// RSA-Key
-#[derive(PartialEq, Eq, Debug, Clone)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
 #[cfg_attr(feature = "enable-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Key(pub BigUint, pub BigUint);
 
@@ -52,6 +65,35 @@ impl Key {
         ))
     }
 
+    /// Constructs new key from `bytes` in any supported format: text one,
+    /// see [`Key::from_str()`], or legacy byte one, see [`Key::from_bytes()`]
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rpass::key::Key;
+    /// use std::str::FromStr;
+    ///
+    /// let key = Key::from_str("898:19634").unwrap();
+    /// assert_eq!(Key::from_any(b"898:19634").unwrap(), key);
+    /// assert_eq!(Key::from_any(&key.as_bytes()).unwrap(), key);
+    /// ```
+    pub fn from_any(bytes: &[u8]) -> Result<Self> {
+        if let Some(key) = std::str::from_utf8(bytes)
+            .ok()
+            .and_then(|s| Self::from_str(s).ok())
+        {
+            return Ok(key);
+        }
+
+        let mut rest = bytes;
+        let parts = Self::read_part(&mut rest).and_then(|e| Ok((e, Self::read_part(&mut rest)?)));
+        match parts {
+            Ok((e, n)) if rest.is_empty() => Ok(Key(e, n)),
+            _ => Err(ParseError::InvalidFormat.into()),
+        }
+    }
+
     /// Reads key from file by `path`
     ///
     /// # Example
@@ -69,7 +111,23 @@ impl Key {
     where
         P: AsRef<Path>,
     {
-        let content = fs::read_to_string(path)?;
+        Self::from_reader(fs::File::open(path)?)
+    }
+
+    /// Reads key in text format, see [`Key::from_str()`], from `reader` until EOF
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use rpass::key::Key;
+    /// use std::str::FromStr;
+    ///
+    /// let key = Key::from_reader(&b"898:19634"[..]).unwrap();
+    /// assert_eq!(key, Key::from_str("898:19634").unwrap());
+    /// ```
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self> {
+        let mut content = String::new();
+        reader.read_to_string(&mut content)?;
         Self::from_str(&content).map_err(|err| err.into())
     }
 
@@ -90,18 +148,77 @@ impl Key {
     where
         P: AsRef<Path>,
     {
-        let content = self.to_string();
-        fs::write(path, content).map_err(|err| err.into())
+        self.to_writer(fs::File::create(path)?)
     }
 
-    /// Generate pair of public and secret keys
+    /// Writes key in text format, see [`Key::from_str()`], to `writer`
     ///
-    /// TODO
+    /// # Example
+    ///
+    /// ```rust
+    /// use rpass::key::Key;
+    /// use std::str::FromStr;
+    ///
+    /// let mut bytes = vec![];
+    /// Key::from_str("898:19634").unwrap().to_writer(&mut bytes).unwrap();
+    /// assert_eq!(bytes, b"898:19634");
+    /// ```
+    pub fn to_writer<W: Write>(&self, mut writer: W) -> Result<()> {
+        write!(writer, "{self}")?;
+        writer.flush().map_err(|err| err.into())
+    }
+
+    /// Gets compact fingerprint of the key: lowercase hex SHA-256 digest of
+    /// its text form, see [`Key::to_string()`]
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rpass::key::Key;
+    /// use std::str::FromStr;
+    ///
+    /// let fingerprint = Key::from_str("898:19634").unwrap().fingerprint();
+    /// assert_eq!(fingerprint.len(), 64);
+    /// assert_ne!(fingerprint, Key::from_str("898:19635").unwrap().fingerprint());
+    /// ```
+    pub fn fingerprint(&self) -> String {
+        Sha256::digest(self.to_string().as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    /// Generates random pair of public and secret RSA keys with
+    /// [`KEY_BITS`]-bit modulus
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rpass::key::Key;
+    ///
+    /// let (pub_key, sec_key) = Key::generate_pair();
+    /// assert_eq!(pub_key.1, sec_key.1);
+    /// assert_ne!(Key::generate_pair().0, pub_key);
+    /// ```
     pub fn generate_pair() -> (Self, Self) {
-        (
-            Key(269.to_biguint().unwrap(), 221.to_biguint().unwrap()),
-            Key(5.to_biguint().unwrap(), 221.to_biguint().unwrap()),
-        )
+        let mut rng = rand::thread_rng();
+        let public_exponent = BigUint::from(PUBLIC_EXPONENT);
+        loop {
+            let p = Self::generate_prime(&mut rng, KEY_BITS / 2);
+            let q = Self::generate_prime(&mut rng, KEY_BITS - KEY_BITS / 2);
+            if p == q {
+                continue;
+            }
+
+            let phi = (&p - 1u32) * (&q - 1u32);
+            if let Some(secret_exponent) = public_exponent.modinv(&phi) {
+                let modulus = p * q;
+                return (
+                    Key(public_exponent, modulus.clone()),
+                    Key(secret_exponent, modulus),
+                );
+            }
+        }
     }
 
     /// Encrypt `s` with key
@@ -118,6 +235,57 @@ impl Key {
         s.to_owned()
     }
 
+    /// Generates random prime of exactly `bits` bits. Two highest bits are
+    /// set, so product of two such primes has exactly twice as many bits
+    fn generate_prime<R: Rng>(rng: &mut R, bits: u64) -> BigUint {
+        loop {
+            let mut candidate = rng.gen_biguint(bits);
+            candidate.set_bit(bits - 1, true);
+            candidate.set_bit(bits - 2, true);
+            candidate.set_bit(0, true);
+            if Self::is_probable_prime(rng, &candidate) {
+                return candidate;
+            }
+        }
+    }
+
+    /// Checks if odd `n` is prime with trial division by small primes and
+    /// Miller-Rabin test
+    fn is_probable_prime<R: Rng>(rng: &mut R, n: &BigUint) -> bool {
+        const SMALL_PRIMES: [u32; 15] = [3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53];
+
+        for small_prime in SMALL_PRIMES {
+            if *n == BigUint::from(small_prime) {
+                return true;
+            }
+            if (n % small_prime) == BigUint::from(0u32) {
+                return false;
+            }
+        }
+
+        let one = BigUint::from(1u32);
+        let n_minus_one = n - 1u32;
+        let shift = n_minus_one.trailing_zeros().unwrap_or(0);
+        let odd_part = &n_minus_one >> shift;
+        let two = BigUint::from(2u32);
+
+        'rounds: for _ in 0..PRIMALITY_ROUNDS {
+            let base = rng.gen_biguint_range(&two, &n_minus_one);
+            let mut x = base.modpow(&odd_part, n);
+            if x == one || x == n_minus_one {
+                continue;
+            }
+            for _ in 1..shift {
+                x = x.modpow(&two, n);
+                if x == n_minus_one {
+                    continue 'rounds;
+                }
+            }
+            return false;
+        }
+        true
+    }
+
     /// Writes one part of key to the `write`
     ///
     /// # Panics
@@ -133,9 +301,12 @@ impl Key {
 
     /// Reads one part of key from the `read`
     fn read_part<R: Read>(mut read: R) -> Result<BigUint> {
-        let len = read.read_u64::<LittleEndian>()? as usize;
-        let mut part_bytes = vec![0u8; len];
-        read.read_exact(&mut part_bytes)?;
+        let len = read.read_u64::<LittleEndian>()?;
+        let mut part_bytes = vec![];
+        read.take(len).read_to_end(&mut part_bytes)?;
+        if part_bytes.len() as u64 != len {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
         Ok(BigUint::from_bytes_le(&part_bytes))
     }
 }
@@ -167,8 +338,8 @@ impl FromStr for Key {
     }
 }
 
-impl ToString for Key {
-    /// Converts key to string
+impl fmt::Display for Key {
+    /// Formats key as `<first_num>:<second_num>`
     ///
     /// # Examples
     ///
@@ -178,8 +349,8 @@ impl ToString for Key {
     /// let key = Key(845u64.to_biguint().unwrap(), 947u64.to_biguint().unwrap());
     /// assert_eq!(key.to_string(), "845:947");
     /// ```
-    fn to_string(&self) -> String {
-        self.0.to_string() + ":" + &self.1.to_string()
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.0, self.1)
     }
 }
 
@@ -264,6 +435,101 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_from_any() {
+        let key = Key(657u64.to_biguint().unwrap(), 298u64.to_biguint().unwrap());
+        assert_eq!(Key::from_any(b"657:298").unwrap(), key);
+        assert_eq!(Key::from_any(&key.as_bytes()).unwrap(), key);
+    }
+
+    #[test]
+    fn test_from_any_invalid() {
+        let key = Key(657u64.to_biguint().unwrap(), 298u64.to_biguint().unwrap());
+        let mut trailing = key.as_bytes();
+        trailing.push(0);
+        let mut truncated = key.as_bytes();
+        truncated.pop();
+        let mut huge_len = vec![];
+        huge_len.write_u64::<LittleEndian>(u64::MAX).unwrap();
+
+        for bytes in [
+            &b"657"[..],
+            b"",
+            b"657:298\n",
+            &trailing,
+            &truncated,
+            &huge_len,
+        ] {
+            assert!(
+                matches!(Key::from_any(bytes), Err(Error::ParseKey(_))),
+                "{bytes:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let key = Key::from_reader(std::io::Cursor::new("18764:8975")).unwrap();
+        assert_eq!(
+            key,
+            Key(
+                18764u64.to_biguint().unwrap(),
+                8975u64.to_biguint().unwrap()
+            )
+        );
+
+        assert!(matches!(
+            Key::from_reader(std::io::Cursor::new("18764")),
+            Err(Error::ParseKey(ParseError::InvalidFormat))
+        ));
+        assert!(matches!(
+            Key::from_reader(std::io::Cursor::new([0xff, b':', b'1'])),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_to_from_writer() {
+        let key = Key(657u64.to_biguint().unwrap(), 298u64.to_biguint().unwrap());
+
+        let mut bytes: Vec<u8> = vec![];
+        key.to_writer(&mut bytes).unwrap();
+        assert_eq!(bytes, b"657:298");
+        assert_eq!(Key::from_reader(&bytes[..]).unwrap(), key);
+    }
+
+    #[test]
+    fn test_generate_pair() {
+        let (pub_key, sec_key) = Key::generate_pair();
+        assert_eq!(pub_key.1, sec_key.1);
+        assert_eq!(pub_key.1.bits(), KEY_BITS);
+
+        let message = 123_456_789u64.to_biguint().unwrap();
+        let encrypted = message.modpow(&pub_key.0, &pub_key.1);
+        assert_ne!(encrypted, message);
+        assert_eq!(encrypted.modpow(&sec_key.0, &sec_key.1), message);
+
+        assert_ne!(Key::generate_pair().0, pub_key);
+    }
+
+    #[test]
+    fn test_is_probable_prime() {
+        let mut rng = rand::thread_rng();
+        for prime in [3u64, 53, 7919, 2_305_843_009_213_693_951] {
+            assert!(Key::is_probable_prime(
+                &mut rng,
+                &prime.to_biguint().unwrap()
+            ));
+        }
+        // 561 and 41041 are Carmichael numbers
+        for composite in [9u64, 561, 41041, 7919 * 7927] {
+            assert!(!Key::is_probable_prime(
+                &mut rng,
+                &composite.to_biguint().unwrap()
+            ));
+        }
+    }
+
     /// Computes number of bytes needful to represent `bits` number of bits
     fn bytes_per_bits(bits: u64) -> u64 {
         match bits % 8 {
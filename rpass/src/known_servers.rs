@@ -0,0 +1,174 @@
+//! Trust-on-first-use store of server public keys
+//!
+//! Key of a server is remembered on the first connection. Later connections
+//! are refused if the server presents another key, cause it can be a
+//! man-in-the-middle attack
+
+use crate::key::Key;
+use crate::{Error, Result};
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Path of the store file relative to the home directory
+const STORE_PATH: &str = ".rpass/known_servers";
+
+/// Public keys of servers seen before, keyed by host.
+///
+/// Every line of the file is a key and a host separated by a space
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownServers {
+    path: PathBuf,
+}
+
+impl KnownServers {
+    /// Creates store kept in file at `path`. File is created on the first save
+    pub fn new(path: PathBuf) -> Self {
+        KnownServers { path }
+    }
+
+    /// Gets default path of the store file: *~/.rpass/known_servers*.
+    /// Returns `None` if home directory is unknown
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("HOME").map(|home| Path::new(&home).join(STORE_PATH))
+    }
+
+    /// Gets remembered key of `host`. Lines which can't be parsed are ignored
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't read the store file. Missing file means no known servers
+    pub fn get(&self, host: &str) -> Result<Option<Key>> {
+        Ok(self
+            .read_entries()?
+            .into_iter()
+            .find(|(known_host, _)| known_host == host)
+            .map(|(_, key)| key))
+    }
+
+    /// Checks `key` presented by `host` against the remembered one.
+    /// Key of a new host is remembered
+    ///
+    /// # Errors
+    ///
+    /// * `ServerKeyChanged` - if `host` presented another key before
+    /// * `Io` - if can't read or write the store file
+    pub fn check(&self, host: &str, key: &Key) -> Result<()> {
+        match self.get(host)? {
+            Some(known_key) if known_key == *key => Ok(()),
+            Some(_) => Err(Error::ServerKeyChanged {
+                host: host.to_owned(),
+            }),
+            None => self.accept(host, key),
+        }
+    }
+
+    /// Remembers `key` for `host` replacing previous one, e.g. after server
+    /// key rotation was confirmed by the user
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't read or write the store file
+    pub fn accept(&self, host: &str, key: &Key) -> Result<()> {
+        let mut entries = self.read_entries()?;
+        entries.retain(|(known_host, _)| known_host != host);
+        entries.push((host.to_owned(), key.clone()));
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::File::create(&self.path)?;
+        for (host, key) in entries {
+            writeln!(file, "{key} {host}")?;
+        }
+        Ok(())
+    }
+
+    /// Reads all entries of the store file
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't read the store file
+    fn read_entries(&self) -> Result<Vec<(String, Key)>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(content
+            .lines()
+            .filter_map(|line| {
+                let (key, host) = line.split_once(' ')?;
+                Some((host.to_owned(), Key::from_str(key).ok()?))
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOST: &str = "127.0.0.1:3747";
+
+    #[test]
+    fn test_first_connect() {
+        let known_servers = build_known_servers("first_connect");
+        assert_eq!(known_servers.get(HOST).unwrap(), None);
+
+        known_servers.check(HOST, &build_key(1)).unwrap();
+        assert_eq!(known_servers.get(HOST).unwrap(), Some(build_key(1)));
+        fs::remove_file(known_servers.path).unwrap();
+    }
+
+    #[test]
+    fn test_same_key() {
+        let known_servers = build_known_servers("same_key");
+        known_servers.check(HOST, &build_key(1)).unwrap();
+        known_servers
+            .check("unix:/tmp/rpass.sock", &build_key(2))
+            .unwrap();
+
+        known_servers.check(HOST, &build_key(1)).unwrap();
+        known_servers
+            .check("unix:/tmp/rpass.sock", &build_key(2))
+            .unwrap();
+        fs::remove_file(known_servers.path).unwrap();
+    }
+
+    #[test]
+    fn test_changed_key() {
+        let known_servers = build_known_servers("changed_key");
+        known_servers.check(HOST, &build_key(1)).unwrap();
+
+        assert!(matches!(
+            known_servers.check(HOST, &build_key(2)),
+            Err(Error::ServerKeyChanged { host }) if host == HOST
+        ));
+        assert_eq!(known_servers.get(HOST).unwrap(), Some(build_key(1)));
+
+        known_servers.accept(HOST, &build_key(2)).unwrap();
+        known_servers.check(HOST, &build_key(2)).unwrap();
+        fs::remove_file(known_servers.path).unwrap();
+    }
+
+    /// Builds store at not yet existing path unique for test `name`
+    fn build_known_servers(name: &str) -> KnownServers {
+        let path = std::env::temp_dir().join(format!(
+            "rpass_test_known_servers_{name}_{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        KnownServers::new(path)
+    }
+
+    /// Builds key unique for `n`
+    fn build_key(n: u32) -> Key {
+        Key::from_str(&format!("{n}:221")).unwrap()
+    }
+}
@@ -1,6 +1,13 @@
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod compression;
 pub mod error;
+pub mod framing;
 pub mod key;
+pub mod known_servers;
+pub mod name;
 pub mod record;
+pub mod request_id;
 pub mod session;
 
 pub use error::Error;
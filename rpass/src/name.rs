@@ -0,0 +1,70 @@
+/// Maximum length of user and resource names in bytes
+pub const MAX_LENGTH: usize = 32;
+
+/// Names shadowing files in the root of the server storage
+const STORAGE_RESERVED: [&str; 4] = ["key", "key.pub", "key.sec", "admins"];
+
+/// Names reserved by Windows, with or without extension
+const OS_RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Checks if `name` can't be used as user name cause user directory would
+/// shadow server storage files or its name is reserved by OS. Case-insensitive
+pub fn is_reserved(name: &str) -> bool {
+    STORAGE_RESERVED
+        .iter()
+        .any(|reserved| name.eq_ignore_ascii_case(reserved))
+        || is_os_reserved(name)
+}
+
+/// Checks if file can't be named `name` on some OS, e.g. `nul` or `con.txt` on
+/// Windows. Case-insensitive
+pub fn is_os_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default();
+    OS_RESERVED
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+}
+
+/// Checks if `name` isn't too long and isn't reserved, see [`MAX_LENGTH`] and
+/// [`is_reserved()`]. Other rules are checked by server only
+pub fn is_acceptable(name: &str) -> bool {
+    !name.is_empty() && name.len() <= MAX_LENGTH && !is_reserved(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_reserved() {
+        for name in [
+            "key", "key.pub", "KEY.SEC", "admins", "CON", "nul", "Com1.txt", "lpt9",
+        ] {
+            assert!(is_reserved(name), "{name}");
+        }
+        for name in ["user", "keys", "key.txt", "console", "com10", "my.con"] {
+            assert!(!is_reserved(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_is_os_reserved() {
+        for name in ["nul", "CON.txt", "Com1.example.com"] {
+            assert!(is_os_reserved(name), "{name}");
+        }
+        for name in ["key", "admins", "console", "my.con"] {
+            assert!(!is_os_reserved(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_is_acceptable() {
+        assert!(is_acceptable(&"x".repeat(MAX_LENGTH)));
+        assert!(!is_acceptable(&"x".repeat(MAX_LENGTH + 1)));
+        assert!(!is_acceptable(""));
+        assert!(!is_acceptable("admins"));
+    }
+}
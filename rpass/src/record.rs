@@ -1,13 +1,29 @@
+pub use generator::{generate_password, PasswordOptions, PasswordOptionsError};
+pub use strength::{password_strength, Strength, StrengthLevel};
+
+mod generator;
+mod strength;
+
+use std::fmt;
 use std::result::Result;
 use std::str::FromStr;
 
 /// User record with password
-#[derive(Default, Debug, PartialEq, Eq)]
+///
+/// New fields can be added in future versions, so construct records with
+/// [`Record::builder()`] instead of struct literals to stay source compatible
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "enable-serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Record {
     pub resource: String, // Resource to store password from
     pub password: String, // Password, encrypted with user public key
-    pub notes: String,    // Additional notes, encrypted with user public key
+    pub notes: String,    // Additional notes, plaintext unless `encrypted_notes` is set
+    /// If client encrypted *notes* like the password, so they can't be read or searched
+    #[cfg_attr(
+        feature = "enable-serde",
+        serde(default, skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub encrypted_notes: bool,
 }
 
 impl Record {
@@ -15,44 +31,502 @@ impl Record {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Creates new [`RecordBuilder`]
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rpass::record::Record;
+    ///
+    /// let record = Record::builder()
+    ///     .resource("example.com")
+    ///     .password("secret")
+    ///     .build();
+    /// assert_eq!(record.notes, "");
+    /// ```
+    pub fn builder() -> RecordBuilder {
+        RecordBuilder::default()
+    }
+
+    /// Marks *notes* as encrypted or plaintext. The flag is kept by
+    /// [`Record::to_string()`] and [`Record::from_str()`], but notes aren't
+    /// encrypted or decrypted by this call
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rpass::record::Record;
+    /// use std::str::FromStr;
+    ///
+    /// let record = Record::builder()
+    ///     .password("secret")
+    ///     .notes("encrypted notes")
+    ///     .build()
+    ///     .with_encrypted_notes(true);
+    /// assert!(Record::from_str(&record.to_string()).unwrap().encrypted_notes);
+    /// ```
+    pub fn with_encrypted_notes(mut self, encrypted_notes: bool) -> Self {
+        self.encrypted_notes = encrypted_notes;
+        self
+    }
+
+    /// Formats record like [`Record::to_string()`], but always with format
+    /// version header, e.g. to store it on disk. Header lets to read records
+    /// written in older formats after format changes
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rpass::record::Record;
+    /// use std::str::FromStr;
+    ///
+    /// let record = Record::builder().password("secret").notes("notes").build();
+    /// assert_eq!(record.to_versioned_string(), "v2\nsecret\nnotes");
+    /// assert_eq!(record.to_string(), "secret\nnotes");
+    /// assert_eq!(Record::from_str(&record.to_versioned_string()).unwrap(), record);
+    /// ```
+    pub fn to_versioned_string(&self) -> String {
+        match self.encrypted_notes {
+            true => self.to_string(),
+            false => format!("v2\n{}\n{}", escape(&self.password), escape(&self.notes)),
+        }
+    }
+
+    /// Checks if `query` is a case-insensitive substring of *resource* or
+    /// *notes*. Password is never matched. Empty query matches any record
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rpass::record::Record;
+    ///
+    /// let record = Record::builder().resource("GitHub").notes("work").build();
+    /// assert!(record.matches("hub"));
+    /// assert!(record.matches("WORK"));
+    /// assert!(!record.matches("gitlab"));
+    /// ```
+    pub fn matches(&self, query: &str) -> bool {
+        self.matches_with(query, MatchMode::Substring)
+    }
+
+    /// Checks if `query` matches *resource* or *notes* according to `mode`.
+    /// Comparison is case-insensitive and doesn't allocate
+    pub fn matches_with(&self, query: &str, mode: MatchMode) -> bool {
+        let field_matches = match mode {
+            MatchMode::Substring => contains_ignore_case,
+            MatchMode::WholeField => eq_ignore_case,
+        };
+        field_matches(&self.resource, query) || field_matches(&self.notes, query)
+    }
+}
+
+/// Previous version of record kept by server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordVersion {
+    /// Id of the version, milliseconds since UNIX epoch when it was replaced.
+    /// Greater for newer versions
+    pub version: u64,
+    pub record: Record,
+}
+
+/// Changes of record fields, see [`crate::session::Authorized::patch_record()`]
+///
+/// `None` leaves field unchanged, while `Some(String::new())` sets it to empty
+///
+/// # Example
+///
+/// ```
+/// use rpass::record::PatchRecord;
+///
+/// let patch = PatchRecord {
+///     notes: Some(String::from("new notes")),
+///     ..PatchRecord::default()
+/// };
+/// assert!(!patch.is_empty());
+/// ```
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct PatchRecord {
+    pub password: Option<String>,
+    pub notes: Option<String>,
+}
+
+impl PatchRecord {
+    /// Checks if patch doesn't change any field
+    pub fn is_empty(&self) -> bool {
+        self.fields().next().is_none()
+    }
+
+    /// Applies non-`None` fields to `record`
+    pub fn apply(&self, record: &mut Record) {
+        if let Some(password) = &self.password {
+            record.password = password.clone();
+        }
+        if let Some(notes) = &self.notes {
+            record.notes = notes.clone();
+        }
+    }
+
+    /// Gets names and values of fields to change in the order they're sent
+    /// to the server
+    pub(crate) fn fields(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [("password", &self.password), ("notes", &self.notes)]
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value.as_deref()?)))
+    }
 }
 
+/// How query is compared with record fields, see [`Record::matches_with()`]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchMode {
+    /// Query is contained in field
+    #[default]
+    Substring,
+    /// Query is equal to the whole field
+    WholeField,
+}
+
+/// Checks if `lhs` and `rhs` are equal ignoring case of Unicode characters
+fn eq_ignore_case(lhs: &str, rhs: &str) -> bool {
+    lhs.chars()
+        .flat_map(char::to_lowercase)
+        .eq(rhs.chars().flat_map(char::to_lowercase))
+}
+
+/// Checks if `haystack` contains `needle` ignoring case of Unicode characters
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    let needle = || needle.chars().flat_map(char::to_lowercase);
+    haystack
+        .char_indices()
+        .map(|(i, _)| &haystack[i..])
+        .any(|rest| {
+            let mut rest = rest.chars().flat_map(char::to_lowercase);
+            needle().all(|c| rest.next() == Some(c))
+        })
+        || needle().next().is_none()
+}
+
+/// Builder for [`Record`]
+///
+/// Fields that weren't specified are default-initialized
+#[derive(Default, Debug)]
+pub struct RecordBuilder {
+    record: Record,
+}
+
+impl RecordBuilder {
+    /// Sets resource name
+    pub fn resource(mut self, resource: impl Into<String>) -> Self {
+        self.record.resource = resource.into();
+        self
+    }
+
+    /// Sets password
+    pub fn password(mut self, password: impl Into<String>) -> Self {
+        self.record.password = password.into();
+        self
+    }
+
+    /// Sets notes
+    pub fn notes(mut self, notes: impl Into<String>) -> Self {
+        self.record.notes = notes.into();
+        self
+    }
+
+    /// Sets if notes are encrypted, see [`Record::with_encrypted_notes()`]
+    pub fn encrypted_notes(mut self, encrypted_notes: bool) -> Self {
+        self.record.encrypted_notes = encrypted_notes;
+        self
+    }
+
+    /// Builds record
+    pub fn build(self) -> Record {
+        self.record
+    }
+}
+
+/// The latest version of the record format produced by
+/// [`Record::to_versioned_string()`]
+pub const RECORD_FORMAT_VERSION: u32 = 3;
+
+/// Name of *notes* field in the list of encrypted fields of version 3 record
+const ENCRYPTED_NOTES_FLAG: &str = "notes";
+
 #[derive(thiserror::Error, Debug)]
 pub enum ParseRecordError {
     #[error("empty string")]
     EmptyString,
+
+    #[error("unsupported record format version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("unknown encrypted field `{0}`")]
+    UnknownEncryptedField(String),
 }
 
 impl FromStr for Record {
     type Err = ParseRecordError;
 
-    /// Constructs new record from string. Expects password and notes delimited
-    /// by new line character
+    /// Constructs new record from string produced by [`Record::to_string()`]
+    ///
+    /// String can start with *"v\<number\>"* line with format version followed
+    /// by at least two lines. String without such header is a version 1 record:
+    /// password and notes delimited by new line character. Version 2 adds the
+    /// header and escaping of both fields. Version 3 adds a line with
+    /// space-separated names of fields encrypted besides the password after the
+    /// header. Escape sequences are unescaped only since version 2, so records
+    /// written before escaping was introduced are read without changes
     ///
     /// *resource* field will be set to default
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (password, notes) = s.split_once('\n').ok_or(ParseRecordError::EmptyString)?;
-        Ok(Record {
-            resource: String::default(),
-            password: password.to_owned(),
-            notes: notes.to_owned(),
-        })
+        match split_version_header(s) {
+            None => parse_v1(s, false),
+            Some((1, body)) => parse_v1(body, false),
+            Some((2, body)) => parse_v1(body, true),
+            Some((3, body)) => parse_v3(body),
+            Some((version, _)) => Err(ParseRecordError::UnsupportedVersion(version)),
+        }
     }
 }
 
-impl ToString for Record {
-    /// Converts record to string **without** *resource* field
+/// Splits `s` into format version and the rest if `s` starts with version header
+/// followed by at least two lines
+fn split_version_header(s: &str) -> Option<(u32, &str)> {
+    let (header, body) = s.split_once('\n')?;
+    let version = header.strip_prefix('v')?.parse().ok()?;
+    body.contains('\n').then_some((version, body))
+}
+
+/// Parses version 1 record: password and notes delimited by new line character.
+/// Fields are unescaped if `escaped` is set, see [`escape()`]
+fn parse_v1(s: &str, escaped: bool) -> Result<Record, ParseRecordError> {
+    let (password, notes) = s.split_once('\n').ok_or(ParseRecordError::EmptyString)?;
+    let field = |value: &str| match escaped {
+        true => unescape(value),
+        false => value.to_owned(),
+    };
+    Ok(Record {
+        resource: String::default(),
+        password: field(password),
+        notes: field(notes),
+        encrypted_notes: false,
+    })
+}
+
+/// Parses version 3 record: line with encrypted fields followed by
+/// version 2 record body
+fn parse_v3(s: &str) -> Result<Record, ParseRecordError> {
+    let (fields, body) = s.split_once('\n').ok_or(ParseRecordError::EmptyString)?;
+    let mut record = parse_v1(body, true)?;
+    for field in fields.split_whitespace() {
+        match field {
+            ENCRYPTED_NOTES_FLAG => record.encrypted_notes = true,
+            unknown => return Err(ParseRecordError::UnknownEncryptedField(unknown.to_owned())),
+        }
+    }
+    Ok(record)
+}
+
+impl fmt::Display for Record {
+    /// Formats record **without** *resource* field in the wire format
+    ///
+    /// Password will be placed at the first line. The next line is notes.
+    /// Backslashes, quotes and control characters in both fields are escaped,
+    /// so the result is safe to send over the wire
+    ///
+    /// Version 2 header is prepended only if escaping changed any field, cause
+    /// records without header are read as is. So peers which don't know the
+    /// header parse records without special characters correctly
     ///
-    /// Password will be placed at the first line. The next lines is notes
-    fn to_string(&self) -> String {
-        self.password.clone() + "\n" + &self.notes
+    /// If notes are encrypted, version 3 header followed by a line with
+    /// encrypted fields is prepended, cause version 1 can't keep the flag.
+    /// Only peers supporting version 3 can read such records
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (password, notes) = (escape(&self.password), escape(&self.notes));
+        if self.encrypted_notes {
+            write!(f, "v{RECORD_FORMAT_VERSION}\n{ENCRYPTED_NOTES_FLAG}\n")?;
+        } else if password != self.password || notes != self.notes {
+            f.write_str("v2\n")?;
+        }
+        write!(f, "{password}\n{notes}")
+    }
+}
+
+/// Order of records in the list
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Alphabetical order of resource names
+    #[default]
+    Name,
+    /// Reverse alphabetical order of resource names
+    NameDesc,
+    /// Recently updated records first
+    Updated,
+    /// Recently created records first
+    Created,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("unknown sort order `{0}`")]
+pub struct ParseSortOrderError(String);
+
+impl FromStr for SortOrder {
+    type Err = ParseSortOrderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Self::Name),
+            "name_desc" => Ok(Self::NameDesc),
+            "updated" => Ok(Self::Updated),
+            "created" => Ok(Self::Created),
+            unknown => Err(ParseSortOrderError(unknown.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Name => "name",
+            Self::NameDesc => "name_desc",
+            Self::Updated => "updated",
+            Self::Created => "created",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Escapes `s` so that it contains no control characters and quotes.
+///
+/// `\\`, `\n` and `\r` are used for backslash, new line and carriage return,
+/// `\xHH` is used for quotes and other control characters
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '"' => escaped.push_str("\\x22"),
+            c if c.is_ascii_control() => escaped.push_str(&format!("\\x{:02x}", c as u8)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Reverses [`escape()`]
+///
+/// Unknown or malformed escape sequences are left as is
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(pos) = rest.find('\\') {
+        unescaped.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        let (c, len) = match rest.as_bytes().get(1) {
+            Some(b'\\') => ('\\', 2),
+            Some(b'n') => ('\n', 2),
+            Some(b'r') => ('\r', 2),
+            Some(b'x') => match rest
+                .get(2..4)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) if byte.is_ascii() => (byte as char, 4),
+                _ => ('\\', 1),
+            },
+            _ => ('\\', 1),
+        };
+        unescaped.push(c);
+        rest = &rest[len..];
     }
+    unescaped.push_str(rest);
+    unescaped
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_matches() {
+        let record = Record::builder()
+            .resource("Example.com")
+            .password("secret")
+            .notes("Личный кабинет")
+            .build();
+
+        for query in [
+            "example",
+            "EXAMPLE.COM",
+            "ample.c",
+            "кабинет",
+            "ЛИЧНЫЙ",
+            "й к",
+            "",
+        ] {
+            assert!(record.matches(query), "{query}");
+        }
+        for query in ["secret", "example.org", "кабинеты", "Личный  кабинет"] {
+            assert!(!record.matches(query), "{query}");
+        }
+    }
+
+    #[test]
+    fn test_patch_apply() {
+        let record = Record::builder()
+            .resource("example.com")
+            .password("secret")
+            .notes("notes")
+            .build();
+
+        let mut patched = record.clone();
+        PatchRecord::default().apply(&mut patched);
+        assert_eq!(patched, record);
+
+        let patch = PatchRecord {
+            password: None,
+            notes: Some(String::new()),
+        };
+        let mut patched = record.clone();
+        patch.apply(&mut patched);
+        assert_eq!(patched.password, "secret");
+        assert_eq!(patched.notes, "");
+        assert_eq!(patch.fields().collect::<Vec<_>>(), [("notes", "")]);
+    }
+
+    #[test]
+    fn test_matches_whole_field() {
+        let record = Record::builder()
+            .resource("Example.com")
+            .notes("Почта")
+            .build();
+
+        for query in ["example.com", "EXAMPLE.COM", "почта", "ПОЧТА"] {
+            assert!(record.matches_with(query, MatchMode::WholeField), "{query}");
+        }
+        for query in ["example", "почт", ""] {
+            assert!(
+                !record.matches_with(query, MatchMode::WholeField),
+                "{query}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sort_order_conversions() {
+        for order in [
+            SortOrder::Name,
+            SortOrder::NameDesc,
+            SortOrder::Updated,
+            SortOrder::Created,
+        ] {
+            assert_eq!(SortOrder::from_str(&order.to_string()).unwrap(), order);
+        }
+        assert!(SortOrder::from_str("size").is_err());
+    }
+
     #[test]
     fn test_new() {
         let record = Record::new();
@@ -61,6 +535,37 @@ mod tests {
         assert_eq!(record.notes, "");
     }
 
+    #[test]
+    fn test_builder_minimal() {
+        assert_eq!(
+            Record::builder().resource("example.com").build(),
+            Record {
+                resource: String::from("example.com"),
+                password: String::new(),
+                notes: String::new(),
+                encrypted_notes: false,
+            }
+        );
+        assert_eq!(Record::builder().build(), Record::new());
+    }
+
+    #[test]
+    fn test_builder_full() {
+        assert_eq!(
+            Record::builder()
+                .resource("example.com")
+                .password(String::from("secret"))
+                .notes("notes")
+                .build(),
+            Record {
+                resource: String::from("example.com"),
+                password: String::from("secret"),
+                notes: String::from("notes"),
+                encrypted_notes: false,
+            }
+        );
+    }
+
     #[test]
     fn test_from_str() {
         assert!(matches!(
@@ -72,7 +577,8 @@ mod tests {
             Record {
                 resource: String::default(),
                 password: "secret".to_owned(),
-                notes: "notes\nanother notes".to_owned()
+                notes: "notes\nanother notes".to_owned(),
+                encrypted_notes: false,
             }
         );
     }
@@ -83,7 +589,139 @@ mod tests {
             resource: "example.com".to_owned(),
             password: "secret".to_owned(),
             notes: "some notes\nvery useful".to_owned(),
+            encrypted_notes: false,
         };
-        assert_eq!(record.to_string(), "secret\nsome notes\nvery useful");
+        assert_eq!(record.to_string(), "v2\nsecret\nsome notes\\nvery useful");
+        assert_eq!(
+            record.to_versioned_string(),
+            "v2\nsecret\nsome notes\\nvery useful"
+        );
+        assert_eq!(
+            Record::from_str(&record.to_string()).unwrap(),
+            Record {
+                resource: String::default(),
+                ..record
+            }
+        );
+    }
+
+    #[test]
+    fn test_from_str_versions() {
+        let expected = Record::builder().password("secret").notes("notes").build();
+        for s in ["secret\nnotes", "v1\nsecret\nnotes", "v2\nsecret\nnotes"] {
+            assert_eq!(Record::from_str(s).unwrap(), expected, "{s}");
+        }
+
+        // Header requires two more lines, otherwise it's a version 1 password
+        assert_eq!(
+            Record::from_str("v2\nnotes").unwrap(),
+            Record::builder().password("v2").notes("notes").build()
+        );
+        assert!(matches!(
+            Record::from_str("v4\nsecret\nnotes"),
+            Err(ParseRecordError::UnsupportedVersion(4))
+        ));
+    }
+
+    #[test]
+    fn test_encrypted_notes_round_trip() {
+        for encrypted_notes in [true, false] {
+            let record = Record::builder()
+                .password("secret")
+                .notes("a1b2c3")
+                .build()
+                .with_encrypted_notes(encrypted_notes);
+
+            let (wire_header, versioned_header) = match encrypted_notes {
+                true => ("v3\nnotes\n", "v3\nnotes\n"),
+                false => ("secret\n", "v2\n"),
+            };
+            for (string, header) in [
+                (record.to_string(), wire_header),
+                (record.to_versioned_string(), versioned_header),
+            ] {
+                assert!(string.starts_with(header), "{string}");
+                assert_eq!(Record::from_str(&string).unwrap(), record);
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_str_v3() {
+        assert_eq!(
+            Record::from_str("v3\n\nsecret\nnotes").unwrap(),
+            Record::builder().password("secret").notes("notes").build()
+        );
+        assert!(matches!(
+            Record::from_str("v3\nnotes password\nsecret\nnotes"),
+            Err(ParseRecordError::UnknownEncryptedField(field)) if field == "password"
+        ));
+        assert!(matches!(
+            Record::from_str("v3\nnotes\nsecret"),
+            Err(ParseRecordError::EmptyString)
+        ));
+    }
+
+    #[test]
+    fn test_special_characters_round_trip() {
+        let record = Record {
+            resource: String::default(),
+            password: String::from("pa\"ss\r\nw\x04rd\\"),
+            notes: String::from("first line\r\n\"quoted\"\n\x04\tC:\\path\\x41"),
+            encrypted_notes: false,
+        };
+
+        let string = record.to_string();
+        assert!(string.starts_with("v2\n"));
+        assert_eq!(string.lines().count(), 3);
+        assert!(!string.contains(['\r', '\x04', '"']));
+        assert_eq!(Record::from_str(&string).unwrap(), record);
+    }
+
+    #[test]
+    fn test_unescape_malformed() {
+        assert_eq!(unescape("C:\\path\\x\\"), "C:\\path\\x\\");
+        assert_eq!(unescape("\\xzz\\xff"), "\\xzz\\xff");
+    }
+
+    #[test]
+    fn test_legacy_format_not_unescaped() {
+        let expected = Record::builder()
+            .password("C:\\new")
+            .notes("\\\\server\\share\nsecond line")
+            .build();
+        for s in [
+            "C:\\new\n\\\\server\\share\nsecond line",
+            "v1\nC:\\new\n\\\\server\\share\nsecond line",
+        ] {
+            assert_eq!(Record::from_str(s).unwrap(), expected, "{s}");
+        }
+
+        let string = expected.to_string();
+        assert!(string.starts_with("v2\n"));
+        assert_eq!(Record::from_str(&string).unwrap(), expected);
+    }
+
+    #[cfg(feature = "enable-serde")]
+    #[test]
+    fn test_serde() {
+        let records = vec![
+            Record {
+                resource: String::from("first.com"),
+                password: String::from("secret"),
+                notes: String::from("multiline\nnotes"),
+                encrypted_notes: false,
+            },
+            Record {
+                resource: String::from("second.com"),
+                password: String::from("\"quoted\""),
+                notes: String::new(),
+                encrypted_notes: true,
+            },
+        ];
+
+        let json = serde_json::to_string(&records).unwrap();
+        let deserialized: Vec<Record> = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, records);
     }
 }
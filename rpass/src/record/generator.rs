@@ -0,0 +1,181 @@
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+
+const LOWERCASE: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &[u8] = b"0123456789";
+const SYMBOLS: &[u8] = b"!#$%&()*+,-./:;<=>?@[]^_{|}~";
+
+/// Options for [`generate_password()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordOptions {
+    pub length: usize,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+}
+
+impl PasswordOptions {
+    /// Gets characters of all requested classes
+    fn classes(&self) -> Vec<&'static [u8]> {
+        [
+            (self.lowercase, LOWERCASE),
+            (self.uppercase, UPPERCASE),
+            (self.digits, DIGITS),
+            (self.symbols, SYMBOLS),
+        ]
+        .into_iter()
+        .filter_map(|(requested, class)| requested.then_some(class))
+        .collect()
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum PasswordOptionsError {
+    #[error("no character class requested")]
+    NoClasses,
+
+    #[error("password length should be at least {0}")]
+    TooShort(usize),
+}
+
+impl Default for PasswordOptions {
+    /// 20 characters with all character classes
+    fn default() -> Self {
+        PasswordOptions {
+            length: 20,
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: true,
+        }
+    }
+}
+
+/// Generates random password according to `opts` using cryptographically
+/// secure random number generator
+///
+/// Password contains at least one character of every requested class.
+/// All other characters are chosen uniformly from all requested classes
+///
+/// # Errors
+///
+/// * `NoClasses` - if no character class is requested
+/// * `TooShort` - if `opts.length` is less than number of requested classes
+///
+/// # Example
+///
+/// ```
+/// use rpass::record::{generate_password, PasswordOptions};
+///
+/// let password = generate_password(&PasswordOptions {
+///     length: 12,
+///     symbols: false,
+///     ..PasswordOptions::default()
+/// })
+/// .unwrap();
+/// assert_eq!(password.len(), 12);
+/// assert!(password.chars().all(char::is_alphanumeric));
+/// ```
+pub fn generate_password(opts: &PasswordOptions) -> Result<String, PasswordOptionsError> {
+    let classes = opts.classes();
+    if classes.is_empty() {
+        return Err(PasswordOptionsError::NoClasses);
+    }
+    if opts.length < classes.len() {
+        return Err(PasswordOptionsError::TooShort(classes.len()));
+    }
+
+    let mut rng = thread_rng();
+    let alphabet = classes.concat();
+    let mut password: Vec<u8> = classes
+        .iter()
+        .map(|class| *class.choose(&mut rng).unwrap())
+        .collect();
+    password.extend((classes.len()..opts.length).map(|_| *alphabet.choose(&mut rng).unwrap()));
+    password.shuffle(&mut rng);
+
+    Ok(String::from_utf8(password).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_length() {
+        for length in [4, 20, 128] {
+            let opts = PasswordOptions {
+                length,
+                ..PasswordOptions::default()
+            };
+            assert_eq!(generate_password(&opts).unwrap().len(), length);
+        }
+    }
+
+    #[test]
+    fn test_class_coverage() {
+        let opts = PasswordOptions {
+            length: 4,
+            ..PasswordOptions::default()
+        };
+        for _ in 0..100 {
+            let password = generate_password(&opts).unwrap();
+            for class in opts.classes() {
+                assert!(password.bytes().any(|b| class.contains(&b)), "{password}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_only_requested_classes() {
+        let opts = PasswordOptions {
+            length: 64,
+            lowercase: false,
+            uppercase: false,
+            digits: true,
+            symbols: false,
+        };
+        assert!(generate_password(&opts)
+            .unwrap()
+            .bytes()
+            .all(|b| b.is_ascii_digit()));
+    }
+
+    #[test]
+    fn test_differs() {
+        let opts = PasswordOptions::default();
+        assert_ne!(
+            generate_password(&opts).unwrap(),
+            generate_password(&opts).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_no_classes() {
+        let opts = PasswordOptions {
+            length: 10,
+            lowercase: false,
+            uppercase: false,
+            digits: false,
+            symbols: false,
+        };
+        assert_eq!(
+            generate_password(&opts),
+            Err(PasswordOptionsError::NoClasses)
+        );
+    }
+
+    #[test]
+    fn test_too_short() {
+        let opts = PasswordOptions {
+            length: 3,
+            ..PasswordOptions::default()
+        };
+        assert_eq!(
+            generate_password(&opts),
+            Err(PasswordOptionsError::TooShort(4))
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+
+/// Minimal entropy in bits for password to be considered at least *Medium*
+const MEDIUM_ENTROPY: f64 = 40.0;
+
+/// Minimal entropy in bits for password to be considered *Strong*
+const STRONG_ENTROPY: f64 = 60.0;
+
+/// Character class predicate and number of characters in the class
+type CharClass = (fn(&char) -> bool, u32);
+
+/// Character classes used to estimate alphabet size
+const CHAR_CLASSES: [CharClass; 5] = [
+    (char::is_ascii_lowercase, 26),
+    (char::is_ascii_uppercase, 26),
+    (char::is_ascii_digit, 10),
+    (|c| c.is_ascii_punctuation() || *c == ' ', 33),
+    (|c| !c.is_ascii(), 100),
+];
+
+/// Category of password strength
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StrengthLevel {
+    Weak,
+    Medium,
+    Strong,
+}
+
+impl fmt::Display for StrengthLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self {
+            Self::Weak => "weak",
+            Self::Medium => "medium",
+            Self::Strong => "strong",
+        };
+        write!(f, "{level}")
+    }
+}
+
+/// Estimated password strength
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Strength {
+    pub level: StrengthLevel,
+    /// Estimated entropy in bits
+    pub entropy: f64,
+}
+
+/// Estimates strength of `password`
+///
+/// Entropy is estimated as `length * log2(alphabet size)`, where alphabet
+/// size is the sum of sizes of all character classes found in `password`.
+/// Characters repeating the previous one are not counted in length
+///
+/// # Example
+///
+/// ```
+/// use rpass::record::{password_strength, StrengthLevel};
+///
+/// assert_eq!(password_strength("password").level, StrengthLevel::Weak);
+/// assert_eq!(password_strength("x7$Kp!2qLm#9").level, StrengthLevel::Strong);
+/// ```
+pub fn password_strength(password: &str) -> Strength {
+    let mut alphabet_size = 0;
+    for (belongs, size) in CHAR_CLASSES {
+        if password.chars().any(|c| belongs(&c)) {
+            alphabet_size += size;
+        }
+    }
+
+    let mut length = 0;
+    let mut prev = None;
+    for c in password.chars() {
+        if prev != Some(c) {
+            length += 1;
+        }
+        prev = Some(c);
+    }
+
+    let entropy = match alphabet_size {
+        0 => 0.0,
+        size => length as f64 * f64::from(size).log2(),
+    };
+    let level = if entropy >= STRONG_ENTROPY {
+        StrengthLevel::Strong
+    } else if entropy >= MEDIUM_ENTROPY {
+        StrengthLevel::Medium
+    } else {
+        StrengthLevel::Weak
+    };
+
+    Strength { level, entropy }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(
+            password_strength(""),
+            Strength {
+                level: StrengthLevel::Weak,
+                entropy: 0.0
+            }
+        );
+    }
+
+    #[test]
+    fn test_levels() {
+        for (password, level) in [
+            ("password", StrengthLevel::Weak),
+            ("hello123", StrengthLevel::Weak),
+            ("Summer2024", StrengthLevel::Medium),
+            ("P@ssw0rd", StrengthLevel::Medium),
+            ("Tr0ub4dor&3", StrengthLevel::Strong),
+            ("correct horse battery staple", StrengthLevel::Strong),
+            ("Пароль", StrengthLevel::Weak),
+            ("Пароль2024", StrengthLevel::Strong),
+        ] {
+            assert_eq!(password_strength(password).level, level, "{password}");
+        }
+    }
+
+    #[test]
+    fn test_repeated_characters() {
+        let strength = password_strength(&"a".repeat(100));
+        assert_eq!(strength.level, StrengthLevel::Weak);
+        assert_eq!(strength.entropy, password_strength("a").entropy);
+    }
+
+    #[test]
+    fn test_longer_is_stronger() {
+        assert!(password_strength("abcdefgh").entropy < password_strength("abcdefghi").entropy);
+    }
+}
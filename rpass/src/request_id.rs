@@ -0,0 +1,46 @@
+//! Optional ids correlating responses with requests
+//!
+//! Request may start with [`PREFIX`] followed by an id and a space, e.g.
+//! *"#7 show_record example.com"*. Server echoes the same prefix in every
+//! response message to this request, so client can send several requests
+//! before reading responses. Requests without id are handled as usual
+
+/// First character of the request id prefix
+pub const PREFIX: char = '#';
+
+/// Prepends `message` with `id`. `id` shouldn't contain whitespaces
+///
+/// # Example
+///
+/// ```
+/// use rpass::request_id;
+///
+/// assert_eq!(request_id::tag("7", "ping"), "#7 ping");
+/// ```
+pub fn tag(id: &str, message: &str) -> String {
+    format!("{PREFIX}{id} {message}")
+}
+
+/// Splits `message` into id and the rest of the message.
+/// Returns `None` id if `message` doesn't start with non-empty id prefix
+///
+/// # Example
+///
+/// ```
+/// use rpass::request_id;
+///
+/// assert_eq!(request_id::split("#7 ping"), (Some("7"), "ping"));
+/// assert_eq!(request_id::split("#7"), (Some("7"), ""));
+/// assert_eq!(request_id::split("ping"), (None, "ping"));
+/// assert_eq!(request_id::split("# ping"), (None, "# ping"));
+/// ```
+pub fn split(message: &str) -> (Option<&str>, &str) {
+    let Some(rest) = message.strip_prefix(PREFIX) else {
+        return (None, message);
+    };
+    let (id, rest) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    match id.is_empty() {
+        true => (None, message),
+        false => (Some(id), rest),
+    }
+}
@@ -1,12 +1,17 @@
-pub use authorized::Authorized;
+pub use authorized::{Authorized, ImportSummary, RecordNames, RetryPolicy, UserStats};
+pub use connector::{ServerInfo, ServerVersion};
+#[cfg(unix)]
+pub use transport::UnixTransport;
+pub use transport::{StreamTransport, TcpTransport, Transport};
 pub use unauthorized::Unauthorized;
 
 mod authorized;
 mod connector;
+mod transport;
 mod unauthorized;
 mod utils;
 
 #[mockall_double::double]
-use connector::Connector;
+pub(crate) use connector::Connector;
 
 use super::{error::*, record::Record, Result};
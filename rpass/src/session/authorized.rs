@@ -1,23 +1,198 @@
-use super::{utils, Connector, DeleteMeError, Error, Record, Result, Unauthorized};
-use std::cell::RefCell;
+use super::{
+    utils, Connector, DeleteMeError, Error, Record, Result, ServerInfo, ServerVersion, Unauthorized,
+};
+use crate::error::ErrorCode;
+use crate::key::Key;
+use crate::record::{PatchRecord, RecordVersion, SortOrder};
 use std::str::FromStr;
+use tokio::sync::{Mutex, MutexGuard};
+
+/// Result of [`Authorized::import_records()`]
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// Resources of added or overwritten records
+    pub added: Vec<String>,
+    /// Resources of records which already exist and weren't overwritten
+    pub skipped: Vec<String>,
+    /// Resources of records which can't be added with corresponding errors
+    pub failed: Vec<(String, Error)>,
+}
+
+/// Result of [`Authorized::stats()`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UserStats {
+    /// Number of records
+    pub records: usize,
+    /// Total size of records on the server in bytes
+    pub bytes: u64,
+}
+
+/// Policy of retrying requests failed because of connection loss.
+/// See [`Authorized::set_retry_policy()`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Maximum number of retries of one request. `0` disables retrying
+    pub max_retries: usize,
+    /// Whether to retry requests which modify records.
+    /// Server could have applied such request before connection was lost
+    pub retry_non_idempotent: bool,
+}
+
+/// Names of records received one by one, see [`Authorized::iter_records()`]
+///
+/// Session is locked until all names are read or `RecordNames` is dropped
+#[derive(Debug)]
+pub struct RecordNames<'a> {
+    /// `None` if stream is over
+    connector: Option<MutexGuard<'a, Connector>>,
+}
+
+impl RecordNames<'_> {
+    /// Receives next name. Returns `None` after the last one
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't read bytes from server
+    /// * `ConnectionClosed` - if server closed the connection
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    ///
+    /// Stream is over after any error
+    pub async fn next(&mut self) -> Option<Result<String>> {
+        let connector = self.connector.as_mut()?;
+        match utils::read_good_response(connector).await {
+            Ok(name) if name.is_empty() => {
+                self.connector = None;
+                None
+            }
+            Ok(name) => Some(Ok(name)),
+            Err(err) => {
+                self.connector = None;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl Drop for RecordNames<'_> {
+    fn drop(&mut self) {
+        if let Some(connector) = &mut self.connector {
+            connector.skip_stream();
+        }
+    }
+}
+
+/// Data needed to log in again after reconnection
+#[derive(Debug)]
+struct Credentials {
+    username: String,
+    sec_key: Key,
+}
 
 /// Authorized session
 ///
-/// Represents state when session is associated with user
+/// Represents state when session is associated with user.
+/// Requests made concurrently through `&self` methods are sent one by one
 #[derive(Debug)]
 pub struct Authorized {
-    connector: RefCell<Connector>,
+    connector: Mutex<Connector>,
+    credentials: Option<Credentials>,
+    retry_policy: RetryPolicy,
 }
 
 impl Authorized {
     /// Creates new Authorized with `connector`
     pub(super) fn new(connector: Connector) -> Self {
         Authorized {
-            connector: RefCell::new(connector),
+            connector: Mutex::new(connector),
+            credentials: None,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Creates new Authorized with `connector` remembering `username` and `sec_key`
+    /// to log in again after reconnection
+    pub(super) fn with_credentials(connector: Connector, username: &str, sec_key: &Key) -> Self {
+        Authorized {
+            credentials: Some(Credentials {
+                username: username.to_owned(),
+                sec_key: sec_key.clone(),
+            }),
+            ..Self::new(connector)
+        }
+    }
+
+    /// Sets `policy` of retrying requests failed because of connection loss.
+    ///
+    /// By default requests aren't retried. Retried request is sent after reconnection and
+    /// login with credentials used for the original login.
+    /// Only read-only requests, including [`Authorized::ping()`], and [`Authorized::add_record()`]
+    /// and [`Authorized::delete_record()`] can be retried. The last two are retried only if
+    /// [`RetryPolicy::retry_non_idempotent`] is set
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// Connects to the server again and logs in with credentials used for the original login
+    ///
+    /// # Errors
+    ///
+    /// * `InvalidRequest` - if session doesn't know credentials to log in with
+    /// * See [`Connector::reconnect()`] and [`Unauthorized::login()`] for other errors
+    pub async fn reconnect(&mut self) -> Result<()> {
+        self.try_reconnect().await
+    }
+
+    /// Gets server version received during connection.
+    /// `None` if server is too old to report its version
+    ///
+    /// Waits for the request in progress to finish, if there is some
+    pub async fn server_version(&self) -> Option<ServerVersion> {
+        self.connector.lock().await.server_version()
+    }
+
+    /// Gets server information from the greeting banner.
+    /// `None` if server doesn't send banner
+    ///
+    /// Waits for the request in progress to finish, if there is some
+    pub async fn server_info(&self) -> Option<ServerInfo> {
+        self.connector.lock().await.server_info()
+    }
+
+    /// Checks if server is alive. *ping* is retried after reconnection like other
+    /// read-only requests, see [`Authorized::set_retry_policy()`]
+    ///
+    /// # Errors
+    ///
+    /// See [`Connector::ping()`]
+    pub async fn ping(&mut self) -> Result<()> {
+        match self.send_request(String::from("ping"), true).await? {
+            pong if pong == "pong" => Ok(()),
+            response => Err(Error::UnexpectedResponse {
+                command: String::from("ping"),
+                response,
+            }),
         }
     }
 
+    /// Checks if connection to the server is still alive without changing
+    /// anything on the server. Useful to decide if [`Authorized::reconnect()`]
+    /// is needed before sending a request
+    ///
+    /// Waits for the request in progress to finish, if there is some
+    pub async fn is_alive(&self) -> bool {
+        self.connector.lock().await.is_connected().await
+    }
+
+    /// Ends session and closes connection with server
+    ///
+    /// # Errors
+    ///
+    /// See [`Connector::quit()`]
+    pub async fn quit(self) -> Result<()> {
+        self.connector.into_inner().quit().await
+    }
+
     /// Add `record` to the storage
     ///
     /// # Errors
@@ -40,7 +215,8 @@ impl Authorized {
     /// let record = Record {
     ///     resource: String::from("example.com"),
     ///     password: String::from("secret"),
-    ///     notes: String::from("important notes")
+    ///     notes: String::from("important notes"),
+    ///     encrypted_notes: false,
     /// };
     ///
     /// let mut session = session::Unauthorized::new("127.0.0.1:3747").await?;
@@ -52,10 +228,79 @@ impl Authorized {
     pub async fn add_record(&mut self, record: &Record) -> Result<()> {
         Self::check_resource(&record.resource)?;
 
-        let request = format!("new_record {} \"{}\"", record.resource, record.to_string());
-        self.connector.get_mut().send_request(request).await?;
+        let request = format!(
+            "new_record {} {}",
+            utils::quote(&record.resource),
+            utils::quote(&record.to_string())
+        );
+        let response = self.send_request(request, false).await?;
+        utils::check_ok_response("new_record", response)
+    }
 
-        self.read_ok_response().await
+    /// Changes only fields of record with `resource` name which are set in
+    /// `patch`, other fields are preserved
+    ///
+    /// # Errors
+    ///
+    /// * `InvalidResource` - if `resource` is empty
+    /// * `InvalidRequest` - if `patch` doesn't change any field
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message, e.g. if record
+    ///   doesn't exist
+    /// * `UnexpectedResponse` - if server responses with unexpected message
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::error::Error;
+    /// use rpass::{record::PatchRecord, session::Authorized};
+    ///
+    /// async fn update_notes(session: &mut Authorized) -> Result<(), Box<dyn Error>> {
+    ///     let patch = PatchRecord {
+    ///         notes: Some(String::from("new notes")),
+    ///         ..PatchRecord::default()
+    ///     };
+    ///     session.patch_record("example.com", &patch).await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn patch_record(&mut self, resource: &str, patch: &PatchRecord) -> Result<()> {
+        Self::check_resource(resource)?;
+        if patch.is_empty() {
+            return Err(Error::InvalidRequest {
+                mes: String::from("patch doesn't change any field"),
+            });
+        }
+
+        let mut request = format!("patch_record {}", utils::quote(resource));
+        for (field, value) in patch.fields() {
+            request += &format!(" {field} {}", utils::quote(value));
+        }
+        let response = self.send_request(request, false).await?;
+        utils::check_ok_response("patch_record", response)
+    }
+
+    /// Checks if server would accept `record` without storing it
+    ///
+    /// # Errors
+    ///
+    /// * `InvalidResource` - if `record.resource` is empty
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message, e.g. if
+    ///   resource name or record format is invalid
+    /// * `UnexpectedResponse` - if server responses with unexpected message
+    pub async fn validate_record(&self, record: &Record) -> Result<()> {
+        Self::check_resource(&record.resource)?;
+
+        let request = format!(
+            "validate_record {} {}",
+            utils::quote(&record.resource),
+            utils::quote(&record.to_string())
+        );
+        let response = self.send_request(request, true).await?;
+        utils::check_ok_response("validate_record", response)
     }
 
     /// Deletes record with `resource` name
@@ -89,10 +334,178 @@ impl Authorized {
     pub async fn delete_record(&mut self, resource: &str) -> Result<()> {
         Self::check_resource(resource)?;
 
-        let request = format!("delete_record {}", resource);
-        self.connector.get_mut().send_request(request).await?;
+        let request = format!("delete_record {}", utils::quote(resource));
+        let response = self.send_request(request, false).await?;
+        utils::check_ok_response("delete_record", response)
+    }
+
+    /// Deletes records with `resources` names in one request
+    ///
+    /// Results are returned in the same order as `resources`. One invalid or
+    /// missing record doesn't prevent deletion of others, but existing records
+    /// are deleted by server at once, so failed deletion fails all of them
+    ///
+    /// # Errors
+    ///
+    /// Errors of the whole request:
+    ///
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server can't handle request
+    /// * `UnexpectedResponse` - if server responses with unexpected message
+    ///
+    /// Errors of every record:
+    ///
+    /// * `InvalidResource` - if resource is empty or contains quotes
+    /// * `Server` - if server can't delete record
+    /// * `UnexpectedResponse` - if server responses with unexpected message
+    pub async fn delete_records(
+        &mut self,
+        resources: &[String],
+    ) -> Result<Vec<(String, Result<()>)>> {
+        let valid_resources: Vec<&String> = resources
+            .iter()
+            .filter(|resource| Self::check_resource(resource).is_ok())
+            .collect();
+        let mut statuses = vec![];
+        if !valid_resources.is_empty() {
+            let mut request = String::from("delete_records");
+            for resource in &valid_resources {
+                request += " ";
+                request += &utils::quote(resource);
+            }
+
+            let response = self.send_request(request, false).await?;
+            statuses = response.split('\n').map(str::to_owned).collect();
+            if statuses.len() != valid_resources.len() {
+                return Err(Error::UnexpectedResponse {
+                    command: String::from("delete_records"),
+                    response,
+                });
+            }
+        }
+
+        let mut statuses = statuses.into_iter();
+        let results = resources
+            .iter()
+            .map(|resource| {
+                let res = Self::check_resource(resource).and_then(|_| {
+                    let status = statuses.next().unwrap();
+                    match utils::parse_server_error(&status) {
+                        Some(err) => Err(err),
+                        None => utils::check_ok_response("delete_records", status),
+                    }
+                });
+                (resource.clone(), res)
+            })
+            .collect();
+        Ok(results)
+    }
+
+    /// Gets list of names of deleted records in trash. Trash is used only if
+    /// server is configured to keep deleted records
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    pub async fn list_trash(&self) -> Result<Vec<String>> {
+        let response = self.send_request(String::from("list_trash"), true).await?;
+        if response == "Trash is empty" {
+            return Ok(vec![]);
+        }
+
+        Ok(response.split('\n').map(|s| s.to_owned()).collect())
+    }
+
+    /// Moves deleted record with `resource` name from trash back to records
+    ///
+    /// # Errors
+    ///
+    /// * `InvalidResource` - if `resource` is empty or contains quotes
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    /// * `UnexpectedResponse` - if server responses with unexpected message
+    pub async fn restore_record(&mut self, resource: &str) -> Result<()> {
+        Self::check_resource(resource)?;
+
+        let request = format!("restore_record {}", utils::quote(resource));
+        let response = self.send_request(request, false).await?;
+        utils::check_ok_response("restore_record", response)
+    }
+
+    /// Permanently deletes all records in trash
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    /// * `UnexpectedResponse` - if server responses with unexpected message
+    pub async fn purge_trash(&mut self) -> Result<()> {
+        let response = self.send_request(String::from("purge_trash"), true).await?;
+        utils::check_ok_response("purge_trash", response)
+    }
+
+    /// Gets previous versions of record with `resource` name, oldest first.
+    /// Server keeps limited number of versions or none at all
+    ///
+    /// # Errors
+    ///
+    /// * `InvalidResource` - if `resource` is empty or contains quotes
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    /// * `UnexpectedResponse` - if server responses with unexpected message
+    pub async fn get_record_history(&self, resource: &str) -> Result<Vec<RecordVersion>> {
+        Self::check_resource(resource)?;
+
+        let request = format!("get_record_history {}", utils::quote(resource));
+        let response = self.send_request(request, true).await?;
+        if response == "No history yet" {
+            return Ok(vec![]);
+        }
+
+        let parse_version = |version: &str| {
+            let (version, record) = version.split_once('\n')?;
+            let record = Record::from_str(record).ok()?;
+            Some(RecordVersion {
+                version: version.parse().ok()?,
+                record: Record {
+                    resource: resource.to_owned(),
+                    ..record
+                },
+            })
+        };
+        Self::split_length_prefixed(&response)
+            .and_then(|versions| versions.into_iter().map(parse_version).collect())
+            .ok_or_else(|| Error::UnexpectedResponse {
+                command: String::from("get_record_history"),
+                response: response.clone(),
+            })
+    }
+
+    /// Replaces record with `resource` name with its previous `version`,
+    /// see [`Authorized::get_record_history()`]
+    ///
+    /// # Errors
+    ///
+    /// * `InvalidResource` - if `resource` is empty or contains quotes
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    /// * `UnexpectedResponse` - if server responses with unexpected message
+    pub async fn restore_record_version(&mut self, resource: &str, version: u64) -> Result<()> {
+        Self::check_resource(resource)?;
 
-        self.read_ok_response().await
+        let request = format!(
+            "restore_record_version {} {version}",
+            utils::quote(resource)
+        );
+        let response = self.send_request(request, false).await?;
+        utils::check_ok_response("restore_record_version", response)
     }
 
     /// Get record with `resource` name
@@ -128,12 +541,8 @@ impl Authorized {
     pub async fn get_record(&self, resource: String) -> Result<Record> {
         Self::check_resource(&resource)?;
 
-        let response = {
-            let request = format!("show_record {}", resource);
-            let mut connector = self.connector.borrow_mut();
-            connector.send_request(request).await?;
-            utils::read_good_response(&mut connector).await?
-        };
+        let request = format!("show_record {}", utils::quote(&resource));
+        let response = self.send_request(request, true).await?;
 
         Ok(Record {
             resource,
@@ -141,6 +550,101 @@ impl Authorized {
         })
     }
 
+    /// Same as [`Authorized::get_record()`], but returns `None` if record for
+    /// `resource` doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// See [`Authorized::get_record()`]. `Server` error is returned for every
+    /// reason except missing record
+    pub async fn get_record_or_none(&self, resource: String) -> Result<Option<Record>> {
+        match self.get_record(resource).await {
+            Ok(record) => Ok(Some(record)),
+            Err(Error::Server {
+                code: ErrorCode::RecordNotFound,
+                ..
+            }) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Checks if record for `resource` exists without fetching its content
+    ///
+    /// # Errors
+    ///
+    /// * `InvalidResource` - if `resource` is empty or contains quotes
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    /// * `UnexpectedResponse` - if response isn't *true* or *false*
+    pub async fn exists_record(&self, resource: &str) -> Result<bool> {
+        Self::check_resource(resource)?;
+
+        let command = format!("exists_record {}", utils::quote(resource));
+        let response = self.send_request(command.clone(), true).await?;
+        match response.as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(Error::UnexpectedResponse { command, response }),
+        }
+    }
+
+    /// Gets records with `resources` names in one request
+    ///
+    /// Results are returned in the same order as `resources`, so one missing or invalid
+    /// record doesn't fail the whole request. Server should support *batch* command
+    ///
+    /// # Errors
+    ///
+    /// Errors of the whole request:
+    ///
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server can't handle batch request
+    /// * `UnexpectedResponse` - if server responses with unexpected message
+    ///
+    /// Errors of every record:
+    ///
+    /// * `InvalidResource` - if resource is empty or contains quotes
+    /// * `Server` - if server response contains error message
+    /// * `CantParseRecord` - if server responses with invalid record
+    pub async fn get_records(&self, resources: &[String]) -> Result<Vec<Result<Record>>> {
+        let valid_resources = resources
+            .iter()
+            .filter(|resource| Self::check_resource(resource).is_ok());
+        let mut request = String::from("batch");
+        for resource in valid_resources.clone() {
+            request += &format!("\nshow_record {}", utils::quote(resource));
+        }
+
+        let response = self.send_request(request, true).await?;
+
+        let mut responses = match Self::split_length_prefixed(&response) {
+            Some(responses) if responses.len() == valid_resources.count() => responses.into_iter(),
+            _ => {
+                return Err(Error::UnexpectedResponse {
+                    command: String::from("batch"),
+                    response,
+                })
+            }
+        };
+        let records = resources
+            .iter()
+            .map(|resource| {
+                Self::check_resource(resource)?;
+                let response = responses.next().unwrap();
+                if let Some(err) = utils::parse_server_error(response) {
+                    return Err(err);
+                }
+                Ok(Record {
+                    resource: resource.clone(),
+                    ..Record::from_str(response)?
+                })
+            })
+            .collect();
+        Ok(records)
+    }
+
     /// Get list of all records names
     ///
     /// # Errors
@@ -166,57 +670,394 @@ impl Authorized {
     /// }
     /// ```
     pub async fn get_records_list(&self) -> Result<Vec<String>> {
-        let response = {
-            let mut connector = self.connector.borrow_mut();
-            connector.send_request(String::from("list_records")).await?;
-            utils::read_good_response(&mut connector).await?
-        };
-
-        if response == "No records yet" {
-            return Ok(vec![]);
-        }
-
-        let records = response.split('\n').map(|s| s.to_owned()).collect();
-        Ok(records)
+        let response = self
+            .send_request(String::from("list_records"), true)
+            .await?;
+        Ok(Self::parse_records_list(response))
     }
 
-    /// Deletes all information about user the session is associated with
+    /// Gets list of all records names sorted in `sort` order.
     ///
-    /// Consumes `self` and returns `Unauthorized` object on success or `DeleteMeError` with `self`
-    /// on failure
+    /// Timestamp orders make server read metadata of every record, so they are
+    /// slower than sorting by name
     ///
     /// # Errors
     ///
-    /// `DeleteMeError::source` field can have the next values:
-    ///
     /// * `Io` - if can't write or read bytes to/from server
     /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
     /// * `Server` - if server response contains error message
+    pub async fn get_records_list_sorted(&self, sort: SortOrder) -> Result<Vec<String>> {
+        let response = self
+            .send_request(format!("list_records {sort}"), true)
+            .await?;
+        Ok(Self::parse_records_list(response))
+    }
+
+    /// Gets page of at most `limit` records names starting from `offset`
+    /// along with the total number of records.
     ///
-    /// # Example
+    /// Page is empty if `offset` is beyond the end or `limit` is zero
     ///
-    /// ```
-    /// use rpass::session::{Authorized, Unauthorized};
-    /// use tokio::io::{AsyncBufReadExt, BufReader, stdin};
+    /// # Errors
     ///
-    /// async fn delete_user(authorized: Authorized) -> Result<Unauthorized, Authorized> {
-    ///     println!("Deleting user... Are you sure?");
-    ///     let mut buffer = String::new();
-    ///     let mut stdin = BufReader::new(stdin());
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    /// * `UnexpectedResponse` - if server responses with unexpected message
+    pub async fn get_records_page(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<String>, usize)> {
+        let command = format!("list_records {offset} {limit}");
+        let response = self.send_request(command.clone(), true).await?;
+
+        let mut lines = response.split('\n');
+        let total = lines.next().and_then(|total| total.parse().ok());
+        match total {
+            Some(total) => Ok((lines.map(|s| s.to_owned()).collect(), total)),
+            None => Err(Error::UnexpectedResponse { command, response }),
+        }
+    }
+
+    /// Starts receiving names of all records one by one in alphabetical order.
     ///
-    ///     if let Err(_) = stdin.read_line(&mut buffer).await {
-    ///         return Err(authorized);
-    ///     }
+    /// Unlike [`Authorized::get_records_list()`], the whole list isn't
+    /// buffered, so it suits accounts with a huge number of records.
+    /// Request isn't retried on connection loss
     ///
-    ///     if buffer == "Yes" {
-    ///         return authorized.delete_me().await.map_err(|err| err.authorized);
-    ///     }
+    /// # Errors
+    ///
+    /// * `Io` - if can't write bytes to server
+    ///
+    /// # Example
     ///
-    ///     Err(authorized)
-    /// }
     /// ```
-    pub async fn delete_me(mut self) -> std::result::Result<Unauthorized, DeleteMeError> {
-        match self.try_delete_me().await {
+    /// # use std::error::Error;
+    /// use rpass::session::Authorized;
+    ///
+    /// async fn print_all_records(session: &Authorized) -> Result<(), Box<dyn Error>> {
+    ///     let mut names = session.iter_records().await?;
+    ///     while let Some(name) = names.next().await {
+    ///         println!("{}", name?);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn iter_records(&self) -> Result<RecordNames<'_>> {
+        let mut connector = self.connector.lock().await;
+        connector
+            .send_request(String::from("stream_records"))
+            .await?;
+        Ok(RecordNames {
+            connector: Some(connector),
+        })
+    }
+
+    /// Gets number of user records without listing their names
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    /// * `UnexpectedResponse` - if response isn't a number
+    pub async fn count_records(&self) -> Result<usize> {
+        let command = String::from("count_records");
+        let response = self.send_request(command.clone(), true).await?;
+        response
+            .parse()
+            .map_err(|_| Error::UnexpectedResponse { command, response })
+    }
+
+    /// Gets number of user records and their total size on the server
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    /// * `UnexpectedResponse` - if server responses with unexpected message
+    pub async fn stats(&self) -> Result<UserStats> {
+        let response = self.send_request(String::from("stats"), true).await?;
+
+        let parse = || {
+            let (records, bytes) = response.split_once('\n')?;
+            Some(UserStats {
+                records: records.parse().ok()?,
+                bytes: bytes.parse().ok()?,
+            })
+        };
+        parse().ok_or_else(|| Error::UnexpectedResponse {
+            command: String::from("stats"),
+            response: response.clone(),
+        })
+    }
+
+    /// Gets name of the user this session is logged in as
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    pub async fn whoami(&self) -> Result<String> {
+        self.send_request(String::from("whoami"), true).await
+    }
+
+    /// Gets names of commands supported by the server. Aliases are listed
+    /// after commands as *"alias -> command"*
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    pub async fn help(&self) -> Result<Vec<String>> {
+        let response = self.send_request(String::from("help"), true).await?;
+        Ok(response.lines().map(str::to_owned).collect())
+    }
+
+    /// Issues short-lived token to resume this session on a new connection
+    /// without logging in, see [`Unauthorized::resume()`].
+    /// Token is revoked by the server after [`Authorized::quit()`] and
+    /// [`Authorized::delete_me()`]
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    /// * `UnexpectedResponse` - if response isn't a single word
+    pub async fn issue_session_token(&mut self) -> Result<String> {
+        let response = self
+            .send_request(String::from("issue_session_token"), false)
+            .await?;
+        if response.is_empty() || response.contains(char::is_whitespace) {
+            return Err(Error::UnexpectedResponse {
+                command: String::from("issue_session_token"),
+                response,
+            });
+        }
+        Ok(response)
+    }
+
+    /// Checks if user `username` is registered. Available only for
+    /// administrators
+    ///
+    /// # Errors
+    ///
+    /// * `InvalidRequest` - if `username` is empty, too long, reserved or
+    ///   contains whitespaces, see [`crate::name::is_acceptable()`]
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message, e.g. if user
+    ///   isn't an administrator
+    /// * `UnexpectedResponse` - if server responses with unexpected message
+    pub async fn user_exists(&self, username: &str) -> Result<bool> {
+        utils::check_username(username)?;
+
+        let response = self
+            .send_request(format!("user_exists {}", username), true)
+            .await?;
+        response.parse().map_err(|_| Error::UnexpectedResponse {
+            command: String::from("user_exists"),
+            response,
+        })
+    }
+
+    /// Gets all user records
+    ///
+    /// Records are fetched one by one, so it can take a while for big accounts.
+    /// Returns empty vector if user has no records
+    ///
+    /// # Errors
+    ///
+    /// See [`Authorized::get_records_list()`] and [`Authorized::get_record()`]
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rpass::session::Authorized;
+    /// use std::error::Error;
+    ///
+    /// async fn print_all_passwords(session: &Authorized) -> Result<(), Box<dyn Error>> {
+    ///     for record in session.export_records().await? {
+    ///         println!("{}: {}", record.resource, record.password);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn export_records(&self) -> Result<Vec<Record>> {
+        let mut records = vec![];
+        for resource in self.get_records_list().await? {
+            records.push(self.get_record(resource).await?);
+        }
+
+        Ok(records)
+    }
+
+    /// Adds all `records` to the storage
+    ///
+    /// Records which already exist are overwritten if `overwrite` is `true` and skipped
+    /// otherwise. Failure to add one record doesn't stop importing of others, all failures
+    /// are reported in returned [`ImportSummary`]
+    ///
+    /// # Errors
+    ///
+    /// See [`Authorized::get_records_list()`]
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rpass::{session::Authorized, record::Record};
+    /// use std::error::Error;
+    ///
+    /// async fn restore(session: &mut Authorized, records: &[Record]) -> Result<(), Box<dyn Error>> {
+    ///     let summary = session.import_records(records, false).await?;
+    ///     for (resource, err) in summary.failed {
+    ///         println!("{}: {}", resource, err);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn import_records(
+        &mut self,
+        records: &[Record],
+        overwrite: bool,
+    ) -> Result<ImportSummary> {
+        let existing = self.get_records_list().await?;
+
+        let mut summary = ImportSummary::default();
+        for record in records {
+            let resource = record.resource.clone();
+            if !overwrite && existing.contains(&resource) {
+                summary.skipped.push(resource);
+                continue;
+            }
+
+            match self.add_record(record).await {
+                Ok(()) => summary.added.push(resource),
+                Err(err) => summary.failed.push((resource, err)),
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Changes public key of the user the session is associated with to `new_pub`
+    ///
+    /// Server proves that user owns the new key pair by sending confirmation string encrypted
+    /// with `new_pub`, which is decrypted with `new_sec` and sent back
+    ///
+    /// Already stored records are not re-encrypted, so if they were encrypted with the old key
+    /// pair then only the old secret key can decrypt them
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    /// * `UnexpectedResponse` - if server responses with unexpected message
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::error::Error;
+    /// use rpass::{session, key::Key};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> std::result::Result<(), Box<dyn Error>> {
+    /// let sec_key = Key::from_file("~/key.sec")?;
+    /// let (new_pub_key, new_sec_key) = Key::generate_pair();
+    ///
+    /// let session = session::Unauthorized::new("127.0.0.1:3747").await?;
+    /// let mut session = session.login("user", &sec_key).await?;
+    /// session.change_pub_key(&new_pub_key, &new_sec_key).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn change_pub_key(&mut self, new_pub: &Key, new_sec: &Key) -> Result<()> {
+        let connector = self.connector.get_mut();
+
+        let request = format!("change_pub_key {}", new_pub);
+        connector.send_request(request).await?;
+        let response = utils::read_good_response(connector).await?;
+
+        let confirmation = new_sec.decrypt(&response);
+        let encrypted_confirmation = connector.server_pub_key().encrypt(&confirmation);
+
+        let confirm_request = format!("confirm_change_pub_key {}", encrypted_confirmation);
+        connector.send_request(confirm_request).await?;
+
+        self.read_ok_response("confirm_change_pub_key").await?;
+        if let Some(credentials) = &mut self.credentials {
+            credentials.sec_key = new_sec.clone();
+        }
+        Ok(())
+    }
+
+    /// Renames user the session is associated with to `new_username`.
+    /// Session stays logged in with the new name
+    ///
+    /// # Errors
+    ///
+    /// * `InvalidRequest` - if `new_username` is empty, too long, reserved or
+    ///   contains whitespaces
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    /// * `UnexpectedResponse` - if server responses with unexpected message
+    pub async fn rename_me(&mut self, new_username: &str) -> Result<()> {
+        utils::check_username(new_username)?;
+
+        let request = format!("rename_me {}", new_username);
+        self.connector.get_mut().send_request(request).await?;
+
+        self.read_ok_response("rename_me").await?;
+        if let Some(credentials) = &mut self.credentials {
+            credentials.username = new_username.to_owned();
+        }
+        Ok(())
+    }
+
+    /// Deletes all information about user the session is associated with
+    ///
+    /// Consumes `self` and returns `Unauthorized` object on success or `DeleteMeError` with `self`
+    /// on failure
+    ///
+    /// # Errors
+    ///
+    /// `DeleteMeError::source` field can have the next values:
+    ///
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rpass::session::{Authorized, Unauthorized};
+    /// use tokio::io::{AsyncBufReadExt, BufReader, stdin};
+    ///
+    /// async fn delete_user(authorized: Authorized) -> Result<Unauthorized, Authorized> {
+    ///     println!("Deleting user... Are you sure?");
+    ///     let mut buffer = String::new();
+    ///     let mut stdin = BufReader::new(stdin());
+    ///
+    ///     if let Err(_) = stdin.read_line(&mut buffer).await {
+    ///         return Err(authorized);
+    ///     }
+    ///
+    ///     if buffer == "Yes" {
+    ///         return authorized.delete_me().await.map_err(|err| err.authorized);
+    ///     }
+    ///
+    ///     Err(authorized)
+    /// }
+    /// ```
+    pub async fn delete_me(mut self) -> std::result::Result<Unauthorized, DeleteMeError> {
+        match self.try_delete_me().await {
             Ok(()) => Ok(Unauthorized::with_connector(self.connector.into_inner())),
             Err(err) => Err(DeleteMeError {
                 source: err,
@@ -233,391 +1074,2134 @@ impl Authorized {
             .get_mut()
             .send_request(String::from("delete_me"))
             .await?;
-        self.read_ok_response().await
+        self.read_ok_response("delete_me").await
+    }
+
+    /// Reconnects and logs in again. See [`Authorized::reconnect()`]
+    async fn try_reconnect(&self) -> Result<()> {
+        let credentials = self
+            .credentials
+            .as_ref()
+            .ok_or_else(|| Error::InvalidRequest {
+                mes: String::from("session doesn't know credentials to log in again"),
+            })?;
+
+        let mut connector = self.connector.lock().await;
+        connector.reconnect().await?;
+        utils::login(&mut connector, &credentials.username, &credentials.sec_key).await
+    }
+
+    /// Parses names of records from `list_records` response without pagination
+    fn parse_records_list(response: String) -> Vec<String> {
+        if response == "No records yet" {
+            return vec![];
+        }
+
+        response.split('\n').map(|s| s.to_owned()).collect()
+    }
+
+    /// Sends `request` and reads response to it. If connection is lost, reconnects and
+    /// sends `request` again according to the retry policy.
+    /// Requests which aren't `idempotent` are retried only if
+    /// [`RetryPolicy::retry_non_idempotent`] is set
+    ///
+    /// # Errors
+    ///
+    /// * See [`utils::read_good_response()`]
+    /// * See [`Authorized::reconnect()`] for errors of reconnection
+    async fn send_request(&self, request: String, idempotent: bool) -> Result<String> {
+        let may_retry = idempotent || self.retry_policy.retry_non_idempotent;
+        let mut retries = 0;
+        loop {
+            let result = {
+                let mut connector = self.connector.lock().await;
+                match connector.send_request(request.clone()).await {
+                    Ok(()) => utils::read_good_response(&mut connector).await,
+                    Err(err) => Err(err),
+                }
+            };
+            match result {
+                Err(Error::ConnectionClosed | Error::Io(_))
+                    if may_retry && retries < self.retry_policy.max_retries =>
+                {
+                    retries += 1;
+                    self.try_reconnect().await?;
+                }
+                result => return result,
+            }
+        }
     }
 
-    /// Checks if `resource` is empty
+    /// Splits response consisting of parts prefixed with their length in bytes
+    /// and "\n", e.g. response to the *batch* request
+    ///
+    /// Returns `None` if `response` has invalid format
+    fn split_length_prefixed(mut response: &str) -> Option<Vec<&str>> {
+        let mut responses = vec![];
+        while !response.is_empty() {
+            let (len, rest) = response.split_once('\n')?;
+            let len = len.parse().ok()?;
+            responses.push(rest.get(..len)?);
+            response = &rest[len..];
+        }
+        Some(responses)
+    }
+
+    /// Checks if `resource` is not empty and can be sent in quotes
     ///
     /// # Errors
     ///
-    /// Returns `InvalidResource` if `resource` is empty
+    /// Returns `InvalidResource` if `resource` is empty or contains quotes
     fn check_resource(resource: &str) -> Result<()> {
-        if let true = resource.is_empty() {
+        if resource.is_empty() {
             return Err(Error::InvalidResource {
                 mes: String::from("record's resource can't be empty"),
             });
         }
+        if resource.contains('"') {
+            return Err(Error::InvalidResource {
+                mes: String::from("record's resource can't contain quotes"),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// See [`utils::read_ok_response()`]
+    async fn read_ok_response(&mut self, command: &str) -> Result<()> {
+        utils::read_ok_response(self.connector.get_mut(), command).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockall::predicate::*;
+    use std::io;
+
+    /// Tests for `Authorized::ping()`
+    mod ping {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, String::from("ping"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("pong")));
+
+            let mut authorized = Authorized::new(connector);
+            authorized.ping().await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_unexpected_response() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, String::from("ping"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("Ok")));
+
+            let mut authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.ping().await,
+                Err(Error::UnexpectedResponse { command, response })
+                    if command == "ping" && response == "Ok"
+            ));
+        }
+    }
+
+    mod is_alive {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_alive() {
+            let mut connector = Connector::default();
+            connector.expect_is_connected().times(1).returning(|| true);
+
+            let authorized = Authorized::new(connector);
+            assert!(authorized.is_alive().await);
+        }
+
+        #[tokio::test]
+        async fn test_closed() {
+            let mut connector = Connector::default();
+            connector.expect_is_connected().times(1).returning(|| false);
+
+            let authorized = Authorized::new(connector);
+            assert!(!authorized.is_alive().await);
+        }
+    }
+
+    /// Tests for `Authorized::add_record()`
+    mod add_record {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let record = build_record();
+
+            let mut connector = Connector::default();
+            expect_all_ok(&mut connector, build_request(&record));
+
+            let mut authorized = Authorized::new(connector);
+            authorized.add_record(&record).await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_spaced_resource() {
+            let record = Record {
+                resource: String::from("My Bank"),
+                ..build_record()
+            };
+
+            let mut connector = Connector::default();
+            expect_all_ok(
+                &mut connector,
+                String::from("new_record \"My Bank\" \"secret\nimportant notes\""),
+            );
+
+            let mut authorized = Authorized::new(connector);
+            authorized.add_record(&record).await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_escaped_notes() {
+            let record = Record {
+                password: String::from(r"pass\word"),
+                notes: String::from("notes with \"quotes\"\n\""),
+                ..build_record()
+            };
+
+            let mut connector = Connector::default();
+            expect_all_ok(
+                &mut connector,
+                String::from(concat!(
+                    r#"new_record "test.com" "v2"#,
+                    "\n",
+                    r#"pass\\\\word"#,
+                    "\n",
+                    r#"notes with \\x22quotes\\x22\\n\\x22""#
+                )),
+            );
+
+            let mut authorized = Authorized::new(connector);
+            authorized.add_record(&record).await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_quoted_resource() {
+            let record = Record {
+                resource: String::from("\"quoted\""),
+                ..build_record()
+            };
+
+            let mut authorized = Authorized::new(Connector::default());
+            assert!(matches!(
+                authorized.add_record(&record).await,
+                Err(Error::InvalidResource { .. })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_invalid_resource() {
+            let record = Record {
+                resource: String::default(),
+                password: String::from("secret"),
+                notes: String::from("notes"),
+                encrypted_notes: false,
+            };
+
+            let connector = Connector::default();
+
+            let mut authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.add_record(&record).await,
+                Err(Error::InvalidResource { .. })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_cant_send_request() {
+            let record = build_record();
+
+            let mut connector = Connector::default();
+            expect_failing_send_request(&mut connector, build_request(&record));
+
+            let mut authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.add_record(&record).await,
+                Err(Error::Io(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_cant_recv_response() {
+            let record = build_record();
+
+            let mut connector = Connector::default();
+            expect_failing_recv_response(&mut connector, build_request(&record));
+
+            let mut authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.add_record(&record).await,
+                Err(Error::InvalidResponseEncoding(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_unexpected_response() {
+            let record = build_record();
+
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, build_request(&record));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("Record successfully added")));
+
+            let mut authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.add_record(&record).await,
+                Err(Error::UnexpectedResponse { command, response })
+                    if command == "new_record" && response == "Record successfully added"
+            ));
+        }
+
+        /// Builds test record
+        fn build_record() -> Record {
+            Record {
+                resource: String::from("test.com"),
+                password: String::from("secret"),
+                notes: String::from("important notes"),
+                encrypted_notes: false,
+            }
+        }
+
+        /// Build expected request for `record`
+        fn build_request(record: &Record) -> String {
+            format!("new_record \"{}\" \"{}\"", record.resource, record)
+        }
+    }
+
+    /// Tests for `Authorized::validate_record()`
+    mod validate_record {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let record = build_record();
+
+            let mut connector = Connector::default();
+            expect_all_ok(&mut connector, build_request(&record));
+
+            let authorized = Authorized::new(connector);
+            authorized.validate_record(&record).await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_invalid_resource() {
+            let record = Record {
+                resource: String::default(),
+                ..build_record()
+            };
+
+            let authorized = Authorized::new(Connector::default());
+            assert!(matches!(
+                authorized.validate_record(&record).await,
+                Err(Error::InvalidResource { .. })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_server_error() {
+            let record = build_record();
+
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, build_request(&record));
+            connector.expect_recv_response().times(1).returning(|| {
+                Ok(String::from(
+                    "Error[InvalidRecordFormat]: invalid record format",
+                ))
+            });
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.validate_record(&record).await,
+                Err(Error::Server {
+                    code: ErrorCode::InvalidRecordFormat,
+                    ..
+                })
+            ));
+        }
+
+        /// Builds test record
+        fn build_record() -> Record {
+            Record {
+                resource: String::from("test.com"),
+                password: String::from("secret"),
+                notes: String::from("important notes"),
+                encrypted_notes: false,
+            }
+        }
+
+        /// Build expected request for `record`
+        fn build_request(record: &Record) -> String {
+            format!("validate_record \"{}\" \"{}\"", record.resource, record)
+        }
+    }
+
+    /// Tests for `Authorized::delete_record()`
+    mod delete_record {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let resource = "test_resource";
+
+            let mut connector = Connector::default();
+            expect_all_ok(
+                &mut connector,
+                String::from("delete_record \"test_resource\""),
+            );
+
+            let mut authorized = Authorized::new(connector);
+            authorized.delete_record(resource).await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_invalid_resource() {
+            let resource = "";
+
+            let connector = Connector::default();
+
+            let mut authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.delete_record(resource).await,
+                Err(Error::InvalidResource { .. })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_cant_send_request() {
+            let resource = "test_resource";
+
+            let mut connector = Connector::default();
+            expect_failing_send_request(
+                &mut connector,
+                String::from("delete_record \"test_resource\""),
+            );
+
+            let mut authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.delete_record(resource).await,
+                Err(Error::Io(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_cant_recv_response() {
+            let resource = "test_resource";
+
+            let mut connector = Connector::default();
+            expect_failing_recv_response(
+                &mut connector,
+                String::from("delete_record \"test_resource\""),
+            );
+
+            let mut authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.delete_record(resource).await,
+                Err(Error::InvalidResponseEncoding(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_unexpected_response() {
+            let resource = "test_resource";
+
+            let mut connector = Connector::default();
+            expect_ok_send_request(
+                &mut connector,
+                String::from("delete_record \"test_resource\""),
+            );
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("Record successfully deleted")));
+
+            let mut authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.delete_record(resource).await,
+                Err(Error::UnexpectedResponse { command, response })
+                    if command == "delete_record" && response == "Record successfully deleted"
+            ));
+        }
+    }
+
+    /// Tests for `Authorized::delete_records()`
+    mod delete_records {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_mixed_resources() {
+            let resources = [
+                String::from("example.com"),
+                String::from("bad\"name"),
+                String::from("missing.com"),
+                String::from("../passwd"),
+            ];
+
+            let mut connector = Connector::default();
+            expect_ok_send_request(
+                &mut connector,
+                String::from("delete_records \"example.com\" \"missing.com\" \"../passwd\""),
+            );
+            connector.expect_recv_response().times(1).returning(|| {
+                Ok(String::from(
+                    "Ok\n\
+                     Error[RecordNotFound]: no such record: `missing.com`\n\
+                     Error[InvalidResourceName]: invalid resource name",
+                ))
+            });
+
+            let mut authorized = Authorized::new(connector);
+            let results = authorized.delete_records(&resources).await.unwrap();
+            let names: Vec<&str> = results.iter().map(|(name, _)| name.as_str()).collect();
+            assert_eq!(
+                names,
+                ["example.com", "bad\"name", "missing.com", "../passwd"]
+            );
+            assert!(results[0].1.is_ok());
+            assert!(matches!(results[1].1, Err(Error::InvalidResource { .. })));
+            assert!(matches!(
+                results[2].1,
+                Err(Error::Server {
+                    code: ErrorCode::RecordNotFound,
+                    ..
+                })
+            ));
+            assert!(matches!(
+                results[3].1,
+                Err(Error::Server {
+                    code: ErrorCode::InvalidResourceName,
+                    ..
+                })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_no_valid_resources() {
+            let connector = Connector::default();
+
+            let mut authorized = Authorized::new(connector);
+            let results = authorized.delete_records(&[String::new()]).await.unwrap();
+            assert_eq!(results.len(), 1);
+            assert!(matches!(results[0].1, Err(Error::InvalidResource { .. })));
+        }
+
+        #[tokio::test]
+        async fn test_wrong_number_of_statuses() {
+            let resources = [String::from("first.com"), String::from("second.com")];
+
+            let mut connector = Connector::default();
+            expect_ok_send_request(
+                &mut connector,
+                String::from("delete_records \"first.com\" \"second.com\""),
+            );
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("Ok")));
+
+            let mut authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.delete_records(&resources).await,
+                Err(Error::UnexpectedResponse { .. })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_cant_send_request() {
+            let mut connector = Connector::default();
+            expect_failing_send_request(
+                &mut connector,
+                String::from("delete_records \"example.com\""),
+            );
+
+            let mut authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized
+                    .delete_records(&[String::from("example.com")])
+                    .await,
+                Err(Error::Io(_))
+            ));
+        }
+    }
+
+    /// Tests for `Authorized::get_record()`
+    mod get_record {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let resource = "test_resource";
+
+            let record = Record {
+                resource: resource.to_string(),
+                password: String::from("secret"),
+                notes: String::from("notes"),
+                encrypted_notes: false,
+            };
+            let record_str = record.to_string();
+
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, format!("show_record \"{}\"", resource));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .return_once(move || Ok(record_str));
+
+            let authorized = Authorized::new(connector);
+            assert_eq!(
+                authorized.get_record(resource.to_string()).await.unwrap(),
+                record
+            );
+        }
+
+        #[tokio::test]
+        async fn test_invalid_resource() {
+            let resource = String::default();
+
+            let connector = Connector::default();
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.get_record(resource).await,
+                Err(Error::InvalidResource { .. })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_cant_send_request() {
+            let resource = String::from("test_resource");
+
+            let mut connector = Connector::default();
+            expect_failing_send_request(&mut connector, format!("show_record \"{}\"", resource));
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.get_record(resource).await,
+                Err(Error::Io(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_cant_recv_response() {
+            let resource = String::from("test_resource");
+
+            let mut connector = Connector::default();
+            expect_failing_recv_response(&mut connector, format!("show_record \"{}\"", resource));
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.get_record(resource).await,
+                Err(Error::InvalidResponseEncoding(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_error_from_server() {
+            let resource = String::from("test_resource");
+
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, format!("show_record \"{}\"", resource));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("Error: no such record")));
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.get_record(resource).await,
+                Err(Error::Server { mes, .. }) if mes == "no such record"
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_wrong_record_format() {
+            let resource = String::from("test_resource");
+
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, format!("show_record \"{}\"", resource));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("secret, notes")));
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.get_record(resource).await,
+                Err(Error::CantParseRecord(_))
+            ));
+        }
+    }
+
+    /// Tests for `Authorized::get_record_or_none()`
+    mod exists_record {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            for (response, exists) in [("true", true), ("false", false)] {
+                let mut connector = Connector::default();
+                expect_ok_send_request(
+                    &mut connector,
+                    String::from("exists_record \"example.com\""),
+                );
+                connector
+                    .expect_recv_response()
+                    .times(1)
+                    .returning(move || Ok(response.to_owned()));
+
+                let authorized = Authorized::new(connector);
+                assert_eq!(
+                    authorized.exists_record("example.com").await.unwrap(),
+                    exists
+                );
+            }
+        }
+
+        #[tokio::test]
+        async fn test_invalid_resource() {
+            let authorized = Authorized::new(Connector::default());
+            assert!(matches!(
+                authorized.exists_record("\"quoted\"").await,
+                Err(Error::InvalidResource { .. })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_server_error() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, String::from("exists_record \"../unsafe\""));
+            connector.expect_recv_response().times(1).returning(|| {
+                Ok(String::from(
+                    "Error[InvalidResourceName]: invalid resource name",
+                ))
+            });
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.exists_record("../unsafe").await,
+                Err(Error::Server {
+                    code: ErrorCode::InvalidResourceName,
+                    ..
+                })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_unexpected_response() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(
+                &mut connector,
+                String::from("exists_record \"example.com\""),
+            );
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("maybe")));
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.exists_record("example.com").await,
+                Err(Error::UnexpectedResponse { command, response })
+                    if command == "exists_record \"example.com\"" && response == "maybe"
+            ));
+        }
+    }
+
+    mod get_record_or_none {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_present() {
+            let record = Record {
+                resource: String::from("test_resource"),
+                password: String::from("secret"),
+                notes: String::from("notes"),
+                encrypted_notes: false,
+            };
+            let record_str = record.to_string();
+
+            let mut connector = Connector::default();
+            expect_ok_send_request(
+                &mut connector,
+                String::from("show_record \"test_resource\""),
+            );
+            connector
+                .expect_recv_response()
+                .times(1)
+                .return_once(move || Ok(record_str));
+
+            let authorized = Authorized::new(connector);
+            assert_eq!(
+                authorized
+                    .get_record_or_none(String::from("test_resource"))
+                    .await
+                    .unwrap(),
+                Some(record)
+            );
+        }
+
+        #[tokio::test]
+        async fn test_absent() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(
+                &mut connector,
+                String::from("show_record \"test_resource\""),
+            );
+            connector.expect_recv_response().times(1).returning(|| {
+                Ok(String::from(
+                    "Error[RecordNotFound]: no such record: `test_resource`",
+                ))
+            });
+
+            let authorized = Authorized::new(connector);
+            assert_eq!(
+                authorized
+                    .get_record_or_none(String::from("test_resource"))
+                    .await
+                    .unwrap(),
+                None
+            );
+        }
+
+        #[tokio::test]
+        async fn test_other_server_error() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(
+                &mut connector,
+                String::from("show_record \"test_resource\""),
+            );
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("Error[CantDecrypt]: storage error")));
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized
+                    .get_record_or_none(String::from("test_resource"))
+                    .await,
+                Err(Error::Server {
+                    code: ErrorCode::CantDecrypt,
+                    ..
+                })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_transport_error() {
+            let mut connector = Connector::default();
+            expect_failing_send_request(
+                &mut connector,
+                String::from("show_record \"test_resource\""),
+            );
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized
+                    .get_record_or_none(String::from("test_resource"))
+                    .await,
+                Err(Error::Io(_))
+            ));
+        }
+    }
+
+    /// Tests for `Authorized::get_records()`
+    mod get_records {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_mixed_results() {
+            let record = Record {
+                resource: String::from("first"),
+                password: String::from("secret"),
+                notes: String::from("notes"),
+                encrypted_notes: false,
+            };
+            let record_str = record.to_string();
+            let error = "Error[InvalidResourceName]: no record";
+
+            let mut connector = Connector::default();
+            expect_ok_send_request(
+                &mut connector,
+                String::from("batch\nshow_record \"first\"\nshow_record \"missing\""),
+            );
+            connector
+                .expect_recv_response()
+                .times(1)
+                .return_once(move || {
+                    Ok(format!(
+                        "{}\n{}{}\n{}",
+                        record_str.len(),
+                        record_str,
+                        error.len(),
+                        error
+                    ))
+                });
+
+            let authorized = Authorized::new(connector);
+            let resources = [
+                String::from("first"),
+                String::from("bad\"resource"),
+                String::from("missing"),
+            ];
+            let records = authorized.get_records(&resources).await.unwrap();
+            assert_eq!(records.len(), 3);
+            assert_eq!(records[0].as_ref().unwrap(), &record);
+            assert!(matches!(records[1], Err(Error::InvalidResource { .. })));
+            assert!(matches!(
+                &records[2],
+                Err(Error::Server { code, mes })
+                    if *code == ErrorCode::InvalidResourceName && mes == "no record"
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_wrong_responses_count() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(
+                &mut connector,
+                String::from("batch\nshow_record \"first\"\nshow_record \"second\""),
+            );
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("2\nOk")));
+
+            let authorized = Authorized::new(connector);
+            let resources = [String::from("first"), String::from("second")];
+            assert!(matches!(
+                authorized.get_records(&resources).await,
+                Err(Error::UnexpectedResponse { .. })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_batch_not_supported() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, String::from("batch\nshow_record \"first\""));
+            connector.expect_recv_response().times(1).returning(|| {
+                Ok(String::from(
+                    "Error[UndefinedCommand]: undefined command `batch`",
+                ))
+            });
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.get_records(&[String::from("first")]).await,
+                Err(Error::Server { .. })
+            ));
+        }
+
+        #[test]
+        fn test_split_length_prefixed() {
+            assert_eq!(
+                Authorized::split_length_prefixed("4\npong4\nПр").unwrap(),
+                ["pong", "Пр"]
+            );
+            assert!(Authorized::split_length_prefixed("").unwrap().is_empty());
+            assert!(Authorized::split_length_prefixed("5\npong").is_none());
+            assert!(Authorized::split_length_prefixed("1\nПр").is_none());
+            assert!(Authorized::split_length_prefixed("pong").is_none());
+        }
+    }
+
+    /// Tests for `Authorized::get_records_list()`
+    mod get_records_list {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, String::from("list_records"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .return_once(move || Ok(String::from("example.com\ntest.ru\nyoutube.com")));
+
+            let expected_list: Vec<String> = ["example.com", "test.ru", "youtube.com"]
+                .into_iter()
+                .map(ToOwned::to_owned)
+                .collect();
+
+            let authorized = Authorized::new(connector);
+            assert_eq!(authorized.get_records_list().await.unwrap(), expected_list);
+        }
+
+        #[tokio::test]
+        async fn test_no_records() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, String::from("list_records"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .return_once(move || Ok(String::from("No records yet")));
+
+            let authorized = Authorized::new(connector);
+            assert!(authorized.get_records_list().await.unwrap().is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_cant_send_request() {
+            let mut connector = Connector::default();
+            expect_failing_send_request(&mut connector, String::from("list_records"));
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.get_records_list().await,
+                Err(Error::Io(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_cant_recv_response() {
+            let mut connector = Connector::default();
+            expect_failing_recv_response(&mut connector, String::from("list_records"));
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.get_records_list().await,
+                Err(Error::InvalidResponseEncoding(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_error_from_server() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, String::from("list_records"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("Error: some internal error")));
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.get_records_list().await,
+                Err(Error::Server { mes, .. }) if mes == "some internal error"
+            ));
+        }
+    }
+
+    mod list_trash {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, String::from("list_trash"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("example.com\ntest.ru")));
+
+            let authorized = Authorized::new(connector);
+            assert_eq!(
+                authorized.list_trash().await.unwrap(),
+                ["example.com", "test.ru"]
+            );
+        }
+
+        #[tokio::test]
+        async fn test_empty() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, String::from("list_trash"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("Trash is empty")));
+
+            let authorized = Authorized::new(connector);
+            assert!(authorized.list_trash().await.unwrap().is_empty());
+        }
+    }
+
+    mod restore_record {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let mut connector = Connector::default();
+            expect_all_ok(
+                &mut connector,
+                String::from("restore_record \"example.com\""),
+            );
+
+            let mut authorized = Authorized::new(connector);
+            authorized.restore_record("example.com").await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_error_from_server() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(
+                &mut connector,
+                String::from("restore_record \"example.com\""),
+            );
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("Error[Io]: record isn't in trash")));
+
+            let mut authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.restore_record("example.com").await,
+                Err(Error::Server {
+                    code: ErrorCode::Io,
+                    ..
+                })
+            ));
+        }
+    }
+
+    mod purge_trash {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let mut connector = Connector::default();
+            expect_all_ok(&mut connector, String::from("purge_trash"));
+
+            let mut authorized = Authorized::new(connector);
+            authorized.purge_trash().await.unwrap();
+        }
+    }
+
+    mod get_record_history {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(
+                &mut connector,
+                String::from("get_record_history \"example.com\""),
+            );
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("15\n100\nfirst\nnotes11\n200\nsecond\n")));
+
+            let authorized = Authorized::new(connector);
+            let build_version = |version, password: &str, notes: &str| RecordVersion {
+                version,
+                record: Record::builder()
+                    .resource("example.com")
+                    .password(password)
+                    .notes(notes)
+                    .build(),
+            };
+            assert_eq!(
+                authorized.get_record_history("example.com").await.unwrap(),
+                [
+                    build_version(100, "first", "notes"),
+                    build_version(200, "second", "")
+                ]
+            );
+        }
+
+        #[tokio::test]
+        async fn test_encrypted_notes() {
+            let versions = [
+                RecordVersion {
+                    version: 100,
+                    record: Record::builder()
+                        .resource("example.com")
+                        .password("first")
+                        .notes("a1b2c3")
+                        .build()
+                        .with_encrypted_notes(true),
+                },
+                RecordVersion {
+                    version: 200,
+                    record: Record::builder()
+                        .resource("example.com")
+                        .password("second")
+                        .notes("plain notes")
+                        .build(),
+                },
+            ];
+            let response: String = versions
+                .iter()
+                .map(|record_version| {
+                    let version = format!("{}\n{}", record_version.version, record_version.record);
+                    format!("{}\n{version}", version.len())
+                })
+                .collect();
+
+            let mut connector = Connector::default();
+            expect_ok_send_request(
+                &mut connector,
+                String::from("get_record_history \"example.com\""),
+            );
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(move || Ok(response.clone()));
+
+            let authorized = Authorized::new(connector);
+            assert_eq!(
+                authorized.get_record_history("example.com").await.unwrap(),
+                versions
+            );
+        }
+
+        #[tokio::test]
+        async fn test_no_history() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(
+                &mut connector,
+                String::from("get_record_history \"example.com\""),
+            );
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("No history yet")));
+
+            let authorized = Authorized::new(connector);
+            assert!(authorized
+                .get_record_history("example.com")
+                .await
+                .unwrap()
+                .is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_unexpected_response() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(
+                &mut connector,
+                String::from("get_record_history \"example.com\""),
+            );
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("latest\nfirst\nnotes")));
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.get_record_history("example.com").await,
+                Err(Error::UnexpectedResponse { command, .. }) if command == "get_record_history"
+            ));
+        }
+    }
+
+    mod patch_record {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            for (patch, request) in [
+                (
+                    PatchRecord {
+                        password: Some(String::from("secret")),
+                        notes: None,
+                    },
+                    "patch_record \"example.com\" password \"secret\"",
+                ),
+                (
+                    PatchRecord {
+                        password: None,
+                        notes: Some(String::from("\"quoted\"\nnotes")),
+                    },
+                    "patch_record \"example.com\" notes \"\\\"quoted\\\"\nnotes\"",
+                ),
+                (
+                    PatchRecord {
+                        password: Some(String::new()),
+                        notes: Some(String::new()),
+                    },
+                    "patch_record \"example.com\" password \"\" notes \"\"",
+                ),
+            ] {
+                let mut connector = Connector::default();
+                expect_all_ok(&mut connector, String::from(request));
+
+                let mut authorized = Authorized::new(connector);
+                authorized
+                    .patch_record("example.com", &patch)
+                    .await
+                    .unwrap();
+            }
+        }
+
+        #[tokio::test]
+        async fn test_empty_patch() {
+            let mut authorized = Authorized::new(Connector::default());
+            assert!(matches!(
+                authorized
+                    .patch_record("example.com", &PatchRecord::default())
+                    .await,
+                Err(Error::InvalidRequest { .. })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_invalid_resource() {
+            let patch = PatchRecord {
+                notes: Some(String::from("notes")),
+                ..PatchRecord::default()
+            };
+
+            let mut authorized = Authorized::new(Connector::default());
+            assert!(matches!(
+                authorized.patch_record("", &patch).await,
+                Err(Error::InvalidResource { .. })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_record_not_found() {
+            let patch = PatchRecord {
+                notes: Some(String::from("notes")),
+                ..PatchRecord::default()
+            };
+
+            let mut connector = Connector::default();
+            expect_ok_send_request(
+                &mut connector,
+                String::from("patch_record \"example.com\" notes \"notes\""),
+            );
+            connector.expect_recv_response().times(1).returning(|| {
+                Ok(String::from(
+                    "Error[RecordNotFound]: no such record: `example.com`",
+                ))
+            });
+
+            let mut authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.patch_record("example.com", &patch).await,
+                Err(Error::Server {
+                    code: ErrorCode::RecordNotFound,
+                    ..
+                })
+            ));
+        }
+    }
+
+    mod restore_record_version {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let mut connector = Connector::default();
+            expect_all_ok(
+                &mut connector,
+                String::from("restore_record_version \"example.com\" 100"),
+            );
+
+            let mut authorized = Authorized::new(connector);
+            authorized
+                .restore_record_version("example.com", 100)
+                .await
+                .unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_invalid_resource() {
+            let mut authorized = Authorized::new(Connector::default());
+            assert!(matches!(
+                authorized.restore_record_version("", 100).await,
+                Err(Error::InvalidResource { .. })
+            ));
+        }
+    }
+
+    mod get_records_list_sorted {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            for (sort, request) in [
+                (SortOrder::Name, "list_records name"),
+                (SortOrder::NameDesc, "list_records name_desc"),
+                (SortOrder::Updated, "list_records updated"),
+                (SortOrder::Created, "list_records created"),
+            ] {
+                let mut connector = Connector::default();
+                expect_ok_send_request(&mut connector, String::from(request));
+                connector
+                    .expect_recv_response()
+                    .times(1)
+                    .returning(|| Ok(String::from("test.ru\nexample.com")));
+
+                let authorized = Authorized::new(connector);
+                assert_eq!(
+                    authorized.get_records_list_sorted(sort).await.unwrap(),
+                    ["test.ru", "example.com"]
+                );
+            }
+        }
 
-        Ok(())
-    }
+        #[tokio::test]
+        async fn test_no_records() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, String::from("list_records updated"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("No records yet")));
 
-    /// See [`utils::read_ok_response()`]
-    async fn read_ok_response(&mut self) -> Result<()> {
-        utils::read_ok_response(self.connector.get_mut()).await
+            let authorized = Authorized::new(connector);
+            assert!(authorized
+                .get_records_list_sorted(SortOrder::Updated)
+                .await
+                .unwrap()
+                .is_empty());
+        }
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use mockall::predicate::*;
-    use std::io;
 
-    /// Tests for `Authorized::add_record()`
-    mod add_record {
+    mod get_records_page {
         use super::*;
 
         #[tokio::test]
-        async fn test_ok() {
-            let record = build_record();
+        async fn test_first_page() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, String::from("list_records 0 2"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("5\nexample.com\ntest.ru")));
+
+            let authorized = Authorized::new(connector);
+            assert_eq!(
+                authorized.get_records_page(0, 2).await.unwrap(),
+                (
+                    vec![String::from("example.com"), String::from("test.ru")],
+                    5
+                )
+            );
+        }
 
+        #[tokio::test]
+        async fn test_middle_page() {
             let mut connector = Connector::default();
-            expect_all_ok(&mut connector, build_request(&record));
+            expect_ok_send_request(&mut connector, String::from("list_records 2 2"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("5\nwikipedia.org\nyoutube.com")));
 
-            let mut authorized = Authorized::new(connector);
-            authorized.add_record(&record).await.unwrap();
+            let authorized = Authorized::new(connector);
+            assert_eq!(
+                authorized.get_records_page(2, 2).await.unwrap(),
+                (
+                    vec![String::from("wikipedia.org"), String::from("youtube.com")],
+                    5
+                )
+            );
         }
 
         #[tokio::test]
-        async fn test_invalid_resource() {
-            let record = Record {
-                resource: String::default(),
-                password: String::from("secret"),
-                notes: String::from("notes"),
-            };
+        async fn test_out_of_range_offset() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, String::from("list_records 10 2"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("5")));
 
-            let connector = Connector::default();
+            let authorized = Authorized::new(connector);
+            assert_eq!(
+                authorized.get_records_page(10, 2).await.unwrap(),
+                (vec![], 5)
+            );
+        }
 
-            let mut authorized = Authorized::new(connector);
+        #[tokio::test]
+        async fn test_unexpected_response() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, String::from("list_records 0 2"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("No records yet")));
+
+            let authorized = Authorized::new(connector);
             assert!(matches!(
-                authorized.add_record(&record).await,
-                Err(Error::InvalidResource { .. })
+                authorized.get_records_page(0, 2).await,
+                Err(Error::UnexpectedResponse { command, .. }) if command == "list_records 0 2"
             ));
         }
+    }
+
+    /// Tests for `Authorized::export_records()`
+    mod export_records {
+        use super::*;
+        use mockall::Sequence;
 
         #[tokio::test]
-        async fn test_cant_send_request() {
-            let record = build_record();
+        async fn test_ok() {
+            let mut connector = Connector::default();
+            let mut seq = Sequence::new();
+            expect_request(
+                &mut connector,
+                &mut seq,
+                "list_records",
+                "first.com\nsecond.com",
+            );
+            expect_request(
+                &mut connector,
+                &mut seq,
+                "show_record \"first.com\"",
+                "first_secret\nfirst notes",
+            );
+            expect_request(
+                &mut connector,
+                &mut seq,
+                "show_record \"second.com\"",
+                "second_secret\n",
+            );
+
+            let authorized = Authorized::new(connector);
+            assert_eq!(
+                authorized.export_records().await.unwrap(),
+                [
+                    Record {
+                        resource: String::from("first.com"),
+                        password: String::from("first_secret"),
+                        notes: String::from("first notes"),
+                        encrypted_notes: false,
+                    },
+                    Record {
+                        resource: String::from("second.com"),
+                        password: String::from("second_secret"),
+                        notes: String::new(),
+                        encrypted_notes: false,
+                    }
+                ]
+            );
+        }
 
+        #[tokio::test]
+        async fn test_no_records() {
             let mut connector = Connector::default();
-            expect_failing_send_request(&mut connector, build_request(&record));
+            let mut seq = Sequence::new();
+            expect_request(&mut connector, &mut seq, "list_records", "No records yet");
 
-            let mut authorized = Authorized::new(connector);
+            let authorized = Authorized::new(connector);
+            assert!(authorized.export_records().await.unwrap().is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_error_from_server() {
+            let mut connector = Connector::default();
+            let mut seq = Sequence::new();
+            expect_request(&mut connector, &mut seq, "list_records", "first.com");
+            expect_request(
+                &mut connector,
+                &mut seq,
+                "show_record \"first.com\"",
+                "Error: some internal error",
+            );
+
+            let authorized = Authorized::new(connector);
             assert!(matches!(
-                authorized.add_record(&record).await,
-                Err(Error::Io(_))
+                authorized.export_records().await,
+                Err(Error::Server { mes, .. }) if mes == "some internal error"
             ));
         }
 
-        #[tokio::test]
-        async fn test_cant_recv_response() {
-            let record = build_record();
+        /// Expect `connector` to receive `request` and respond with `response`
+        fn expect_request(
+            connector: &mut Connector,
+            seq: &mut Sequence,
+            request: &str,
+            response: &'static str,
+        ) {
+            connector
+                .expect_send_request()
+                .with(eq(String::from(request)))
+                .times(1)
+                .in_sequence(seq)
+                .returning(|_| Ok(()));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .in_sequence(seq)
+                .returning(move || Ok(String::from(response)));
+        }
+    }
+
+    /// Tests for `Authorized::import_records()`
+    mod import_records {
+        use super::*;
 
+        #[tokio::test]
+        async fn test_skip_existing() {
+            let records = build_records();
             let mut connector = Connector::default();
-            expect_failing_recv_response(&mut connector, build_request(&record));
+            expect_records_list(&mut connector);
+            expect_all_ok(&mut connector, build_request(&records[1]));
 
             let mut authorized = Authorized::new(connector);
-            assert!(matches!(
-                authorized.add_record(&record).await,
-                Err(Error::InvalidResponseEncoding(_))
-            ));
+            let summary = authorized.import_records(&records, false).await.unwrap();
+            assert_eq!(summary.added, ["new.com"]);
+            assert_eq!(summary.skipped, ["existing.com"]);
+            assert!(summary.failed.is_empty());
         }
 
         #[tokio::test]
-        async fn test_unexpected_response() {
-            let record = build_record();
+        async fn test_overwrite_existing() {
+            let records = build_records();
+            let mut connector = Connector::default();
+            expect_records_list(&mut connector);
+            connector
+                .expect_send_request()
+                .with(in_iter([
+                    build_request(&records[0]),
+                    build_request(&records[1]),
+                ]))
+                .times(2)
+                .returning(|_| Ok(()));
+            connector
+                .expect_recv_response()
+                .times(2)
+                .returning(|| Ok(String::from("Ok")));
 
+            let mut authorized = Authorized::new(connector);
+            let summary = authorized.import_records(&records, true).await.unwrap();
+            assert_eq!(summary.added, ["existing.com", "new.com"]);
+            assert!(summary.skipped.is_empty());
+            assert!(summary.failed.is_empty());
+        }
+
+        #[tokio::test]
+        async fn test_failed_record() {
+            let mut records = build_records();
+            records.insert(
+                0,
+                Record {
+                    resource: String::from("../unsafe"),
+                    password: String::from("secret"),
+                    notes: String::new(),
+                    encrypted_notes: false,
+                },
+            );
             let mut connector = Connector::default();
-            expect_ok_send_request(&mut connector, build_request(&record));
+            expect_records_list(&mut connector);
+            expect_ok_send_request(&mut connector, build_request(&records[0]));
             connector
                 .expect_recv_response()
                 .times(1)
-                .returning(|| Ok(String::from("Record successfully added")));
+                .returning(|| Ok(String::from("Error: invalid resource name")));
+            expect_all_ok(&mut connector, build_request(&records[2]));
 
             let mut authorized = Authorized::new(connector);
+            let summary = authorized.import_records(&records, false).await.unwrap();
+            assert_eq!(summary.added, ["new.com"]);
+            assert_eq!(summary.skipped, ["existing.com"]);
             assert!(matches!(
-                authorized.add_record(&record).await,
-                Err(Error::UnexpectedResponse { response })
-                    if response == "Record successfully added"
+                summary.failed.as_slice(),
+                [(resource, Error::Server { mes, .. })]
+                    if resource == "../unsafe" && mes == "invalid resource name"
             ));
         }
 
-        /// Builds test record
-        fn build_record() -> Record {
-            Record {
-                resource: String::from("test.com"),
-                password: String::from("secret"),
-                notes: String::from("important notes"),
-            }
+        fn expect_records_list(connector: &mut Connector) {
+            expect_ok_send_request(connector, String::from("list_records"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("existing.com\nother.com")));
+        }
+
+        fn build_records() -> Vec<Record> {
+            vec![
+                Record {
+                    resource: String::from("existing.com"),
+                    password: String::from("secret"),
+                    notes: String::from("notes"),
+                    encrypted_notes: false,
+                },
+                Record {
+                    resource: String::from("new.com"),
+                    password: String::from("new_secret"),
+                    notes: String::new(),
+                    encrypted_notes: false,
+                },
+            ]
         }
 
-        /// Build expected request for `record`
         fn build_request(record: &Record) -> String {
-            format!("new_record {} \"{}\"", record.resource, record.to_string())
+            format!("new_record \"{}\" \"{}\"", record.resource, record)
         }
     }
 
-    /// Tests for `Authorized::delete_record()`
-    mod delete_record {
+    /// Tests for `Authorized::change_pub_key()`
+    mod change_pub_key {
         use super::*;
+        use mockall::Sequence;
+
+        const CONFIRMATION: &str = "confirmation";
 
         #[tokio::test]
         async fn test_ok() {
-            let resource = "test_resource";
+            let (new_pub_key, new_sec_key) = Key::generate_pair();
+            let (server_pub_key, _) = Key::generate_pair();
+            let expected_confirmation = server_pub_key.encrypt(&new_sec_key.decrypt(CONFIRMATION));
 
             let mut connector = Connector::default();
-            expect_all_ok(&mut connector, String::from("delete_record test_resource"));
+            let mut seq = Sequence::new();
+            expect_change_pub_key_request(&mut connector, &mut seq, &new_pub_key);
+            connector
+                .expect_server_pub_key()
+                .times(1)
+                .return_const(server_pub_key);
+            connector
+                .expect_send_request()
+                .with(eq(format!(
+                    "confirm_change_pub_key {}",
+                    expected_confirmation
+                )))
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|_| Ok(()));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|| Ok(String::from("Ok")));
 
             let mut authorized = Authorized::new(connector);
-            authorized.delete_record(resource).await.unwrap();
+            authorized
+                .change_pub_key(&new_pub_key, &new_sec_key)
+                .await
+                .unwrap();
         }
 
         #[tokio::test]
-        async fn test_invalid_resource() {
-            let resource = "";
+        async fn test_error_in_change_pub_key_response() {
+            let (new_pub_key, new_sec_key) = Key::generate_pair();
 
-            let connector = Connector::default();
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, format!("change_pub_key {}", new_pub_key));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("Error: invalid key")));
 
             let mut authorized = Authorized::new(connector);
             assert!(matches!(
-                authorized.delete_record(resource).await,
-                Err(Error::InvalidResource { .. })
+                authorized.change_pub_key(&new_pub_key, &new_sec_key).await,
+                Err(Error::Server { mes, .. }) if mes == "invalid key"
             ));
         }
 
         #[tokio::test]
-        async fn test_cant_send_request() {
-            let resource = "test_resource";
+        async fn test_error_in_confirm_response() {
+            let (new_pub_key, new_sec_key) = Key::generate_pair();
+            let (server_pub_key, _) = Key::generate_pair();
 
             let mut connector = Connector::default();
-            expect_failing_send_request(
-                &mut connector,
-                String::from("delete_record test_resource"),
-            );
+            let mut seq = Sequence::new();
+            expect_change_pub_key_request(&mut connector, &mut seq, &new_pub_key);
+            connector
+                .expect_server_pub_key()
+                .times(1)
+                .return_const(server_pub_key);
+            connector
+                .expect_send_request()
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|_| Ok(()));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|| Ok(String::from("Error: invalid confirmation string")));
 
             let mut authorized = Authorized::new(connector);
             assert!(matches!(
-                authorized.delete_record(resource).await,
-                Err(Error::Io(_))
+                authorized.change_pub_key(&new_pub_key, &new_sec_key).await,
+                Err(Error::Server { mes, .. }) if mes == "invalid confirmation string"
             ));
         }
 
+        /// Expect `connector` to successfully send *change_pub_key* request with `new_pub_key`
+        /// and to receive `CONFIRMATION` as response
+        fn expect_change_pub_key_request(
+            connector: &mut Connector,
+            seq: &mut Sequence,
+            new_pub_key: &Key,
+        ) {
+            connector
+                .expect_send_request()
+                .with(eq(format!("change_pub_key {}", new_pub_key)))
+                .times(1)
+                .in_sequence(seq)
+                .returning(|_| Ok(()));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .in_sequence(seq)
+                .returning(|| Ok(String::from(CONFIRMATION)));
+        }
+    }
+
+    mod quit {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let mut connector = Connector::default();
+            connector.expect_quit().times(1).returning(|| Ok(()));
+
+            let authorized = Authorized::new(connector);
+            authorized.quit().await.unwrap();
+        }
+
         #[tokio::test]
-        async fn test_cant_recv_response() {
-            let resource = "test_resource";
+        async fn test_unexpected_response() {
+            let mut connector = Connector::default();
+            connector.expect_quit().times(1).returning(|| {
+                Err(Error::UnexpectedResponse {
+                    command: String::from("quit"),
+                    response: String::from("Goodbye"),
+                })
+            });
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(
+                authorized.quit().await,
+                Err(Error::UnexpectedResponse { .. })
+            ));
+        }
+    }
+
+    mod iter_records {
+        use super::*;
+
+        /// Expect `connector` to stream `responses` to the *stream_records* request
+        fn expect_stream(connector: &mut Connector, responses: &[&str]) {
+            expect_ok_send_request(connector, String::from("stream_records"));
+            let mut responses: Vec<_> = responses.iter().map(|&s| s.to_owned()).collect();
+            responses.reverse();
+            connector
+                .expect_recv_response()
+                .times(responses.len())
+                .returning(move || Ok(responses.pop().unwrap()));
+        }
 
+        #[tokio::test]
+        async fn test_ok() {
             let mut connector = Connector::default();
-            expect_failing_recv_response(
+            expect_stream(
                 &mut connector,
-                String::from("delete_record test_resource"),
+                &["example.com", "test.ru", "youtube.com", ""],
             );
 
-            let mut authorized = Authorized::new(connector);
+            let authorized = Authorized::new(connector);
+            let mut names = authorized.iter_records().await.unwrap();
+            for expected in ["example.com", "test.ru", "youtube.com"] {
+                assert_eq!(names.next().await.unwrap().unwrap(), expected);
+            }
+            assert!(names.next().await.is_none());
+            assert!(names.next().await.is_none());
+        }
+
+        #[tokio::test]
+        async fn test_no_records() {
+            let mut connector = Connector::default();
+            expect_stream(&mut connector, &[""]);
+
+            let authorized = Authorized::new(connector);
+            let mut names = authorized.iter_records().await.unwrap();
+            assert!(names.next().await.is_none());
+        }
+
+        #[tokio::test]
+        async fn test_error() {
+            let mut connector = Connector::default();
+            expect_stream(&mut connector, &["Error[not_authorized]: not logged in"]);
+
+            let authorized = Authorized::new(connector);
+            let mut names = authorized.iter_records().await.unwrap();
             assert!(matches!(
-                authorized.delete_record(resource).await,
-                Err(Error::InvalidResponseEncoding(_))
+                names.next().await,
+                Some(Err(Error::Server { .. }))
             ));
+            assert!(names.next().await.is_none());
+        }
+
+        #[tokio::test]
+        async fn test_dropped_before_end() {
+            let mut connector = Connector::default();
+            expect_stream(&mut connector, &["example.com"]);
+            connector.expect_skip_stream().times(1).return_const(());
+
+            let authorized = Authorized::new(connector);
+            let mut names = authorized.iter_records().await.unwrap();
+            assert_eq!(names.next().await.unwrap().unwrap(), "example.com");
+        }
+
+        #[tokio::test]
+        async fn test_cant_send_request() {
+            let mut connector = Connector::default();
+            expect_failing_send_request(&mut connector, String::from("stream_records"));
+
+            let authorized = Authorized::new(connector);
+            assert!(matches!(authorized.iter_records().await, Err(Error::Io(_))));
+        }
+    }
+
+    mod count_records {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            for count in ["0", "1", "42"] {
+                let mut connector = Connector::default();
+                expect_ok_send_request(&mut connector, String::from("count_records"));
+                connector
+                    .expect_recv_response()
+                    .times(1)
+                    .returning(move || Ok(count.to_owned()));
+
+                let authorized = Authorized::new(connector);
+                assert_eq!(
+                    authorized.count_records().await.unwrap(),
+                    count.parse::<usize>().unwrap()
+                );
+            }
         }
 
         #[tokio::test]
         async fn test_unexpected_response() {
-            let resource = "test_resource";
-
             let mut connector = Connector::default();
-            expect_ok_send_request(&mut connector, String::from("delete_record test_resource"));
+            expect_ok_send_request(&mut connector, String::from("count_records"));
             connector
                 .expect_recv_response()
                 .times(1)
-                .returning(|| Ok(String::from("Record successfully deleted")));
+                .returning(|| Ok(String::from("No records yet")));
 
-            let mut authorized = Authorized::new(connector);
+            let authorized = Authorized::new(connector);
             assert!(matches!(
-                authorized.delete_record(resource).await,
-                Err(Error::UnexpectedResponse { response })
-                    if response == "Record successfully deleted"
+                authorized.count_records().await,
+                Err(Error::UnexpectedResponse { command, response })
+                    if command == "count_records" && response == "No records yet"
             ));
         }
     }
 
-    /// Tests for `Authorized::get_record()`
-    mod get_record {
+    mod stats {
         use super::*;
 
         #[tokio::test]
         async fn test_ok() {
-            let resource = "test_resource";
-
-            let record = Record {
-                resource: resource.to_string(),
-                password: String::from("secret"),
-                notes: String::from("notes"),
-            };
-            let record_str = record.to_string();
-
             let mut connector = Connector::default();
-            expect_ok_send_request(&mut connector, format!("show_record {}", resource));
+            expect_ok_send_request(&mut connector, String::from("stats"));
             connector
                 .expect_recv_response()
                 .times(1)
-                .return_once(move || Ok(record_str));
+                .returning(|| Ok(String::from("2\n128")));
 
             let authorized = Authorized::new(connector);
             assert_eq!(
-                authorized.get_record(resource.to_string()).await.unwrap(),
-                record
+                authorized.stats().await.unwrap(),
+                UserStats {
+                    records: 2,
+                    bytes: 128
+                }
             );
         }
 
         #[tokio::test]
-        async fn test_invalid_resource() {
-            let resource = String::default();
-
-            let connector = Connector::default();
+        async fn test_unexpected_response() {
+            let mut connector = Connector::default();
+            expect_ok_send_request(&mut connector, String::from("stats"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("2 records")));
 
             let authorized = Authorized::new(connector);
             assert!(matches!(
-                authorized.get_record(resource).await,
-                Err(Error::InvalidResource { .. })
+                authorized.stats().await,
+                Err(Error::UnexpectedResponse { command, response })
+                    if command == "stats" && response == "2 records"
             ));
         }
+    }
 
-        #[tokio::test]
-        async fn test_cant_send_request() {
-            let resource = String::from("test_resource");
+    mod whoami {
+        use super::*;
 
+        #[tokio::test]
+        async fn test_ok() {
             let mut connector = Connector::default();
-            expect_failing_send_request(&mut connector, format!("show_record {}", resource));
+            expect_ok_send_request(&mut connector, String::from("whoami"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("test_user")));
 
             let authorized = Authorized::new(connector);
-            assert!(matches!(
-                authorized.get_record(resource).await,
-                Err(Error::Io(_))
-            ));
+            assert_eq!(authorized.whoami().await.unwrap(), "test_user");
         }
+    }
 
-        #[tokio::test]
-        async fn test_cant_recv_response() {
-            let resource = String::from("test_resource");
+    mod help {
+        use super::*;
 
+        #[tokio::test]
+        async fn test_ok() {
             let mut connector = Connector::default();
-            expect_failing_recv_response(&mut connector, format!("show_record {}", resource));
+            expect_ok_send_request(&mut connector, String::from("help"));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("list_records\nwhoami\nls -> list_records")));
 
             let authorized = Authorized::new(connector);
-            assert!(matches!(
-                authorized.get_record(resource).await,
-                Err(Error::InvalidResponseEncoding(_))
-            ));
+            assert_eq!(
+                authorized.help().await.unwrap(),
+                ["list_records", "whoami", "ls -> list_records"]
+            );
         }
+    }
 
-        #[tokio::test]
-        async fn test_error_from_server() {
-            let resource = String::from("test_resource");
+    mod issue_session_token {
+        use super::*;
 
+        #[tokio::test]
+        async fn test_ok() {
             let mut connector = Connector::default();
-            expect_ok_send_request(&mut connector, format!("show_record {}", resource));
+            expect_ok_send_request(&mut connector, String::from("issue_session_token"));
             connector
                 .expect_recv_response()
                 .times(1)
-                .returning(|| Ok(String::from("Error: no such record")));
+                .returning(|| Ok(String::from("token")));
 
-            let authorized = Authorized::new(connector);
-            assert!(matches!(
-                authorized.get_record(resource).await,
-                Err(Error::Server { mes }) if mes == "no such record"
-            ));
+            let mut authorized = Authorized::new(connector);
+            assert_eq!(authorized.issue_session_token().await.unwrap(), "token");
         }
 
         #[tokio::test]
-        async fn test_wrong_record_format() {
-            let resource = String::from("test_resource");
-
+        async fn test_unexpected_response() {
             let mut connector = Connector::default();
-            expect_ok_send_request(&mut connector, format!("show_record {}", resource));
+            expect_ok_send_request(&mut connector, String::from("issue_session_token"));
             connector
                 .expect_recv_response()
                 .times(1)
-                .returning(|| Ok(String::from("secret, notes")));
+                .returning(|| Ok(String::new()));
 
-            let authorized = Authorized::new(connector);
+            let mut authorized = Authorized::new(connector);
             assert!(matches!(
-                authorized.get_record(resource).await,
-                Err(Error::CantParseRecord(_))
+                authorized.issue_session_token().await,
+                Err(Error::UnexpectedResponse { .. })
             ));
         }
     }
 
-    /// Tests for `Authorized::get_records_list()`
-    mod get_records_list {
+    mod user_exists {
         use super::*;
 
         #[tokio::test]
         async fn test_ok() {
             let mut connector = Connector::default();
-            expect_ok_send_request(&mut connector, String::from("list_records"));
+            expect_ok_send_request(&mut connector, String::from("user_exists test_user"));
             connector
                 .expect_recv_response()
                 .times(1)
-                .return_once(move || Ok(String::from("example.com\ntest.ru\nyoutube.com")));
-
-            let expected_list: Vec<String> = ["example.com", "test.ru", "youtube.com"]
-                .into_iter()
-                .map(ToOwned::to_owned)
-                .collect();
+                .returning(|| Ok(String::from("true")));
 
             let authorized = Authorized::new(connector);
-            assert_eq!(authorized.get_records_list().await.unwrap(), expected_list);
+            assert!(authorized.user_exists("test_user").await.unwrap());
         }
 
         #[tokio::test]
-        async fn test_no_records() {
+        async fn test_permission_denied() {
             let mut connector = Connector::default();
-            expect_ok_send_request(&mut connector, String::from("list_records"));
+            expect_ok_send_request(&mut connector, String::from("user_exists test_user"));
             connector
                 .expect_recv_response()
                 .times(1)
-                .return_once(move || Ok(String::from("No records yet")));
+                .returning(|| Ok(String::from("Error[PermissionDenied]: permission denied")));
 
             let authorized = Authorized::new(connector);
-            assert!(authorized.get_records_list().await.unwrap().is_empty());
+            assert!(matches!(
+                authorized.user_exists("test_user").await,
+                Err(Error::Server {
+                    code: ErrorCode::PermissionDenied,
+                    ..
+                })
+            ));
         }
 
         #[tokio::test]
-        async fn test_cant_send_request() {
-            let mut connector = Connector::default();
-            expect_failing_send_request(&mut connector, String::from("list_records"));
-
-            let authorized = Authorized::new(connector);
-            assert!(matches!(
-                authorized.get_records_list().await,
-                Err(Error::Io(_))
-            ));
+        async fn test_invalid_username() {
+            let authorized = Authorized::new(Connector::default());
+            for username in ["", "test user", "admins"] {
+                assert!(matches!(
+                    authorized.user_exists(username).await,
+                    Err(Error::InvalidRequest { .. })
+                ));
+            }
         }
+    }
+
+    mod rename_me {
+        use super::*;
 
         #[tokio::test]
-        async fn test_cant_recv_response() {
+        async fn test_ok() {
             let mut connector = Connector::default();
-            expect_failing_recv_response(&mut connector, String::from("list_records"));
+            expect_all_ok(&mut connector, String::from("rename_me new_user"));
 
-            let authorized = Authorized::new(connector);
-            assert!(matches!(
-                authorized.get_records_list().await,
-                Err(Error::InvalidResponseEncoding(_))
-            ));
+            let mut authorized = Authorized::new(connector);
+            authorized.rename_me("new_user").await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_invalid_username() {
+            let mut authorized = Authorized::new(Connector::default());
+            for username in [
+                "",
+                "new user",
+                "admins",
+                &"x".repeat(crate::name::MAX_LENGTH + 1),
+            ] {
+                assert!(matches!(
+                    authorized.rename_me(username).await,
+                    Err(Error::InvalidRequest { .. })
+                ));
+            }
         }
 
         #[tokio::test]
         async fn test_error_from_server() {
             let mut connector = Connector::default();
-            expect_ok_send_request(&mut connector, String::from("list_records"));
-            connector
-                .expect_recv_response()
-                .times(1)
-                .returning(|| Ok(String::from("Error: some internal error")));
+            expect_ok_send_request(&mut connector, String::from("rename_me new_user"));
+            connector.expect_recv_response().times(1).returning(|| {
+                Ok(String::from(
+                    "Error[UserAlreadyExists]: user new_user already exists",
+                ))
+            });
 
-            let authorized = Authorized::new(connector);
+            let mut authorized = Authorized::new(connector);
             assert!(matches!(
-                authorized.get_records_list().await,
-                Err(Error::Server { mes }) if mes == "some internal error"
+                authorized.rename_me("new_user").await,
+                Err(Error::Server {
+                    code: ErrorCode::UserAlreadyExists,
+                    ..
+                })
             ));
         }
     }
@@ -678,11 +3262,313 @@ mod tests {
             assert!(matches!(
                 authorized.delete_me().await,
                 Err(DeleteMeError {
-                    source: Error::UnexpectedResponse { response },
+                    source: Error::UnexpectedResponse { command, response },
                     ..
-                }) if response == "You were successfully deleted"
+                }) if command == "delete_me" && response == "You were successfully deleted"
+            ));
+        }
+    }
+
+    /// Tests for concurrent usage of `Authorized`
+    mod concurrency {
+        use super::*;
+        use mockall::Sequence;
+
+        #[tokio::test]
+        async fn test_concurrent_requests() {
+            let mut seq = Sequence::new();
+            let mut connector = Connector::default();
+            for (request, response) in [("list_records", "first"), ("stats", "1\n42")] {
+                connector
+                    .expect_send_request()
+                    .with(eq(String::from(request)))
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .returning(|_| Ok(()));
+                connector
+                    .expect_recv_response()
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .returning(move || Ok(String::from(response)));
+            }
+
+            let authorized = Authorized::new(connector);
+            let (records, stats) = tokio::join!(authorized.get_records_list(), authorized.stats());
+            assert_eq!(records.unwrap(), ["first"]);
+            assert_eq!(
+                stats.unwrap(),
+                UserStats {
+                    records: 1,
+                    bytes: 42
+                }
+            );
+        }
+
+        #[test]
+        fn test_futures_are_send() {
+            fn assert_send<T: Send>(_: T) {}
+
+            let authorized = Authorized::new(Connector::default());
+            assert_send(authorized.get_records_list());
+            assert_send(authorized.get_record(String::from("resource")));
+            assert_send(authorized.stats());
+        }
+    }
+
+    /// Tests for retrying requests after reconnection
+    mod retries {
+        use super::*;
+        use mockall::Sequence;
+        use num_bigint::ToBigUint;
+
+        const TEST_USER: &str = "test_user";
+        const CONFIRMATION: &str = "confirmation";
+
+        #[tokio::test]
+        async fn test_retry_after_disconnect() {
+            let (server_pub_key, pub_key, sec_key) = generate_keys();
+            let mut seq = Sequence::new();
+            let mut connector = Connector::default();
+            expect_disconnect(&mut connector, &mut seq, "list_records");
+            connector
+                .expect_reconnect()
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|| Ok(()));
+            expect_login(&mut connector, &mut seq, server_pub_key, pub_key);
+            expect_response(&mut connector, &mut seq, "list_records", "first\nsecond");
+
+            let mut authorized = Authorized::with_credentials(connector, TEST_USER, &sec_key);
+            authorized.set_retry_policy(RetryPolicy {
+                max_retries: 1,
+                retry_non_idempotent: false,
+            });
+            assert_eq!(
+                authorized.get_records_list().await.unwrap(),
+                ["first", "second"]
+            );
+        }
+
+        #[tokio::test]
+        async fn test_ping_retried() {
+            let (server_pub_key, pub_key, sec_key) = generate_keys();
+            let mut seq = Sequence::new();
+            let mut connector = Connector::default();
+            expect_disconnect(&mut connector, &mut seq, "ping");
+            connector
+                .expect_reconnect()
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|| Ok(()));
+            expect_login(&mut connector, &mut seq, server_pub_key, pub_key);
+            expect_response(&mut connector, &mut seq, "ping", "pong");
+
+            let mut authorized = Authorized::with_credentials(connector, TEST_USER, &sec_key);
+            authorized.set_retry_policy(RetryPolicy {
+                max_retries: 1,
+                retry_non_idempotent: false,
+            });
+            authorized.ping().await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_retries_disabled() {
+            let (_, _, sec_key) = generate_keys();
+            let mut seq = Sequence::new();
+            let mut connector = Connector::default();
+            expect_disconnect(&mut connector, &mut seq, "list_records");
+
+            let authorized = Authorized::with_credentials(connector, TEST_USER, &sec_key);
+            assert!(matches!(
+                authorized.get_records_list().await,
+                Err(Error::ConnectionClosed)
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_retries_exhausted() {
+            let (server_pub_key, pub_key, sec_key) = generate_keys();
+            let mut seq = Sequence::new();
+            let mut connector = Connector::default();
+            expect_disconnect(&mut connector, &mut seq, "stats");
+            connector
+                .expect_reconnect()
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|| Ok(()));
+            expect_login(&mut connector, &mut seq, server_pub_key, pub_key);
+            expect_disconnect(&mut connector, &mut seq, "stats");
+
+            let mut authorized = Authorized::with_credentials(connector, TEST_USER, &sec_key);
+            authorized.set_retry_policy(RetryPolicy {
+                max_retries: 1,
+                retry_non_idempotent: false,
+            });
+            assert!(matches!(
+                authorized.stats().await,
+                Err(Error::ConnectionClosed)
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_non_idempotent_not_retried() {
+            let (_, _, sec_key) = generate_keys();
+            let mut seq = Sequence::new();
+            let mut connector = Connector::default();
+            expect_disconnect(&mut connector, &mut seq, "delete_record \"example.com\"");
+
+            let mut authorized = Authorized::with_credentials(connector, TEST_USER, &sec_key);
+            authorized.set_retry_policy(RetryPolicy {
+                max_retries: 1,
+                retry_non_idempotent: false,
+            });
+            assert!(matches!(
+                authorized.delete_record("example.com").await,
+                Err(Error::ConnectionClosed)
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_non_idempotent_retried() {
+            let (server_pub_key, pub_key, sec_key) = generate_keys();
+            let request = "delete_record \"example.com\"";
+            let mut seq = Sequence::new();
+            let mut connector = Connector::default();
+            expect_disconnect(&mut connector, &mut seq, request);
+            connector
+                .expect_reconnect()
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|| Ok(()));
+            expect_login(&mut connector, &mut seq, server_pub_key, pub_key);
+            expect_response(&mut connector, &mut seq, request, "Ok");
+
+            let mut authorized = Authorized::with_credentials(connector, TEST_USER, &sec_key);
+            authorized.set_retry_policy(RetryPolicy {
+                max_retries: 1,
+                retry_non_idempotent: true,
+            });
+            authorized.delete_record("example.com").await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_cant_reconnect() {
+            let (_, _, sec_key) = generate_keys();
+            let mut seq = Sequence::new();
+            let mut connector = Connector::default();
+            expect_disconnect(&mut connector, &mut seq, "list_records");
+            connector
+                .expect_reconnect()
+                .times(1)
+                .in_sequence(&mut seq)
+                .returning(|| {
+                    Err(Error::CantConnectToTheServer {
+                        addr: String::from("127.0.0.1:3747"),
+                        source: std::io::ErrorKind::ConnectionRefused.into(),
+                    })
+                });
+
+            let mut authorized = Authorized::with_credentials(connector, TEST_USER, &sec_key);
+            authorized.set_retry_policy(RetryPolicy {
+                max_retries: 3,
+                retry_non_idempotent: false,
+            });
+            assert!(matches!(
+                authorized.get_records_list().await,
+                Err(Error::CantConnectToTheServer { .. })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_reconnect_without_credentials() {
+            let mut authorized = Authorized::new(Connector::default());
+            assert!(matches!(
+                authorized.reconnect().await,
+                Err(Error::InvalidRequest { .. })
             ));
         }
+
+        /// Expects `request` to be sent and connection to be closed before response
+        fn expect_disconnect(connector: &mut Connector, seq: &mut Sequence, request: &str) {
+            connector
+                .expect_send_request()
+                .with(eq(request.to_owned()))
+                .times(1)
+                .in_sequence(seq)
+                .returning(|_| Ok(()));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .in_sequence(seq)
+                .returning(|| Err(Error::ConnectionClosed));
+        }
+
+        /// Expects `request` to be sent and answered with `response`
+        fn expect_response(
+            connector: &mut Connector,
+            seq: &mut Sequence,
+            request: &str,
+            response: &'static str,
+        ) {
+            connector
+                .expect_send_request()
+                .with(eq(request.to_owned()))
+                .times(1)
+                .in_sequence(seq)
+                .returning(|_| Ok(()));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .in_sequence(seq)
+                .returning(move || Ok(String::from(response)));
+        }
+
+        /// Expects login handshake of `TEST_USER`
+        fn expect_login(
+            connector: &mut Connector,
+            seq: &mut Sequence,
+            server_pub_key: Key,
+            pub_key: Key,
+        ) {
+            connector
+                .expect_send_request()
+                .with(eq(format!("login {}", TEST_USER)))
+                .times(1)
+                .in_sequence(seq)
+                .returning(|_| Ok(()));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .in_sequence(seq)
+                .returning(move || Ok(pub_key.encrypt(CONFIRMATION)));
+            connector
+                .expect_server_pub_key()
+                .times(1)
+                .in_sequence(seq)
+                .return_const(server_pub_key);
+            connector
+                .expect_send_request()
+                .with(function(|request: &String| {
+                    request.starts_with("confirm_login ")
+                }))
+                .times(1)
+                .in_sequence(seq)
+                .returning(|_| Ok(()));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .in_sequence(seq)
+                .returning(|| Ok(String::from("Ok")));
+        }
+
+        /// Generates server public key and user's public and secret keys
+        fn generate_keys() -> (Key, Key, Key) {
+            let server_pub_key = Key(11.to_biguint().unwrap(), 22.to_biguint().unwrap());
+            let pub_key = Key(269.to_biguint().unwrap(), 221.to_biguint().unwrap());
+            let sec_key = Key(5.to_biguint().unwrap(), 221.to_biguint().unwrap());
+
+            (server_pub_key, pub_key, sec_key)
+        }
     }
 
     /// Expect `connector` to have successful `send_request()` with `request` as expected request
@@ -710,7 +3596,7 @@ mod tests {
             .expect_send_request()
             .with(eq(request))
             .times(1)
-            .returning(|_| Err(Error::Io(io::Error::new(io::ErrorKind::Other, ""))));
+            .returning(|_| Err(Error::Io(io::Error::other(""))));
     }
 
     /// Expect `connector` to have successful `send_request()` with `request` as expected request
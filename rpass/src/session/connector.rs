@@ -1,10 +1,6 @@
+use super::transport::Transport;
 use crate::key::Key;
-use crate::{Error, Result};
-
-use tokio::{
-    io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader},
-    net::{tcp, TcpStream},
-};
+use crate::{compression, Error, Result};
 
 use std::str::FromStr;
 
@@ -14,225 +10,769 @@ use mockall::automock;
 /// Connector that interacts with *rpass_db*
 #[derive(Debug)]
 pub struct Connector {
-    _stream: Box<TcpStream>,
-    reader: BufReader<tcp::ReadHalf<'static>>,
-    writer: tcp::WriteHalf<'static>,
+    transport: Box<dyn Transport>,
     server_pub_key: Key,
+    server_version: Option<ServerVersion>,
+    server_info: Option<ServerInfo>,
+    /// If large messages are compressed, see [`compression`]
+    compression: bool,
+    /// Server pub key known in advance, see [`Connector::with_pinned_key()`]
+    pinned_key: Option<Key>,
+    /// If rest of the streamed response should be skipped, see
+    /// [`Connector::skip_stream()`]
+    skip_stream: bool,
 }
 
-/// End of transmission character
-const EOT: u8 = 0x04;
+/// Server information from the greeting banner
+///
+/// Banner is an optional message sent by the server before its pub key.
+/// First line has *"<name>/<version>"* format, all other lines are
+/// the message of the day
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    /// Server software name, e.g. *rpass_db*
+    pub name: String,
+    /// Server software version
+    pub version: String,
+    /// Message of the day. `None` if banner has only one line
+    pub motd: Option<String>,
+}
+
+impl FromStr for ServerInfo {
+    type Err = Error;
+
+    /// Parses greeting banner
+    ///
+    /// # Errors
+    ///
+    /// * `UnexpectedResponse` - if first line isn't in *"<name>/<version>"* format
+    fn from_str(banner: &str) -> Result<Self> {
+        let (header, motd) = match banner.split_once('\n') {
+            Some((header, motd)) => (header, Some(motd.to_owned())),
+            None => (banner, None),
+        };
+
+        match header.split_once('/') {
+            Some((name, version)) if !name.is_empty() && !version.is_empty() => Ok(ServerInfo {
+                name: name.to_owned(),
+                version: version.to_owned(),
+                motd,
+            }),
+            _ => Err(Error::UnexpectedResponse {
+                command: String::from("greeting"),
+                response: banner.to_owned(),
+            }),
+        }
+    }
+}
+
+/// Server version and commands it supports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerVersion {
+    /// Semver string
+    pub version: String,
+    /// Names of supported commands
+    pub commands: Vec<String>,
+}
+
+impl ServerVersion {
+    /// Checks if server supports `command`
+    pub fn supports(&self, command: &str) -> bool {
+        self.commands.iter().any(|supported| supported == command)
+    }
+}
 
 #[cfg_attr(test, automock, allow(dead_code))]
 impl Connector {
     /// Creates new Connector
     ///
-    /// Reads server pub key from `stream`
+    /// Reads optional server banner and server pub key from `transport` and
+    /// then requests server version.
+    /// Enables compression if server supports it
     ///
     /// # Errors
     ///
-    /// * `Io` - if can't clone `stream` or some error during writing/reading
-    /// bytes to/from server
-    /// * `InvalidKey` - if can't parse server key
+    /// * `Io` - if some error during writing/reading bytes to/from server
+    /// * `ConnectionClosed` - if server closed the connection
+    /// * `InvalidServerKey` - if server sent neither a banner nor a key or can't
+    ///   parse server key after the banner
     /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
-    pub async fn new(mut stream: Box<TcpStream>) -> Result<Self> {
-        let stream_ptr: *mut TcpStream = &mut *stream;
-        let (reader, writer) = unsafe { <*mut TcpStream>::as_mut(stream_ptr).unwrap().split() };
-        let mut reader = BufReader::new(reader);
-        let server_pub_key = Self::read_server_pub_key(&mut reader).await?;
+    /// * `UnexpectedResponse` - if can't parse server version or
+    ///   server refused to enable compression
+    pub async fn new(transport: Box<dyn Transport>) -> Result<Self> {
+        Self::connect(transport, None).await
+    }
+
+    /// Creates new Connector trusting only `server_pub_key` distributed out of
+    /// band
+    ///
+    /// Server still sends its key during connection, but it isn't used. It's
+    /// checked to be equal to `server_pub_key` instead, on every reconnect too
+    ///
+    /// # Errors
+    ///
+    /// * `KeyMismatch` - if server sent another key
+    /// * See [`Connector::new()`] for other errors
+    pub async fn with_pinned_key(
+        transport: Box<dyn Transport>,
+        server_pub_key: Key,
+    ) -> Result<Self> {
+        Self::connect(transport, Some(server_pub_key)).await
+    }
+
+    /// Creates new Connector checking server key against `pinned_key` if
+    /// there is some
+    ///
+    /// # Errors
+    ///
+    /// See [`Connector::with_pinned_key()`]
+    async fn connect(mut transport: Box<dyn Transport>, pinned_key: Option<Key>) -> Result<Self> {
+        let Handshake {
+            server_pub_key,
+            server_version,
+            server_info,
+            compression,
+        } = handshake(transport.as_mut(), pinned_key.as_ref()).await?;
         Ok(Connector {
-            _stream: stream,
-            reader,
-            writer,
+            transport,
             server_pub_key,
+            server_version,
+            server_info,
+            compression,
+            pinned_key,
+            skip_stream: false,
         })
     }
 
+    /// Connects to the same server again replacing current connection
+    ///
+    /// Server banner, pub key and version are read again, cause server could be
+    /// restarted
+    ///
+    /// # Errors
+    ///
+    /// * `CantConnectToTheServer` - if can't connect to the server
+    /// * See [`Connector::new()`] for other errors
+    pub async fn reconnect(&mut self) -> Result<()> {
+        self.transport.reconnect().await?;
+        let handshake = handshake(self.transport.as_mut(), self.pinned_key.as_ref()).await?;
+        self.server_pub_key = handshake.server_pub_key;
+        self.server_version = handshake.server_version;
+        self.server_info = handshake.server_info;
+        self.compression = handshake.compression;
+        self.skip_stream = false;
+        Ok(())
+    }
+
     /// Receives response from server
     ///
-    /// Returns response without EOT byte and "\r\n" ending if there is some
+    /// Returns decompressed response without "\r\n" ending if there is some
     ///
     /// # Errors
     ///
-    /// * `Io` - if can't retrieve bytes from server
+    /// * `Io` - if can't retrieve bytes from server or decompress them
+    /// * `ConnectionClosed` - if server closed the connection
     /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
     pub async fn recv_response(&mut self) -> Result<String> {
-        read_response(&mut self.reader).await
+        if self.skip_stream {
+            self.skip_stream = false;
+            while !parse_response(self.transport.recv().await?)?.is_empty() {}
+        }
+        parse_response(self.transport.recv().await?)
+    }
+
+    /// Makes next [`Connector::recv_response()`] skip rest of the streamed
+    /// response, which ends with an empty message.
+    ///
+    /// Used if reading of the stream was abandoned, so the next response isn't
+    /// taken from it
+    pub fn skip_stream(&mut self) {
+        self.skip_stream = true;
     }
 
-    /// Sends `request` to the server
+    /// Sends `request` to the server. Large request is compressed if
+    /// compression is enabled
     ///
     /// # Errors
     ///
     /// * `Io` - if can't send bytes to the server
-    /// * `InvalidRequest` - if `request` contains EOT byte
     pub async fn send_request(&mut self, request: String) -> Result<()> {
-        write_request(&mut self.writer, request).await
+        let mut request = make_request(request);
+        if self.compression {
+            request = compression::compress(request);
+        }
+        self.transport.send(request).await
     }
 
-    /// Reads server public key from `reader`
+    /// Checks if server is alive sending *ping* request
     ///
     /// # Errors
     ///
-    /// * See [`read_response()`]
-    /// * `InvalidKey` - if can't parse server key
-    async fn read_server_pub_key<R: AsyncBufRead + Unpin + 'static>(reader: &mut R) -> Result<Key> {
-        let key = read_response(reader).await?;
-        Key::from_str(&key).map_err(|err| err.into())
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `UnexpectedResponse` - if server responses with anything but *"pong"*
+    pub async fn ping(&mut self) -> Result<()> {
+        self.send_request(String::from("ping")).await?;
+        match self.recv_response().await? {
+            pong if pong == "pong" => Ok(()),
+            response => Err(Error::UnexpectedResponse {
+                command: String::from("ping"),
+                response,
+            }),
+        }
+    }
+
+    /// Checks if connection is still alive with [`Connector::ping()`].
+    /// *ping* doesn't change anything on the server, so it's safe to call at
+    /// any time between requests
+    pub async fn is_connected(&mut self) -> bool {
+        self.ping().await.is_ok()
+    }
+
+    /// Ends session sending *quit* request. Waits for server acknowledgement
+    /// and then shuts down writing half of the connection
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `UnexpectedResponse` - if server responses with anything but *"Bye"*
+    pub async fn quit(&mut self) -> Result<()> {
+        self.send_request(String::from("quit")).await?;
+        match self.recv_response().await? {
+            bye if bye == "Bye" => self.transport.shutdown().await,
+            response => Err(Error::UnexpectedResponse {
+                command: String::from("quit"),
+                response,
+            }),
+        }
     }
 
     /// Get a reference to the connector's server pub key.
     pub fn server_pub_key(&self) -> &Key {
         &self.server_pub_key
     }
+
+    /// Gets server version received during connection.
+    /// `None` if server is too old to report its version
+    pub fn server_version(&self) -> Option<ServerVersion> {
+        self.server_version.clone()
+    }
+
+    /// Gets server information from the greeting banner.
+    /// `None` if server sent its pub key without banner
+    pub fn server_info(&self) -> Option<ServerInfo> {
+        self.server_info.clone()
+    }
+}
+
+/// Data received from the server during [`handshake()`]
+struct Handshake {
+    server_pub_key: Key,
+    server_version: Option<ServerVersion>,
+    server_info: Option<ServerInfo>,
+    compression: bool,
 }
 
-/// Reads response from `reader`
+/// Reads optional server banner and server pub key from `transport`, then
+/// requests server version and enables compression if server supports it
 ///
-/// Returns response without EOT byte and "\r\n" ending if there is some
+/// First message is treated as a key if it has the key format, so servers
+/// sending no banner are supported. Key is checked to be equal to `pinned_key`
+/// if there is some
 ///
 /// # Errors
 ///
-/// * `Io` - if can't read bytes from `reader`
-/// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
-async fn read_response<R: AsyncBufRead + Unpin>(mut reader: R) -> Result<String> {
-    let mut buf = vec![];
-    let size = reader.read_until(EOT, &mut buf).await?;
-    if size == 0 {
-        return Ok(String::new());
+/// See [`Connector::with_pinned_key()`]
+async fn handshake(transport: &mut dyn Transport, pinned_key: Option<&Key>) -> Result<Handshake> {
+    let greeting = parse_greeting(transport.recv().await?)?;
+    let (server_info, server_pub_key) = match Key::from_str(&greeting) {
+        Ok(key) => (None, key),
+        Err(_) => {
+            let server_info =
+                ServerInfo::from_str(&greeting).map_err(|_| invalid_server_key(&greeting))?;
+            let key = parse_greeting(transport.recv().await?)?;
+            let key = Key::from_str(&key).map_err(|_| invalid_server_key(&key))?;
+            (Some(server_info), key)
+        }
+    };
+    if pinned_key.is_some_and(|pinned_key| *pinned_key != server_pub_key) {
+        return Err(Error::KeyMismatch);
     }
 
-    if *buf.last().unwrap() == EOT {
-        buf.pop();
+    transport
+        .send(make_request(String::from("version")))
+        .await?;
+    let server_version = parse_server_version(parse_response(transport.recv().await?)?)?;
+
+    let compression = server_version
+        .as_ref()
+        .is_some_and(|version| version.supports(compression::COMMAND));
+    if compression {
+        transport
+            .send(make_request(String::from(compression::COMMAND)))
+            .await?;
+        let response = parse_response(transport.recv().await?)?;
+        super::utils::check_ok_response(compression::COMMAND, response)?;
     }
 
-    let response = String::from_utf8(buf)?;
-    if let Some(stripped) = response.strip_suffix("\r\n") {
-        return Ok(stripped.to_string());
+    Ok(Handshake {
+        server_pub_key,
+        server_version,
+        server_info,
+        compression,
+    })
+}
+
+/// Parses message received before server version, see [`parse_response()`]
+///
+/// # Errors
+///
+/// * `Io` - if can't decompress `message`
+/// * `InvalidServerKey` - if `message` isn't UTF-8 encoded, cause it's surely
+///   not an rpass server
+fn parse_greeting(message: Vec<u8>) -> Result<String> {
+    match parse_response(message) {
+        Err(Error::InvalidResponseEncoding(err)) => {
+            Err(invalid_server_key(&String::from_utf8_lossy(err.as_bytes())))
+        }
+        res => res,
     }
+}
 
-    Ok(response)
+/// Makes [`Error::InvalidServerKey`] with `raw` message truncated to a
+/// reasonable length
+fn invalid_server_key(raw: &str) -> Error {
+    /// Maximum number of characters of the message kept in the error
+    const MAX_RAW_LEN: usize = 64;
+
+    let mut raw: String = raw.chars().take(MAX_RAW_LEN + 1).collect();
+    if raw.chars().count() > MAX_RAW_LEN {
+        raw.pop();
+        raw.push_str("...");
+    }
+    Error::InvalidServerKey { raw }
 }
 
-/// Writes `request` to `writer`
+/// Parses `response` to the *version* request
+///
+/// Returns `None` if server doesn't know *version* command
 ///
 /// # Errors
 ///
-/// * `Io` - if can't send bytes to `writer`
-/// * `InvalidRequest` - if `request` contains EOT byte
-async fn write_request<W: AsyncWrite + Unpin>(mut writer: W, request: String) -> Result<()> {
-    writer
-        .write_all(&make_request(request)?)
-        .await
-        .map_err(|err| err.into())
+/// * `UnexpectedResponse` - if `response` has invalid format
+fn parse_server_version(response: String) -> Result<Option<ServerVersion>> {
+    if super::utils::parse_server_error(&response).is_some() {
+        return Ok(None);
+    }
+
+    match response.split_once('\n') {
+        Some((version, commands)) => Ok(Some(ServerVersion {
+            version: version.to_owned(),
+            commands: commands.split(',').map(str::to_owned).collect(),
+        })),
+        None => Err(Error::UnexpectedResponse {
+            command: String::from("version"),
+            response,
+        }),
+    }
 }
 
-/// Takes raw `request` string, adds *"\r\n"* at the end if needed and
-/// converts to bytes
-fn make_request(mut request: String) -> Result<Vec<u8>> {
-    if request.bytes().any(|byte| byte == EOT) {
-        return Err(Error::InvalidRequest {
-            mes: String::from("request should not contain EOT byte"),
-        });
+/// Converts `response` message to string without "\r\n" ending if there is some.
+/// Compressed `response` is decompressed first
+///
+/// # Errors
+///
+/// * `Io` - if can't decompress `response`
+/// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+fn parse_response(response: Vec<u8>) -> Result<String> {
+    let response = String::from_utf8(compression::decompress(response)?)?;
+    if let Some(stripped) = response.strip_suffix("\r\n") {
+        return Ok(stripped.to_string());
     }
 
+    Ok(response)
+}
+
+/// Takes raw `request` string, adds *"\r\n"* at the end if needed and
+/// converts to bytes
+fn make_request(mut request: String) -> Vec<u8> {
     if !request.ends_with("\r\n") {
         request += "\r\n";
     }
 
-    let mut bytes = request.into_bytes();
-    bytes.push(EOT);
-    Ok(bytes)
+    request.into_bytes()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::framing::Framing;
+    use crate::session::StreamTransport;
+
+    use num_bigint::ToBigUint;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    /// Server side of the in-memory connection
+    struct TestServer {
+        stream: DuplexStream,
+    }
+
+    impl TestServer {
+        /// Creates Connector connected to the new TestServer.
+        /// Server sends `pub_key` and answers *version* request with `version`
+        async fn connect(pub_key: &Key, version: &str) -> (Connector, TestServer) {
+            Self::connect_with_banner(None, pub_key, version).await
+        }
+
+        /// Same as [`TestServer::connect()`], but server sends `banner` before
+        /// `pub_key` if there is some
+        async fn connect_with_banner(
+            banner: Option<&str>,
+            pub_key: &Key,
+            version: &str,
+        ) -> (Connector, TestServer) {
+            let (client, server) = tokio::io::duplex(16 * 1024);
+            let mut server = TestServer { stream: server };
+            if let Some(banner) = banner {
+                server.send(&format!("{banner}\r\n")).await;
+            }
+            server.send(&format!("{}\r\n", pub_key)).await;
+
+            let version = version.to_owned();
+            let connector = Connector::new(Box::new(StreamTransport::new(client)));
+            let server_future = async {
+                assert_eq!(server.recv().await, "version\r\n");
+                server.send(&version).await;
+                if version.contains(compression::COMMAND) {
+                    assert_eq!(server.recv().await, "enable_compression\r\n");
+                    server.send("Ok\r\n").await;
+                }
+            };
+            let (connector, ()) = tokio::join!(connector, server_future);
+            (connector.unwrap(), server)
+        }
+
+        /// Sends `message` with EOT byte at the end
+        async fn send(&mut self, message: &str) {
+            self.send_bytes(message.as_bytes()).await;
+        }
+
+        /// Sends escaped `message` bytes with EOT byte at the end
+        async fn send_bytes(&mut self, message: &[u8]) {
+            let bytes = Framing::EOT.frame(message);
+            self.stream.write_all(&bytes).await.unwrap();
+        }
+
+        /// Receives message until EOT byte
+        async fn recv(&mut self) -> String {
+            String::from_utf8(self.recv_bytes().await).unwrap()
+        }
+
+        /// Receives message bytes until not escaped EOT byte
+        async fn recv_bytes(&mut self) -> Vec<u8> {
+            let mut buf = vec![];
+            while !Framing::EOT.is_complete(&buf) {
+                buf.push(self.stream.read_u8().await.unwrap());
+            }
+            Framing::EOT.unframe(&buf)
+        }
+    }
+
+    fn server_pub_key() -> Key {
+        Key(11.to_biguint().unwrap(), 22.to_biguint().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_new() {
+        let (connector, _server) =
+            TestServer::connect(&server_pub_key(), "0.1.0\nping,version").await;
+
+        assert_eq!(connector.server_pub_key(), &server_pub_key());
+        assert_eq!(
+            connector.server_version(),
+            Some(ServerVersion {
+                version: String::from("0.1.0"),
+                commands: vec![String::from("ping"), String::from("version")],
+            })
+        );
+        assert_eq!(connector.server_info(), None);
+    }
+
+    #[tokio::test]
+    async fn test_new_with_banner() {
+        let (connector, _server) = TestServer::connect_with_banner(
+            Some("rpass_db/0.2.0\nWelcome!\nMaintenance at 12:00"),
+            &server_pub_key(),
+            "0.2.0\nping,version",
+        )
+        .await;
+
+        assert_eq!(connector.server_pub_key(), &server_pub_key());
+        assert_eq!(
+            connector.server_info(),
+            Some(ServerInfo {
+                name: String::from("rpass_db"),
+                version: String::from("0.2.0"),
+                motd: Some(String::from("Welcome!\nMaintenance at 12:00")),
+            })
+        );
+        assert_eq!(connector.server_version().unwrap().version, "0.2.0");
+    }
+
+    #[tokio::test]
+    async fn test_new_invalid_banner() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut server = TestServer { stream: server };
+        server.send("garbage\r\n").await;
 
-    use std::io::Cursor;
-    use std::task::Poll;
-    use tokio::io::AsyncRead;
-
-    /// Reader that fails to read
-    struct TestReader;
-
-    impl AsyncRead for TestReader {
-        fn poll_read(
-            self: std::pin::Pin<&mut Self>,
-            _cx: &mut std::task::Context<'_>,
-            _buf: &mut tokio::io::ReadBuf<'_>,
-        ) -> Poll<std::io::Result<()>> {
-            Poll::Ready(Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "read error",
-            )))
+        assert!(matches!(
+            Connector::new(Box::new(StreamTransport::new(client))).await,
+            Err(Error::InvalidServerKey { raw }) if raw == "garbage"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_new_invalid_server_key() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut server = TestServer { stream: server };
+        server.send("rpass_db/0.2.0\r\n").await;
+        server
+            .send(&format!("{}\r\n", "HTTP/1.1 ".repeat(10)))
+            .await;
+
+        match Connector::new(Box::new(StreamTransport::new(client))).await {
+            Err(Error::InvalidServerKey { raw }) => {
+                assert!(raw.starts_with("HTTP/1.1 HTTP/1.1"), "{raw}");
+                assert!(raw.ends_with("..."), "{raw}");
+                assert_eq!(raw.chars().count(), 67);
+            }
+            res => panic!("unexpected result: {res:?}"),
         }
     }
 
     #[tokio::test]
-    async fn test_read_response_basic() {
-        let mut reader = Cursor::new("response");
-        assert_eq!(read_response(&mut reader).await.unwrap(), "response");
+    async fn test_with_pinned_key() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut server = TestServer { stream: server };
+        server.send(&format!("{}\r\n", server_pub_key())).await;
+
+        let connector =
+            Connector::with_pinned_key(Box::new(StreamTransport::new(client)), server_pub_key());
+        let server_future = async {
+            assert_eq!(server.recv().await, "version\r\n");
+            server.send("0.1.0\nping,version").await;
+        };
+        let (connector, ()) = tokio::join!(connector, server_future);
+        assert_eq!(connector.unwrap().server_pub_key(), &server_pub_key());
     }
 
     #[tokio::test]
-    async fn test_read_response_empty() {
-        let mut reader = Cursor::new("");
-        assert_eq!(read_response(&mut reader).await.unwrap(), "");
+    async fn test_with_pinned_key_mismatch() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut server = TestServer { stream: server };
+        server.send("rpass_db/0.2.0\r\n").await;
+        server.send(&format!("{}\r\n", server_pub_key())).await;
+
+        let pinned_key = Key(33.to_biguint().unwrap(), 22.to_biguint().unwrap());
+        assert!(matches!(
+            Connector::with_pinned_key(Box::new(StreamTransport::new(client)), pinned_key).await,
+            Err(Error::KeyMismatch)
+        ));
     }
 
     #[tokio::test]
-    async fn test_read_response_with_eot_at_the_end() {
-        let mut response = String::from("response").into_bytes();
-        response.push(EOT);
+    async fn test_send_request_and_recv_response() {
+        let (mut connector, mut server) =
+            TestServer::connect(&server_pub_key(), "0.1.0\nping,version").await;
+
+        connector
+            .send_request(String::from("show_record \"example.com\""))
+            .await
+            .unwrap();
+        assert_eq!(server.recv().await, "show_record \"example.com\"\r\n");
+
+        server.send("secret\nnotes\r\n").await;
+        assert_eq!(connector.recv_response().await.unwrap(), "secret\nnotes");
+    }
 
-        let mut reader = Cursor::new(response);
-        assert_eq!(read_response(&mut reader).await.unwrap(), "response");
+    #[tokio::test]
+    async fn test_skip_stream() {
+        let (mut connector, mut server) =
+            TestServer::connect(&server_pub_key(), "0.1.0\nping,version").await;
+
+        server.send("example.com\r\n").await;
+        assert_eq!(connector.recv_response().await.unwrap(), "example.com");
+        connector.skip_stream();
+
+        server.send("test.ru\r\n").await;
+        server.send("youtube.com\r\n").await;
+        server.send("\r\n").await;
+        server.send("pong\r\n").await;
+        assert_eq!(connector.recv_response().await.unwrap(), "pong");
     }
 
     #[tokio::test]
-    async fn test_read_response_carriage_return() {
-        let mut reader = Cursor::new("response\r\n");
-        assert_eq!(read_response(&mut reader).await.unwrap(), "response");
+    async fn test_compression() {
+        let (mut connector, mut server) =
+            TestServer::connect(&server_pub_key(), "0.2.0\nenable_compression,ping,version").await;
+        assert!(connector.compression);
+
+        let notes = "note ".repeat(1000);
+        let request = format!("new_record \"example.com\" \"{notes}\"");
+        connector.send_request(request.clone()).await.unwrap();
+        let bytes = server.recv_bytes().await;
+        assert_eq!(bytes[0], compression::MARKER);
+        assert_eq!(
+            compression::decompress(bytes).unwrap(),
+            format!("{request}\r\n").into_bytes()
+        );
+
+        connector.send_request(String::from("ping")).await.unwrap();
+        assert_eq!(server.recv().await, "ping\r\n");
+
+        let response = format!("secret\n{notes}\r\n").into_bytes();
+        server
+            .send_bytes(&compression::compress(response.clone()))
+            .await;
+        assert_eq!(
+            connector.recv_response().await.unwrap(),
+            format!("secret\n{notes}")
+        );
     }
 
     #[tokio::test]
-    async fn test_read_response_io_error() {
-        let mut reader = BufReader::new(TestReader {});
+    async fn test_compression_not_supported() {
+        let (mut connector, mut server) =
+            TestServer::connect(&server_pub_key(), "0.1.0\nping,version").await;
+        assert!(!connector.compression);
+
+        let request = format!("new_record \"example.com\" \"{}\"", "note ".repeat(1000));
+        connector.send_request(request.clone()).await.unwrap();
+        assert_eq!(server.recv().await, format!("{request}\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_recv_response_connection_closed() {
+        let (mut connector, server) =
+            TestServer::connect(&server_pub_key(), "0.1.0\nping,version").await;
+
+        drop(server);
         assert!(matches!(
-            read_response(&mut reader).await,
-            Err(Error::Io(_))
+            connector.recv_response().await,
+            Err(Error::ConnectionClosed)
         ));
     }
 
     #[tokio::test]
-    async fn test_read_response_invalid_response() {
-        let mut reader = Cursor::new([0, 1, 128, EOT]);
+    async fn test_is_connected() {
+        let (mut connector, mut server) =
+            TestServer::connect(&server_pub_key(), "0.1.0\nping,version").await;
+
+        let server_future = async {
+            assert_eq!(server.recv().await, "ping\r\n");
+            server.send("pong\r\n").await;
+        };
+        let (is_connected, ()) = tokio::join!(connector.is_connected(), server_future);
+        assert!(is_connected);
+    }
+
+    #[tokio::test]
+    async fn test_is_connected_closed() {
+        let (mut connector, server) =
+            TestServer::connect(&server_pub_key(), "0.1.0\nping,version").await;
+
+        drop(server);
+        assert!(!connector.is_connected().await);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_not_supported() {
+        let (mut connector, _server) =
+            TestServer::connect(&server_pub_key(), "0.1.0\nping,version").await;
+
+        assert!(matches!(
+            connector.reconnect().await,
+            Err(Error::CantConnectToTheServer { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_response_carriage_return() {
+        assert_eq!(
+            parse_response(b"response\r\n".to_vec()).unwrap(),
+            "response"
+        );
+        assert_eq!(parse_response(b"response".to_vec()).unwrap(), "response");
+    }
+
+    #[test]
+    fn test_parse_response_invalid_encoding() {
         assert!(matches!(
-            read_response(&mut reader).await,
+            parse_response(vec![0, 1, 128]),
             Err(Error::InvalidResponseEncoding(_))
         ));
     }
 
-    #[tokio::test]
-    async fn test_make_request_with_eot_at_the_end() {
-        let mut bytes = "login".as_bytes().to_vec();
-        bytes.push(EOT);
-        bytes.extend_from_slice("user".as_bytes());
+    #[test]
+    fn test_server_info_from_str() {
+        assert_eq!(
+            ServerInfo::from_str("rpass_db/0.2.0").unwrap(),
+            ServerInfo {
+                name: String::from("rpass_db"),
+                version: String::from("0.2.0"),
+                motd: None,
+            }
+        );
+        for banner in ["rpass_db", "/0.2.0", "rpass_db/\nhello"] {
+            assert!(
+                matches!(
+                    ServerInfo::from_str(banner),
+                    Err(Error::UnexpectedResponse { .. })
+                ),
+                "{banner}"
+            );
+        }
+    }
 
-        let request = String::from_utf8(bytes).unwrap();
+    #[test]
+    fn test_parse_server_version() {
+        let server_version =
+            parse_server_version(String::from("0.1.0\nlogin,ping,version")).unwrap();
+        assert_eq!(
+            server_version,
+            Some(ServerVersion {
+                version: String::from("0.1.0"),
+                commands: vec![
+                    String::from("login"),
+                    String::from("ping"),
+                    String::from("version")
+                ],
+            })
+        );
+        assert!(server_version.as_ref().unwrap().supports("ping"));
+        assert!(!server_version.unwrap().supports("delete_record"));
+    }
+
+    #[test]
+    fn test_parse_server_version_old_server() {
+        let response = String::from("Error: undefined command `version`");
+        assert_eq!(parse_server_version(response).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_server_version_invalid() {
         assert!(matches!(
-            make_request(request),
-            Err(Error::InvalidRequest { .. })
-        ))
+            parse_server_version(String::from("0.1.0")),
+            Err(Error::UnexpectedResponse { command, response })
+                if command == "version" && response == "0.1.0"
+        ));
     }
 
-    #[tokio::test]
-    async fn test_make_request_carriage_return() {
+    #[test]
+    fn test_make_request_carriage_return() {
         let request = String::from("login user");
-        let mut expected = (request.clone() + "\r\n").into_bytes();
-        expected.push(EOT);
-
-        assert_eq!(&make_request(request).unwrap(), &expected);
+        assert_eq!(make_request(request), b"login user\r\n");
+        assert_eq!(make_request(String::from("ping\r\n")), b"ping\r\n");
     }
 }
@@ -0,0 +1,380 @@
+use crate::{framing::Framing, Error, Result};
+
+use futures::future::BoxFuture;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, ReadHalf, WriteHalf},
+    net::{TcpStream, ToSocketAddrs},
+};
+
+use std::fmt::{Debug, Display};
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+/// Transport to exchange messages with *rpass_db*
+///
+/// Implementation is responsible for splitting byte stream into messages
+pub trait Transport: Debug + Send {
+    /// Sends `message` to the server
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't send bytes to the server
+    /// * `InvalidRequest` - if `message` can't be framed
+    fn send(&mut self, message: Vec<u8>) -> BoxFuture<'_, Result<()>>;
+
+    /// Receives next message from the server
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't retrieve bytes from server
+    /// * `ConnectionClosed` - if server closed the connection
+    fn recv(&mut self) -> BoxFuture<'_, Result<Vec<u8>>>;
+
+    /// Shuts down sending half of the connection
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't shut down connection
+    fn shutdown(&mut self) -> BoxFuture<'_, Result<()>>;
+
+    /// Connects to the same server again replacing current connection.
+    /// Not supported by default
+    ///
+    /// # Errors
+    ///
+    /// * `CantConnectToTheServer` - if can't connect to the server
+    fn reconnect(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async {
+            Err(Error::CantConnectToTheServer {
+                addr: String::from("<unknown>"),
+                source: std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "transport doesn't support reconnection",
+                ),
+            })
+        })
+    }
+}
+
+/// Transport over any byte stream with messages framed by [`Framing`]
+#[derive(Debug)]
+pub struct StreamTransport<S> {
+    reader: BufReader<ReadHalf<S>>,
+    writer: WriteHalf<S>,
+    framing: Framing,
+}
+
+impl<S: AsyncRead + AsyncWrite> StreamTransport<S> {
+    /// Creates new StreamTransport exchanging messages through `stream`
+    /// with default framing
+    pub fn new(stream: S) -> Self {
+        Self::with_framing(stream, Framing::default())
+    }
+
+    /// Creates new StreamTransport exchanging messages through `stream`
+    /// framed with `framing`
+    pub fn with_framing(stream: S, framing: Framing) -> Self {
+        let (reader, writer) = tokio::io::split(stream);
+        StreamTransport {
+            reader: BufReader::new(reader),
+            writer,
+            framing,
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Debug + Send> Transport for StreamTransport<S> {
+    /// Writes escaped `message` with delimiter byte at the end,
+    /// see [`Framing::frame()`]
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't send bytes to the server
+    fn send(&mut self, message: Vec<u8>) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            self.writer
+                .write_all(&self.framing.frame(&message))
+                .await
+                .map_err(|err| err.into())
+        })
+    }
+
+    /// Reads bytes until not escaped delimiter byte or end of stream.
+    /// Returns unescaped message without delimiter byte
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't retrieve bytes from server
+    /// * `ConnectionClosed` - if stream reached EOF before any byte was read
+    fn recv(&mut self) -> BoxFuture<'_, Result<Vec<u8>>> {
+        Box::pin(async {
+            let buf = self.framing.read_frame_async(&mut self.reader).await?;
+            if buf.is_empty() {
+                return Err(Error::ConnectionClosed);
+            }
+
+            Ok(self.framing.unframe(&buf))
+        })
+    }
+
+    fn shutdown(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async { self.writer.shutdown().await.map_err(|err| err.into()) })
+    }
+}
+
+/// Transport over TCP. Supports reconnection
+#[derive(Debug)]
+pub struct TcpTransport {
+    addr: SocketAddr,
+    stream: StreamTransport<TcpStream>,
+}
+
+impl TcpTransport {
+    /// Connects to the server on `addr`
+    ///
+    /// # Errors
+    ///
+    /// * `CantConnectToTheServer` - if can't connect to the server
+    /// * `Io` - if can't get address of the server
+    pub async fn connect<A: ToSocketAddrs + Display>(addr: A) -> Result<Self> {
+        let addr_string = addr.to_string();
+        let stream =
+            TcpStream::connect(addr)
+                .await
+                .map_err(|source| Error::CantConnectToTheServer {
+                    addr: addr_string,
+                    source,
+                })?;
+        Ok(TcpTransport {
+            addr: stream.peer_addr()?,
+            stream: StreamTransport::new(stream),
+        })
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&mut self, message: Vec<u8>) -> BoxFuture<'_, Result<()>> {
+        self.stream.send(message)
+    }
+
+    fn recv(&mut self) -> BoxFuture<'_, Result<Vec<u8>>> {
+        self.stream.recv()
+    }
+
+    fn shutdown(&mut self) -> BoxFuture<'_, Result<()>> {
+        self.stream.shutdown()
+    }
+
+    fn reconnect(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async {
+            *self = Self::connect(self.addr).await?;
+            Ok(())
+        })
+    }
+}
+
+/// Transport over Unix domain socket. Supports reconnection
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct UnixTransport {
+    path: PathBuf,
+    stream: StreamTransport<UnixStream>,
+}
+
+#[cfg(unix)]
+impl UnixTransport {
+    /// Connects to the server listening on socket at `path`
+    ///
+    /// # Errors
+    ///
+    /// * `CantConnectToTheServer` - if can't connect to the server
+    pub async fn connect<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let stream =
+            UnixStream::connect(&path)
+                .await
+                .map_err(|source| Error::CantConnectToTheServer {
+                    addr: path.as_ref().display().to_string(),
+                    source,
+                })?;
+        Ok(UnixTransport {
+            path: path.as_ref().to_owned(),
+            stream: StreamTransport::new(stream),
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixTransport {
+    fn send(&mut self, message: Vec<u8>) -> BoxFuture<'_, Result<()>> {
+        self.stream.send(message)
+    }
+
+    fn recv(&mut self) -> BoxFuture<'_, Result<Vec<u8>>> {
+        self.stream.recv()
+    }
+
+    fn shutdown(&mut self) -> BoxFuture<'_, Result<()>> {
+        self.stream.shutdown()
+    }
+
+    fn reconnect(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async {
+            *self = Self::connect(&self.path).await?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncReadExt, ReadBuf};
+
+    /// Stream that fails to read
+    #[derive(Debug)]
+    struct FailingStream;
+
+    impl AsyncRead for FailingStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Err(std::io::Error::other("read error")))
+        }
+    }
+
+    impl AsyncWrite for FailingStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut transport = StreamTransport::new(client);
+
+        transport.send(b"ping".to_vec()).await.unwrap();
+        let mut buf = [0; 5];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping\x04");
+    }
+
+    #[tokio::test]
+    async fn test_connect_refused() {
+        let addr = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+
+        let err = TcpTransport::connect(addr).await.unwrap_err();
+        assert!(matches!(&err, Error::CantConnectToTheServer { .. }));
+        assert!(err.to_string().contains(&addr.to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_send_with_eot() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut transport = StreamTransport::new(client);
+
+        transport.send(b"login\x04\x05user".to_vec()).await.unwrap();
+        let mut buf = [0; 14];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"login\x05\x04\x05\x05user\x04");
+    }
+
+    #[tokio::test]
+    async fn test_recv_escaped() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut transport = StreamTransport::new(client);
+
+        server
+            .write_all(b"pass\x05\x04word\x05\x05\x04next\x04")
+            .await
+            .unwrap();
+        assert_eq!(transport.recv().await.unwrap(), b"pass\x04word\x05");
+        assert_eq!(transport.recv().await.unwrap(), b"next");
+    }
+
+    #[tokio::test]
+    async fn test_recv() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut transport = StreamTransport::new(client);
+
+        server.write_all(b"first\x04second\x04").await.unwrap();
+        assert_eq!(transport.recv().await.unwrap(), b"first");
+        assert_eq!(transport.recv().await.unwrap(), b"second");
+    }
+
+    #[tokio::test]
+    async fn test_text_framing() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut transport = StreamTransport::with_framing(client, Framing::TEXT);
+
+        transport.send(b"ping\r\n".to_vec()).await.unwrap();
+        let mut buf = [0; 8];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping\r\x05\n\n");
+
+        server.write_all(b"secret\x05\nnotes\n").await.unwrap();
+        assert_eq!(transport.recv().await.unwrap(), b"secret\nnotes");
+    }
+
+    #[tokio::test]
+    async fn test_recv_without_eot_at_the_end() {
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut transport = StreamTransport::new(client);
+
+        server.write_all(b"response").await.unwrap();
+        drop(server);
+        assert_eq!(transport.recv().await.unwrap(), b"response");
+    }
+
+    #[tokio::test]
+    async fn test_recv_eof() {
+        let (client, server) = tokio::io::duplex(64);
+        let mut transport = StreamTransport::new(client);
+
+        drop(server);
+        assert!(matches!(
+            transport.recv().await,
+            Err(Error::ConnectionClosed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_recv_io_error() {
+        let mut transport = StreamTransport::new(FailingStream);
+        assert!(matches!(transport.recv().await, Err(Error::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_not_supported() {
+        let (client, _server) = tokio::io::duplex(64);
+        let mut transport = StreamTransport::new(client);
+
+        assert!(matches!(
+            transport.reconnect().await,
+            Err(Error::CantConnectToTheServer { .. })
+        ));
+    }
+}
@@ -1,8 +1,12 @@
-use super::{utils, Authorized, Connector, Error, LoginError, Result};
+use super::{
+    utils, Authorized, Connector, Error, LoginError, Result, ServerInfo, ServerVersion,
+    TcpTransport, Transport,
+};
 
 use crate::key::Key;
+use crate::known_servers::KnownServers;
 
-use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::net::ToSocketAddrs;
 
 /// Unauthorized session
 ///
@@ -22,26 +26,143 @@ impl Unauthorized {
     /// * `CantConnectToTheServer` - if can't connect to the server
     /// * `Io` - if can't read bytes from server
     /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
-    /// * `InvalidKey` - if can't parse server key
-    pub async fn new<A: ToSocketAddrs>(addr: A) -> Result<Self> {
-        let stream = Box::new(
-            TcpStream::connect(addr)
-                .await
-                .map_err(|_| Error::CantConnectToTheServer)?,
-        );
-        let connector = Connector::new(stream).await?;
+    /// * `InvalidServerKey` - if server sent invalid key, probably it's not an rpass server
+    pub async fn new<A: ToSocketAddrs + std::fmt::Display>(addr: A) -> Result<Self> {
+        let transport = TcpTransport::connect(addr).await?;
+        Self::with_transport(transport).await
+    }
+
+    /// Creates new Unauthorized
+    ///
+    /// Connects to rpass server on `addr` and checks its key against the one
+    /// remembered in `known_servers` for that address, see [`KnownServers::check()`]
+    ///
+    /// # Errors
+    ///
+    /// * `ServerKeyChanged` - if server presented another key before
+    /// * See [`Unauthorized::new()`] for other errors
+    pub async fn new_with_known_servers<A>(addr: A, known_servers: &KnownServers) -> Result<Self>
+    where
+        A: ToSocketAddrs + std::fmt::Display,
+    {
+        let host = addr.to_string();
+        let unauthorized = Self::new(addr).await?;
+        known_servers.check(&host, unauthorized.server_pub_key())?;
+        Ok(unauthorized)
+    }
+
+    /// Creates new Unauthorized
+    ///
+    /// Connects to rpass server on `addr` trusting only `server_pub_key`,
+    /// see [`Connector::with_pinned_key()`]
+    ///
+    /// # Errors
+    ///
+    /// * `KeyMismatch` - if server sent another key
+    /// * See [`Unauthorized::new()`] for other errors
+    pub async fn new_pinned<A: ToSocketAddrs + std::fmt::Display>(
+        addr: A,
+        server_pub_key: Key,
+    ) -> Result<Self> {
+        let transport = TcpTransport::connect(addr).await?;
+        let connector = Connector::with_pinned_key(Box::new(transport), server_pub_key).await?;
+        Ok(Unauthorized { connector })
+    }
+
+    /// Creates new Unauthorized
+    ///
+    /// Connects to rpass server listening on Unix domain socket at `path`
+    ///
+    /// # Errors
+    ///
+    /// See [`Unauthorized::new()`]
+    #[cfg(unix)]
+    pub async fn new_unix<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        let transport = super::UnixTransport::connect(path).await?;
+        Self::with_transport(transport).await
+    }
+
+    /// Creates new Unauthorized communicating with server through `transport`
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `InvalidServerKey` - if server sent invalid key, probably it's not an rpass server
+    pub async fn with_transport<T: Transport + 'static>(transport: T) -> Result<Self> {
+        let connector = Connector::new(Box::new(transport)).await?;
         Ok(Unauthorized { connector })
     }
 
     /// Creates new Unauthorized directly accepting `connector`
-    pub(super) fn with_connector(connector: Connector) -> Self {
+    pub(crate) fn with_connector(connector: Connector) -> Self {
         Unauthorized { connector }
     }
 
+    /// Gets server pub key received during connection
+    pub fn server_pub_key(&self) -> &Key {
+        self.connector.server_pub_key()
+    }
+
+    /// Gets server version received during connection.
+    /// `None` if server is too old to report its version
+    pub fn server_version(&self) -> Option<ServerVersion> {
+        self.connector.server_version()
+    }
+
+    /// Gets server information from the greeting banner.
+    /// `None` if server doesn't send banner
+    pub fn server_info(&self) -> Option<ServerInfo> {
+        self.connector.server_info()
+    }
+
+    /// Checks if server is alive
+    ///
+    /// # Errors
+    ///
+    /// See [`Connector::ping()`]
+    pub async fn ping(&mut self) -> Result<()> {
+        self.connector.ping().await
+    }
+
+    /// Gets names of commands supported by the server. Aliases are listed
+    /// after commands as *"alias -> command"*
+    ///
+    /// # Errors
+    ///
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `Server` - if server response contains error message
+    pub async fn help(&mut self) -> Result<Vec<String>> {
+        self.connector.send_request(String::from("help")).await?;
+        let response = utils::read_good_response(&mut self.connector).await?;
+        Ok(response.lines().map(str::to_owned).collect())
+    }
+
+    /// Connects to the server again, e.g. after connection was lost
+    ///
+    /// # Errors
+    ///
+    /// See [`Connector::reconnect()`]
+    pub async fn reconnect(&mut self) -> Result<()> {
+        self.connector.reconnect().await
+    }
+
+    /// Ends session and closes connection with server
+    ///
+    /// # Errors
+    ///
+    /// See [`Connector::quit()`]
+    pub async fn quit(mut self) -> Result<()> {
+        self.connector.quit().await
+    }
+
     /// Registers new user with `username` and `pub_key`
     ///
     /// # Errors
     ///
+    /// * `InvalidRequest` - if `username` is empty, too long, reserved or
+    ///   contains whitespaces, see [`crate::name`]
     /// * `Io` - if can't write or read bytes to/from server
     /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
     /// * `UnexpectedResponse` - if server responses with unexpected message
@@ -63,10 +184,12 @@ impl Unauthorized {
     /// # }
     /// ```
     pub async fn register(&mut self, username: &str, pub_key: &Key) -> Result<()> {
-        let register_request = format!("register {} {}", username, pub_key.to_string());
+        utils::check_username(username)?;
+
+        let register_request = format!("register {} {}", username, pub_key);
         self.connector.send_request(register_request).await?;
 
-        utils::read_ok_response(&mut self.connector).await
+        utils::read_ok_response(&mut self.connector, "register").await
     }
 
     /// Attempts to log in to the server with `username` name.
@@ -104,8 +227,12 @@ impl Unauthorized {
         username: &str,
         sec_key: &Key,
     ) -> std::result::Result<Authorized, LoginError> {
-        match self.try_login(username, sec_key).await {
-            Ok(()) => Ok(Authorized::new(self.connector)),
+        match utils::login(&mut self.connector, username, sec_key).await {
+            Ok(()) => Ok(Authorized::with_credentials(
+                self.connector,
+                username,
+                sec_key,
+            )),
             Err(err) => Err(LoginError {
                 source: err,
                 unauthorized: self,
@@ -113,30 +240,177 @@ impl Unauthorized {
         }
     }
 
-    /// Tries to log in to the server
+    /// Resumes session of the user with `token` issued by
+    /// [`Authorized::issue_session_token()`], so login isn't needed.
     ///
-    /// See [`Unauthorized::login()`] for details
-    async fn try_login(&mut self, username: &str, sec_key: &Key) -> Result<()> {
-        let login_request = format!("login {}", username);
-        self.connector.send_request(login_request).await?;
-
-        let login_response = utils::read_good_response(&mut self.connector).await?;
-
-        let confirmation = sec_key.decrypt(&login_response);
-        let encrypted_confirmation = self.connector.server_pub_key().encrypt(&confirmation);
+    /// Resumed session doesn't know credentials, so it can't log in again after
+    /// reconnection. Consumes `self` and returns `Authorized` object on success
+    /// or `LoginError` with `self` on failure
+    ///
+    /// # Errors
+    ///
+    /// `LoginError::source` field can have the next values:
+    ///
+    /// * `InvalidRequest` - if `token` is empty or contains whitespaces
+    /// * `Io` - if can't write or read bytes to/from server
+    /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+    /// * `UnexpectedResponse` - if server responses with unexpected message
+    /// * `Server` - if server response contains error message, e.g. if token
+    ///   is expired or revoked
+    pub async fn resume(mut self, token: &str) -> std::result::Result<Authorized, LoginError> {
+        match self.try_resume(token).await {
+            Ok(()) => Ok(Authorized::new(self.connector)),
+            Err(err) => Err(LoginError {
+                source: err,
+                unauthorized: self,
+            }),
+        }
+    }
 
-        let confirm_login_request = format!("confirm_login {}", encrypted_confirmation);
-        self.connector.send_request(confirm_login_request).await?;
+    /// Tries to resume session with `token`. See [`Unauthorized::resume()`]
+    async fn try_resume(&mut self, token: &str) -> Result<()> {
+        if token.is_empty() || token.contains(char::is_whitespace) {
+            return Err(Error::InvalidRequest {
+                mes: String::from("session token can't be empty or contain whitespaces"),
+            });
+        }
 
-        utils::read_ok_response(&mut self.connector).await
+        self.connector
+            .send_request(format!("resume {token}"))
+            .await?;
+        utils::read_ok_response(&mut self.connector, "resume").await
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::Error;
     use num_bigint::ToBigUint;
 
+    /// Tests for `Unauthorized::ping()`
+    mod ping {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let mut connector = Connector::default();
+            connector.expect_ping().times(1).returning(|| Ok(()));
+
+            let mut unauthorized = Unauthorized { connector };
+            unauthorized.ping().await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_error() {
+            let mut connector = Connector::default();
+            connector.expect_ping().times(1).returning(|| {
+                Err(Error::UnexpectedResponse {
+                    command: String::from("ping"),
+                    response: String::from("undefined command `ping`"),
+                })
+            });
+
+            let mut unauthorized = Unauthorized { connector };
+            assert!(matches!(
+                unauthorized.ping().await,
+                Err(Error::UnexpectedResponse { .. })
+            ));
+        }
+    }
+
+    /// Tests for `Unauthorized::help()`
+    mod help {
+        use super::*;
+
+        use mockall::predicate::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let mut connector = Connector::default();
+            connector
+                .expect_send_request()
+                .with(eq(String::from("help")))
+                .times(1)
+                .returning(|_| Ok(()));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("login\nping\nls -> list_records")));
+
+            let mut unauthorized = Unauthorized { connector };
+            assert_eq!(
+                unauthorized.help().await.unwrap(),
+                ["login", "ping", "ls -> list_records"]
+            );
+        }
+
+        #[tokio::test]
+        async fn test_server_error() {
+            let mut connector = Connector::default();
+            connector
+                .expect_send_request()
+                .with(eq(String::from("help")))
+                .times(1)
+                .returning(|_| Ok(()));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(|| Ok(String::from("Error: undefined command `help`")));
+
+            let mut unauthorized = Unauthorized { connector };
+            assert!(matches!(
+                unauthorized.help().await,
+                Err(Error::Server { .. })
+            ));
+        }
+    }
+
+    /// Tests for `Unauthorized::reconnect()`
+    mod reconnect {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let mut connector = Connector::default();
+            connector.expect_reconnect().times(1).returning(|| Ok(()));
+
+            let mut unauthorized = Unauthorized { connector };
+            unauthorized.reconnect().await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_cant_connect() {
+            let mut connector = Connector::default();
+            connector.expect_reconnect().times(1).returning(|| {
+                Err(Error::CantConnectToTheServer {
+                    addr: String::from("127.0.0.1:3747"),
+                    source: std::io::ErrorKind::ConnectionRefused.into(),
+                })
+            });
+
+            let mut unauthorized = Unauthorized { connector };
+            assert!(matches!(
+                unauthorized.reconnect().await,
+                Err(Error::CantConnectToTheServer { .. })
+            ));
+        }
+    }
+
+    /// Tests for `Unauthorized::quit()`
+    mod quit {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_ok() {
+            let mut connector = Connector::default();
+            connector.expect_quit().times(1).returning(|| Ok(()));
+
+            let unauthorized = Unauthorized { connector };
+            unauthorized.quit().await.unwrap();
+        }
+    }
+
     /// Tests for `Unauthorized::register()`
     mod register {
         use super::*;
@@ -153,11 +427,7 @@ mod tests {
             let mut connector = Connector::default();
             connector
                 .expect_send_request()
-                .with(eq(format!(
-                    "register {} {}",
-                    TEST_USER,
-                    pub_key.to_string()
-                )))
+                .with(eq(format!("register {} {}", TEST_USER, pub_key)))
                 .times(1)
                 .returning(|_| Ok(()));
             connector
@@ -169,19 +439,35 @@ mod tests {
             unauthorized.register(TEST_USER, &pub_key).await.unwrap();
         }
 
+        #[tokio::test]
+        async fn test_invalid_username() {
+            let (_, pub_key, _) = generate_keys();
+            let mut unauthorized = Unauthorized {
+                connector: Connector::default(),
+            };
+            for username in [
+                "",
+                "test user",
+                "key",
+                "CON",
+                &"x".repeat(crate::name::MAX_LENGTH + 1),
+            ] {
+                assert!(matches!(
+                    unauthorized.register(username, &pub_key).await,
+                    Err(Error::InvalidRequest { .. })
+                ));
+            }
+        }
+
         #[tokio::test]
         async fn test_cant_send_request() {
             let (_, pub_key, _) = generate_keys();
             let mut connector = Connector::default();
             connector
                 .expect_send_request()
-                .with(eq(format!(
-                    "register {} {}",
-                    TEST_USER,
-                    pub_key.to_string()
-                )))
+                .with(eq(format!("register {} {}", TEST_USER, pub_key)))
                 .times(1)
-                .returning(|_| Err(Error::Io(io::Error::new(io::ErrorKind::Other, ""))));
+                .returning(|_| Err(Error::Io(io::Error::other(""))));
 
             let mut unauthorized = Unauthorized { connector };
             assert!(matches!(
@@ -196,11 +482,7 @@ mod tests {
             let mut connector = Connector::default();
             connector
                 .expect_send_request()
-                .with(eq(format!(
-                    "register {} {}",
-                    TEST_USER,
-                    pub_key.to_string()
-                )))
+                .with(eq(format!("register {} {}", TEST_USER, pub_key)))
                 .times(1)
                 .returning(|_| Ok(()));
             connector.expect_recv_response().times(1).returning(|| {
@@ -222,11 +504,7 @@ mod tests {
             let mut connector = Connector::default();
             connector
                 .expect_send_request()
-                .with(eq(format!(
-                    "register {} {}",
-                    TEST_USER,
-                    pub_key.to_string()
-                )))
+                .with(eq(format!("register {} {}", TEST_USER, pub_key)))
                 .times(1)
                 .returning(|_| Ok(()));
             connector
@@ -237,11 +515,94 @@ mod tests {
             let mut unauthorized = Unauthorized { connector };
             assert!(matches!(
                 unauthorized.register(TEST_USER, &pub_key).await,
-                Err(Error::UnexpectedResponse { response }) if response == "Registered"
+                Err(Error::UnexpectedResponse { command, response })
+                    if command == "register" && response == "Registered"
             ));
         }
     }
 
+    /// Tests for `Unauthorized::resume()`
+    mod resume {
+        use super::*;
+        use crate::error::ErrorCode;
+        use mockall::predicate::*;
+
+        const TOKEN: &str = "token";
+
+        #[tokio::test]
+        async fn test_ok() {
+            let connector = build_connector("Ok");
+
+            let unauthorized = Unauthorized { connector };
+            unauthorized.resume(TOKEN).await.unwrap();
+        }
+
+        #[tokio::test]
+        async fn test_expired_token() {
+            let connector = build_connector("Error[SessionTokenExpired]: session token expired");
+
+            let unauthorized = Unauthorized { connector };
+            assert!(matches!(
+                unauthorized.resume(TOKEN).await,
+                Err(LoginError {
+                    source: Error::Server {
+                        code: ErrorCode::SessionTokenExpired,
+                        ..
+                    },
+                    ..
+                })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_revoked_token() {
+            let connector = build_connector("Error[InvalidSessionToken]: invalid session token");
+
+            let unauthorized = Unauthorized { connector };
+            assert!(matches!(
+                unauthorized.resume(TOKEN).await,
+                Err(LoginError {
+                    source: Error::Server {
+                        code: ErrorCode::InvalidSessionToken,
+                        ..
+                    },
+                    ..
+                })
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_invalid_token() {
+            for token in ["", "two tokens"] {
+                let unauthorized = Unauthorized {
+                    connector: Connector::default(),
+                };
+                assert!(matches!(
+                    unauthorized.resume(token).await,
+                    Err(LoginError {
+                        source: Error::InvalidRequest { .. },
+                        ..
+                    })
+                ));
+            }
+        }
+
+        /// Builds connector expecting *resume* request and answering with `response`
+        fn build_connector(response: &'static str) -> Connector {
+            let mut connector = Connector::default();
+            connector
+                .expect_send_request()
+                .with(eq(format!("resume {TOKEN}")))
+                .times(1)
+                .returning(|_| Ok(()));
+            connector
+                .expect_recv_response()
+                .times(1)
+                .returning(move || Ok(String::from(response)));
+            connector
+        }
+    }
+
     /// Tests for `Unauthorized::login()`
     mod login {
         use super::*;
@@ -280,7 +641,7 @@ mod tests {
                 .expect_send_request()
                 .with(eq(format!("login {}", TEST_USER)))
                 .times(1)
-                .returning(|_| Err(Error::Io(io::Error::new(io::ErrorKind::Other, ""))));
+                .returning(|_| Err(Error::Io(io::Error::other(""))));
 
             let unauthorized = Unauthorized { connector };
             assert!(matches!(
@@ -369,7 +730,7 @@ mod tests {
                 .times(2)
                 .returning_st(move |_| match send_response_call_counter.get() {
                     1 => Ok(()),
-                    _ => Err(Error::Io(io::Error::new(io::ErrorKind::Other, ""))),
+                    _ => Err(Error::Io(io::Error::other(""))),
                 });
             connector
                 .expect_recv_response()
@@ -408,7 +769,7 @@ mod tests {
                         return Ok(pub_key.encrypt(CONFIRMATION));
                     }
 
-                    Err(Error::Io(io::Error::new(io::ErrorKind::Other, "")))
+                    Err(Error::Io(io::Error::other("")))
                 });
 
             let unauthorized = Unauthorized { connector };
@@ -450,7 +811,7 @@ mod tests {
             assert!(matches!(
                 unauthorized.login(TEST_USER, &sec_key).await,
                 Err(LoginError {
-                    source: Error::Server { mes },
+                    source: Error::Server { mes, .. },
                     ..
                 }) if mes == "invalid confirmation string"
             ));
@@ -485,9 +846,9 @@ mod tests {
             assert!(matches!(
                 unauthorized.login(TEST_USER, &sec_key).await,
                 Err(LoginError {
-                    source: Error::UnexpectedResponse { response},
+                    source: Error::UnexpectedResponse { command, response },
                     ..
-                }) if response == "Successfully logged in"
+                }) if command == "confirm_login" && response == "Successfully logged in"
             ));
         }
 
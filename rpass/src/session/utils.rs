@@ -1,44 +1,134 @@
-use super::{Connector, Error, Result};
+use super::{Connector, Error, ErrorCode, Result};
+use crate::key::Key;
+use crate::name;
 
 /// Reads response from with `connector` and returns it if it doesn't contain error message
 ///
 /// # Errors
 ///
 /// * `Io` - if can't write or read bytes to/from server
+/// * `ConnectionClosed` - if server closed the connection
 /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
 /// * `Server` - if server response contains error message
 pub async fn read_good_response(connector: &mut Connector) -> Result<String> {
     let response = connector.recv_response().await?;
 
-    if let Some(stripped) = response.strip_prefix("Error: ") {
-        return Err(Error::Server {
-            mes: stripped.to_string(),
+    match parse_server_error(&response) {
+        Some(err) => Err(err),
+        None => Ok(response),
+    }
+}
+
+/// Parses `response` as server error in `Error[<code>]: <message>` format.
+/// Responses in `Error: <message>` format from older servers get unknown code
+///
+/// Returns `None` if `response` isn't an error
+pub fn parse_server_error(response: &str) -> Option<Error> {
+    let stripped = response.strip_prefix("Error")?;
+    if let Some(mes) = stripped.strip_prefix(": ") {
+        return Some(Error::Server {
+            code: ErrorCode::Unknown(String::new()),
+            mes: mes.to_owned(),
         });
     }
 
-    Ok(response)
+    let (code, mes) = stripped.strip_prefix('[')?.split_once("]: ")?;
+    Some(Error::Server {
+        code: ErrorCode::from(code),
+        mes: mes.to_owned(),
+    })
 }
 
-/// Checks if server response contains *"Ok"* value
+/// Checks if server response to `command` request contains *"Ok"* value
 ///
 /// # Errors
 ///
 /// * `Io` - if can't write or read bytes to/from server
+/// * `ConnectionClosed` - if server closed the connection
 /// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
 /// * `Server` - if server response contains error message
 /// * `UnexpectedResponse` - if response isn't *"Ok"* or error
-pub async fn read_ok_response(connector: &mut Connector) -> Result<()> {
+pub async fn read_ok_response(connector: &mut Connector, command: &str) -> Result<()> {
     let response = read_good_response(connector).await?;
+    check_ok_response(command, response)
+}
+
+/// Checks if `response` to `command` request is *"Ok"*
+///
+/// # Errors
+///
+/// * `UnexpectedResponse` - if `response` isn't *"Ok"*
+pub fn check_ok_response(command: &str, response: String) -> Result<()> {
     match response {
         ok if ok == "Ok" => Ok(()),
-        mes => Err(Error::UnexpectedResponse { response: mes }),
+        mes => Err(Error::UnexpectedResponse {
+            command: command.to_owned(),
+            response: mes,
+        }),
+    }
+}
+
+/// Wraps `arg` into quotes `"` escaping quotes and backslashes inside it,
+/// so server reads it as a single argument
+pub fn quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Checks if `username` can be sent to the server, see [`name::is_acceptable()`]
+///
+/// # Errors
+///
+/// * `InvalidRequest` - if `username` is empty, too long, reserved or contains
+///   whitespaces
+pub fn check_username(username: &str) -> Result<()> {
+    if !name::is_acceptable(username) || username.contains(char::is_whitespace) {
+        return Err(Error::InvalidRequest {
+            mes: format!(
+                "username can't be empty, reserved, longer than {} bytes or contain whitespaces",
+                name::MAX_LENGTH
+            ),
+        });
     }
+    Ok(())
+}
+
+/// Logs in to the server with `username` name using `sec_key` to prove identity
+///
+/// # Errors
+///
+/// * `Io` - if can't write or read bytes to/from server
+/// * `ConnectionClosed` - if server closed the connection
+/// * `InvalidResponseEncoding` - if response isn't UTF-8 encoded
+/// * `UnexpectedResponse` - if server responses with unexpected message
+/// * `Server` - if server response contains error message
+pub async fn login(connector: &mut Connector, username: &str, sec_key: &Key) -> Result<()> {
+    let login_request = format!("login {}", username);
+    connector.send_request(login_request).await?;
+
+    let login_response = read_good_response(connector).await?;
+
+    let confirmation = sec_key.decrypt(&login_response);
+    let encrypted_confirmation = connector.server_pub_key().encrypt(&confirmation);
+
+    let confirm_login_request = format!("confirm_login {}", encrypted_confirmation);
+    connector.send_request(confirm_login_request).await?;
+
+    read_ok_response(connector, "confirm_login").await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("example.com"), "\"example.com\"");
+        assert_eq!(
+            quote(r#"back\slash and "quotes""#),
+            r#""back\\slash and \"quotes\"""#
+        );
+    }
+
     mod read_good_response {
         use super::*;
         use std::io;
@@ -63,7 +153,7 @@ mod tests {
             connector
                 .expect_recv_response()
                 .times(1)
-                .returning(|| Err(Error::Io(io::Error::new(io::ErrorKind::Other, ""))));
+                .returning(|| Err(Error::Io(io::Error::other(""))));
 
             assert!(matches!(
                 read_good_response(&mut connector).await,
@@ -71,6 +161,24 @@ mod tests {
             ))
         }
 
+        #[tokio::test]
+        async fn test_connection_closed() {
+            let mut connector = Connector::default();
+            connector
+                .expect_recv_response()
+                .times(2)
+                .returning(|| Err(Error::ConnectionClosed));
+
+            assert!(matches!(
+                read_good_response(&mut connector).await,
+                Err(Error::ConnectionClosed)
+            ));
+            assert!(matches!(
+                read_ok_response(&mut connector, "test").await,
+                Err(Error::ConnectionClosed)
+            ));
+        }
+
         #[tokio::test]
         async fn test_response_with_error() {
             let mut connector = Connector::default();
@@ -81,11 +189,66 @@ mod tests {
 
             assert!(matches!(
                 read_good_response(&mut connector).await,
-                Err(Error::Server { mes }) if mes == "test error"
+                Err(Error::Server { mes, .. }) if mes == "test error"
             ))
         }
     }
 
+    mod parse_server_error {
+        use super::*;
+
+        #[test]
+        fn test_codes() {
+            for (response, expected_code, expected_mes) in [
+                (
+                    "Error[UserDoesNotExist]: user test_user doesn't exist",
+                    ErrorCode::UserDoesNotExist,
+                    "user test_user doesn't exist",
+                ),
+                (
+                    "Error[UndefinedCommand]: undefined command `ping`",
+                    ErrorCode::UndefinedCommand,
+                    "undefined command `ping`",
+                ),
+                (
+                    "Error[InvalidKey]: invalid key: `invalid format`",
+                    ErrorCode::InvalidKey,
+                    "invalid key: `invalid format`",
+                ),
+            ] {
+                assert!(matches!(
+                    parse_server_error(response),
+                    Some(Error::Server { code, mes }) if code == expected_code && mes == expected_mes
+                ));
+            }
+        }
+
+        #[test]
+        fn test_unknown_code() {
+            assert!(matches!(
+                parse_server_error("Error[SomeNewCode]: new error"),
+                Some(Error::Server { code: ErrorCode::Unknown(code), mes })
+                    if code == "SomeNewCode" && mes == "new error"
+            ));
+        }
+
+        #[test]
+        fn test_without_code() {
+            assert!(matches!(
+                parse_server_error("Error: old error"),
+                Some(Error::Server { code: ErrorCode::Unknown(code), mes })
+                    if code.is_empty() && mes == "old error"
+            ));
+        }
+
+        #[test]
+        fn test_not_error() {
+            assert!(parse_server_error("Ok").is_none());
+            assert!(parse_server_error("Errors are everywhere").is_none());
+            assert!(parse_server_error("Error[Unclosed: bracket").is_none());
+        }
+    }
+
     mod read_ok_response {
         use super::*;
 
@@ -109,8 +272,8 @@ mod tests {
                 .returning(|| Ok(String::from("Good")));
 
             assert!(matches!(
-                read_ok_response(&mut connector).await,
-                Err(Error::UnexpectedResponse { response }) if response == "Good"
+                read_ok_response(&mut connector, "test").await,
+                Err(Error::UnexpectedResponse { command, response }) if command == "test" && response == "Good"
             ))
         }
     }
@@ -0,0 +1,85 @@
+//! Register and login through Unix domain socket against a scripted server
+
+#![cfg(unix)]
+
+use num_bigint::ToBigUint;
+use rpass::{framing::Framing, key::Key, session::Unauthorized};
+use std::path::PathBuf;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+const TEST_USER: &str = "test_user";
+const CONFIRMATION: &str = "confirmation";
+
+/// Generates public and secret keys used both by user and server
+fn generate_keys() -> (Key, Key) {
+    let pub_key = Key(269.to_biguint().unwrap(), 221.to_biguint().unwrap());
+    let sec_key = Key(5.to_biguint().unwrap(), 221.to_biguint().unwrap());
+    (pub_key, sec_key)
+}
+
+/// Builds unique socket path in the temporary directory
+fn socket_path() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rpass-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("rpass.sock")
+}
+
+async fn send(stream: &mut BufReader<UnixStream>, message: &str) {
+    let bytes = Framing::EOT.frame(format!("{message}\r\n").as_bytes());
+    stream.get_mut().write_all(&bytes).await.unwrap();
+}
+
+async fn recv(stream: &mut BufReader<UnixStream>) -> String {
+    let mut buf = vec![];
+    stream
+        .read_until(Framing::EOT.delimiter(), &mut buf)
+        .await
+        .unwrap();
+    String::from_utf8(Framing::EOT.unframe(&buf))
+        .unwrap()
+        .trim_end_matches("\r\n")
+        .to_owned()
+}
+
+/// Serves one client expecting it to register and log in
+async fn serve(listener: UnixListener) {
+    let (pub_key, sec_key) = generate_keys();
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut stream = BufReader::new(stream);
+
+    send(&mut stream, &pub_key.to_string()).await;
+    assert_eq!(recv(&mut stream).await, "version");
+    send(&mut stream, "0.1.0\nconfirm_login,login,register,version").await;
+
+    assert_eq!(
+        recv(&mut stream).await,
+        format!("register {TEST_USER} {pub_key}")
+    );
+    send(&mut stream, "Ok").await;
+
+    assert_eq!(recv(&mut stream).await, format!("login {TEST_USER}"));
+    send(&mut stream, &pub_key.encrypt(CONFIRMATION)).await;
+
+    let confirmation = recv(&mut stream).await;
+    let confirmation = confirmation.strip_prefix("confirm_login ").unwrap();
+    assert_eq!(sec_key.decrypt(confirmation), CONFIRMATION);
+    send(&mut stream, "Ok").await;
+}
+
+#[tokio::test]
+async fn test_register_and_login() {
+    let path = socket_path();
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    let server = tokio::spawn(serve(listener));
+
+    let (pub_key, sec_key) = generate_keys();
+    let mut session = Unauthorized::new_unix(&path).await.unwrap();
+    assert!(session.server_version().unwrap().supports("register"));
+    session.register(TEST_USER, &pub_key).await.unwrap();
+    session.login(TEST_USER, &sec_key).await.unwrap();
+
+    server.await.unwrap();
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
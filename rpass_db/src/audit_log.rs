@@ -0,0 +1,162 @@
+use crate::session::ConnectionInfo;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Authentication event worth to be audited
+#[derive(Debug, PartialEq, Eq)]
+pub enum AuditEvent {
+    Registered,
+    LoginSucceeded,
+    /// Authorized session was resumed with session token
+    SessionResumed,
+    /// Login attempt failed with `reason`. Reason shouldn't contain any secrets
+    LoginFailed {
+        reason: String,
+    },
+    AccountDeleted,
+}
+
+impl fmt::Display for AuditEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Registered => write!(f, "event=registered"),
+            Self::LoginSucceeded => write!(f, "event=login_succeeded"),
+            Self::SessionResumed => write!(f, "event=session_resumed"),
+            Self::LoginFailed { reason } => write!(f, "event=login_failed reason={reason:?}"),
+            Self::AccountDeleted => write!(f, "event=account_deleted"),
+        }
+    }
+}
+
+/// Append-only log of authentication events, separate from the request log
+///
+/// Every entry is a single line with timestamp, event, username and peer address
+pub struct AuditLog {
+    sink: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AuditLog {
+    /// Creates audit log writing entries to `sink`
+    pub fn new<W: Write + Send + 'static>(sink: W) -> Self {
+        AuditLog {
+            sink: Mutex::new(Box::new(sink)),
+        }
+    }
+
+    /// Opens audit log file at `path` for appending, creating it if needed
+    ///
+    /// # Errors
+    ///
+    /// Any error during file opening
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(file))
+    }
+
+    /// Creates audit log discarding all entries
+    #[cfg(test)]
+    pub fn disabled() -> Self {
+        Self::new(io::sink())
+    }
+
+    /// Writes entry about `event` of user `username` connected via `connection`
+    ///
+    /// Failures are logged, but not returned, so auditing never breaks requests
+    pub fn record(&self, event: AuditEvent, username: &str, connection: &ConnectionInfo) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let entry = format!(
+            "timestamp={timestamp} {event} user={username:?} peer={:?}\n",
+            connection.peer_addr
+        );
+
+        let mut sink = self.sink.lock().unwrap();
+        if let Err(err) = sink.write_all(entry.as_bytes()).and_then(|_| sink.flush()) {
+            log::error!("Can't write audit log entry: {err}");
+        }
+    }
+}
+
+/// Sink keeping written entries in memory, so tests can inspect them
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct MemorySink(std::sync::Arc<Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl MemorySink {
+    /// Gets written entries
+    pub fn entries(&self) -> Vec<String> {
+        String::from_utf8(self.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl Write for MemorySink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let sink = MemorySink::default();
+        let audit_log = AuditLog::new(sink.clone());
+        let connection = ConnectionInfo {
+            peer_addr: String::from("127.0.0.1:4000"),
+            ..ConnectionInfo::default()
+        };
+
+        audit_log.record(AuditEvent::Registered, "user", &connection);
+        audit_log.record(
+            AuditEvent::LoginFailed {
+                reason: String::from("invalid confirmation string"),
+            },
+            "user",
+            &connection,
+        );
+
+        let entries = sink.entries();
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].starts_with("timestamp="));
+        assert!(entries[0].ends_with(" event=registered user=\"user\" peer=\"127.0.0.1:4000\""));
+        assert!(entries[1].ends_with(
+            " event=login_failed reason=\"invalid confirmation string\" user=\"user\" peer=\"127.0.0.1:4000\""
+        ));
+    }
+
+    #[test]
+    fn test_open_appends() {
+        let path = std::env::temp_dir().join(format!("rpass_db_audit_{}", std::process::id()));
+        let connection = ConnectionInfo::default();
+
+        AuditLog::open(&path)
+            .unwrap()
+            .record(AuditEvent::Registered, "first", &connection);
+        AuditLog::open(&path)
+            .unwrap()
+            .record(AuditEvent::AccountDeleted, "first", &connection);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 2);
+        assert!(content.contains("event=account_deleted"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
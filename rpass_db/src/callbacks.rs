@@ -1,29 +1,85 @@
+mod change_pub_key;
+mod confirm_change_pub_key;
 mod confirm_login;
+mod count_records;
 mod delete_me;
 mod delete_record;
+mod delete_records;
 mod error;
+mod exists_record;
+mod get_record_history;
+mod help;
+mod issue_session_token;
 mod list_records;
+mod list_trash;
+mod list_users;
 mod login;
+mod maintenance;
+mod metrics;
 mod new_record;
+mod patch_record;
+mod ping;
+mod purge_trash;
+mod quit;
 mod register;
+mod rename_me;
+mod restore_record;
+mod restore_record_version;
+mod resume;
 mod show_record;
+mod stats;
+mod stream_records;
+mod user_exists;
+mod validate_record;
+mod version;
+mod whoami;
 
 mod utils;
 
 pub use crate::storage;
+pub use change_pub_key::change_pub_key;
+pub use confirm_change_pub_key::confirm_change_pub_key;
 pub use confirm_login::confirm_login;
+pub use count_records::count_records;
 pub use delete_me::delete_me;
 pub use delete_record::delete_record;
+pub use delete_records::delete_records;
 pub use error::Error;
+pub use exists_record::exists_record;
+pub use get_record_history::get_record_history;
+pub use help::help;
+pub use issue_session_token::issue_session_token;
 pub use list_records::list_records;
+pub use list_trash::list_trash;
+pub use list_users::list_users;
 pub use login::login;
+pub use maintenance::maintenance;
+pub use metrics::{metrics, MetricsCache};
 pub use new_record::new_record;
+pub use patch_record::patch_record;
+pub use ping::ping;
+pub use purge_trash::purge_trash;
+pub use quit::quit;
 pub use register::register;
+pub use rename_me::rename_me;
+pub use restore_record::restore_record;
+pub use restore_record_version::restore_record_version;
+pub use resume::resume;
 pub use show_record::show_record;
+pub use stats::stats;
+pub use stream_records::stream_records;
+pub use user_exists::user_exists;
+pub use validate_record::validate_record;
+pub use version::version;
+pub use whoami::whoami;
 pub type Result<T> = std::result::Result<T, Error>;
 
+use crate::audit_log::{AuditEvent, AuditLog};
+use crate::key_allowlist::KeyAllowlist;
+use crate::maintenance::Maintenance;
 use crate::request_dispatcher::ArgIter;
 use crate::session;
+use crate::session_tokens::{SessionTokens, TokenError};
 
 use crate::AsyncStorage;
 
@@ -31,3 +87,232 @@ use crate::AsyncStorage;
 use std::sync::{Arc, RwLock};
 #[cfg(test)]
 type AsyncUserStorage = Arc<RwLock<storage::UserStorage>>;
+
+#[cfg(test)]
+mod tests {
+    use super::session::{Authorized, Session};
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+    use storage::{FileStore, InMemoryStore, RecordStore};
+
+    const TEST_USER: &str = "test_user";
+
+    /// Generates a test running every `check` against every record store backend
+    macro_rules! backend_tests {
+        ($($check:ident),* $(,)?) => {
+            mod in_memory {
+                $(
+                    #[test]
+                    fn $check() {
+                        super::$check(super::build_user_storage_with(
+                            super::InMemoryStore::new(),
+                        ));
+                    }
+                )*
+            }
+
+            mod files {
+                $(
+                    #[test]
+                    fn $check() {
+                        let dir = super::StoreDir::new(concat!("files_", stringify!($check)));
+                        let store = super::FileStore::new(&dir.0, super::build_cipher());
+                        super::$check(super::build_user_storage_with(store));
+                    }
+                )*
+            }
+
+            #[cfg(feature = "sqlite")]
+            mod sqlite {
+                $(
+                    #[test]
+                    fn $check() {
+                        let dir = super::StoreDir::new(concat!("sqlite_", stringify!($check)));
+                        let store =
+                            super::storage::SqliteStore::open(&dir.0, super::build_cipher())
+                                .unwrap();
+                        super::$check(super::build_user_storage_with(store));
+                    }
+                )*
+            }
+        };
+    }
+
+    backend_tests!(check_write_and_read, check_listing, check_deleting);
+
+    /// Temporary directory of the store, removed on drop
+    struct StoreDir(PathBuf);
+
+    impl StoreDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("rpass_db_callbacks_{name}_{}", std::process::id()));
+            if path.exists() {
+                std::fs::remove_dir_all(&path).unwrap();
+            }
+            std::fs::create_dir_all(&path).unwrap();
+            StoreDir(path)
+        }
+    }
+
+    impl Drop for StoreDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn build_cipher() -> storage::Cipher {
+        let (_, sec_key) = storage::Key::generate_pair();
+        storage::Cipher::new(&sec_key, TEST_USER)
+    }
+
+    type Call<'a> = dyn Fn(fn(&Session, ArgIter) -> Result<String>, &[&str]) -> Result<String> + 'a;
+
+    /// Runs `check` with authorized session over `user_storage` and function
+    /// to call callbacks with string arguments
+    fn with_session(user_storage: AsyncUserStorage, check: impl FnOnce(&Session, &Call<'_>)) {
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), user_storage));
+        let call = |callback: fn(&Session, ArgIter) -> Result<String>, args: &[&str]| {
+            callback(&session, &mut args.iter().map(|&arg| arg.to_owned()))
+        };
+        check(&session, &call);
+    }
+
+    fn new_record_unlimited(session: &Session, arg_iter: ArgIter) -> Result<String> {
+        new_record(session, arg_iter, None, None)
+    }
+
+    /// Checks that records written by callbacks can be read back from `user_storage`
+    fn check_write_and_read(user_storage: AsyncUserStorage) {
+        let validate_record =
+            |session: &Session, arg_iter: ArgIter| validate_record(session, arg_iter, None);
+        let patch_record =
+            |session: &Session, arg_iter: ArgIter| patch_record(session, arg_iter, None);
+
+        with_session(user_storage, |_, call| {
+            assert_eq!(
+                call(new_record_unlimited, &["example.com", "secret\nnotes"]).unwrap(),
+                "Ok"
+            );
+            assert_eq!(call(exists_record, &["example.com"]).unwrap(), "true");
+            assert_eq!(
+                call(validate_record, &["other.com", "pass\n"]).unwrap(),
+                "Ok"
+            );
+            assert_eq!(call(exists_record, &["other.com"]).unwrap(), "false");
+            assert_eq!(
+                call(show_record, &["example.com"]).unwrap(),
+                "secret\nnotes"
+            );
+            assert_eq!(
+                call(patch_record, &["example.com", "notes", "new notes"]).unwrap(),
+                "Ok"
+            );
+            assert_eq!(
+                call(show_record, &["example.com"]).unwrap(),
+                "secret\nnew notes"
+            );
+        });
+    }
+
+    /// Checks that callbacks list records from `user_storage` in alphabetical order
+    fn check_listing(user_storage: AsyncUserStorage) {
+        with_session(user_storage, |session, call| {
+            assert_eq!(call(list_records, &[]).unwrap(), "No records yet");
+            assert_eq!(count_records(session).unwrap(), "0");
+
+            for resource in ["example.com", "My Bank"] {
+                assert_eq!(
+                    call(new_record_unlimited, &[resource, "secret\n"]).unwrap(),
+                    "Ok"
+                );
+            }
+            assert_eq!(call(list_records, &[]).unwrap(), "My Bank\nexample.com");
+            let mut streamed = vec![];
+            stream_records(session, &mut |name| streamed.push(name)).unwrap();
+            assert_eq!(streamed, ["My Bank", "example.com"]);
+            assert_eq!(call(list_records, &["1", "1"]).unwrap(), "2\nexample.com");
+            assert!(stats(session).unwrap().starts_with("2\n"));
+            assert_eq!(count_records(session).unwrap(), "2");
+        });
+    }
+
+    /// Checks that callbacks delete records from `user_storage`
+    fn check_deleting(user_storage: AsyncUserStorage) {
+        with_session(user_storage, |_, call| {
+            for resource in ["example.com", "My Bank", "other.com"] {
+                assert_eq!(
+                    call(new_record_unlimited, &[resource, "secret\n"]).unwrap(),
+                    "Ok"
+                );
+            }
+
+            assert_eq!(call(delete_record, &["example.com"]).unwrap(), "Ok");
+            assert_eq!(call(exists_record, &["example.com"]).unwrap(), "false");
+            assert!(matches!(
+                call(show_record, &["example.com"]),
+                Err(Error::RecordNotFound(_))
+            ));
+            assert!(matches!(
+                call(delete_record, &["example.com"]),
+                Err(Error::Storage(_))
+            ));
+            assert_eq!(call(list_records, &[]).unwrap(), "My Bank\nother.com");
+
+            assert_eq!(
+                call(delete_records, &["other.com", "missing.com", "My Bank"]).unwrap(),
+                "Ok\nError[RecordNotFound]: no such record: `missing.com`\nOk"
+            );
+            assert_eq!(call(list_records, &[]).unwrap(), "No records yet");
+        });
+    }
+
+    /// Builds user storage passing record operations to `store` instead of
+    /// checking expectations
+    fn build_user_storage_with<S: RecordStore + 'static>(store: S) -> AsyncUserStorage {
+        let store = Arc::new(Mutex::new(store));
+        let mut user_storage = storage::UserStorage::default();
+
+        user_storage.expect_record_locks().returning(Arc::default);
+        let write_store = store.clone();
+        user_storage
+            .expect_write_record()
+            .returning(move |record| write_store.lock().unwrap().write_record(record));
+        let get_store = store.clone();
+        user_storage
+            .expect_get_record()
+            .returning(move |resource| get_store.lock().unwrap().get_record(resource));
+        let list_store = store.clone();
+        user_storage
+            .expect_list_records()
+            .returning(move || list_store.lock().unwrap().list_records());
+        let visit_store = store.clone();
+        user_storage
+            .expect_visit_records()
+            .returning(move |visitor| visit_store.lock().unwrap().visit_records(visitor));
+        let exists_store = store.clone();
+        user_storage
+            .expect_record_exists()
+            .returning(move |resource| exists_store.lock().unwrap().record_exists(resource));
+        let count_store = store.clone();
+        user_storage
+            .expect_count_records()
+            .returning(move || count_store.lock().unwrap().count_records());
+        let delete_store = store.clone();
+        user_storage
+            .expect_delete_record()
+            .returning(move |resource| delete_store.lock().unwrap().delete_record(resource));
+        let delete_many_store = store.clone();
+        user_storage
+            .expect_delete_records()
+            .returning(move |resources| {
+                delete_many_store.lock().unwrap().delete_records(resources)
+            });
+        user_storage
+            .expect_stats()
+            .returning(move || store.lock().unwrap().stats());
+
+        Arc::new(RwLock::new(user_storage))
+    }
+}
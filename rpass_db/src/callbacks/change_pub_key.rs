@@ -0,0 +1,138 @@
+use super::{session::*, utils, ArgIter, Error, KeyAllowlist, Result};
+use crate::storage::Key;
+use rand::thread_rng;
+use std::str::FromStr;
+
+/// First part of user public key changing. Reads new public key from
+/// `arg_iter`, generates random confirmation string and stores both in
+/// `session`. Returns *Ok() with confirmation encrypted with new key* in success
+///
+/// The next step user should decrypt confirmation string with new secret key,
+/// encrypt it with storage public key and send it back, proving that he owns
+/// new key pair.
+///
+/// If `key_allowlist` is set, only keys from it can be used, same as on
+/// registration.
+///
+/// See [`super::confirm_change_pub_key()`] function for second part
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `EmptyKey` - if no key was provided
+/// * `InvalidKey` - if key is invalid
+/// * `KeyNotAllowed` - if key isn't in `key_allowlist`
+pub fn change_pub_key(
+    session: &mut Session,
+    key_allowlist: Option<&KeyAllowlist>,
+    arg_iter: ArgIter,
+) -> Result<String> {
+    let authorized_session = session
+        .as_authorized_mut()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let key_string = arg_iter.next().ok_or(Error::EmptyKey)?;
+    let pub_key = Key::from_str(&key_string)?;
+    if key_allowlist.is_some_and(|allowlist| !allowlist.contains(&pub_key)) {
+        return Err(Error::KeyNotAllowed);
+    }
+
+    let confirmation = utils::generate_confirmation(&mut thread_rng());
+    let encrypted_confirmation = pub_key.encrypt(&confirmation);
+    authorized_session.pub_key_change = Some(PubKeyChange {
+        pub_key,
+        confirmation,
+    });
+
+    Ok(encrypted_confirmation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::AsyncUserStorage;
+    use super::*;
+
+    const TEST_USER: &str = "test_user";
+
+    #[test]
+    fn test_ok() {
+        let mut session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+        let mut arg_iter = ["11:11"].iter().map(|&s| s.to_owned());
+
+        let res = change_pub_key(&mut session, None, &mut arg_iter);
+        let pub_key_change = session
+            .as_authorized()
+            .unwrap()
+            .pub_key_change
+            .as_ref()
+            .unwrap();
+        assert_eq!(pub_key_change.pub_key, Key::from_str("11:11").unwrap());
+        assert_eq!(
+            res.unwrap(),
+            pub_key_change.pub_key.encrypt(&pub_key_change.confirmation)
+        );
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let mut session = Session::default();
+        let mut arg_iter = ["11:11"].iter().map(|&s| s.to_owned());
+
+        assert!(matches!(
+            change_pub_key(&mut session, None, &mut arg_iter),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_empty_key() {
+        let mut session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+        let mut arg_iter = [].iter().map(|s: &&str| s.to_string());
+
+        assert!(matches!(
+            change_pub_key(&mut session, None, &mut arg_iter),
+            Err(Error::EmptyKey)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        let mut session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+        let mut arg_iter = ["key"].iter().map(|&s| s.to_owned());
+
+        assert!(matches!(
+            change_pub_key(&mut session, None, &mut arg_iter),
+            Err(Error::InvalidKey(_))
+        ));
+        assert!(session.as_authorized().unwrap().pub_key_change.is_none());
+    }
+
+    #[test]
+    fn test_key_not_allowed() {
+        let allowlist = KeyAllowlist::from_str("11:11").unwrap();
+        let mut session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+        let mut arg_iter = ["13:11"].iter().map(|&s| s.to_owned());
+
+        assert!(matches!(
+            change_pub_key(&mut session, Some(&allowlist), &mut arg_iter),
+            Err(Error::KeyNotAllowed)
+        ));
+        assert!(session.as_authorized().unwrap().pub_key_change.is_none());
+
+        let mut arg_iter = ["11:11"].iter().map(|&s| s.to_owned());
+        assert!(change_pub_key(&mut session, Some(&allowlist), &mut arg_iter).is_ok());
+    }
+}
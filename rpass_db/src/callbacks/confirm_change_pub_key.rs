@@ -0,0 +1,255 @@
+use super::{session::*, ArgIter, AsyncStorage, Error, Result, SessionTokens};
+
+/// Second and final part of user public key changing. Reads encrypted
+/// confirmation string from `arg_iter`, decrypts it with `storage.sec_key` and
+/// checks if it is equal to the confirmation stored in `session`.
+///
+/// If everything is good then overwrites user public key with the new one,
+/// revokes all session tokens of the user in `tokens`, so the old key can't be
+/// used to resume sessions, and returns *Ok("Ok")*. Pending key change is discarded in any case, so a new
+/// one should be started after failure.
+///
+/// Records already stored by user are left untouched, so if they were encrypted
+/// with the old key then only the old secret key can decrypt them.
+///
+/// See [`super::change_pub_key()`] function for first part
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant or there is no pending key change
+/// * `EmptyConfirmationString` - if confirmation string wasn't provided
+/// * `InvalidConfirmationString` - if confirmation string isn't equal to the
+///   one stored in `session`
+/// * `Storage` - if new key is already used by another user or can't write
+///   new key cause of some error in `user_storage` from `session`
+pub fn confirm_change_pub_key(
+    storage: AsyncStorage,
+    tokens: &SessionTokens,
+    session: &mut Session,
+    arg_iter: ArgIter,
+) -> Result<String> {
+    let authorized_session = session
+        .as_authorized_mut()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+    let pub_key_change = authorized_session
+        .pub_key_change
+        .take()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let encrypted_confirmation = arg_iter.next().ok_or(Error::EmptyConfirmationString)?;
+
+    let mut storage_write = storage.write().unwrap();
+    let confirmation = storage_write.sec_key().decrypt(&encrypted_confirmation);
+    if confirmation != pub_key_change.confirmation {
+        return Err(Error::InvalidConfirmationString);
+    }
+
+    storage_write.index_pub_key(&authorized_session.username, &pub_key_change.pub_key)?;
+    let mut user_storage_write = authorized_session.user_storage.write().unwrap();
+    user_storage_write.set_pub_key(pub_key_change.pub_key)?;
+    drop(user_storage_write);
+
+    tokens.revoke_user(&authorized_session.username);
+    authorized_session.session_token = None;
+    Ok("Ok".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{storage, AsyncUserStorage};
+    use super::*;
+    use crate::storage::Key;
+    use mockall::predicate;
+    use std::io;
+    use std::str::FromStr;
+    use std::time::{Duration, Instant};
+
+    const TEST_USER: &str = "test_user";
+    const CONFIRMATION: &str = "confirmation";
+    const NEW_PUB_KEY: &str = "17:19";
+
+    #[test]
+    fn test_ok() {
+        let mock_storage = AsyncStorage::default();
+        let mock_user_storage = AsyncUserStorage::default();
+        let (server_pub_key, server_sec_key) = Key::generate_pair();
+
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_sec_key()
+            .times(1)
+            .return_const(server_sec_key);
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_index_pub_key()
+            .with(
+                predicate::eq(TEST_USER),
+                predicate::eq(Key::from_str(NEW_PUB_KEY).unwrap()),
+            )
+            .times(1)
+            .returning(|_, _| Ok(()));
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_set_pub_key()
+            .with(predicate::eq(Key::from_str(NEW_PUB_KEY).unwrap()))
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let mut session = build_session(mock_user_storage);
+        let encrypted_confirmation = server_pub_key.encrypt(CONFIRMATION);
+        let mut arg_iter = [encrypted_confirmation].into_iter();
+
+        let tokens = build_tokens();
+        let now = Instant::now();
+        let token = tokens.issue(TEST_USER, now);
+        let other_token = tokens.issue("other_user", now);
+
+        let res = confirm_change_pub_key(mock_storage, &tokens, &mut session, &mut arg_iter);
+        assert_eq!(res.unwrap(), "Ok");
+        assert!(session.as_authorized().unwrap().pub_key_change.is_none());
+        assert!(tokens.resume(&token, now).is_err());
+        assert!(tokens.resume(&other_token, now).is_ok());
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let mock_storage = AsyncStorage::default();
+        let mut session = Session::default();
+        let mut arg_iter = [CONFIRMATION].iter().map(|&s| s.to_owned());
+
+        assert!(matches!(
+            confirm_change_pub_key(mock_storage, &build_tokens(), &mut session, &mut arg_iter),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_no_pending_change() {
+        let mock_storage = AsyncStorage::default();
+        let mut session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+        let mut arg_iter = [CONFIRMATION].iter().map(|&s| s.to_owned());
+
+        assert!(matches!(
+            confirm_change_pub_key(mock_storage, &build_tokens(), &mut session, &mut arg_iter),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_empty_confirmation_string() {
+        let mock_storage = AsyncStorage::default();
+        let mut session = build_session(AsyncUserStorage::default());
+        let mut arg_iter = [].iter().map(|s: &&str| s.to_string());
+
+        assert!(matches!(
+            confirm_change_pub_key(mock_storage, &build_tokens(), &mut session, &mut arg_iter),
+            Err(Error::EmptyConfirmationString)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_confirmation_string() {
+        let mock_storage = AsyncStorage::default();
+        let (server_pub_key, server_sec_key) = Key::generate_pair();
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_sec_key()
+            .times(1)
+            .return_const(server_sec_key);
+
+        let mut session = build_session(AsyncUserStorage::default());
+        let encrypted_confirmation = server_pub_key.encrypt("wrong_confirmation");
+        let mut arg_iter = [encrypted_confirmation].into_iter();
+
+        assert!(matches!(
+            confirm_change_pub_key(mock_storage, &build_tokens(), &mut session, &mut arg_iter),
+            Err(Error::InvalidConfirmationString)
+        ));
+        assert!(session.as_authorized().unwrap().pub_key_change.is_none());
+    }
+
+    #[test]
+    fn test_storage_error() {
+        let mock_storage = AsyncStorage::default();
+        let mock_user_storage = AsyncUserStorage::default();
+        let (server_pub_key, server_sec_key) = Key::generate_pair();
+
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_sec_key()
+            .times(1)
+            .return_const(server_sec_key);
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_index_pub_key()
+            .times(1)
+            .returning(|_, _| Ok(()));
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_set_pub_key()
+            .times(1)
+            .returning(|_| Err(storage::Error::Io(io::Error::other(""))));
+
+        let mut session = build_session(mock_user_storage);
+        let encrypted_confirmation = server_pub_key.encrypt(CONFIRMATION);
+        let mut arg_iter = [encrypted_confirmation].into_iter();
+
+        assert!(matches!(
+            confirm_change_pub_key(mock_storage, &build_tokens(), &mut session, &mut arg_iter),
+            Err(Error::Storage(_))
+        ));
+    }
+
+    #[test]
+    fn test_key_already_in_use() {
+        let mock_storage = AsyncStorage::default();
+        let (server_pub_key, server_sec_key) = Key::generate_pair();
+
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_sec_key()
+            .times(1)
+            .return_const(server_sec_key);
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_index_pub_key()
+            .times(1)
+            .returning(|_, _| Err(storage::Error::KeyAlreadyInUse));
+
+        let mut session = build_session(AsyncUserStorage::default());
+        let encrypted_confirmation = server_pub_key.encrypt(CONFIRMATION);
+        let mut arg_iter = [encrypted_confirmation].into_iter();
+
+        assert!(matches!(
+            confirm_change_pub_key(mock_storage, &build_tokens(), &mut session, &mut arg_iter),
+            Err(Error::Storage(storage::Error::KeyAlreadyInUse))
+        ));
+    }
+
+    /// Builds authorized session with pending change to `NEW_PUB_KEY`
+    fn build_session(user_storage: AsyncUserStorage) -> Session {
+        let mut authorized = Authorized::new(TEST_USER.to_owned(), user_storage);
+        authorized.pub_key_change = Some(PubKeyChange {
+            pub_key: Key::from_str(NEW_PUB_KEY).unwrap(),
+            confirmation: CONFIRMATION.to_owned(),
+        });
+        Session::Authorized(authorized)
+    }
+
+    fn build_tokens() -> SessionTokens {
+        SessionTokens::new(Duration::from_secs(60))
+    }
+}
@@ -1,71 +1,132 @@
-use super::{session::*, ArgIter, AsyncStorage, Error, Result};
+use super::{session::*, ArgIter, AsyncStorage, AuditEvent, AuditLog, Error, Result};
+use std::time::Duration;
 
 /// Second and final part of user logging. Reads encrypted confirmation string
 /// from `arg_iter`, decrypts it with `storage.sec_key` and checks if it is
-/// equal to the *login_confirmation* in session.
+/// equal to the *login_confirmation* in session bound to the connection and
+/// issued not earlier than `expiry` ago.
+///
+/// If `max_sessions` is set, user can't have more active sessions at once.
 ///
 /// If everything is good then:
 /// 1. Sets `session` to the [`Authorized`] state
 /// 3. Return *Ok("Ok")*
 ///
+/// Login confirmation is discarded in any case, so it can't be replayed
+/// and a new login should be started after failure. Result of the attempt is
+/// written to `audit_log` with `connection` peer address.
+///
 /// See [`super::login()`] function for first part
 ///
 /// # Errors
 ///
 /// * `UnacceptableRequestAtThisState` - if session is not an Unauthorized
-/// variant
+///   variant or there is no pending login confirmation
 /// * `EmptyConfirmationString` - if confirmation string wasn't provided
+/// * `ConfirmationExpired` - if login confirmation was issued more than
+///   `expiry` ago
 /// * `InvalidConfirmationString` - if confirmation string isn't equal to the
-/// one stored in `session`
+///   one stored in `session`
+/// * `TooManySessions` - if user already has `max_sessions` active sessions
+/// * `Storage` - if can't get user storage cause of some error in `storage`
 pub fn confirm_login(
     storage: AsyncStorage,
+    audit_log: &AuditLog,
     session: &mut Session,
+    connection: &ConnectionInfo,
     arg_iter: ArgIter,
+    expiry: Duration,
+    max_sessions: Option<usize>,
 ) -> Result<String> {
     let unauthorized_session = session
-        .as_unauthorized()
+        .as_unauthorized_mut()
         .ok_or(Error::UnacceptableRequestAtThisState)?;
+    let login_confirmation = unauthorized_session
+        .login_confirmation
+        .take()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+    let username = unauthorized_session.username.clone();
+
+    let res = check_confirmation(
+        &storage,
+        unauthorized_session,
+        &login_confirmation,
+        arg_iter,
+        expiry,
+    )
+    .and_then(|()| {
+        let mut storage_write = storage.write().unwrap();
+        if let Some(max_sessions) = max_sessions {
+            if storage_write.session_count(&username) >= max_sessions {
+                return Err(Error::TooManySessions(max_sessions));
+            }
+        }
+        let user_storage = storage_write.get_user_storage(&username)?;
+        *session = Session::Authorized(Authorized::new(username.clone(), user_storage));
+        Ok("Ok".to_owned())
+    });
+
+    let event = match &res {
+        Ok(_) => AuditEvent::LoginSucceeded,
+        Err(err) => AuditEvent::LoginFailed {
+            reason: err.to_string(),
+        },
+    };
+    audit_log.record(event, &username, connection);
+    res
+}
 
+/// Checks if encrypted confirmation string from `arg_iter` matches
+/// `login_confirmation` of `unauthorized_session`
+///
+/// # Errors
+///
+/// See [`confirm_login()`]
+fn check_confirmation(
+    storage: &AsyncStorage,
+    unauthorized_session: &Unauthorized,
+    login_confirmation: &LoginConfirmation,
+    arg_iter: ArgIter,
+    expiry: Duration,
+) -> Result<()> {
     let encrypted_confirmation = arg_iter.next().ok_or(Error::EmptyConfirmationString)?;
 
+    if login_confirmation.issued_at.elapsed() > expiry {
+        return Err(Error::ConfirmationExpired);
+    }
+
     let sec_key = {
         let storage_read = storage.read().unwrap();
         storage_read.sec_key().clone()
     };
 
     let confirmation = sec_key.decrypt(&encrypted_confirmation);
-    if confirmation != unauthorized_session.login_confirmation {
+    if confirmation != unauthorized_session.bind_to_connection(&login_confirmation.confirmation) {
         return Err(Error::InvalidConfirmationString);
     }
-
-    let mut storage_write = storage.write().unwrap();
-    *session = Session::Authorized(Authorized {
-        username: unauthorized_session.username.clone(),
-        user_storage: storage_write.get_user_storage(&unauthorized_session.username)?,
-    });
-    Ok("Ok".to_owned())
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::super::{storage, AsyncUserStorage};
     use super::*;
+    use crate::audit_log::MemorySink;
     use crate::storage::Key;
     use mockall::predicate;
     use std::sync::Arc;
+    use std::time::Instant;
 
     const TEST_USER: &str = "test_user";
+    const CONFIRMATION: &str = "confirmation";
+    const EXPIRY: Duration = Duration::from_secs(30);
 
     #[test]
     fn test_ok() {
         let mock_storage = AsyncStorage::default();
-        let mut session = Session::Unauthorized(Unauthorized {
-            username: TEST_USER.to_owned(),
-            login_confirmation: String::from("confirmation"),
-        });
+        let mut session = build_session(Instant::now());
         let (pub_key, sec_key) = Key::generate_pair();
-        let encrypted_confirmation =
-            pub_key.encrypt(&session.as_unauthorized().unwrap().login_confirmation);
+        let encrypted_confirmation = encrypt_challenge(&pub_key, &session);
         let mut arg_iter = encrypted_confirmation.split_whitespace().map(str::to_owned);
 
         {
@@ -80,7 +141,67 @@ mod tests {
                 .times(1)
                 .returning(|_| Ok(Arc::default()));
         }
-        let res = confirm_login(mock_storage, &mut session, &mut arg_iter);
+        let res = confirm_login(
+            mock_storage,
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+            EXPIRY,
+            None,
+        );
+        assert_eq!(res.unwrap(), "Ok");
+        assert!(session.is_authorized());
+    }
+
+    #[test]
+    fn test_max_sessions() {
+        const MAX_SESSIONS: usize = 2;
+        let mock_storage = AsyncStorage::default();
+        let (pub_key, sec_key) = Key::generate_pair();
+        {
+            // Every closure holds one more reference, so the rest are sessions
+            let user_storage = AsyncUserStorage::default();
+            let counted_user_storage = user_storage.clone();
+            let mut mock_storage_write = mock_storage.write().unwrap();
+            mock_storage_write.expect_sec_key().return_const(sec_key);
+            mock_storage_write
+                .expect_session_count()
+                .with(predicate::eq(TEST_USER))
+                .returning(move |_| Arc::strong_count(&counted_user_storage) - 2);
+            mock_storage_write
+                .expect_get_user_storage()
+                .with(predicate::eq(TEST_USER))
+                .returning(move |_| Ok(user_storage.clone()));
+        }
+        let login = || {
+            let mut session = build_session(Instant::now());
+            let encrypted_confirmation = encrypt_challenge(&pub_key, &session);
+            let res = confirm_login(
+                mock_storage.clone(),
+                &AuditLog::disabled(),
+                &mut session,
+                &ConnectionInfo::default(),
+                &mut [encrypted_confirmation].into_iter(),
+                EXPIRY,
+                Some(MAX_SESSIONS),
+            );
+            (res, session)
+        };
+
+        let mut sessions = vec![];
+        for _ in 0..MAX_SESSIONS {
+            let (res, session) = login();
+            assert_eq!(res.unwrap(), "Ok");
+            sessions.push(session);
+        }
+
+        let (res, session) = login();
+        assert!(matches!(res, Err(Error::TooManySessions(MAX_SESSIONS))));
+        assert!(session.is_unauthorized());
+
+        sessions.pop();
+        let (res, session) = login();
         assert_eq!(res.unwrap(), "Ok");
         assert!(session.is_authorized());
     }
@@ -88,49 +209,153 @@ mod tests {
     #[test]
     fn test_session_is_authorized() {
         let mock_storage = AsyncStorage::default();
-        let mut session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: AsyncUserStorage::default(),
-        });
+        let mut session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
 
         let mut arg_iter = [""].iter().map(|&s| s.to_owned());
 
-        let res = confirm_login(mock_storage, &mut session, &mut arg_iter);
+        let res = confirm_login(
+            mock_storage,
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+            EXPIRY,
+            None,
+        );
         assert!(matches!(res, Err(Error::UnacceptableRequestAtThisState)));
     }
 
     #[test]
     fn test_session_is_ended() {
         let mock_storage = AsyncStorage::default();
-        let mut session = Session::Authorized(Authorized {
-            username: String::from("test"),
-            user_storage: AsyncUserStorage::default(),
-        });
+        let mut session = Session::Authorized(Authorized::new(
+            String::from("test"),
+            AsyncUserStorage::default(),
+        ));
 
         let mut arg_iter = [""].iter().map(|&s| s.to_owned());
 
-        let res = confirm_login(mock_storage, &mut session, &mut arg_iter);
+        let res = confirm_login(
+            mock_storage,
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+            EXPIRY,
+            None,
+        );
         assert!(matches!(res, Err(Error::UnacceptableRequestAtThisState)));
     }
 
     #[test]
     fn test_empty_confirmation_string() {
         let mock_storage = AsyncStorage::default();
-        let mut session = Session::default();
+        let mut session = build_session(Instant::now());
         let mut arg_iter = [].iter().map(|s: &&str| s.to_string());
 
-        let res = confirm_login(mock_storage, &mut session, &mut arg_iter);
+        let res = confirm_login(
+            mock_storage,
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+            EXPIRY,
+            None,
+        );
         assert!(matches!(res, Err(Error::EmptyConfirmationString)));
         assert!(session.is_unauthorized());
     }
 
+    #[test]
+    fn test_no_login_confirmation() {
+        let mock_storage = AsyncStorage::default();
+        let mut session = Session::default();
+        let mut arg_iter = [CONFIRMATION].iter().map(|&s| s.to_owned());
+
+        let res = confirm_login(
+            mock_storage,
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+            EXPIRY,
+            None,
+        );
+        assert!(matches!(res, Err(Error::UnacceptableRequestAtThisState)));
+        assert!(session.is_unauthorized());
+    }
+
+    #[test]
+    fn test_expired_confirmation() {
+        let mock_storage = AsyncStorage::default();
+        let issued_at = Instant::now()
+            .checked_sub(EXPIRY + Duration::from_secs(1))
+            .unwrap();
+        let mut session = build_session(issued_at);
+        let (pub_key, _) = Key::generate_pair();
+        let encrypted_confirmation = encrypt_challenge(&pub_key, &session);
+        let mut arg_iter = encrypted_confirmation.split_whitespace().map(str::to_owned);
+
+        let res = confirm_login(
+            mock_storage,
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+            EXPIRY,
+            None,
+        );
+        assert!(matches!(res, Err(Error::ConfirmationExpired)));
+        assert!(session.is_unauthorized());
+    }
+
+    #[test]
+    fn test_confirmation_reuse() {
+        let mock_storage = AsyncStorage::default();
+        let mut session = build_session(Instant::now());
+        let (pub_key, sec_key) = Key::generate_pair();
+
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_sec_key()
+            .times(1)
+            .return_const(sec_key);
+        let wrong_confirmation = pub_key.encrypt("wrong_confirmation");
+        let mut arg_iter = wrong_confirmation.split_whitespace().map(str::to_owned);
+        let res = confirm_login(
+            mock_storage.clone(),
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+            EXPIRY,
+            None,
+        );
+        assert!(matches!(res, Err(Error::InvalidConfirmationString)));
+
+        let encrypted_confirmation = encrypt_challenge(&pub_key, &session);
+        let mut arg_iter = encrypted_confirmation.split_whitespace().map(str::to_owned);
+        let res = confirm_login(
+            mock_storage,
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+            EXPIRY,
+            None,
+        );
+        assert!(matches!(res, Err(Error::UnacceptableRequestAtThisState)));
+        assert!(session.is_unauthorized());
+    }
+
     #[test]
     fn test_invalid_confirmation_string() {
         let mock_storage = AsyncStorage::default();
-        let mut session = Session::Unauthorized(Unauthorized {
-            login_confirmation: String::from("confirmation"),
-            ..Unauthorized::default()
-        });
+        let mut session = build_session(Instant::now());
         let (pub_key, sec_key) = Key::generate_pair();
         let encrypted_confirmation = pub_key.encrypt("wrong_confirmation");
         let mut arg_iter = encrypted_confirmation.split_whitespace().map(str::to_owned);
@@ -141,7 +366,82 @@ mod tests {
             .expect_sec_key()
             .times(1)
             .return_const(sec_key);
-        let res = confirm_login(mock_storage, &mut session, &mut arg_iter);
+        let res = confirm_login(
+            mock_storage,
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+            EXPIRY,
+            None,
+        );
+        assert!(matches!(res, Err(Error::InvalidConfirmationString)));
+        assert!(session.is_unauthorized());
+    }
+
+    #[test]
+    fn test_failed_login_audited() {
+        let mock_storage = AsyncStorage::default();
+        let sink = MemorySink::default();
+        let audit_log = AuditLog::new(sink.clone());
+        let connection = ConnectionInfo {
+            peer_addr: String::from("127.0.0.1:4000"),
+            ..ConnectionInfo::default()
+        };
+        let mut session = build_session(Instant::now());
+        let (pub_key, sec_key) = Key::generate_pair();
+        let encrypted_confirmation = pub_key.encrypt("wrong_confirmation");
+        let mut arg_iter = encrypted_confirmation.split_whitespace().map(str::to_owned);
+
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_sec_key()
+            .times(1)
+            .return_const(sec_key);
+        let res = confirm_login(
+            mock_storage,
+            &audit_log,
+            &mut session,
+            &connection,
+            &mut arg_iter,
+            EXPIRY,
+            None,
+        );
+        assert!(matches!(res, Err(Error::InvalidConfirmationString)));
+
+        let entries = sink.entries();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].contains("event=login_failed"));
+        assert!(entries[0].contains(&format!("user={TEST_USER:?}")));
+        assert!(entries[0].contains("peer=\"127.0.0.1:4000\""));
+        assert!(!entries[0].contains("wrong_confirmation"));
+    }
+
+    #[test]
+    fn test_cross_session_replay() {
+        let mock_storage = AsyncStorage::default();
+        let captured_session = build_session(Instant::now());
+        let mut session = build_session(Instant::now());
+        let (pub_key, sec_key) = Key::generate_pair();
+        let encrypted_confirmation = encrypt_challenge(&pub_key, &captured_session);
+        let mut arg_iter = encrypted_confirmation.split_whitespace().map(str::to_owned);
+
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_sec_key()
+            .times(1)
+            .return_const(sec_key);
+        let res = confirm_login(
+            mock_storage,
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+            EXPIRY,
+            None,
+        );
         assert!(matches!(res, Err(Error::InvalidConfirmationString)));
         assert!(session.is_unauthorized());
     }
@@ -149,13 +449,9 @@ mod tests {
     #[test]
     fn test_storage_error() {
         let mock_storage = AsyncStorage::default();
-        let mut session = Session::Unauthorized(Unauthorized {
-            username: TEST_USER.to_owned(),
-            login_confirmation: String::from("confirmation"),
-        });
+        let mut session = build_session(Instant::now());
         let (pub_key, sec_key) = Key::generate_pair();
-        let encrypted_confirmation =
-            pub_key.encrypt(&session.as_unauthorized().unwrap().login_confirmation);
+        let encrypted_confirmation = encrypt_challenge(&pub_key, &session);
         let mut arg_iter = encrypted_confirmation.split_whitespace().map(str::to_owned);
 
         {
@@ -170,8 +466,37 @@ mod tests {
                 .times(1)
                 .returning(|_| Err(storage::Error::UserAlreadyExists(TEST_USER.to_owned())));
         }
-        let res = confirm_login(mock_storage, &mut session, &mut arg_iter);
+        let res = confirm_login(
+            mock_storage,
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+            EXPIRY,
+            None,
+        );
         assert!(matches!(res, Err(Error::Storage(_))));
         assert!(session.is_unauthorized());
     }
+
+    fn build_session(issued_at: Instant) -> Session {
+        Session::Unauthorized(Unauthorized {
+            username: TEST_USER.to_owned(),
+            login_confirmation: Some(LoginConfirmation {
+                confirmation: CONFIRMATION.to_owned(),
+                issued_at,
+            }),
+            ..Unauthorized::default()
+        })
+    }
+
+    /// Encrypts login challenge of `session` as client should do
+    fn encrypt_challenge(pub_key: &Key, session: &Session) -> String {
+        pub_key.encrypt(
+            &session
+                .as_unauthorized()
+                .unwrap()
+                .bind_to_connection(CONFIRMATION),
+        )
+    }
 }
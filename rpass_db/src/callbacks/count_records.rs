@@ -0,0 +1,69 @@
+use super::{session::*, Error, Result};
+
+/// Gets number of records of user stored in `session`.
+/// Cheaper than [`super::list_records()`], cause record names aren't sent
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `Storage` - if can't count records cause of some error in `user_storage`
+///   from session
+pub fn count_records(session: &Session) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let count = authorized_session
+        .user_storage
+        .read()
+        .unwrap()
+        .count_records()?;
+    Ok(count.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{storage, AsyncUserStorage};
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn test_ok() {
+        for count in [0, 1, 5] {
+            let mock_user_storage = AsyncUserStorage::default();
+            mock_user_storage
+                .write()
+                .unwrap()
+                .expect_count_records()
+                .times(1)
+                .returning(move || Ok(count));
+            let session =
+                Session::Authorized(Authorized::new(String::default(), mock_user_storage));
+
+            assert_eq!(count_records(&session).unwrap(), count.to_string());
+        }
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        assert!(matches!(
+            count_records(&Session::default()),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_storage_error() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_count_records()
+            .times(1)
+            .returning(|| Err(storage::Error::Io(io::Error::other(""))));
+        let session = Session::Authorized(Authorized::new(String::default(), mock_user_storage));
+
+        assert!(matches!(count_records(&session), Err(Error::Storage(_))));
+    }
+}
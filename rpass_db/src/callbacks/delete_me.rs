@@ -1,14 +1,21 @@
-use super::{session::*, AsyncStorage, Error, Result};
+use super::{session::*, AsyncStorage, AuditEvent, AuditLog, Error, Result, SessionTokens};
 
 /// Deletes current user. Takes *username* from `session` and deletes it in
-/// `storage`
+/// `storage`. Deletion is written to `audit_log` with `connection` peer address.
+/// All session tokens of the user are revoked in `tokens`
 ///
 /// # Errors
 ///
 /// * `UnacceptableRequestAtThisState` - if session is not an Authorized
-/// variant
+///   variant
 /// * `Storage` - if can't delete user cause of some error in `storage`
-pub fn delete_me(storage: AsyncStorage, session: &mut Session) -> Result<String> {
+pub fn delete_me(
+    storage: AsyncStorage,
+    audit_log: &AuditLog,
+    tokens: &SessionTokens,
+    session: &mut Session,
+    connection: &ConnectionInfo,
+) -> Result<String> {
     let authorized_session = session
         .as_authorized()
         .ok_or(Error::UnacceptableRequestAtThisState)?;
@@ -18,13 +25,13 @@ pub fn delete_me(storage: AsyncStorage, session: &mut Session) -> Result<String>
 
     let mut storage_write = storage.write().unwrap();
     if let Err(err) = storage_write.delete_user(&username) {
-        *session = Session::Authorized(Authorized {
-            user_storage: storage_write.get_user_storage(&username).unwrap(),
-            username,
-        });
+        let user_storage = storage_write.get_user_storage(&username).unwrap();
+        *session = Session::Authorized(Authorized::new(username, user_storage));
         return Err(err.into());
     }
 
+    tokens.revoke_user(&username);
+    audit_log.record(AuditEvent::AccountDeleted, &username, connection);
     Ok("Ok".to_owned())
 }
 
@@ -34,16 +41,17 @@ mod tests {
     use super::*;
     use mockall::predicate;
     use std::io;
+    use std::time::{Duration, Instant};
 
     const TEST_USER: &str = "test_user";
 
     #[test]
     fn test_ok() {
         let mock_storage = AsyncStorage::default();
-        let mut session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: AsyncUserStorage::default(),
-        });
+        let mut session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
 
         mock_storage
             .write()
@@ -51,7 +59,13 @@ mod tests {
             .expect_delete_user()
             .with(predicate::eq(TEST_USER))
             .returning(|_| Ok(()));
-        let res = delete_me(mock_storage, &mut session);
+        let res = delete_me(
+            mock_storage,
+            &AuditLog::disabled(),
+            &build_tokens(),
+            &mut session,
+            &ConnectionInfo::default(),
+        );
         assert_eq!(res.unwrap(), "Ok");
         assert!(matches!(session, Session::Unauthorized(_)));
         assert!(session.as_unauthorized().unwrap().username.is_empty());
@@ -63,7 +77,13 @@ mod tests {
         let mut session = Session::default();
 
         assert!(matches!(
-            delete_me(mock_storage, &mut session),
+            delete_me(
+                mock_storage,
+                &AuditLog::disabled(),
+                &build_tokens(),
+                &mut session,
+                &ConnectionInfo::default()
+            ),
             Err(Error::UnacceptableRequestAtThisState)
         ));
     }
@@ -71,10 +91,10 @@ mod tests {
     #[test]
     fn test_multi_session() {
         let mock_storage = AsyncStorage::default();
-        let mut session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: AsyncUserStorage::default(),
-        });
+        let mut session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
 
         {
             let mut mock_storage_write = mock_storage.write().unwrap();
@@ -87,7 +107,14 @@ mod tests {
                 .with(predicate::eq(TEST_USER))
                 .returning(|_| Ok(AsyncUserStorage::default()));
         }
-        assert!(delete_me(mock_storage, &mut session).is_err());
+        assert!(delete_me(
+            mock_storage,
+            &AuditLog::disabled(),
+            &build_tokens(),
+            &mut session,
+            &ConnectionInfo::default()
+        )
+        .is_err());
         assert!(matches!(session, Session::Authorized(_)));
     }
 
@@ -95,10 +122,10 @@ mod tests {
     #[should_panic]
     fn test_double_storage_error() {
         let mock_storage = AsyncStorage::default();
-        let mut session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: AsyncUserStorage::default(),
-        });
+        let mut session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
 
         {
             let mut mock_storage_write = mock_storage.write().unwrap();
@@ -109,8 +136,46 @@ mod tests {
             mock_storage_write
                 .expect_get_user_storage()
                 .with(predicate::eq(TEST_USER))
-                .returning(|_| Err(storage::Error::Io(io::Error::new(io::ErrorKind::Other, ""))));
+                .returning(|_| Err(storage::Error::Io(io::Error::other(""))));
         }
-        let _ = delete_me(mock_storage, &mut session);
+        let _ = delete_me(
+            mock_storage,
+            &AuditLog::disabled(),
+            &build_tokens(),
+            &mut session,
+            &ConnectionInfo::default(),
+        );
+    }
+
+    #[test]
+    fn test_revokes_session_tokens() {
+        let mock_storage = AsyncStorage::default();
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_delete_user()
+            .with(predicate::eq(TEST_USER))
+            .returning(|_| Ok(()));
+        let tokens = build_tokens();
+        let now = Instant::now();
+        let token = tokens.issue(TEST_USER, now);
+        let mut session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+
+        delete_me(
+            mock_storage,
+            &AuditLog::disabled(),
+            &tokens,
+            &mut session,
+            &ConnectionInfo::default(),
+        )
+        .unwrap();
+        assert!(tokens.resume(&token, now).is_err());
+    }
+
+    fn build_tokens() -> SessionTokens {
+        SessionTokens::new(Duration::from_secs(60))
     }
 }
@@ -3,28 +3,36 @@ use super::{session::*, utils, ArgIter, Error, Result};
 /// Deletes record for user stored in `session`.
 /// Resource name is read from `arg_iter`
 ///
+/// Record is deleted holding per-record lock, like in [`super::patch_record()`],
+/// so concurrent patch of the same record can't bring it back
+///
 /// # Errors
 ///
 /// * `UnacceptableRequestAtThisState` - if session is not an Authorized
-/// variant
+///   variant
 /// * `EmptyResourceName` - if resource name wasn't provided
 /// * `InvalidResourceName` - if resource name is invalid
 /// * `Storage` - if can't delete record cause of some error in `user_storage`
-/// from session
+///   from session
 pub fn delete_record(session: &Session, arg_iter: ArgIter) -> Result<String> {
     let authorized_session = session
         .as_authorized()
         .ok_or(Error::UnacceptableRequestAtThisState)?;
 
     let resource_name = arg_iter.next().ok_or(Error::EmptyResourceName)?;
-    if !utils::is_safe_for_filename(&resource_name) {
+    if !utils::is_safe_for_resource_name(&resource_name) {
         return Err(Error::InvalidResourceName);
     }
 
-    let mut storage_write = authorized_session.user_storage.write().unwrap();
-    storage_write.delete_record(&resource_name)?;
-
-    Ok("Ok".to_owned())
+    let user_storage = &authorized_session.user_storage;
+    let record_locks = user_storage.read().unwrap().record_locks();
+    record_locks.with_record_lock(&resource_name, || {
+        user_storage
+            .write()
+            .unwrap()
+            .delete_record(&resource_name)?;
+        Ok("Ok".to_owned())
+    })
 }
 
 #[cfg(test)]
@@ -32,6 +40,7 @@ mod tests {
     use super::super::{storage, AsyncUserStorage};
     use super::*;
     use std::io;
+    use std::sync::Arc;
 
     use mockall::predicate;
 
@@ -40,7 +49,7 @@ mod tests {
 
     #[test]
     fn test_ok() {
-        let mock_user_storage = AsyncUserStorage::default();
+        let mock_user_storage = build_locking_storage();
         mock_user_storage
             .write()
             .unwrap()
@@ -48,10 +57,7 @@ mod tests {
             .with(predicate::eq(TEST_RESOURCE))
             .returning(|_| Ok(()));
 
-        let session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: mock_user_storage,
-        });
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
 
         let args = [TEST_RESOURCE.to_owned()];
         let mut arg_iter = args.iter().cloned();
@@ -74,10 +80,10 @@ mod tests {
 
     #[test]
     fn test_empty_resource() {
-        let session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: AsyncUserStorage::default(),
-        });
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
 
         let args = [];
         let mut arg_iter = args.iter().cloned();
@@ -90,10 +96,10 @@ mod tests {
 
     #[test]
     fn test_invalid_resource() {
-        let session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: AsyncUserStorage::default(),
-        });
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
 
         let args = ["/etc/passwd".to_owned()];
         let mut arg_iter = args.iter().cloned();
@@ -106,19 +112,16 @@ mod tests {
 
     #[test]
     fn test_storage_error() {
-        let mock_user_storage = AsyncUserStorage::default();
+        let mock_user_storage = build_locking_storage();
         mock_user_storage
             .write()
             .unwrap()
             .expect_delete_record()
             .times(1)
             .with(predicate::eq(TEST_RESOURCE))
-            .returning(|_| Err(storage::Error::Io(io::Error::new(io::ErrorKind::Other, ""))));
+            .returning(|_| Err(storage::Error::Io(io::Error::other(""))));
 
-        let session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: mock_user_storage,
-        });
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
 
         let args = [TEST_RESOURCE.to_owned()];
         let mut arg_iter = args.iter().cloned();
@@ -128,4 +131,16 @@ mod tests {
             Err(Error::Storage(_))
         ));
     }
+
+    /// Builds user storage mock expecting to be asked for record locks once
+    fn build_locking_storage() -> AsyncUserStorage {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_record_locks()
+            .times(1)
+            .returning(Arc::default);
+        mock_user_storage
+    }
 }
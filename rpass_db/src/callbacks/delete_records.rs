@@ -0,0 +1,186 @@
+use super::{session::*, utils, ArgIter, Error, Result};
+
+/// Deletes records for user stored in `session`.
+/// Resource names are read from `arg_iter`
+///
+/// Invalid or missing resources don't prevent deletion of others. All existing
+/// records are deleted at once with [`UserStorage::delete_records()`],
+/// so with transactional backend either all of them are deleted or none.
+/// Returns one line per resource in the same order: *"Ok"* or error in
+/// `Error[<code>]: <message>` format. If deletion fails, every existing
+/// resource gets its error
+///
+/// [`UserStorage::delete_records()`]: crate::storage::UserStorage::delete_records()
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `EmptyResourceName` - if no resource names were provided
+pub fn delete_records(session: &Session, arg_iter: ArgIter) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let resources: Vec<String> = arg_iter.collect();
+    if resources.is_empty() {
+        return Err(Error::EmptyResourceName);
+    }
+
+    let mut storage_write = authorized_session.user_storage.write().unwrap();
+    let mut existing = vec![];
+    let mut statuses: Vec<String> = resources
+        .into_iter()
+        .map(|resource| {
+            if !utils::is_safe_for_resource_name(&resource) {
+                return Err(Error::InvalidResourceName);
+            }
+            match storage_write.record_exists(&resource)? {
+                true if !existing.contains(&resource) => {
+                    existing.push(resource);
+                    Ok(())
+                }
+                _ => Err(Error::RecordNotFound(resource)),
+            }
+        })
+        .map(|res| match res {
+            Ok(()) => String::from("Ok"),
+            Err(err) => format_error(&err),
+        })
+        .collect();
+
+    if !existing.is_empty() {
+        if let Err(err) = storage_write.delete_records(&existing) {
+            let error = format_error(&err.into());
+            for status in statuses.iter_mut().filter(|status| *status == "Ok") {
+                status.clone_from(&error);
+            }
+        }
+    }
+
+    Ok(statuses.join("\n"))
+}
+
+/// Formats `err` as a status line of one resource
+fn format_error(err: &Error) -> String {
+    format!("Error[{}]: {err}", err.code())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{storage, AsyncUserStorage};
+    use super::*;
+
+    use mockall::predicate;
+    use std::io;
+
+    const TEST_USER: &str = "test_user";
+
+    #[test]
+    fn test_mixed_resources() {
+        let mock_user_storage = AsyncUserStorage::default();
+        {
+            let mut storage_write = mock_user_storage.write().unwrap();
+            storage_write
+                .expect_record_exists()
+                .with(predicate::eq("example.com"))
+                .returning(|_| Ok(true));
+            storage_write
+                .expect_record_exists()
+                .with(predicate::eq("missing.com"))
+                .returning(|_| Ok(false));
+            storage_write
+                .expect_record_exists()
+                .with(predicate::eq("broken.com"))
+                .returning(|_| Err(storage::Error::Io(io::Error::other("disk failure"))));
+            storage_write
+                .expect_delete_records()
+                .times(1)
+                .with(predicate::eq(vec![String::from("example.com")]))
+                .returning(|_| Ok(()));
+        }
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
+
+        let args = [
+            "example.com",
+            "../passwd",
+            "missing.com",
+            "broken.com",
+            "example.com",
+        ];
+        let mut arg_iter = args.iter().map(|&arg| arg.to_owned());
+
+        assert_eq!(
+            delete_records(&session, &mut arg_iter).unwrap(),
+            "Ok\n\
+             Error[InvalidResourceName]: invalid resource name\n\
+             Error[RecordNotFound]: no such record: `missing.com`\n\
+             Error[Io]: storage error: io error: disk failure\n\
+             Error[RecordNotFound]: no such record: `example.com`"
+        );
+    }
+
+    #[test]
+    fn test_deletion_failure() {
+        let mock_user_storage = AsyncUserStorage::default();
+        {
+            let mut storage_write = mock_user_storage.write().unwrap();
+            storage_write
+                .expect_record_exists()
+                .with(predicate::in_iter(["a.com", "b.com"]))
+                .returning(|_| Ok(true));
+            storage_write
+                .expect_record_exists()
+                .with(predicate::eq("missing.com"))
+                .returning(|_| Ok(false));
+            storage_write
+                .expect_delete_records()
+                .times(1)
+                .with(predicate::eq(vec![
+                    String::from("a.com"),
+                    String::from("b.com"),
+                ]))
+                .returning(|_| Err(storage::Error::Io(io::Error::other("disk failure"))));
+        }
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
+
+        let args = ["a.com", "missing.com", "b.com"];
+        let mut arg_iter = args.iter().map(|&arg| arg.to_owned());
+
+        assert_eq!(
+            delete_records(&session, &mut arg_iter).unwrap(),
+            "Error[Io]: storage error: io error: disk failure\n\
+             Error[RecordNotFound]: no such record: `missing.com`\n\
+             Error[Io]: storage error: io error: disk failure"
+        );
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let session = Session::default();
+
+        let args = [String::from("example.com")];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            delete_records(&session, &mut arg_iter),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_empty_resources() {
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+
+        let args: [String; 0] = [];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            delete_records(&session, &mut arg_iter),
+            Err(Error::EmptyResourceName)
+        ));
+    }
+}
@@ -1,10 +1,20 @@
 use super::storage;
+use rpass::error::ErrorCode;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("unacceptable request at this state")]
     UnacceptableRequestAtThisState,
 
+    #[error("permission denied")]
+    PermissionDenied,
+
+    #[error("server is in maintenance mode, try again later")]
+    Maintenance,
+
+    #[error("invalid maintenance mode: `{0}`, expected `on` or `off`")]
+    InvalidMaintenanceMode(String),
+
     #[error("empty username")]
     EmptyUsername,
 
@@ -17,24 +27,103 @@ pub enum Error {
     #[error("invalid key: `{0}`")]
     InvalidKey(#[from] rpass::key::ParseError),
 
+    #[error("key isn't allowed to register on this server")]
+    KeyNotAllowed,
+
     #[error("empty confirmation string")]
     EmptyConfirmationString,
 
     #[error("invalid confirmation string")]
     InvalidConfirmationString,
 
+    #[error("confirmation string expired")]
+    ConfirmationExpired,
+
+    #[error("empty session token")]
+    EmptySessionToken,
+
+    #[error("invalid session token")]
+    InvalidSessionToken,
+
+    #[error("session token expired")]
+    SessionTokenExpired,
+
     #[error("empty resource name")]
     EmptyResourceName,
 
     #[error("invalid resource name")]
     InvalidResourceName,
 
+    #[error("no such record: `{0}`")]
+    RecordNotFound(String),
+
     #[error("empty record content")]
     EmptyRecordContent,
 
+    #[error("record is larger than {0} bytes")]
+    RecordTooLarge(usize),
+
+    #[error("quota of {0} records exceeded")]
+    QuotaExceeded(usize),
+
+    #[error("limit of {0} concurrent sessions reached, log out somewhere else first")]
+    TooManySessions(usize),
+
     #[error("invalid record format")]
     InvalidRecordFormat(#[from] storage::ParseRecordError),
 
+    #[error("empty record patch")]
+    EmptyRecordPatch,
+
+    #[error("invalid record patch: {0}")]
+    InvalidRecordPatch(String),
+
+    #[error("empty record version")]
+    EmptyRecordVersion,
+
+    #[error("invalid record version: `{0}`")]
+    InvalidRecordVersion(String),
+
+    #[error("invalid pagination argument: `{0}`")]
+    InvalidPagination(String),
+
     #[error("storage error: {0}")]
     Storage(#[from] storage::Error),
 }
+
+impl Error {
+    /// Gets machine-readable code of the error
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::UnacceptableRequestAtThisState => ErrorCode::UnacceptableRequestAtThisState,
+            Self::PermissionDenied => ErrorCode::PermissionDenied,
+            Self::Maintenance => ErrorCode::Maintenance,
+            Self::InvalidMaintenanceMode(_) => ErrorCode::InvalidMaintenanceMode,
+            Self::EmptyUsername => ErrorCode::EmptyUsername,
+            Self::InvalidUsername(_) => ErrorCode::InvalidUsername,
+            Self::EmptyKey => ErrorCode::EmptyKey,
+            Self::InvalidKey(_) => ErrorCode::InvalidKey,
+            Self::KeyNotAllowed => ErrorCode::KeyNotAllowed,
+            Self::EmptyConfirmationString => ErrorCode::EmptyConfirmationString,
+            Self::InvalidConfirmationString => ErrorCode::InvalidConfirmationString,
+            Self::ConfirmationExpired => ErrorCode::ConfirmationExpired,
+            Self::EmptySessionToken => ErrorCode::EmptySessionToken,
+            Self::InvalidSessionToken => ErrorCode::InvalidSessionToken,
+            Self::SessionTokenExpired => ErrorCode::SessionTokenExpired,
+            Self::EmptyResourceName => ErrorCode::EmptyResourceName,
+            Self::InvalidResourceName => ErrorCode::InvalidResourceName,
+            Self::RecordNotFound(_) => ErrorCode::RecordNotFound,
+            Self::EmptyRecordContent => ErrorCode::EmptyRecordContent,
+            Self::RecordTooLarge(_) => ErrorCode::RecordTooLarge,
+            Self::QuotaExceeded(_) => ErrorCode::QuotaExceeded,
+            Self::TooManySessions(_) => ErrorCode::TooManySessions,
+            Self::InvalidRecordFormat(_) => ErrorCode::InvalidRecordFormat,
+            Self::EmptyRecordPatch => ErrorCode::EmptyRecordPatch,
+            Self::InvalidRecordPatch(_) => ErrorCode::InvalidRecordPatch,
+            Self::EmptyRecordVersion => ErrorCode::EmptyRecordVersion,
+            Self::InvalidRecordVersion(_) => ErrorCode::InvalidRecordVersion,
+            Self::InvalidPagination(_) => ErrorCode::InvalidPagination,
+            Self::Storage(err) => err.code(),
+        }
+    }
+}
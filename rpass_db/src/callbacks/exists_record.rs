@@ -0,0 +1,124 @@
+use super::{session::*, utils, ArgIter, Error, Result};
+
+/// Checks if user stored in `session` has record for resource from `arg_iter`.
+/// Cheaper than [`super::show_record()`], cause record isn't read
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `EmptyResourceName` - if resource name wasn't provided
+/// * `InvalidResourceName` - if resource name is invalid
+/// * `Storage` - if can't check record cause of some error in `user_storage`
+///   from `session`
+pub fn exists_record(session: &Session, arg_iter: ArgIter) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let resource = arg_iter.next().ok_or(Error::EmptyResourceName)?;
+    if !utils::is_safe_for_resource_name(&resource) {
+        return Err(Error::InvalidResourceName);
+    }
+
+    let exists = authorized_session
+        .user_storage
+        .read()
+        .unwrap()
+        .record_exists(&resource)?;
+    Ok(exists.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{storage, AsyncUserStorage};
+    use super::*;
+    use mockall::predicate;
+    use std::io;
+
+    const TEST_USER: &str = "test_user";
+    const TEST_RESOURCE: &str = "example.com";
+
+    #[test]
+    fn test_ok() {
+        for exists in [true, false] {
+            let mock_user_storage = AsyncUserStorage::default();
+            mock_user_storage
+                .write()
+                .unwrap()
+                .expect_record_exists()
+                .times(1)
+                .with(predicate::eq(TEST_RESOURCE))
+                .returning(move |_| Ok(exists));
+            let session =
+                Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
+            let args = [TEST_RESOURCE.to_owned()];
+            let mut arg_iter = args.iter().cloned();
+
+            assert_eq!(
+                exists_record(&session, &mut arg_iter).unwrap(),
+                exists.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let args = [TEST_RESOURCE.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            exists_record(&Session::default(), &mut arg_iter),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_empty_resource() {
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+        let args = [];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            exists_record(&session, &mut arg_iter),
+            Err(Error::EmptyResourceName)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_resource() {
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+        let args = ["./../resource.com".to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            exists_record(&session, &mut arg_iter),
+            Err(Error::InvalidResourceName)
+        ));
+    }
+
+    #[test]
+    fn test_storage_error() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_record_exists()
+            .times(1)
+            .returning(|_| Err(storage::Error::Io(io::Error::other(""))));
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
+        let args = [TEST_RESOURCE.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            exists_record(&session, &mut arg_iter),
+            Err(Error::Storage(_))
+        ));
+    }
+}
@@ -0,0 +1,152 @@
+use super::{session::*, utils, ArgIter, Error, Result};
+
+/// Shows previous versions of record for resource from `arg_iter` for user
+/// stored in `session`, oldest first
+///
+/// Every version is version id and record itself delimited by "\n"
+/// (See [`Record::to_string()`]), prefixed with its length in bytes and "\n".
+/// Records take different number of lines depending on format, so length
+/// lets client split versions
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `EmptyResourceName` - if resource name wasn't provided
+/// * `InvalidResourceName` - if resource name is invalid
+/// * `Storage` - if can't retrieve history cause of some error in `user_storage`
+///   from `session`
+pub fn get_record_history(session: &Session, arg_iter: ArgIter) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let resource = arg_iter.next().ok_or(Error::EmptyResourceName)?;
+    if !utils::is_safe_for_resource_name(&resource) {
+        return Err(Error::InvalidResourceName);
+    }
+
+    let history = {
+        let storage_read = authorized_session.user_storage.read().unwrap();
+        storage_read.get_record_history(&resource)?
+    };
+    if history.is_empty() {
+        return Ok(String::from("No history yet"));
+    }
+
+    Ok(history
+        .iter()
+        .map(|record_version| {
+            let version = format!("{}\n{}", record_version.version, record_version.record);
+            format!("{}\n{version}", version.len())
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{storage, AsyncUserStorage};
+    use super::*;
+    use mockall::predicate;
+    use std::io;
+
+    const TEST_USER: &str = "test_user";
+    const TEST_RESOURCE: &str = "example.com";
+
+    #[test]
+    fn test_ok() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_get_record_history()
+            .times(1)
+            .with(predicate::eq(TEST_RESOURCE))
+            .returning(|_| {
+                Ok(["first", "second"]
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, password)| storage::RecordVersion {
+                        version: i as u64 + 1,
+                        record: storage::Record::builder()
+                            .password(password)
+                            .build()
+                            .with_encrypted_notes(i == 1),
+                    })
+                    .collect())
+            });
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
+        let args = [TEST_RESOURCE.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert_eq!(
+            get_record_history(&session, &mut arg_iter).unwrap(),
+            "8\n1\nfirst\n18\n2\nv3\nnotes\nsecond\n"
+        );
+    }
+
+    #[test]
+    fn test_no_history() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_get_record_history()
+            .times(1)
+            .returning(|_| Ok(vec![]));
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
+        let args = [TEST_RESOURCE.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert_eq!(
+            get_record_history(&session, &mut arg_iter).unwrap(),
+            "No history yet"
+        );
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let session = Session::default();
+        let args = [TEST_RESOURCE.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            get_record_history(&session, &mut arg_iter),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_resource() {
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+        let args = ["./../resource.com".to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            get_record_history(&session, &mut arg_iter),
+            Err(Error::InvalidResourceName)
+        ));
+    }
+
+    #[test]
+    fn test_storage_error() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_get_record_history()
+            .times(1)
+            .returning(|_| Err(storage::Error::Io(io::Error::other(""))));
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
+        let args = [TEST_RESOURCE.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            get_record_history(&session, &mut arg_iter),
+            Err(Error::Storage(_))
+        ));
+    }
+}
@@ -0,0 +1,31 @@
+use super::Result;
+
+/// Returns `commands` and `aliases` delimited by a new line character.
+/// Every alias is formatted as *"alias -> command"*. Works in any session state
+pub fn help(commands: &[String], aliases: &[(String, String)]) -> Result<String> {
+    let alias_lines = aliases
+        .iter()
+        .map(|(alias, command)| format!("{alias} -> {command}"));
+    Ok(commands
+        .iter()
+        .cloned()
+        .chain(alias_lines)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_help() {
+        let commands = ["help".to_owned(), "list_records".to_owned()];
+        let aliases = [("ls".to_owned(), "list_records".to_owned())];
+
+        assert_eq!(
+            help(&commands, &aliases).unwrap(),
+            "help\nlist_records\nls -> list_records"
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use super::{session::*, Error, Result, SessionTokens};
+use std::time::Instant;
+
+/// Issues token allowing to resume session of user stored in `session` on a
+/// new connection, see [`super::resume()`].
+/// Token is revoked when `session` is ended with *quit* request
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+pub fn issue_session_token(session: &mut Session, tokens: &SessionTokens) -> Result<String> {
+    let authorized_session = session
+        .as_authorized_mut()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let token = tokens.issue(&authorized_session.username, Instant::now());
+    if let Some(previous) = authorized_session.session_token.replace(token.clone()) {
+        tokens.revoke(&previous);
+    }
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::AsyncUserStorage;
+    use super::*;
+    use std::time::Duration;
+
+    const TEST_USER: &str = "test_user";
+
+    #[test]
+    fn test_ok() {
+        let tokens = SessionTokens::new(Duration::from_secs(60));
+        let mut session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+
+        let first = issue_session_token(&mut session, &tokens).unwrap();
+        let second = issue_session_token(&mut session, &tokens).unwrap();
+
+        let now = Instant::now();
+        assert!(tokens.resume(&first, now).is_err());
+        assert_eq!(tokens.resume(&second, now).unwrap(), TEST_USER);
+        assert_eq!(
+            session.as_authorized().unwrap().session_token.as_ref(),
+            Some(&second)
+        );
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let tokens = SessionTokens::new(Duration::from_secs(60));
+        let mut session = Session::default();
+
+        assert!(matches!(
+            issue_session_token(&mut session, &tokens),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+}
@@ -1,30 +1,76 @@
-use super::{session::*, Error, Result};
+use super::{session::*, storage::SortOrder, ArgIter, Error, Result};
+use std::str::FromStr;
 
-/// Lists all records names for user stored in `session`.
+/// Lists records names for user stored in `session`.
 /// Names will be delimited by a new line character
 ///
+/// Without arguments all records are listed in alphabetical order. Optional
+/// first argument in `arg_iter` is a sort order, see [`SortOrder`]. If `offset`
+/// and optional `limit` are provided after it, only names from that page are
+/// listed, preceded by a line with the total number of records. Offset beyond
+/// the end or zero limit give an empty page
+///
 /// # Errors
 ///
 /// * `UnacceptableRequestAtThisState` - if session is not an Authorized
-/// variant
+///   variant
+/// * `InvalidPagination` - if offset or limit isn't a non-negative integer
 /// * `Storage` - if can't list records cause of some error in `user_storage`
-/// from session
-pub fn list_records(session: &Session) -> Result<String> {
+///   from session
+pub fn list_records(session: &Session, arg_iter: ArgIter) -> Result<String> {
     let authorized_session = session
         .as_authorized()
         .ok_or(Error::UnacceptableRequestAtThisState)?;
 
+    let mut arg = arg_iter.next();
+    let order = match arg.as_deref().map(SortOrder::from_str) {
+        Some(Ok(order)) => {
+            arg = arg_iter.next();
+            Some(order)
+        }
+        _ => None,
+    };
+
+    let page = match arg {
+        Some(offset) => Some((
+            parse_page_arg(offset)?,
+            arg_iter.next().map(parse_page_arg).transpose()?,
+        )),
+        None => None,
+    };
+
     let record_names = {
         let storage_read = authorized_session.user_storage.read().unwrap();
-        storage_read.list_records()?
+        match order {
+            Some(order) => storage_read.list_records_sorted(order)?,
+            None => storage_read.list_records()?,
+        }
+    };
+
+    let Some((offset, limit)) = page else {
+        return match to_string_with_delimiter(&record_names, "\n") {
+            x if x.is_empty() => Ok(String::from("No records yet")),
+            s => Ok(s),
+        };
     };
 
-    match to_string_with_delimiter(&record_names, "\n") {
-        x if x.is_empty() => Ok(String::from("No records yet")),
-        s => Ok(s),
+    let total = record_names.len();
+    let page_names: Vec<String> = record_names
+        .into_iter()
+        .skip(offset)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+    match to_string_with_delimiter(&page_names, "\n") {
+        x if x.is_empty() => Ok(total.to_string()),
+        s => Ok(format!("{total}\n{s}")),
     }
 }
 
+/// Parses offset or limit of records page
+fn parse_page_arg(arg: String) -> Result<usize> {
+    arg.parse().map_err(|_| Error::InvalidPagination(arg))
+}
+
 /// Catenates strings from `values` delimiting them with `delimiter`
 fn to_string_with_delimiter(values: &[String], delimiter: &str) -> String {
     match !values.is_empty() {
@@ -40,6 +86,7 @@ fn to_string_with_delimiter(values: &[String], delimiter: &str) -> String {
 mod tests {
     use super::super::{storage, AsyncUserStorage};
     use super::*;
+    use mockall::predicate;
     use std::io;
 
     #[test]
@@ -51,12 +98,12 @@ mod tests {
             .expect_list_records()
             .times(1)
             .returning(|| Ok(vec!["first".to_owned(), "second".to_owned()]));
-        let session = Session::Authorized(Authorized {
-            username: String::default(),
-            user_storage: mock_user_storage,
-        });
+        let session = Session::Authorized(Authorized::new(String::default(), mock_user_storage));
 
-        assert_eq!(list_records(&session).unwrap(), "first\nsecond");
+        assert_eq!(
+            list_records(&session, &mut std::iter::empty()).unwrap(),
+            "first\nsecond"
+        );
     }
 
     #[test]
@@ -68,12 +115,117 @@ mod tests {
             .expect_list_records()
             .times(1)
             .returning(|| Ok(vec![]));
-        let session = Session::Authorized(Authorized {
-            username: String::default(),
-            user_storage: mock_user_storage,
-        });
+        let session = Session::Authorized(Authorized::new(String::default(), mock_user_storage));
+
+        assert_eq!(
+            list_records(&session, &mut std::iter::empty()).unwrap(),
+            "No records yet"
+        );
+    }
+
+    #[test]
+    fn test_first_page() {
+        let session = build_session(&["a", "b", "c", "d", "e"]);
 
-        assert_eq!(list_records(&session).unwrap(), "No records yet");
+        assert_eq!(
+            list_records(&session, &mut ["0", "2"].iter().map(|&s| s.to_owned())).unwrap(),
+            "5\na\nb"
+        );
+    }
+
+    #[test]
+    fn test_middle_page() {
+        let session = build_session(&["a", "b", "c", "d", "e"]);
+
+        assert_eq!(
+            list_records(&session, &mut ["2", "2"].iter().map(|&s| s.to_owned())).unwrap(),
+            "5\nc\nd"
+        );
+    }
+
+    #[test]
+    fn test_page_without_limit() {
+        let session = build_session(&["a", "b", "c"]);
+
+        assert_eq!(
+            list_records(&session, &mut ["1"].iter().map(|&s| s.to_owned())).unwrap(),
+            "3\nb\nc"
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_offset() {
+        let session = build_session(&["a", "b"]);
+
+        assert_eq!(
+            list_records(&session, &mut ["10", "2"].iter().map(|&s| s.to_owned())).unwrap(),
+            "2"
+        );
+    }
+
+    #[test]
+    fn test_zero_limit() {
+        let session = build_session(&["a", "b"]);
+
+        assert_eq!(
+            list_records(&session, &mut ["0", "0"].iter().map(|&s| s.to_owned())).unwrap(),
+            "2"
+        );
+    }
+
+    #[test]
+    fn test_sorted() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_list_records_sorted()
+            .with(predicate::eq(SortOrder::Updated))
+            .times(1)
+            .returning(|_| Ok(vec!["second".to_owned(), "first".to_owned()]));
+        let session = Session::Authorized(Authorized::new(String::default(), mock_user_storage));
+
+        assert_eq!(
+            list_records(&session, &mut ["updated"].iter().map(|&s| s.to_owned())).unwrap(),
+            "second\nfirst"
+        );
+    }
+
+    #[test]
+    fn test_sorted_page() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_list_records_sorted()
+            .with(predicate::eq(SortOrder::NameDesc))
+            .times(1)
+            .returning(|_| Ok(vec!["c".to_owned(), "b".to_owned(), "a".to_owned()]));
+        let session = Session::Authorized(Authorized::new(String::default(), mock_user_storage));
+
+        assert_eq!(
+            list_records(
+                &session,
+                &mut ["name_desc", "1", "1"].iter().map(|&s| s.to_owned())
+            )
+            .unwrap(),
+            "3\nb"
+        );
+    }
+
+    #[test]
+    fn test_invalid_pagination() {
+        for args in [["-1", "2"], ["0", "two"]] {
+            let session = Session::Authorized(Authorized::new(
+                String::default(),
+                AsyncUserStorage::default(),
+            ));
+
+            assert!(matches!(
+                list_records(&session, &mut args.iter().map(|&s| s.to_owned())),
+                Err(Error::InvalidPagination(_))
+            ));
+        }
     }
 
     #[test]
@@ -81,7 +233,7 @@ mod tests {
         let session = Session::default();
 
         assert!(matches!(
-            list_records(&session),
+            list_records(&session, &mut std::iter::empty()),
             Err(Error::UnacceptableRequestAtThisState)
         ));
     }
@@ -94,12 +246,23 @@ mod tests {
             .unwrap()
             .expect_list_records()
             .times(1)
-            .returning(|| Err(storage::Error::Io(io::Error::new(io::ErrorKind::Other, ""))));
-        let session = Session::Authorized(Authorized {
-            username: String::default(),
-            user_storage: mock_user_storage,
-        });
+            .returning(|| Err(storage::Error::Io(io::Error::other(""))));
+        let session = Session::Authorized(Authorized::new(String::default(), mock_user_storage));
 
-        assert!(matches!(list_records(&session), Err(Error::Storage(_))));
+        assert!(matches!(
+            list_records(&session, &mut std::iter::empty()),
+            Err(Error::Storage(_))
+        ));
+    }
+
+    fn build_session(names: &'static [&'static str]) -> Session {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_list_records()
+            .times(1)
+            .returning(|| Ok(names.iter().map(|&s| s.to_owned()).collect()));
+        Session::Authorized(Authorized::new(String::default(), mock_user_storage))
     }
 }
@@ -0,0 +1,85 @@
+use super::{session::*, Error, Result};
+
+/// Lists names of deleted records in trash for user stored in `session`.
+/// Names will be delimited by a new line character
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `Storage` - if can't list trash cause of some error in `user_storage`
+///   from session
+pub fn list_trash(session: &Session) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let record_names = {
+        let storage_read = authorized_session.user_storage.read().unwrap();
+        storage_read.list_trash()?
+    };
+
+    match record_names.join("\n") {
+        x if x.is_empty() => Ok(String::from("Trash is empty")),
+        s => Ok(s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{storage, AsyncUserStorage};
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn test_ok() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_list_trash()
+            .times(1)
+            .returning(|| Ok(vec!["first".to_owned(), "second".to_owned()]));
+        let session = Session::Authorized(Authorized::new(String::default(), mock_user_storage));
+
+        assert_eq!(list_trash(&session).unwrap(), "first\nsecond");
+    }
+
+    #[test]
+    fn test_empty_trash() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_list_trash()
+            .times(1)
+            .returning(|| Ok(vec![]));
+        let session = Session::Authorized(Authorized::new(String::default(), mock_user_storage));
+
+        assert_eq!(list_trash(&session).unwrap(), "Trash is empty");
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let session = Session::default();
+
+        assert!(matches!(
+            list_trash(&session),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_storage_error() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_list_trash()
+            .times(1)
+            .returning(|| Err(storage::Error::Io(io::Error::other(""))));
+        let session = Session::Authorized(Authorized::new(String::default(), mock_user_storage));
+
+        assert!(matches!(list_trash(&session), Err(Error::Storage(_))));
+    }
+}
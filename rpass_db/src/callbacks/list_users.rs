@@ -0,0 +1,92 @@
+use super::{session::*, utils, AsyncStorage, Error, Result};
+
+/// Lists names of all registered users. Available only for administrators,
+/// see [`crate::storage::Storage::is_admin()`].
+/// Names will be delimited by a new line character
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `PermissionDenied` - if user stored in `session` isn't an administrator
+/// * `Storage` - if can't check permissions or list users cause of some error
+///   in `storage`
+pub fn list_users(storage: AsyncStorage, session: &Session) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let storage_read = storage.read().unwrap();
+    utils::check_admin(&storage_read, authorized_session)?;
+
+    Ok(storage_read.list_users()?.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{storage, utils::admin_tests::*};
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn test_ok() {
+        let mock_storage = AsyncStorage::default();
+        {
+            let mut storage_write = mock_storage.write().unwrap();
+            expect_is_admin(&mut storage_write, true);
+            storage_write
+                .expect_list_users()
+                .times(1)
+                .returning(|| Ok(vec![ADMIN.to_owned(), "user".to_owned()]));
+        }
+
+        assert_eq!(
+            list_users(mock_storage, &build_admin_session()).unwrap(),
+            "admin\nuser"
+        );
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let mock_storage = AsyncStorage::default();
+        let session = Session::default();
+
+        assert!(matches!(
+            list_users(mock_storage, &session),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_not_admin() {
+        let mock_storage = AsyncStorage::default();
+        {
+            let mut storage_write = mock_storage.write().unwrap();
+            expect_is_admin(&mut storage_write, false);
+            storage_write.expect_list_users().never();
+        }
+
+        assert!(matches!(
+            list_users(mock_storage, &build_admin_session()),
+            Err(Error::PermissionDenied)
+        ));
+    }
+
+    #[test]
+    fn test_storage_error() {
+        let mock_storage = AsyncStorage::default();
+        {
+            let mut storage_write = mock_storage.write().unwrap();
+            expect_is_admin(&mut storage_write, true);
+            storage_write
+                .expect_list_users()
+                .times(1)
+                .returning(|| Err(storage::Error::Io(io::Error::other(""))));
+        }
+
+        assert!(matches!(
+            list_users(mock_storage, &build_admin_session()),
+            Err(Error::Storage(_))
+        ));
+    }
+}
@@ -1,47 +1,97 @@
-use super::{session::*, utils, ArgIter, AsyncStorage, Error, Result};
-use rand::distributions::Alphanumeric;
+use super::{session::*, utils, ArgIter, AsyncStorage, AuditEvent, AuditLog, Error, Result};
 use rand::{thread_rng, Rng};
+use std::time::Instant;
 
 /// First part of user logging. Reads username from `arg_iter`, gets his key
 /// from `storage`, initializes `session` with Unauthorized variant and writes
-/// random encrypted string into it's `login_confirmation` field.
-/// Returns *Ok() with login confirmation* in success
+/// random string with issue time into it's `login_confirmation` field.
+/// Returns *Ok() with login challenge* in success, that is random string
+/// bound to the connection and encrypted with user key
 ///
 /// The next step user should decrypt that random confirmation string,
 /// encrypt if with storage public key and send it back.
 ///
 /// See [`super::confirm_login()`] function for second part
 ///
+/// Attempts with unknown username are written to `audit_log` with `connection`
+/// peer address
+///
 /// # Errors
 ///
 /// * `EmptyUsername` - if no username was provided
 /// * `InvalidUsername` - if username is invalid
 /// * `Storage` - if can't create record cause of some error in
-/// `storage`
-pub fn login(storage: AsyncStorage, session: &mut Session, arg_iter: ArgIter) -> Result<String> {
+///   `storage`
+pub fn login(
+    storage: AsyncStorage,
+    audit_log: &AuditLog,
+    session: &mut Session,
+    connection: &ConnectionInfo,
+    arg_iter: ArgIter,
+) -> Result<String> {
+    login_with_rng(
+        storage,
+        audit_log,
+        session,
+        connection,
+        arg_iter,
+        &mut thread_rng(),
+    )
+}
+
+/// Same as [`login()`], but confirmation string is generated with `rng`
+///
+/// # Errors
+///
+/// See [`login()`]
+pub fn login_with_rng<R: Rng + ?Sized>(
+    storage: AsyncStorage,
+    audit_log: &AuditLog,
+    session: &mut Session,
+    connection: &ConnectionInfo,
+    arg_iter: ArgIter,
+    rng: &mut R,
+) -> Result<String> {
     let username = arg_iter.next().ok_or(Error::EmptyUsername)?;
-    if !utils::is_safe_for_filename(&username) {
+    if !utils::is_safe_for_username(&username) {
         return Err(Error::InvalidUsername(username));
     }
 
-    let user_pub_key = {
+    let user_pub_key_res = {
         let storage_read = storage.read().unwrap();
-        storage_read.get_user_pub_key(&username)?
+        storage_read.get_user_pub_key(&username)
+    };
+    let user_pub_key = match user_pub_key_res {
+        Ok(user_pub_key) => user_pub_key,
+        Err(err) => {
+            let err = Error::from(err);
+            let reason = err.to_string();
+            audit_log.record(AuditEvent::LoginFailed { reason }, &username, connection);
+            return Err(err);
+        }
     };
 
-    const RAND_STRING_LENGTH: usize = 30;
-    let rand_string: String = thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(RAND_STRING_LENGTH)
-        .map(char::from)
-        .collect();
-
-    let login_confirmation = user_pub_key.encrypt(&rand_string);
-    *session = Session::Unauthorized(Unauthorized {
+    let connection_id = match session.as_unauthorized() {
+        Some(unauthorized) => unauthorized.connection_id.clone(),
+        None => Unauthorized::new().connection_id,
+    };
+    let unauthorized = Unauthorized {
         username,
-        login_confirmation: login_confirmation.clone(),
-    });
-    Ok(login_confirmation)
+        login_confirmation: Some(LoginConfirmation {
+            confirmation: utils::generate_confirmation(rng),
+            issued_at: Instant::now(),
+        }),
+        connection_id,
+    };
+    let challenge = unauthorized.bind_to_connection(
+        &unauthorized
+            .login_confirmation
+            .as_ref()
+            .unwrap()
+            .confirmation,
+    );
+    *session = Session::Unauthorized(unauthorized);
+    Ok(user_pub_key.encrypt(&challenge))
 }
 
 #[cfg(test)]
@@ -49,7 +99,10 @@ mod tests {
     use super::{super::storage, *};
     use crate::storage::Key;
     use mockall::predicate;
-    use std::str::FromStr;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::sync::Arc;
+    use std::time::Duration;
 
     const TEST_USER: &str = "test_user";
 
@@ -58,6 +111,7 @@ mod tests {
         let mock_storage = AsyncStorage::default();
         let mut session = Session::default();
         let mut arg_iter = [TEST_USER].iter().map(|&s| s.to_owned());
+        let (pub_key, sec_key) = Key::generate_pair();
 
         mock_storage
             .write()
@@ -65,12 +119,75 @@ mod tests {
             .expect_get_user_pub_key()
             .times(1)
             .with(predicate::eq(TEST_USER))
-            .returning(|_| Ok(Key::from_str("11:11").unwrap()));
+            .returning(move |_| Ok(pub_key.clone()));
 
-        let res = login(mock_storage, &mut session, &mut arg_iter);
-        assert!(res.is_ok());
+        let connection_id = session.as_unauthorized().unwrap().connection_id.clone();
+        let res = login(
+            mock_storage,
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+        );
+        assert!(sec_key.decrypt(&res.unwrap()).starts_with(&connection_id));
         assert!(session.is_unauthorized());
-        assert_eq!(session.as_unauthorized().unwrap().username, TEST_USER);
+        let unauthorized = session.as_unauthorized().unwrap();
+        assert_eq!(unauthorized.username, TEST_USER);
+        assert_eq!(unauthorized.connection_id, connection_id);
+    }
+
+    #[test]
+    fn test_seeded_rng() {
+        const SEED: u64 = 42;
+        let mock_storage = AsyncStorage::default();
+        let mut session = Session::default();
+        let mut arg_iter = [TEST_USER].iter().map(|&s| s.to_owned());
+        let (pub_key, sec_key) = Key::generate_pair();
+        {
+            let mut mock_storage_write = mock_storage.write().unwrap();
+            let user_pub_key = pub_key.clone();
+            mock_storage_write
+                .expect_get_user_pub_key()
+                .times(1)
+                .with(predicate::eq(TEST_USER))
+                .returning(move |_| Ok(user_pub_key.clone()));
+            mock_storage_write
+                .expect_sec_key()
+                .times(1)
+                .return_const(sec_key.clone());
+            mock_storage_write
+                .expect_get_user_storage()
+                .with(predicate::eq(TEST_USER))
+                .times(1)
+                .returning(|_| Ok(Arc::default()));
+        }
+
+        let connection_id = session.as_unauthorized().unwrap().connection_id.clone();
+        let res = login_with_rng(
+            mock_storage.clone(),
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+            &mut StdRng::seed_from_u64(SEED),
+        );
+        let challenge = sec_key.decrypt(&res.unwrap());
+        let confirmation = utils::generate_confirmation(&mut StdRng::seed_from_u64(SEED));
+        assert_eq!(confirmation.len(), utils::CONFIRMATION_LENGTH);
+        assert_eq!(challenge, format!("{connection_id}{confirmation}"));
+
+        let mut arg_iter = [pub_key.encrypt(&challenge)].into_iter();
+        let res = super::super::confirm_login(
+            mock_storage,
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+            Duration::from_secs(30),
+            None,
+        );
+        assert_eq!(res.unwrap(), "Ok");
+        assert!(session.is_authorized());
     }
 
     #[test]
@@ -79,7 +196,13 @@ mod tests {
         let mut session = Session::default();
         let mut arg_iter = [].iter().map(|s: &&str| s.to_string());
 
-        let res = login(mock_storage, &mut session, &mut arg_iter);
+        let res = login(
+            mock_storage,
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+        );
         assert!(matches!(res, Err(Error::EmptyUsername)));
     }
 
@@ -90,7 +213,13 @@ mod tests {
         let invalid_username = String::from("/etc/passwd");
         let mut arg_iter = [invalid_username].into_iter();
 
-        let res = login(mock_storage, &mut session, &mut arg_iter);
+        let res = login(
+            mock_storage,
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+        );
         assert!(matches!(res, Err(Error::InvalidUsername(_))));
     }
 
@@ -107,7 +236,13 @@ mod tests {
             .times(1)
             .with(predicate::eq(TEST_USER))
             .returning(|_| Err(storage::Error::UserDoesNotExist(TEST_USER.to_owned())));
-        let res = login(mock_storage, &mut session, &mut arg_iter);
+        let res = login(
+            mock_storage,
+            &AuditLog::disabled(),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+        );
         assert!(matches!(res, Err(Error::Storage(_))));
     }
 }
@@ -0,0 +1,120 @@
+use super::{session::*, utils, ArgIter, AsyncStorage, Error, Maintenance, Result};
+
+/// Enables or disables maintenance mode. Available only for administrators,
+/// see [`crate::storage::Storage::is_admin()`].
+/// Reads *"on"* or *"off"* from `arg_iter` and returns *"Ok"*. If there is no
+/// argument, returns current mode in the same format
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `PermissionDenied` - if user stored in `session` isn't an administrator
+/// * `InvalidMaintenanceMode` - if argument is neither *"on"* nor *"off"*
+/// * `Storage` - if can't check permissions cause of some error in `storage`
+pub fn maintenance(
+    storage: AsyncStorage,
+    session: &Session,
+    maintenance: &Maintenance,
+    arg_iter: ArgIter,
+) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    utils::check_admin(&storage.read().unwrap(), authorized_session)?;
+
+    let enabled = match arg_iter.next().as_deref() {
+        None => {
+            let mode = if maintenance.is_enabled() {
+                "on"
+            } else {
+                "off"
+            };
+            return Ok(mode.to_owned());
+        }
+        Some("on") => true,
+        Some("off") => false,
+        Some(mode) => return Err(Error::InvalidMaintenanceMode(mode.to_owned())),
+    };
+    maintenance.set_enabled(enabled);
+    log::warn!(
+        "Maintenance mode is {} by {}",
+        if enabled { "enabled" } else { "disabled" },
+        authorized_session.username
+    );
+
+    Ok("Ok".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::utils::admin_tests::*;
+    use super::*;
+    use mockall::predicate;
+
+    #[test]
+    fn test_ok() {
+        let mock_storage = AsyncStorage::default();
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_is_admin()
+            .with(predicate::eq(ADMIN))
+            .returning(|_| Ok(true));
+        let maintenance = Maintenance::default();
+        let call = |args: &[&str]| {
+            super::maintenance(
+                mock_storage.clone(),
+                &build_admin_session(),
+                &maintenance,
+                &mut args.iter().map(|&arg| arg.to_owned()),
+            )
+        };
+
+        assert_eq!(call(&[]).unwrap(), "off");
+        assert_eq!(call(&["on"]).unwrap(), "Ok");
+        assert!(maintenance.is_enabled());
+        assert_eq!(call(&[]).unwrap(), "on");
+        assert_eq!(call(&["off"]).unwrap(), "Ok");
+        assert!(!maintenance.is_enabled());
+        assert!(matches!(
+            call(&["maybe"]),
+            Err(Error::InvalidMaintenanceMode(mode)) if mode == "maybe"
+        ));
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let args = [String::from("on")];
+
+        assert!(matches!(
+            maintenance(
+                AsyncStorage::default(),
+                &Session::default(),
+                &Maintenance::default(),
+                &mut args.iter().cloned()
+            ),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_not_admin() {
+        let mock_storage = AsyncStorage::default();
+        expect_is_admin(&mut mock_storage.write().unwrap(), false);
+        let maintenance = Maintenance::default();
+        let args = [String::from("on")];
+
+        assert!(matches!(
+            super::maintenance(
+                mock_storage,
+                &build_admin_session(),
+                &maintenance,
+                &mut args.iter().cloned()
+            ),
+            Err(Error::PermissionDenied)
+        ));
+        assert!(!maintenance.is_enabled());
+    }
+}
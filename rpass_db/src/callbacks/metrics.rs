@@ -0,0 +1,167 @@
+use super::{session::*, utils, AsyncStorage, Error, Result};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Storage counters reused by [`metrics()`] for some time, so the whole
+/// storage isn't walked on every request
+pub struct MetricsCache {
+    started_at: Instant,
+    ttl: Duration,
+    /// Time of counting and numbers of users and records
+    counters: Mutex<Option<(Instant, usize, usize)>>,
+}
+
+impl MetricsCache {
+    /// Creates cache for server started at `started_at` keeping counters for `ttl`
+    pub fn new(started_at: Instant, ttl: Duration) -> Self {
+        MetricsCache {
+            started_at,
+            ttl,
+            counters: Mutex::new(None),
+        }
+    }
+}
+
+/// Gets server metrics. Available only for administrators,
+/// see [`crate::storage::Storage::is_admin()`].
+/// Number of users, total number of records and server uptime in seconds are
+/// delimited by a new line character
+///
+/// Numbers of users and records can be outdated up to `cache` ttl
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `PermissionDenied` - if user stored in `session` isn't an administrator
+/// * `Storage` - if can't check permissions or count users and records cause of
+///   some error in `storage`
+pub fn metrics(storage: AsyncStorage, session: &Session, cache: &MetricsCache) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let storage_read = storage.read().unwrap();
+    utils::check_admin(&storage_read, authorized_session)?;
+
+    let now = Instant::now();
+    let mut counters = cache.counters.lock().unwrap();
+    let (users, records) = match *counters {
+        Some((counted_at, users, records)) if now.duration_since(counted_at) < cache.ttl => {
+            (users, records)
+        }
+        _ => {
+            let (users, records) = (storage_read.user_count()?, storage_read.record_count()?);
+            *counters = Some((now, users, records));
+            (users, records)
+        }
+    };
+
+    let uptime = now.duration_since(cache.started_at).as_secs();
+    Ok(format!("{users}\n{records}\n{uptime}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{storage, utils::admin_tests::*};
+    use super::*;
+    use mockall::predicate;
+    use std::io;
+
+    #[test]
+    fn test_ok() {
+        let mock_storage = AsyncStorage::default();
+        {
+            let mut storage_write = mock_storage.write().unwrap();
+            storage_write
+                .expect_is_admin()
+                .with(predicate::eq(ADMIN))
+                .times(2)
+                .returning(|_| Ok(true));
+            storage_write
+                .expect_user_count()
+                .times(1)
+                .returning(|| Ok(3));
+            storage_write
+                .expect_record_count()
+                .times(1)
+                .returning(|| Ok(10));
+        }
+        let started_at = Instant::now() - Duration::from_secs(120);
+        let cache = MetricsCache::new(started_at, Duration::from_secs(60));
+
+        for _ in 0..2 {
+            let response = metrics(mock_storage.clone(), &build_admin_session(), &cache).unwrap();
+            let lines: Vec<&str> = response.lines().collect();
+            assert_eq!(lines[..2], ["3", "10"]);
+            assert!(lines[2].parse::<u64>().unwrap() >= 120);
+        }
+    }
+
+    #[test]
+    fn test_cache_expired() {
+        let mock_storage = AsyncStorage::default();
+        {
+            let mut storage_write = mock_storage.write().unwrap();
+            storage_write.expect_is_admin().returning(|_| Ok(true));
+            storage_write
+                .expect_user_count()
+                .times(2)
+                .returning(|| Ok(1));
+            storage_write
+                .expect_record_count()
+                .times(2)
+                .returning(|| Ok(0));
+        }
+        let cache = MetricsCache::new(Instant::now(), Duration::ZERO);
+
+        for _ in 0..2 {
+            assert!(
+                metrics(mock_storage.clone(), &build_admin_session(), &cache)
+                    .unwrap()
+                    .starts_with("1\n0\n")
+            );
+        }
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let cache = MetricsCache::new(Instant::now(), Duration::from_secs(60));
+
+        assert!(matches!(
+            metrics(AsyncStorage::default(), &Session::default(), &cache),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_not_admin() {
+        let mock_storage = AsyncStorage::default();
+        expect_is_admin(&mut mock_storage.write().unwrap(), false);
+        let cache = MetricsCache::new(Instant::now(), Duration::from_secs(60));
+
+        assert!(matches!(
+            metrics(mock_storage, &build_admin_session(), &cache),
+            Err(Error::PermissionDenied)
+        ));
+    }
+
+    #[test]
+    fn test_storage_error() {
+        let mock_storage = AsyncStorage::default();
+        {
+            let mut storage_write = mock_storage.write().unwrap();
+            storage_write.expect_is_admin().returning(|_| Ok(true));
+            storage_write
+                .expect_user_count()
+                .times(1)
+                .returning(|| Err(storage::Error::Io(io::Error::other(""))));
+        }
+        let cache = MetricsCache::new(Instant::now(), Duration::from_secs(60));
+
+        assert!(matches!(
+            metrics(mock_storage, &build_admin_session(), &cache),
+            Err(Error::Storage(_))
+        ));
+    }
+}
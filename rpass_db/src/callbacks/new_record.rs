@@ -4,34 +4,83 @@ use std::str::FromStr;
 /// Adds new record for user stored in `session`
 /// Reads resource name and record (See [`Record::from_str()`]) from `arg_iter`
 ///
+/// Record is written holding per-record lock, like in [`super::patch_record()`],
+/// so concurrent patch of the same record can't overwrite it
+///
 /// # Errors
 ///
 /// * `UnacceptableRequestAtThisState` - if session is not an Authorized
-/// variant
+///   variant
 /// * `EmptyResourceName` - if resource name wasn't provided
 /// * `InvalidResourceName` - if resource name is invalid
 /// * `EmptyRecordContent` - if record wasn't provided
 /// * `InvalidRecordFormat` - if can't parse *Record*
+/// * `RecordTooLarge` - if serialized record is longer than `max_size` bytes
+/// * `QuotaExceeded` - if user already has `max_records` records and the new
+///   one doesn't overwrite any of them
 /// * `Storage` - if can't create record cause of some error in `user_storage`
-/// from `session`
-pub fn new_record(session: &Session, arg_iter: ArgIter) -> Result<String> {
+///   from `session`
+pub fn new_record(
+    session: &Session,
+    arg_iter: ArgIter,
+    max_records: Option<usize>,
+    max_size: Option<usize>,
+) -> Result<String> {
     let authorized_session = session
         .as_authorized()
         .ok_or(Error::UnacceptableRequestAtThisState)?;
 
+    let record = parse_record(arg_iter)?;
+    check_record_size(&record, max_size)?;
+
+    let user_storage = &authorized_session.user_storage;
+    let record_locks = user_storage.read().unwrap().record_locks();
+    record_locks.with_record_lock(&record.resource, || {
+        let mut storage_write = user_storage.write().unwrap();
+        if let Some(max_records) = max_records {
+            let records = storage_write.list_records()?;
+            if records.len() >= max_records && !records.contains(&record.resource) {
+                return Err(Error::QuotaExceeded(max_records));
+            }
+        }
+        storage_write.write_record(&record)?;
+        Ok("Ok".to_owned())
+    })
+}
+
+/// Reads resource name and record (See [`Record::from_str()`]) from `arg_iter`
+///
+/// # Errors
+///
+/// * `EmptyResourceName` - if resource name wasn't provided
+/// * `InvalidResourceName` - if resource name is invalid
+/// * `EmptyRecordContent` - if record wasn't provided
+/// * `InvalidRecordFormat` - if can't parse *Record*
+pub(super) fn parse_record(arg_iter: ArgIter) -> Result<storage::Record> {
     let resource = arg_iter.next().ok_or(Error::EmptyResourceName)?;
-    if !utils::is_safe_for_filename(&resource) {
+    if !utils::is_safe_for_resource_name(&resource) {
         return Err(Error::InvalidResourceName);
     }
 
-    let record = storage::Record {
+    Ok(storage::Record {
         resource,
         ..storage::Record::from_str(&arg_iter.next().ok_or(Error::EmptyRecordContent)?)?
-    };
+    })
+}
 
-    let mut storage_write = authorized_session.user_storage.write().unwrap();
-    storage_write.write_record(&record)?;
-    Ok("Ok".to_owned())
+/// Checks that `record` serialized with [`Record::to_string()`] isn't longer
+/// than `max_size` bytes. `None` means unlimited
+///
+/// # Errors
+///
+/// * `RecordTooLarge` - if `record` is too large
+pub(super) fn check_record_size(record: &storage::Record, max_size: Option<usize>) -> Result<()> {
+    match max_size {
+        Some(max_size) if record.to_string().len() > max_size => {
+            Err(Error::RecordTooLarge(max_size))
+        }
+        _ => Ok(()),
+    }
 }
 
 #[cfg(test)]
@@ -40,6 +89,7 @@ mod tests {
     use super::*;
     use mockall::predicate;
     use std::io;
+    use std::sync::Arc;
 
     const TEST_USER: &str = "test_user";
     const RESOURCE: &str = "example.com";
@@ -54,9 +104,10 @@ mod tests {
             resource: RESOURCE.to_owned(),
             password: PASSWORD.to_owned(),
             notes: NOTES.to_owned(),
+            encrypted_notes: false,
         };
 
-        let mock_storage = AsyncUserStorage::default();
+        let mock_storage = build_locking_storage();
         mock_storage
             .write()
             .unwrap()
@@ -64,19 +115,127 @@ mod tests {
             .times(1)
             .with(predicate::eq(expected_record))
             .returning(|_| Ok(()));
-        let session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: mock_storage,
-        });
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_storage));
         let args = [RESOURCE.to_owned(), content];
         let mut arg_iter = args.iter().cloned();
 
         assert_eq!(
-            new_record(&session, &mut arg_iter).unwrap(),
+            new_record(&session, &mut arg_iter, None, None).unwrap(),
             "Ok".to_owned()
         );
     }
 
+    #[test]
+    fn test_spaced_resource() {
+        let content = String::from(PASSWORD) + "\n" + NOTES;
+
+        let expected_record = storage::Record {
+            resource: "My Bank".to_owned(),
+            password: PASSWORD.to_owned(),
+            notes: NOTES.to_owned(),
+            encrypted_notes: false,
+        };
+
+        let mock_storage = build_locking_storage();
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_write_record()
+            .times(1)
+            .with(predicate::eq(expected_record))
+            .returning(|_| Ok(()));
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_storage));
+        let args = ["My Bank".to_owned(), content];
+        let mut arg_iter = args.iter().cloned();
+
+        assert_eq!(
+            new_record(&session, &mut arg_iter, None, None).unwrap(),
+            "Ok".to_owned()
+        );
+    }
+
+    #[test]
+    fn test_reserved_looking_resource() {
+        for resource in ["con.example.com", "aux.com", "key"] {
+            let mock_storage = build_locking_storage();
+            mock_storage
+                .write()
+                .unwrap()
+                .expect_write_record()
+                .times(1)
+                .returning(|_| Ok(()));
+            let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_storage));
+            let args = [resource.to_owned(), String::from(PASSWORD) + "\n" + NOTES];
+            let mut arg_iter = args.iter().cloned();
+
+            assert_eq!(
+                new_record(&session, &mut arg_iter, None, None).unwrap(),
+                "Ok".to_owned(),
+                "{resource}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_quota() {
+        for (existing, resource, is_ok) in [
+            (vec!["first.com"], RESOURCE, true),
+            (vec!["first.com", "second.com"], RESOURCE, false),
+            (vec!["first.com", RESOURCE], RESOURCE, true),
+        ] {
+            let mock_storage = build_locking_storage();
+            {
+                let mut mock_storage_write = mock_storage.write().unwrap();
+                mock_storage_write
+                    .expect_list_records()
+                    .times(1)
+                    .return_once(move || Ok(existing.into_iter().map(String::from).collect()));
+                mock_storage_write
+                    .expect_write_record()
+                    .times(usize::from(is_ok))
+                    .returning(|_| Ok(()));
+            }
+            let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_storage));
+            let args = [resource.to_owned(), String::from(PASSWORD) + "\n" + NOTES];
+            let mut arg_iter = args.iter().cloned();
+
+            let res = new_record(&session, &mut arg_iter, Some(2), None);
+            if is_ok {
+                assert_eq!(res.unwrap(), "Ok");
+            } else {
+                assert!(matches!(res, Err(Error::QuotaExceeded(2))));
+            }
+        }
+    }
+
+    #[test]
+    fn test_max_size() {
+        let notes = "n".repeat(10);
+        let max_size = build_record_with_notes(&notes).to_string().len();
+
+        let mock_storage = build_locking_storage();
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_write_record()
+            .times(1)
+            .with(predicate::eq(build_record_with_notes(&notes)))
+            .returning(|_| Ok(()));
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_storage));
+
+        for (notes, is_ok) in [(notes.clone(), true), (notes + "n", false)] {
+            let args = [RESOURCE.to_owned(), String::from(PASSWORD) + "\n" + &notes];
+            let mut arg_iter = args.iter().cloned();
+
+            let res = new_record(&session, &mut arg_iter, None, Some(max_size));
+            if is_ok {
+                assert_eq!(res.unwrap(), "Ok");
+            } else {
+                assert!(matches!(res, Err(Error::RecordTooLarge(size)) if size == max_size));
+            }
+        }
+    }
+
     #[test]
     fn test_non_authorized() {
         let content = String::from(PASSWORD) + "\n" + NOTES;
@@ -86,52 +245,52 @@ mod tests {
         let mut arg_iter = args.iter().cloned();
 
         assert!(matches!(
-            new_record(&session, &mut arg_iter),
+            new_record(&session, &mut arg_iter, None, None),
             Err(Error::UnacceptableRequestAtThisState)
         ));
     }
 
     #[test]
     fn test_empty_resource() {
-        let session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: AsyncUserStorage::default(),
-        });
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
         let args = [];
         let mut arg_iter = args.iter().cloned();
 
         assert!(matches!(
-            new_record(&session, &mut arg_iter),
+            new_record(&session, &mut arg_iter, None, None),
             Err(Error::EmptyResourceName)
         ));
     }
 
     #[test]
     fn test_invalid_resource() {
-        let session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: AsyncUserStorage::default(),
-        });
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
         let args = ["../illegal/resource/name".to_owned()];
         let mut arg_iter = args.iter().cloned();
 
         assert!(matches!(
-            new_record(&session, &mut arg_iter),
+            new_record(&session, &mut arg_iter, None, None),
             Err(Error::InvalidResourceName)
         ));
     }
 
     #[test]
     fn test_empty_record_content() {
-        let session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: AsyncUserStorage::default(),
-        });
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
         let args = [RESOURCE.to_owned()];
         let mut arg_iter = args.iter().cloned();
 
         assert!(matches!(
-            new_record(&session, &mut arg_iter),
+            new_record(&session, &mut arg_iter, None, None),
             Err(Error::EmptyRecordContent)
         ));
     }
@@ -139,15 +298,15 @@ mod tests {
     #[test]
     fn test_invalid_record_format() {
         let content = String::from(PASSWORD);
-        let session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: AsyncUserStorage::default(),
-        });
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
         let args = [RESOURCE.to_owned(), content];
         let mut arg_iter = args.iter().cloned();
 
         assert!(matches!(
-            new_record(&session, &mut arg_iter),
+            new_record(&session, &mut arg_iter, None, None),
             Err(Error::InvalidRecordFormat(_))
         ));
     }
@@ -160,25 +319,44 @@ mod tests {
             resource: RESOURCE.to_owned(),
             password: PASSWORD.to_owned(),
             notes: NOTES.to_owned(),
+            encrypted_notes: false,
         };
 
-        let mock_storage = AsyncUserStorage::default();
+        let mock_storage = build_locking_storage();
         mock_storage
             .write()
             .unwrap()
             .expect_write_record()
             .times(1)
             .with(predicate::eq(expected_record))
-            .returning(|_| Err(storage::Error::Io(io::Error::new(io::ErrorKind::Other, ""))));
-        let session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: mock_storage,
-        });
+            .returning(|_| Err(storage::Error::Io(io::Error::other(""))));
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_storage));
         let args = [RESOURCE.to_owned(), content];
         let mut arg_iter = args.iter().cloned();
         assert!(matches!(
-            new_record(&session, &mut arg_iter),
+            new_record(&session, &mut arg_iter, None, None),
             Err(Error::Storage(_))
         ));
     }
+
+    fn build_record_with_notes(notes: &str) -> storage::Record {
+        storage::Record {
+            resource: RESOURCE.to_owned(),
+            password: PASSWORD.to_owned(),
+            notes: notes.to_owned(),
+            encrypted_notes: false,
+        }
+    }
+
+    /// Builds user storage mock expecting to be asked for record locks once
+    fn build_locking_storage() -> AsyncUserStorage {
+        let mock_storage = AsyncUserStorage::default();
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_record_locks()
+            .times(1)
+            .returning(Arc::default);
+        mock_storage
+    }
 }
@@ -0,0 +1,269 @@
+use super::{new_record::check_record_size, session::*, storage, utils, ArgIter, Error, Result};
+
+/// Changes some fields of record for user stored in `session`.
+/// Reads resource name and then pairs of field name (*password* or *notes*)
+/// and its new value from `arg_iter`. Fields which aren't mentioned are
+/// preserved, empty value sets field to empty
+///
+/// Record is read, modified and written back holding per-record lock, so
+/// concurrent patches of the same record from other sessions aren't lost
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `EmptyResourceName` - if resource name wasn't provided
+/// * `InvalidResourceName` - if resource name is invalid
+/// * `EmptyRecordPatch` - if no fields were provided
+/// * `InvalidRecordPatch` - if field name is unknown or has no value
+/// * `RecordNotFound` - if user has no record for resource
+/// * `RecordTooLarge` - if serialized patched record is longer than `max_size`
+///   bytes
+/// * `Storage` - if can't read or write record cause of some error in
+///   `user_storage` from `session`
+pub fn patch_record(
+    session: &Session,
+    arg_iter: ArgIter,
+    max_size: Option<usize>,
+) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let resource = arg_iter.next().ok_or(Error::EmptyResourceName)?;
+    if !utils::is_safe_for_resource_name(&resource) {
+        return Err(Error::InvalidResourceName);
+    }
+    let patch = parse_patch(arg_iter)?;
+
+    let user_storage = &authorized_session.user_storage;
+    let record_locks = user_storage.read().unwrap().record_locks();
+    record_locks.with_record_lock(&resource, || {
+        let mut record = match user_storage.read().unwrap().get_record(&resource) {
+            Ok(record) => record,
+            Err(storage::Error::RecordNotFound(resource)) => {
+                return Err(Error::RecordNotFound(resource));
+            }
+            Err(err) => return Err(err.into()),
+        };
+        patch.apply(&mut record);
+        check_record_size(&record, max_size)?;
+        user_storage.write().unwrap().write_record(&record)?;
+        Ok("Ok".to_owned())
+    })
+}
+
+/// Reads pairs of field name and value from `arg_iter`
+///
+/// # Errors
+///
+/// * `EmptyRecordPatch` - if no fields were provided
+/// * `InvalidRecordPatch` - if field name is unknown or has no value
+fn parse_patch(arg_iter: ArgIter) -> Result<storage::PatchRecord> {
+    let mut patch = storage::PatchRecord::default();
+    while let Some(field) = arg_iter.next() {
+        let value = match arg_iter.next() {
+            Some(value) => value,
+            None => return Err(Error::InvalidRecordPatch(format!("no value for `{field}`"))),
+        };
+        match field.as_str() {
+            "password" => patch.password = Some(value),
+            "notes" => patch.notes = Some(value),
+            _ => {
+                return Err(Error::InvalidRecordPatch(format!(
+                    "unknown field `{field}`"
+                )))
+            }
+        }
+    }
+
+    if patch.is_empty() {
+        return Err(Error::EmptyRecordPatch);
+    }
+    Ok(patch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::AsyncUserStorage;
+    use super::*;
+    use mockall::predicate;
+    use std::sync::Arc;
+
+    const TEST_USER: &str = "test_user";
+    const TEST_RESOURCE: &str = "example.com";
+
+    #[test]
+    fn test_patch_password() {
+        let expected = storage::Record {
+            password: String::from("new secret"),
+            ..build_record()
+        };
+        let session = build_session(expected);
+
+        assert_eq!(
+            call(&session, &[TEST_RESOURCE, "password", "new secret"]).unwrap(),
+            "Ok"
+        );
+    }
+
+    #[test]
+    fn test_patch_notes() {
+        let expected = storage::Record {
+            notes: String::from("new\n\"notes\""),
+            ..build_record()
+        };
+        let session = build_session(expected);
+
+        assert_eq!(
+            call(&session, &[TEST_RESOURCE, "notes", "new\n\"notes\""]).unwrap(),
+            "Ok"
+        );
+    }
+
+    #[test]
+    fn test_patch_to_empty() {
+        let expected = storage::Record {
+            password: String::new(),
+            notes: String::new(),
+            ..build_record()
+        };
+        let session = build_session(expected);
+
+        assert_eq!(
+            call(&session, &[TEST_RESOURCE, "notes", "", "password", ""]).unwrap(),
+            "Ok"
+        );
+    }
+
+    #[test]
+    fn test_too_large() {
+        let max_size = build_record().to_string().len();
+        let mock_user_storage = AsyncUserStorage::default();
+        {
+            let mut storage_write = mock_user_storage.write().unwrap();
+            storage_write.expect_record_locks().returning(Arc::default);
+            storage_write
+                .expect_get_record()
+                .times(2)
+                .returning(|_| Ok(build_record()));
+            storage_write
+                .expect_write_record()
+                .times(1)
+                .returning(|_| Ok(()));
+        }
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
+        let call = |notes: &str| {
+            let args = [TEST_RESOURCE, "notes", notes];
+            patch_record(
+                &session,
+                &mut args.iter().map(|&arg| arg.to_owned()),
+                Some(max_size),
+            )
+        };
+
+        assert_eq!(call("NOTES").unwrap(), "Ok");
+        assert!(matches!(call("notes!"), Err(Error::RecordTooLarge(_))));
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        assert!(matches!(
+            call(&Session::default(), &[TEST_RESOURCE, "notes", ""]),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_empty_resource() {
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+
+        assert!(matches!(call(&session, &[]), Err(Error::EmptyResourceName)));
+        assert!(matches!(
+            call(&session, &["../passwd", "notes", ""]),
+            Err(Error::InvalidResourceName)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_patch() {
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+
+        assert!(matches!(
+            call(&session, &[TEST_RESOURCE]),
+            Err(Error::EmptyRecordPatch)
+        ));
+        assert!(matches!(
+            call(&session, &[TEST_RESOURCE, "notes"]),
+            Err(Error::InvalidRecordPatch(_))
+        ));
+        assert!(matches!(
+            call(&session, &[TEST_RESOURCE, "resource", "other.com"]),
+            Err(Error::InvalidRecordPatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_record_not_found() {
+        let mock_user_storage = AsyncUserStorage::default();
+        {
+            let mut storage_write = mock_user_storage.write().unwrap();
+            storage_write.expect_record_locks().returning(Arc::default);
+            storage_write
+                .expect_get_record()
+                .times(1)
+                .with(predicate::eq(TEST_RESOURCE))
+                .returning(|resource| Err(storage::Error::RecordNotFound(resource.to_owned())));
+            storage_write.expect_write_record().never();
+        }
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
+
+        assert!(matches!(
+            call(&session, &[TEST_RESOURCE, "notes", ""]),
+            Err(Error::RecordNotFound(resource)) if resource == TEST_RESOURCE
+        ));
+    }
+
+    fn call(session: &Session, args: &[&str]) -> Result<String> {
+        patch_record(session, &mut args.iter().map(|&arg| arg.to_owned()), None)
+    }
+
+    /// Builds session with record from [`build_record()`] expecting it to be
+    /// overwritten with `expected`
+    fn build_session(expected: storage::Record) -> Session {
+        let mock_user_storage = AsyncUserStorage::default();
+        {
+            let mut storage_write = mock_user_storage.write().unwrap();
+            storage_write
+                .expect_record_locks()
+                .times(1)
+                .returning(Arc::default);
+            storage_write
+                .expect_get_record()
+                .times(1)
+                .with(predicate::eq(TEST_RESOURCE))
+                .returning(|_| Ok(build_record()));
+            storage_write
+                .expect_write_record()
+                .times(1)
+                .with(predicate::eq(expected))
+                .returning(|_| Ok(()));
+        }
+        Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage))
+    }
+
+    fn build_record() -> storage::Record {
+        storage::Record {
+            resource: TEST_RESOURCE.to_owned(),
+            password: String::from("secret"),
+            notes: String::from("notes"),
+            encrypted_notes: false,
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use super::Result;
+
+/// Answers *"pong"* to let client check that server is alive.
+/// Works in any session state
+pub fn ping() -> Result<String> {
+    Ok("pong".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping() {
+        assert_eq!(ping().unwrap(), "pong");
+    }
+}
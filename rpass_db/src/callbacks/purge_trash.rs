@@ -0,0 +1,65 @@
+use super::{session::*, Error, Result};
+
+/// Permanently deletes all records in trash for user stored in `session`
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `Storage` - if can't purge trash cause of some error in `user_storage`
+///   from session
+pub fn purge_trash(session: &Session) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let mut storage_write = authorized_session.user_storage.write().unwrap();
+    storage_write.purge_trash()?;
+
+    Ok("Ok".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{storage, AsyncUserStorage};
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn test_ok() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_purge_trash()
+            .times(1)
+            .returning(|| Ok(()));
+        let session = Session::Authorized(Authorized::new(String::default(), mock_user_storage));
+
+        assert_eq!(purge_trash(&session).unwrap(), "Ok");
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let session = Session::default();
+
+        assert!(matches!(
+            purge_trash(&session),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_storage_error() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_purge_trash()
+            .times(1)
+            .returning(|| Err(storage::Error::Io(io::Error::other(""))));
+        let session = Session::Authorized(Authorized::new(String::default(), mock_user_storage));
+
+        assert!(matches!(purge_trash(&session), Err(Error::Storage(_))));
+    }
+}
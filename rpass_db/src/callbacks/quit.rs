@@ -0,0 +1,58 @@
+use super::{session::*, Result, SessionTokens};
+
+/// Ends `session` answering *"Bye"*. Server closes connection right after
+/// sending the answer. Works in any session state.
+/// Session token of the authorized session is revoked in `tokens`
+pub fn quit(session: &mut Session, tokens: &SessionTokens) -> Result<String> {
+    if let Some(token) = session
+        .as_authorized()
+        .and_then(|authorized| authorized.session_token.as_ref())
+    {
+        tokens.revoke(token);
+    }
+    *session = Session::Ended;
+    Ok("Bye".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::AsyncUserStorage;
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_unauthorized() {
+        let mut session = Session::default();
+        assert_eq!(quit(&mut session, &build_tokens()).unwrap(), "Bye");
+        assert!(session.is_ended());
+    }
+
+    #[test]
+    fn test_authorized() {
+        let mut session = Session::Authorized(Authorized::new(
+            String::from("user"),
+            AsyncUserStorage::default(),
+        ));
+        assert_eq!(quit(&mut session, &build_tokens()).unwrap(), "Bye");
+        assert!(session.is_ended());
+    }
+
+    #[test]
+    fn test_revokes_session_token() {
+        let tokens = build_tokens();
+        let now = Instant::now();
+        let token = tokens.issue("user", now);
+        let other_token = tokens.issue("user", now);
+        let mut authorized = Authorized::new(String::from("user"), AsyncUserStorage::default());
+        authorized.session_token = Some(token.clone());
+        let mut session = Session::Authorized(authorized);
+
+        assert_eq!(quit(&mut session, &tokens).unwrap(), "Bye");
+        assert!(tokens.resume(&token, now).is_err());
+        assert!(tokens.resume(&other_token, now).is_ok());
+    }
+
+    fn build_tokens() -> SessionTokens {
+        SessionTokens::new(Duration::from_secs(60))
+    }
+}
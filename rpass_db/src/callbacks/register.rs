@@ -1,10 +1,15 @@
-use super::{utils, ArgIter, AsyncStorage, Error, Result};
+use super::{
+    session::ConnectionInfo, utils, ArgIter, AsyncStorage, AuditEvent, AuditLog, Error,
+    KeyAllowlist, Result,
+};
 use crate::storage::Key;
 use std::str::FromStr;
 
 /// Registers new user in `storage` with username and key taken from `arg_iter`
 ///
-/// Performs username validity check
+/// Performs username validity check. If `key_allowlist` is set, only keys
+/// from it can be registered. Successful registration is written to
+/// `audit_log` with `connection` peer address
 ///
 /// Returns *Ok("Ok")* in success
 ///
@@ -14,18 +19,29 @@ use std::str::FromStr;
 /// * `InvalidUsername` - if username is invalid
 /// * `EmptyKey` - if no key was provided
 /// * `InvalidKey` - if key is invalid
+/// * `KeyNotAllowed` - if key isn't in `key_allowlist`
 /// * `Storage` - if can't create record cause of some error in `storage`
-pub fn register(storage: AsyncStorage, arg_iter: ArgIter) -> Result<String> {
+pub fn register(
+    storage: AsyncStorage,
+    audit_log: &AuditLog,
+    key_allowlist: Option<&KeyAllowlist>,
+    connection: &ConnectionInfo,
+    arg_iter: ArgIter,
+) -> Result<String> {
     let username = arg_iter.next().ok_or(Error::EmptyUsername)?;
-    if !utils::is_safe_for_filename(&username) {
+    if !utils::is_safe_for_username(&username) {
         return Err(Error::InvalidUsername(username));
     }
 
     let key_string = arg_iter.next().ok_or(Error::EmptyKey)?;
     let key = Key::from_str(&key_string)?;
+    if key_allowlist.is_some_and(|allowlist| !allowlist.contains(&key)) {
+        return Err(Error::KeyNotAllowed);
+    }
 
     let mut storage_write = storage.write().unwrap();
     storage_write.add_new_user(&username, &key)?;
+    audit_log.record(AuditEvent::Registered, &username, connection);
 
     Ok("Ok".to_owned())
 }
@@ -55,16 +71,67 @@ mod tests {
 
         let args = TEST_USER.to_owned() + " " + KEY_STR;
         let mut arg_iter = args.split_whitespace().map(str::to_owned);
-        let res = register(mock_storage, &mut arg_iter);
+        let res = register(
+            mock_storage,
+            &AuditLog::disabled(),
+            None,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+        );
+        assert_eq!(res.unwrap(), "Ok");
+    }
+
+    #[test]
+    fn test_allowed_key() {
+        let mock_storage = AsyncStorage::default();
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_add_new_user()
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let allowlist = KeyAllowlist::from_str("11:11\n13:11").unwrap();
+
+        let mut arg_iter = "test_user 13:11".split_whitespace().map(str::to_owned);
+        let res = register(
+            mock_storage,
+            &AuditLog::disabled(),
+            Some(&allowlist),
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+        );
         assert_eq!(res.unwrap(), "Ok");
     }
 
+    #[test]
+    fn test_key_not_allowed() {
+        let mock_storage = AsyncStorage::default();
+        mock_storage.write().unwrap().expect_add_new_user().never();
+        let allowlist = KeyAllowlist::from_str("11:11").unwrap();
+
+        let mut arg_iter = "test_user 17:11".split_whitespace().map(str::to_owned);
+        let res = register(
+            mock_storage,
+            &AuditLog::disabled(),
+            Some(&allowlist),
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+        );
+        assert!(matches!(res, Err(Error::KeyNotAllowed)));
+    }
+
     #[test]
     fn test_empty_username() {
         let mock_storage = AsyncStorage::default();
 
         let mut arg_iter = "".split_whitespace().map(str::to_owned);
-        let res = register(mock_storage, &mut arg_iter);
+        let res = register(
+            mock_storage,
+            &AuditLog::disabled(),
+            None,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+        );
         assert!(matches!(res, Err(Error::EmptyUsername)));
     }
 
@@ -75,7 +142,13 @@ mod tests {
         const INVALID_USERNAME: &str = "_invalid_username_";
         let mut arg_iter = INVALID_USERNAME.split_whitespace().map(str::to_owned);
 
-        let res = register(mock_storage, &mut arg_iter);
+        let res = register(
+            mock_storage,
+            &AuditLog::disabled(),
+            None,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+        );
         assert!(matches!(res,
             Err(Error::InvalidUsername(username))
             if username == INVALID_USERNAME));
@@ -86,7 +159,13 @@ mod tests {
         let mock_storage = AsyncStorage::default();
 
         let mut arg_iter = "test_user".split_whitespace().map(str::to_owned);
-        let res = register(mock_storage, &mut arg_iter);
+        let res = register(
+            mock_storage,
+            &AuditLog::disabled(),
+            None,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+        );
         assert!(matches!(res, Err(Error::EmptyKey)));
     }
 
@@ -95,7 +174,13 @@ mod tests {
         let mock_storage = AsyncStorage::default();
 
         let mut arg_iter = "test_user key".split_whitespace().map(str::to_owned);
-        let res = register(mock_storage, &mut arg_iter);
+        let res = register(
+            mock_storage,
+            &AuditLog::disabled(),
+            None,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+        );
         assert!(matches!(res, Err(Error::InvalidKey(_))));
     }
 
@@ -110,7 +195,13 @@ mod tests {
             .times(1)
             .returning(|_, _| Err(storage::Error::UserAlreadyExists("test_user".to_owned())));
         let mut arg_iter = "test_user 11:11".split_whitespace().map(str::to_owned);
-        let res = register(mock_storage, &mut arg_iter);
+        let res = register(
+            mock_storage,
+            &AuditLog::disabled(),
+            None,
+            &ConnectionInfo::default(),
+            &mut arg_iter,
+        );
         assert!(matches!(res, Err(Error::Storage(_))));
     }
 }
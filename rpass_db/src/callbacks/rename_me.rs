@@ -0,0 +1,186 @@
+use super::{session::*, utils, ArgIter, AsyncStorage, Error, Result, SessionTokens};
+
+/// Renames current user. Takes *username* from `session` and new username from
+/// `arg_iter`
+///
+/// Session stays authorized with the new username. All session tokens issued
+/// for the old username are revoked in `tokens`
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `EmptyUsername` - if new username wasn't provided
+/// * `InvalidUsername` - if new username is invalid
+/// * `Storage` - if can't rename user cause of some error in `storage`
+pub fn rename_me(
+    storage: AsyncStorage,
+    tokens: &SessionTokens,
+    session: &mut Session,
+    arg_iter: ArgIter,
+) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let new_username = arg_iter.next().ok_or(Error::EmptyUsername)?;
+    if !utils::is_safe_for_username(&new_username) {
+        return Err(Error::InvalidUsername(new_username));
+    }
+
+    let username = authorized_session.username.clone();
+    *session = Session::Unauthorized(Unauthorized::default());
+
+    let mut storage_write = storage.write().unwrap();
+    let (username, res) = match storage_write.rename_user(&username, &new_username) {
+        Ok(()) => {
+            tokens.revoke_user(&username);
+            (new_username, Ok("Ok".to_owned()))
+        }
+        Err(err) => (username, Err(err.into())),
+    };
+    let user_storage = storage_write.get_user_storage(&username)?;
+    *session = Session::Authorized(Authorized::new(username, user_storage));
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{storage, AsyncUserStorage};
+    use super::*;
+    use mockall::predicate;
+    use std::time::{Duration, Instant};
+
+    const TEST_USER: &str = "test_user";
+    const NEW_USER: &str = "new_user";
+
+    #[test]
+    fn test_ok() {
+        let mock_storage = AsyncStorage::default();
+        let mut session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+
+        {
+            let mut mock_storage_write = mock_storage.write().unwrap();
+            mock_storage_write
+                .expect_rename_user()
+                .with(predicate::eq(TEST_USER), predicate::eq(NEW_USER))
+                .times(1)
+                .returning(|_, _| Ok(()));
+            mock_storage_write
+                .expect_get_user_storage()
+                .with(predicate::eq(NEW_USER))
+                .times(1)
+                .returning(|_| Ok(AsyncUserStorage::default()));
+        }
+        let args = [NEW_USER.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        let tokens = build_tokens();
+        let now = Instant::now();
+        let token = tokens.issue(TEST_USER, now);
+
+        assert_eq!(
+            rename_me(mock_storage, &tokens, &mut session, &mut arg_iter).unwrap(),
+            "Ok"
+        );
+        assert_eq!(session.as_authorized().unwrap().username, NEW_USER);
+        assert!(tokens.resume(&token, now).is_err());
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let mock_storage = AsyncStorage::default();
+        let mut session = Session::default();
+        let args = [NEW_USER.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            rename_me(mock_storage, &build_tokens(), &mut session, &mut arg_iter),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_username() {
+        let mock_storage = AsyncStorage::default();
+        let mut session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+        let args = ["../hacker".to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            rename_me(mock_storage, &build_tokens(), &mut session, &mut arg_iter),
+            Err(Error::InvalidUsername(_))
+        ));
+        assert!(session.is_authorized());
+    }
+
+    #[test]
+    fn test_storage_error() {
+        let mock_storage = AsyncStorage::default();
+        let mut session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+
+        {
+            let mut mock_storage_write = mock_storage.write().unwrap();
+            mock_storage_write
+                .expect_rename_user()
+                .returning(|_, to| Err(storage::Error::UserAlreadyExists(to.to_owned())));
+            mock_storage_write
+                .expect_get_user_storage()
+                .with(predicate::eq(TEST_USER))
+                .times(1)
+                .returning(|_| Ok(AsyncUserStorage::default()));
+        }
+        let args = [NEW_USER.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            rename_me(mock_storage, &build_tokens(), &mut session, &mut arg_iter),
+            Err(Error::Storage(storage::Error::UserAlreadyExists(_)))
+        ));
+        assert_eq!(session.as_authorized().unwrap().username, TEST_USER);
+    }
+
+    #[test]
+    fn test_user_storage_error() {
+        let mock_storage = AsyncStorage::default();
+        let mut session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+
+        {
+            let mut mock_storage_write = mock_storage.write().unwrap();
+            mock_storage_write
+                .expect_rename_user()
+                .times(1)
+                .returning(|_, _| Ok(()));
+            mock_storage_write
+                .expect_get_user_storage()
+                .with(predicate::eq(NEW_USER))
+                .times(1)
+                .returning(|name| Err(storage::Error::UserDoesNotExist(name.to_owned())));
+        }
+        let args = [NEW_USER.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            rename_me(mock_storage, &build_tokens(), &mut session, &mut arg_iter),
+            Err(Error::Storage(storage::Error::UserDoesNotExist(_)))
+        ));
+        assert!(!session.is_authorized());
+    }
+
+    fn build_tokens() -> SessionTokens {
+        SessionTokens::new(Duration::from_secs(60))
+    }
+}
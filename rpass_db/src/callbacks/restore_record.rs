@@ -0,0 +1,102 @@
+use super::{session::*, utils, ArgIter, Error, Result};
+
+/// Restores deleted record from trash for user stored in `session`.
+/// Resource name is read from `arg_iter`
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `EmptyResourceName` - if resource name wasn't provided
+/// * `InvalidResourceName` - if resource name is invalid
+/// * `Storage` - if record isn't in trash, record with the same name already
+///   exists or can't restore it cause of some error in `user_storage` from session
+pub fn restore_record(session: &Session, arg_iter: ArgIter) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let resource_name = arg_iter.next().ok_or(Error::EmptyResourceName)?;
+    if !utils::is_safe_for_resource_name(&resource_name) {
+        return Err(Error::InvalidResourceName);
+    }
+
+    let mut storage_write = authorized_session.user_storage.write().unwrap();
+    storage_write.restore_record(&resource_name)?;
+
+    Ok("Ok".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{storage, AsyncUserStorage};
+    use super::*;
+    use mockall::predicate;
+    use std::io;
+
+    const TEST_USER: &str = "test_user";
+    const TEST_RESOURCE: &str = "example.com";
+
+    #[test]
+    fn test_ok() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_restore_record()
+            .with(predicate::eq(TEST_RESOURCE))
+            .times(1)
+            .returning(|_| Ok(()));
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
+        let args = [TEST_RESOURCE.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert_eq!(restore_record(&session, &mut arg_iter).unwrap(), "Ok");
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let session = Session::default();
+        let args = [TEST_RESOURCE.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            restore_record(&session, &mut arg_iter),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_empty_resource() {
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+        let args = [];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            restore_record(&session, &mut arg_iter),
+            Err(Error::EmptyResourceName)
+        ));
+    }
+
+    #[test]
+    fn test_storage_error() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_restore_record()
+            .times(1)
+            .returning(|_| Err(storage::Error::Io(io::ErrorKind::NotFound.into())));
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
+        let args = [TEST_RESOURCE.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            restore_record(&session, &mut arg_iter),
+            Err(Error::Storage(_))
+        ));
+    }
+}
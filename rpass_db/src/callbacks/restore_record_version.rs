@@ -0,0 +1,126 @@
+use super::{session::*, utils, ArgIter, Error, Result};
+
+/// Restores previous version of record for user stored in `session`.
+/// Resource name and version id are read from `arg_iter`
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `EmptyResourceName` - if resource name wasn't provided
+/// * `InvalidResourceName` - if resource name is invalid
+/// * `EmptyRecordVersion` - if version id wasn't provided
+/// * `InvalidRecordVersion` - if version id isn't a non-negative integer
+/// * `Storage` - if there is no such version or can't restore it cause of some
+///   error in `user_storage` from `session`
+pub fn restore_record_version(session: &Session, arg_iter: ArgIter) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let resource = arg_iter.next().ok_or(Error::EmptyResourceName)?;
+    if !utils::is_safe_for_resource_name(&resource) {
+        return Err(Error::InvalidResourceName);
+    }
+    let version = arg_iter.next().ok_or(Error::EmptyRecordVersion)?;
+    let version = version
+        .parse()
+        .map_err(|_| Error::InvalidRecordVersion(version))?;
+
+    let mut storage_write = authorized_session.user_storage.write().unwrap();
+    storage_write.restore_record_version(&resource, version)?;
+
+    Ok("Ok".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{storage, AsyncUserStorage};
+    use super::*;
+    use mockall::predicate;
+    use std::io;
+
+    const TEST_USER: &str = "test_user";
+    const TEST_RESOURCE: &str = "example.com";
+
+    #[test]
+    fn test_ok() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_restore_record_version()
+            .times(1)
+            .with(predicate::eq(TEST_RESOURCE), predicate::eq(42))
+            .returning(|_, _| Ok(()));
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
+        let args = [TEST_RESOURCE.to_owned(), String::from("42")];
+        let mut arg_iter = args.iter().cloned();
+
+        assert_eq!(
+            restore_record_version(&session, &mut arg_iter).unwrap(),
+            "Ok"
+        );
+    }
+
+    #[test]
+    fn test_empty_version() {
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+        let args = [TEST_RESOURCE.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            restore_record_version(&session, &mut arg_iter),
+            Err(Error::EmptyRecordVersion)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_version() {
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
+        let args = [TEST_RESOURCE.to_owned(), String::from("latest")];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            restore_record_version(&session, &mut arg_iter),
+            Err(Error::InvalidRecordVersion(version)) if version == "latest"
+        ));
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let session = Session::default();
+        let args = [TEST_RESOURCE.to_owned(), String::from("42")];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            restore_record_version(&session, &mut arg_iter),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_storage_error() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_restore_record_version()
+            .times(1)
+            .returning(|_, _| Err(storage::Error::Io(io::ErrorKind::NotFound.into())));
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
+        let args = [TEST_RESOURCE.to_owned(), String::from("42")];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            restore_record_version(&session, &mut arg_iter),
+            Err(Error::Storage(_))
+        ));
+    }
+}
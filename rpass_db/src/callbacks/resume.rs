@@ -0,0 +1,268 @@
+use super::{
+    session::*, ArgIter, AsyncStorage, AuditEvent, AuditLog, Error, Result, SessionTokens,
+    TokenError,
+};
+use std::time::Instant;
+
+/// Resumes authorized session with token from `arg_iter` issued by
+/// [`super::issue_session_token()`], so client can skip logging in.
+/// Sets `session` to the [`Authorized`] state remembering the token, answers
+/// *"Ok"* on success.
+///
+/// Result of the attempt is written to `audit_log` with `connection` peer address
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Unauthorized
+///   variant
+/// * `EmptySessionToken` - if token wasn't provided
+/// * `InvalidSessionToken` - if token wasn't issued or was revoked
+/// * `SessionTokenExpired` - if token was issued too long ago
+/// * `TooManySessions` - if user already has `max_sessions` active sessions
+/// * `Storage` - if can't get user storage cause of some error in `storage`,
+///   e.g. if user was deleted
+pub fn resume(
+    storage: AsyncStorage,
+    tokens: &SessionTokens,
+    audit_log: &AuditLog,
+    session: &mut Session,
+    connection: &ConnectionInfo,
+    arg_iter: ArgIter,
+    max_sessions: Option<usize>,
+) -> Result<String> {
+    if !session.is_unauthorized() {
+        return Err(Error::UnacceptableRequestAtThisState);
+    }
+    let token = arg_iter.next().ok_or(Error::EmptySessionToken)?;
+
+    let username = match tokens.resume(&token, Instant::now()) {
+        Ok(username) => username,
+        Err(TokenError::Unknown) => return Err(Error::InvalidSessionToken),
+        Err(TokenError::Expired) => return Err(Error::SessionTokenExpired),
+    };
+
+    let res = authorize(&storage, session, &username, token, max_sessions);
+
+    let event = match &res {
+        Ok(_) => AuditEvent::SessionResumed,
+        Err(err) => AuditEvent::LoginFailed {
+            reason: err.to_string(),
+        },
+    };
+    audit_log.record(event, &username, connection);
+    res
+}
+
+/// Sets `session` to the [`Authorized`] state of user `username` resumed with
+/// `token`
+///
+/// # Errors
+///
+/// See [`resume()`]
+fn authorize(
+    storage: &AsyncStorage,
+    session: &mut Session,
+    username: &str,
+    token: String,
+    max_sessions: Option<usize>,
+) -> Result<String> {
+    let mut storage_write = storage.write().unwrap();
+    if let Some(max_sessions) = max_sessions {
+        if storage_write.session_count(username) >= max_sessions {
+            return Err(Error::TooManySessions(max_sessions));
+        }
+    }
+    let user_storage = storage_write.get_user_storage(username)?;
+    let mut authorized = Authorized::new(username.to_owned(), user_storage);
+    authorized.session_token = Some(token);
+    *session = Session::Authorized(authorized);
+    Ok("Ok".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::storage;
+    use super::*;
+    use crate::audit_log::MemorySink;
+    use mockall::predicate;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const TEST_USER: &str = "test_user";
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_ok() {
+        let tokens = SessionTokens::new(TTL);
+        let token = tokens.issue(TEST_USER, Instant::now());
+        let mock_storage = AsyncStorage::default();
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_get_user_storage()
+            .with(predicate::eq(TEST_USER))
+            .times(1)
+            .returning(|_| Ok(Arc::default()));
+        let sink = MemorySink::default();
+        let mut session = Session::default();
+
+        let res = resume(
+            mock_storage,
+            &tokens,
+            &AuditLog::new(sink.clone()),
+            &mut session,
+            &ConnectionInfo::default(),
+            &mut [token.clone()].into_iter(),
+            None,
+        );
+        assert_eq!(res.unwrap(), "Ok");
+
+        let authorized = session.as_authorized().unwrap();
+        assert_eq!(authorized.username, TEST_USER);
+        assert_eq!(authorized.session_token, Some(token));
+        assert!(sink.entries()[0].contains("event=session_resumed"));
+    }
+
+    #[test]
+    fn test_expired_token() {
+        let tokens = SessionTokens::new(TTL);
+        let token = tokens.issue(TEST_USER, Instant::now() - TTL - Duration::from_secs(1));
+        let mut session = Session::default();
+
+        assert!(matches!(
+            resume(
+                AsyncStorage::default(),
+                &tokens,
+                &AuditLog::disabled(),
+                &mut session,
+                &ConnectionInfo::default(),
+                &mut [token].into_iter(),
+                None,
+            ),
+            Err(Error::SessionTokenExpired)
+        ));
+        assert!(session.is_unauthorized());
+    }
+
+    #[test]
+    fn test_revoked_token() {
+        let tokens = SessionTokens::new(TTL);
+        let token = tokens.issue(TEST_USER, Instant::now());
+        tokens.revoke(&token);
+        let mut session = Session::default();
+
+        assert!(matches!(
+            resume(
+                AsyncStorage::default(),
+                &tokens,
+                &AuditLog::disabled(),
+                &mut session,
+                &ConnectionInfo::default(),
+                &mut [token].into_iter(),
+                None,
+            ),
+            Err(Error::InvalidSessionToken)
+        ));
+        assert!(session.is_unauthorized());
+    }
+
+    #[test]
+    fn test_empty_token() {
+        let mut session = Session::default();
+
+        assert!(matches!(
+            resume(
+                AsyncStorage::default(),
+                &SessionTokens::new(TTL),
+                &AuditLog::disabled(),
+                &mut session,
+                &ConnectionInfo::default(),
+                &mut std::iter::empty(),
+                None,
+            ),
+            Err(Error::EmptySessionToken)
+        ));
+    }
+
+    #[test]
+    fn test_deleted_user() {
+        let tokens = SessionTokens::new(TTL);
+        let token = tokens.issue(TEST_USER, Instant::now());
+        let mock_storage = AsyncStorage::default();
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_get_user_storage()
+            .times(1)
+            .returning(|username| Err(storage::Error::UserDoesNotExist(username.to_owned())));
+        let mut session = Session::default();
+
+        assert!(matches!(
+            resume(
+                mock_storage,
+                &tokens,
+                &AuditLog::disabled(),
+                &mut session,
+                &ConnectionInfo::default(),
+                &mut [token].into_iter(),
+                None,
+            ),
+            Err(Error::Storage(_))
+        ));
+        assert!(session.is_unauthorized());
+    }
+
+    #[test]
+    fn test_authorized() {
+        let mut session =
+            Session::Authorized(Authorized::new(TEST_USER.to_owned(), Arc::default()));
+
+        assert!(matches!(
+            resume(
+                AsyncStorage::default(),
+                &SessionTokens::new(TTL),
+                &AuditLog::disabled(),
+                &mut session,
+                &ConnectionInfo::default(),
+                &mut [String::from("token")].into_iter(),
+                None,
+            ),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_max_sessions() {
+        const MAX_SESSIONS: usize = 1;
+        let tokens = SessionTokens::new(TTL);
+        let token = tokens.issue(TEST_USER, Instant::now());
+        let mock_storage = AsyncStorage::default();
+        {
+            let mut mock_storage_write = mock_storage.write().unwrap();
+            mock_storage_write
+                .expect_session_count()
+                .with(predicate::eq(TEST_USER))
+                .times(1)
+                .returning(|_| MAX_SESSIONS);
+            mock_storage_write.expect_get_user_storage().times(0);
+        }
+        let sink = MemorySink::default();
+        let mut session = Session::default();
+
+        assert!(matches!(
+            resume(
+                mock_storage,
+                &tokens,
+                &AuditLog::new(sink.clone()),
+                &mut session,
+                &ConnectionInfo::default(),
+                &mut [token.clone()].into_iter(),
+                Some(MAX_SESSIONS),
+            ),
+            Err(Error::TooManySessions(MAX_SESSIONS))
+        ));
+        assert!(session.is_unauthorized());
+        assert!(sink.entries()[0].contains("event=login_failed"));
+        assert_eq!(tokens.resume(&token, Instant::now()).unwrap(), TEST_USER);
+    }
+}
@@ -1,35 +1,40 @@
-use super::{session::*, utils, ArgIter, Error, Result};
+use super::{session::*, storage, utils, ArgIter, Error, Result};
 
 /// Shows record for resource from `arg_iter` for user stored in `session`
 ///
 /// # Errors
 ///
 /// * `UnacceptableRequestAtThisState` - if session is not an Authorized
-/// variant
+///   variant
 /// * `EmptyResourceName` - if resource name wasn't provided
 /// * `InvalidResourceName` - if resource name is invalid
-/// * `Storage` - if can't retrieve record cause of some error in `user_storage`
-/// from `session`
+/// * `RecordNotFound` - if user has no record for resource
+/// * `Storage` - if record is corrupted or can't be read cause of some error in
+///   `user_storage` from `session`
 pub fn show_record(session: &Session, arg_iter: ArgIter) -> Result<String> {
     let authorized_session = session
         .as_authorized()
         .ok_or(Error::UnacceptableRequestAtThisState)?;
 
     let resource = arg_iter.next().ok_or(Error::EmptyResourceName)?;
-    if !utils::is_safe_for_filename(&resource) {
+    if !utils::is_safe_for_resource_name(&resource) {
         return Err(Error::InvalidResourceName);
     }
 
     let storage_read = authorized_session.user_storage.read().unwrap();
-    let record = storage_read.get_record(&resource)?;
-    Ok(record.to_string())
+    match storage_read.get_record(&resource) {
+        Ok(record) => Ok(record.to_string()),
+        Err(storage::Error::RecordNotFound(resource)) => Err(Error::RecordNotFound(resource)),
+        Err(err) => Err(err.into()),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::super::{storage, AsyncUserStorage};
+    use super::super::AsyncUserStorage;
     use super::*;
     use mockall::predicate;
+    use std::io;
     use std::str::FromStr;
 
     const TEST_USER: &str = "test_user";
@@ -46,10 +51,7 @@ mod tests {
             .with(predicate::eq(TEST_RESOURCE))
             .returning(|_| Ok(storage::Record::default()));
 
-        let session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: mock_user_storage,
-        });
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
         let args = [TEST_RESOURCE.to_owned()];
         let mut arg_iter = args.iter().cloned();
 
@@ -71,10 +73,10 @@ mod tests {
 
     #[test]
     fn test_empty_resource() {
-        let session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: AsyncUserStorage::default(),
-        });
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
         let args = [];
         let mut arg_iter = args.iter().cloned();
 
@@ -86,10 +88,10 @@ mod tests {
 
     #[test]
     fn test_invalid_resource() {
-        let session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: AsyncUserStorage::default(),
-        });
+        let session = Session::Authorized(Authorized::new(
+            TEST_USER.to_owned(),
+            AsyncUserStorage::default(),
+        ));
         let args = ["./../resource.com".to_owned()];
         let mut arg_iter = args.iter().cloned();
 
@@ -100,7 +102,45 @@ mod tests {
     }
 
     #[test]
-    fn test_storage_error() {
+    fn test_record_not_found() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_get_record()
+            .times(1)
+            .with(predicate::eq(TEST_RESOURCE))
+            .returning(|resource| Err(storage::Error::RecordNotFound(resource.to_owned())));
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
+        let args = [TEST_RESOURCE.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            show_record(&session, &mut arg_iter),
+            Err(Error::RecordNotFound(resource)) if resource == TEST_RESOURCE
+        ));
+    }
+
+    #[test]
+    fn test_io_error() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_get_record()
+            .times(1)
+            .with(predicate::eq(TEST_RESOURCE))
+            .returning(|_| Err(storage::Error::Io(io::ErrorKind::PermissionDenied.into())));
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
+        let args = [TEST_RESOURCE.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        let err = show_record(&session, &mut arg_iter).unwrap_err();
+        assert_eq!(err.code(), rpass::error::ErrorCode::Io);
+    }
+
+    #[test]
+    fn test_corrupted_record() {
         let mock_user_storage = AsyncUserStorage::default();
         mock_user_storage
             .write()
@@ -113,16 +153,11 @@ mod tests {
                     <storage::Record as FromStr>::Err::EmptyString,
                 ))
             });
-        let session = Session::Authorized(Authorized {
-            username: TEST_USER.to_owned(),
-            user_storage: mock_user_storage,
-        });
+        let session = Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_user_storage));
         let args = [TEST_RESOURCE.to_owned()];
         let mut arg_iter = args.iter().cloned();
 
-        assert!(matches!(
-            show_record(&session, &mut arg_iter),
-            Err(Error::Storage(_))
-        ));
+        let err = show_record(&session, &mut arg_iter).unwrap_err();
+        assert_eq!(err.code(), rpass::error::ErrorCode::CantParseRecord);
     }
 }
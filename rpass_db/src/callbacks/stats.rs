@@ -0,0 +1,53 @@
+use super::{session::*, Error, Result};
+
+/// Gets statistics of user stored in `session`.
+/// Number of records and their total size in bytes are delimited by a new line
+/// character
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `Storage` - if can't get statistics cause of some error in `user_storage`
+///   from session
+pub fn stats(session: &Session) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let stats = authorized_session.user_storage.read().unwrap().stats()?;
+    Ok(format!("{}\n{}", stats.records, stats.bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{storage, AsyncUserStorage};
+    use super::*;
+
+    #[test]
+    fn test_ok() {
+        let mock_storage = AsyncUserStorage::default();
+        mock_storage
+            .write()
+            .unwrap()
+            .expect_stats()
+            .times(1)
+            .returning(|| {
+                Ok(storage::UserStats {
+                    records: 2,
+                    bytes: 128,
+                })
+            });
+        let session = Session::Authorized(Authorized::new(String::from("user"), mock_storage));
+
+        assert_eq!(stats(&session).unwrap(), "2\n128");
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        assert!(matches!(
+            stats(&Session::default()),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+}
@@ -0,0 +1,97 @@
+use super::{session::*, Error, Result};
+
+/// Passes records names for user stored in `session` to `sink` one by one in
+/// alphabetical order
+///
+/// Unlike [`super::list_records()`], names aren't collected into one response,
+/// so they can be streamed while storage is iterated, see
+/// [`RequestDispatcher::add_streaming_callback()`]
+///
+/// [`RequestDispatcher::add_streaming_callback()`]: crate::request_dispatcher::RequestDispatcher::add_streaming_callback()
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `Storage` - if can't list records cause of some error in `user_storage`
+///   from session
+pub fn stream_records(session: &Session, sink: &mut dyn FnMut(String)) -> Result<()> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    authorized_session
+        .user_storage
+        .read()
+        .unwrap()
+        .visit_records(sink)
+        .map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{storage, AsyncUserStorage};
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn test_ok() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_visit_records()
+            .times(1)
+            .returning(|visitor| {
+                visitor(String::from("example.com"));
+                visitor(String::from("test.ru"));
+                Ok(())
+            });
+        let session = Session::Authorized(Authorized::new(String::default(), mock_user_storage));
+
+        let mut names = vec![];
+        stream_records(&session, &mut |name| names.push(name)).unwrap();
+        assert_eq!(names, ["example.com", "test.ru"]);
+    }
+
+    #[test]
+    fn test_no_records() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_visit_records()
+            .times(1)
+            .returning(|_| Ok(()));
+        let session = Session::Authorized(Authorized::new(String::default(), mock_user_storage));
+
+        let mut names: Vec<String> = vec![];
+        stream_records(&session, &mut |name| names.push(name)).unwrap();
+        assert!(names.is_empty());
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        assert!(matches!(
+            stream_records(&Session::default(), &mut |_| ()),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_storage_error() {
+        let mock_user_storage = AsyncUserStorage::default();
+        mock_user_storage
+            .write()
+            .unwrap()
+            .expect_visit_records()
+            .times(1)
+            .returning(|_| Err(storage::Error::Io(io::Error::other(""))));
+        let session = Session::Authorized(Authorized::new(String::default(), mock_user_storage));
+
+        assert!(matches!(
+            stream_records(&session, &mut |_| ()),
+            Err(Error::Storage(_))
+        ));
+    }
+}
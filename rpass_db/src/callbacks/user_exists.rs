@@ -0,0 +1,96 @@
+use super::{session::*, utils, ArgIter, AsyncStorage, Error, Result};
+
+/// Checks if user with name from `arg_iter` is registered. Available only for
+/// administrators, see [`crate::storage::Storage::is_admin()`], so that
+/// usernames can't be enumerated.
+/// Returns *Ok("true")* or *Ok("false")*
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `PermissionDenied` - if user stored in `session` isn't an administrator
+/// * `EmptyUsername` - if no username was provided
+/// * `Storage` - if can't check permissions cause of some error in `storage`
+pub fn user_exists(storage: AsyncStorage, session: &Session, arg_iter: ArgIter) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    let storage_read = storage.read().unwrap();
+    utils::check_admin(&storage_read, authorized_session)?;
+
+    let username = arg_iter.next().ok_or(Error::EmptyUsername)?;
+    Ok(storage_read.user_exists(&username).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::utils::admin_tests::*;
+    use super::*;
+    use mockall::predicate;
+
+    const TEST_USER: &str = "test_user";
+
+    #[test]
+    fn test_ok() {
+        for exists in [true, false] {
+            let mock_storage = AsyncStorage::default();
+            {
+                let mut storage_write = mock_storage.write().unwrap();
+                expect_is_admin(&mut storage_write, true);
+                storage_write
+                    .expect_user_exists()
+                    .with(predicate::eq(TEST_USER))
+                    .times(1)
+                    .return_const(exists);
+            }
+            let mut arg_iter = [TEST_USER].iter().map(|&s| s.to_owned());
+
+            assert_eq!(
+                user_exists(mock_storage, &build_admin_session(), &mut arg_iter).unwrap(),
+                exists.to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let mock_storage = AsyncStorage::default();
+        let session = Session::default();
+        let mut arg_iter = [TEST_USER].iter().map(|&s| s.to_owned());
+
+        assert!(matches!(
+            user_exists(mock_storage, &session, &mut arg_iter),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_not_admin() {
+        let mock_storage = AsyncStorage::default();
+        {
+            let mut storage_write = mock_storage.write().unwrap();
+            expect_is_admin(&mut storage_write, false);
+            storage_write.expect_user_exists().never();
+        }
+        let mut arg_iter = [TEST_USER].iter().map(|&s| s.to_owned());
+
+        assert!(matches!(
+            user_exists(mock_storage, &build_admin_session(), &mut arg_iter),
+            Err(Error::PermissionDenied)
+        ));
+    }
+
+    #[test]
+    fn test_empty_username() {
+        let mock_storage = AsyncStorage::default();
+        expect_is_admin(&mut mock_storage.write().unwrap(), true);
+        let mut arg_iter = [].iter().map(|s: &&str| s.to_string());
+
+        assert!(matches!(
+            user_exists(mock_storage, &build_admin_session(), &mut arg_iter),
+            Err(Error::EmptyUsername)
+        ));
+    }
+}
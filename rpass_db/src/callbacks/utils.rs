@@ -1,19 +1,30 @@
+use super::{session::Authorized, Error, Result};
+use crate::Storage;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use rpass::name;
+
 /// Checks if `name` is a *safe* string to be a filename.
 /// Valid means:
 /// * Not empty
-/// * All characters are ascii alphanumeric or `.`, or `@`, or `_`
-/// * Contains at least one alphabetic character
+/// * All characters are ascii alphanumeric or `.`, or `@`, or `_`, or non-ascii
+///   non-control characters
+/// * Contains at least one alphabetic or non-ascii character
 /// * Doesn't contains `..`
 /// * Doesn't start with `.`, `@` or `_`
 /// * Doesn't end with `.`, `@` or `_`
-/// * Doesn't equal to `key.pub` or `key.sec`
-/// * No more than 32 characters in length
+/// * No more than [`name::MAX_LENGTH`] bytes in length
+///
+/// Non-ascii characters are percent-encoded by storage before touching
+/// the filesystem
 pub fn is_safe_for_filename(name: &str) -> bool {
     !(name.is_empty()
         || !name
             .chars()
-            .all(|c| char::is_ascii_alphanumeric(&c) || c == '.' || c == '@' || c == '_')
-        || !name.chars().any(|c| char::is_ascii_alphabetic(&c))
+            .all(|c| c.is_ascii_alphanumeric() || ".@_".contains(c) || is_non_ascii_printable(c))
+        || !name
+            .chars()
+            .any(|c| c.is_ascii_alphabetic() || is_non_ascii_printable(c))
         || is_contains_two_dots(name)
         || name.starts_with('.')
         || name.starts_with('@')
@@ -21,9 +32,57 @@ pub fn is_safe_for_filename(name: &str) -> bool {
         || name.ends_with('.')
         || name.ends_with('@')
         || name.ends_with('_')
-        || name == "key.pub"
-        || name == "key.sec"
-        || name.len() > 32)
+        || name.len() > name::MAX_LENGTH)
+}
+
+/// Checks if `name` is a *safe* string to be a username.
+///
+/// Same as [`is_safe_for_filename()`], but `name` also can't be reserved,
+/// cause user directories lay next to server key files, see
+/// [`name::is_reserved()`]
+pub fn is_safe_for_username(name: &str) -> bool {
+    is_safe_for_filename(name) && !name::is_reserved(name)
+}
+
+/// Checks if `name` is a *safe* string to be a resource name.
+///
+/// Same as [`is_safe_for_filename()`], but spaces are also allowed
+/// everywhere except the beginning and the end of `name`. `name` also can't
+/// shadow user public key file stored next to records
+pub fn is_safe_for_resource_name(name: &str) -> bool {
+    !(name.starts_with(' ') || name.ends_with(' ') || name.eq_ignore_ascii_case("key.pub"))
+        && is_safe_for_filename(&name.replace(' ', "_"))
+}
+
+/// Checks if user of `authorized_session` is an administrator,
+/// see [`crate::storage::Storage::is_admin()`]
+///
+/// # Errors
+///
+/// * `PermissionDenied` - if user isn't an administrator
+/// * `Storage` - if can't check permissions cause of some error in `storage`
+pub fn check_admin(storage: &Storage, authorized_session: &Authorized) -> Result<()> {
+    if storage.is_admin(&authorized_session.username)? {
+        Ok(())
+    } else {
+        Err(Error::PermissionDenied)
+    }
+}
+
+/// Length of strings generated by [`generate_confirmation()`]
+pub const CONFIRMATION_LENGTH: usize = 30;
+
+/// Generates random alphanumeric string with `rng` to be used as confirmation
+/// of key possession
+pub fn generate_confirmation<R: Rng + ?Sized>(rng: &mut R) -> String {
+    rng.sample_iter(&Alphanumeric)
+        .take(CONFIRMATION_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+fn is_non_ascii_printable(c: char) -> bool {
+    !c.is_ascii() && !c.is_control()
 }
 
 fn is_contains_two_dots(s: &str) -> bool {
@@ -32,13 +91,70 @@ fn is_contains_two_dots(s: &str) -> bool {
         .any(|(c1, c2)| c1 == '.' && c2 == '.')
 }
 
+/// Helpers for tests of callbacks available only for administrators
+#[cfg(test)]
+pub mod admin_tests {
+    use super::super::{session::Session, storage, AsyncUserStorage};
+    use super::*;
+    use mockall::predicate;
+
+    /// Name of the user in session made by [`build_admin_session()`]
+    pub const ADMIN: &str = "admin";
+
+    /// Builds session authorized as [`ADMIN`]
+    pub fn build_admin_session() -> Session {
+        Session::Authorized(Authorized::new(
+            ADMIN.to_owned(),
+            AsyncUserStorage::default(),
+        ))
+    }
+
+    /// Expects single check if [`ADMIN`] is an administrator answered with `is_admin`
+    pub fn expect_is_admin(storage: &mut storage::MockStorage, is_admin: bool) {
+        storage
+            .expect_is_admin()
+            .with(predicate::eq(ADMIN))
+            .times(1)
+            .returning(move |_| Ok(is_admin));
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use super::super::storage;
+    use super::admin_tests::*;
     use super::*;
+    use std::io;
+
+    #[test]
+    fn test_check_admin() {
+        let session = build_admin_session();
+        let authorized_session = session.as_authorized().unwrap();
+        let mut mock_storage = Storage::default();
+        expect_is_admin(&mut mock_storage, true);
+        assert!(check_admin(&mock_storage, authorized_session).is_ok());
+
+        let mut mock_storage = Storage::default();
+        expect_is_admin(&mut mock_storage, false);
+        assert!(matches!(
+            check_admin(&mock_storage, authorized_session),
+            Err(Error::PermissionDenied)
+        ));
+
+        let mut mock_storage = Storage::default();
+        mock_storage
+            .expect_is_admin()
+            .returning(|_| Err(storage::Error::Io(io::Error::other(""))));
+        assert!(matches!(
+            check_admin(&mock_storage, authorized_session),
+            Err(Error::Storage(_))
+        ));
+    }
+
     #[test]
     fn test() {
         assert!(!is_safe_for_filename(""));
-        assert!(!is_safe_for_filename("Борщ"));
+        assert!(!is_safe_for_filename("\u{85}user"));
         assert!(!is_safe_for_filename("786.@09"));
         assert!(!is_safe_for_filename("not/a/hacker/seriously"));
         assert!(!is_safe_for_filename("user..name"));
@@ -48,12 +164,45 @@ mod tests {
         assert!(!is_safe_for_filename("user@"));
         assert!(!is_safe_for_filename("_user"));
         assert!(!is_safe_for_filename("user_"));
-        assert!(!is_safe_for_filename("key.pub"));
-        assert!(!is_safe_for_filename("key.sec"));
-        assert!(!is_safe_for_filename(
-            &String::from_utf8(vec![b'X'; 33]).unwrap()
-        ));
+        assert!(!is_safe_for_filename(&"X".repeat(name::MAX_LENGTH + 1)));
+        assert!(is_safe_for_filename(&"X".repeat(name::MAX_LENGTH)));
 
         assert!(is_safe_for_filename("user_404@example.com"));
+        assert!(!is_safe_for_filename("My Bank"));
+        assert!(is_safe_for_filename("Борщ"));
+        assert!(is_safe_for_filename("🏦"));
+    }
+
+    #[test]
+    fn test_is_safe_for_username() {
+        assert!(is_safe_for_username("user_404@example.com"));
+
+        assert!(!is_safe_for_username("_user"));
+        assert!(!is_safe_for_username("key.pub"));
+        assert!(!is_safe_for_username("key.sec"));
+        assert!(!is_safe_for_username("key"));
+        assert!(!is_safe_for_username("admins"));
+        assert!(!is_safe_for_username("NUL"));
+        assert!(!is_safe_for_username("con.example.com"));
+    }
+
+    #[test]
+    fn test_is_safe_for_resource_name() {
+        assert!(is_safe_for_resource_name("example.com"));
+        assert!(is_safe_for_resource_name("My Bank"));
+        assert!(is_safe_for_resource_name("My  Old Bank"));
+
+        assert!(!is_safe_for_resource_name(" bank"));
+        assert!(!is_safe_for_resource_name("bank "));
+        assert!(!is_safe_for_resource_name("   "));
+        assert!(!is_safe_for_resource_name("../My Bank"));
+        assert!(!is_safe_for_resource_name("My Bank/.."));
+        assert!(!is_safe_for_resource_name("key.pub"));
+        assert!(!is_safe_for_resource_name("KEY.PUB"));
+
+        assert!(is_safe_for_resource_name("con.example.com"));
+        assert!(is_safe_for_resource_name("aux.com"));
+        assert!(is_safe_for_resource_name("key"));
+        assert!(is_safe_for_resource_name("admins"));
     }
 }
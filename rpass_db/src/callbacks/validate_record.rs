@@ -0,0 +1,155 @@
+use super::{
+    new_record::{check_record_size, parse_record},
+    session::*,
+    ArgIter, Error, Result,
+};
+
+/// Checks record for user stored in `session` the same way as
+/// [`super::new_record()`] does, but doesn't write it.
+/// Reads resource name and record (See [`Record::from_str()`]) from `arg_iter`
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+/// * `EmptyResourceName` - if resource name wasn't provided
+/// * `InvalidResourceName` - if resource name is invalid
+/// * `EmptyRecordContent` - if record wasn't provided
+/// * `InvalidRecordFormat` - if can't parse *Record*
+/// * `RecordTooLarge` - if serialized record is longer than `max_size` bytes
+pub fn validate_record(
+    session: &Session,
+    arg_iter: ArgIter,
+    max_size: Option<usize>,
+) -> Result<String> {
+    session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    check_record_size(&parse_record(arg_iter)?, max_size)?;
+    Ok("Ok".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::AsyncUserStorage;
+    use super::*;
+
+    const TEST_USER: &str = "test_user";
+    const RESOURCE: &str = "example.com";
+    const PASSWORD: &str = "secret";
+    const NOTES: &str = "first notes\n\"second notes\"\n\"";
+
+    #[test]
+    fn test_ok() {
+        let content = String::from(PASSWORD) + "\n" + NOTES;
+        let session = build_session();
+        let args = [RESOURCE.to_owned(), content];
+        let mut arg_iter = args.iter().cloned();
+
+        assert_eq!(
+            validate_record(&session, &mut arg_iter, None).unwrap(),
+            "Ok".to_owned()
+        );
+    }
+
+    #[test]
+    fn test_spaced_resource() {
+        let content = String::from(PASSWORD) + "\n" + NOTES;
+        let session = build_session();
+        let args = ["My Bank".to_owned(), content];
+        let mut arg_iter = args.iter().cloned();
+
+        assert_eq!(
+            validate_record(&session, &mut arg_iter, None).unwrap(),
+            "Ok".to_owned()
+        );
+    }
+
+    #[test]
+    fn test_too_large() {
+        let content = String::from(PASSWORD) + "\n" + NOTES;
+        let session = build_session();
+        let args = [RESOURCE.to_owned(), content];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            validate_record(&session, &mut arg_iter, Some(PASSWORD.len())),
+            Err(Error::RecordTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let content = String::from(PASSWORD) + "\n" + NOTES;
+
+        let session = Session::default();
+        let args = [RESOURCE.to_owned(), content];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            validate_record(&session, &mut arg_iter, None),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+
+    #[test]
+    fn test_empty_resource() {
+        let session = build_session();
+        let args = [];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            validate_record(&session, &mut arg_iter, None),
+            Err(Error::EmptyResourceName)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_resource() {
+        let session = build_session();
+        let args = ["../illegal/resource/name".to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            validate_record(&session, &mut arg_iter, None),
+            Err(Error::InvalidResourceName)
+        ));
+    }
+
+    #[test]
+    fn test_empty_record_content() {
+        let session = build_session();
+        let args = [RESOURCE.to_owned()];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            validate_record(&session, &mut arg_iter, None),
+            Err(Error::EmptyRecordContent)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_record_format() {
+        let content = String::from(PASSWORD);
+        let session = build_session();
+        let args = [RESOURCE.to_owned(), content];
+        let mut arg_iter = args.iter().cloned();
+
+        assert!(matches!(
+            validate_record(&session, &mut arg_iter, None),
+            Err(Error::InvalidRecordFormat(_))
+        ));
+    }
+
+    /// Builds authorized session with storage expecting no writes
+    fn build_session() -> Session {
+        let mock_storage = AsyncUserStorage::default();
+        {
+            let mut mock_storage_write = mock_storage.write().unwrap();
+            mock_storage_write.expect_write_record().never();
+            mock_storage_write.expect_list_records().never();
+        }
+        Session::Authorized(Authorized::new(TEST_USER.to_owned(), mock_storage))
+    }
+}
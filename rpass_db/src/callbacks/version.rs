@@ -0,0 +1,27 @@
+use super::Result;
+
+/// Returns server version and comma-separated list of `commands` delimited by
+/// a new line character
+pub fn version(commands: &[String]) -> Result<String> {
+    Ok(format!(
+        "{}\n{}",
+        env!("CARGO_PKG_VERSION"),
+        commands.join(",")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        let commands = ["login".to_owned(), "version".to_owned()];
+
+        let response = version(&commands).unwrap();
+        let (version, commands) = response.split_once('\n').unwrap();
+        assert_eq!(version.split('.').count(), 3);
+        assert!(version.split('.').all(|part| part.parse::<u64>().is_ok()));
+        assert_eq!(commands, "login,version");
+    }
+}
@@ -0,0 +1,41 @@
+use super::{session::*, Error, Result};
+
+/// Gets name of the user stored in `session`
+///
+/// # Errors
+///
+/// * `UnacceptableRequestAtThisState` - if session is not an Authorized
+///   variant
+pub fn whoami(session: &Session) -> Result<String> {
+    let authorized_session = session
+        .as_authorized()
+        .ok_or(Error::UnacceptableRequestAtThisState)?;
+
+    Ok(authorized_session.username.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::AsyncUserStorage;
+    use super::*;
+
+    #[test]
+    fn test_ok() {
+        let session = Session::Authorized(Authorized::new(
+            String::from("test_user"),
+            AsyncUserStorage::default(),
+        ));
+
+        assert_eq!(whoami(&session).unwrap(), "test_user");
+    }
+
+    #[test]
+    fn test_non_authorized() {
+        let session = Session::default();
+
+        assert!(matches!(
+            whoami(&session),
+            Err(Error::UnacceptableRequestAtThisState)
+        ));
+    }
+}
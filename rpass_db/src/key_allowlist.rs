@@ -0,0 +1,100 @@
+use crate::storage::Key;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Public keys allowed to register or be set with key change on a closed
+/// deployment
+///
+/// Keys are compared by their fingerprints, see [`Key::fingerprint()`]
+#[derive(Debug)]
+pub struct KeyAllowlist {
+    fingerprints: HashSet<String>,
+}
+
+impl KeyAllowlist {
+    /// Reads allowlist from file at `path`
+    ///
+    /// Every line is either a key fingerprint or a key in `e:n` format.
+    /// Empty lines and lines starting with `#` are skipped
+    ///
+    /// # Errors
+    ///
+    /// * `InvalidData` - if some line is neither a fingerprint nor a key
+    /// * Any other error during file reading
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Checks if `key` is allowed
+    pub fn contains(&self, key: &Key) -> bool {
+        self.fingerprints.contains(&key.fingerprint())
+    }
+
+    /// Checks if `line` has fingerprint format: 64 lowercase hex digits
+    fn is_fingerprint(line: &str) -> bool {
+        line.len() == 64
+            && line
+                .chars()
+                .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+    }
+}
+
+impl FromStr for KeyAllowlist {
+    type Err = io::Error;
+
+    /// Parses allowlist file content, see [`KeyAllowlist::from_file()`]
+    fn from_str(s: &str) -> io::Result<Self> {
+        let mut fingerprints = HashSet::new();
+        for (number, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fingerprint = match Key::from_str(line) {
+                Ok(key) => key.fingerprint(),
+                Err(_) if Self::is_fingerprint(line) => line.to_owned(),
+                Err(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "line {} of key allowlist is neither a key nor a fingerprint",
+                            number + 1
+                        ),
+                    ))
+                }
+            };
+            fingerprints.insert(fingerprint);
+        }
+        Ok(KeyAllowlist { fingerprints })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_from_file() {
+        let fingerprint = Key::from_str("13:221").unwrap().fingerprint();
+        let path = env::temp_dir().join(format!("rpass_db_key_allowlist_{}", std::process::id()));
+        fs::write(&path, format!("# Team keys\n11:221\n\n{fingerprint}\n")).unwrap();
+
+        let allowlist = KeyAllowlist::from_file(&path).unwrap();
+        assert!(allowlist.contains(&Key::from_str("11:221").unwrap()));
+        assert!(allowlist.contains(&Key::from_str("13:221").unwrap()));
+        assert!(!allowlist.contains(&Key::from_str("17:221").unwrap()));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_line() {
+        let err = KeyAllowlist::from_str("11:221\nnot a key").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 2"), "{err}");
+    }
+}
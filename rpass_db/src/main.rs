@@ -1,78 +1,553 @@
 pub mod storage;
 
+mod audit_log;
 mod callbacks;
+mod key_allowlist;
+mod maintenance;
+mod rate_limiter;
 mod request_dispatcher;
 mod server;
 mod session;
+mod session_tokens;
 
+use anyhow::Context;
+use audit_log::AuditLog;
+use env_logger::Env;
+use key_allowlist::KeyAllowlist;
+use maintenance::Maintenance;
 use request_dispatcher::RequestDispatcher;
+use rpass::framing::Framing;
 use server::Server;
 use session::Session;
+use session_tokens::SessionTokens;
 use std::borrow::Cow;
 use std::io::{Error, ErrorKind};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 #[mockall_double::double]
 use storage::Storage;
 #[macro_use]
 extern crate lazy_static;
 
+/// Environment variable to set log level with, e.g. `RPASS_LOG=debug`
+const LOG_ENV: &str = "RPASS_LOG";
+
+/// Log level used if `LOG_ENV` isn't set
+const DEFAULT_LOG_LEVEL: &str = "info";
+
+/// Environment variable to set storage directory instead of `~/.rpass_storage`
+const STORAGE_ENV: &str = "RPASS_STORAGE";
+
+/// Environment variable to set path of the file with public keys allowed to
+/// register or be set with key change, see [`KeyAllowlist::from_file()`].
+/// Any key is accepted if it isn't set
+const KEY_ALLOWLIST_ENV: &str = "RPASS_KEY_ALLOWLIST";
+
+/// Port to listen on
+const PORT: u16 = 3747;
+
+/// Environment variable to set path of Unix domain socket to listen on instead of `PORT`
+#[cfg(unix)]
+const SOCKET_ENV: &str = "RPASS_SOCKET";
+
+/// Number of login attempts allowed for one connection per `LOGIN_WINDOW`
+const LOGIN_ATTEMPTS: usize = 5;
+
+/// Time window for login attempts
+const LOGIN_WINDOW: Duration = Duration::from_secs(60);
+
+/// Time for client to answer login confirmation
+const LOGIN_CONFIRMATION_EXPIRY: Duration = Duration::from_secs(30);
+
+/// Time after which authorized session without requests expires
+const IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// Time for which session token can be used to resume authorized session
+const SESSION_TOKEN_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Name of the audit log file in the home directory
+const AUDIT_LOG_FILE: &str = ".rpass_audit.log";
+
+/// Environment variable to set maximum number of records per user,
+/// e.g. `RPASS_MAX_RECORDS_PER_USER=1000`. Unlimited if not set or `0`
+const MAX_RECORDS_PER_USER_ENV: &str = "RPASS_MAX_RECORDS_PER_USER";
+
+/// Environment variable to set maximum number of concurrent sessions per user,
+/// e.g. `RPASS_MAX_SESSIONS_PER_USER=3`. Unlimited if not set or `0`
+const MAX_SESSIONS_PER_USER_ENV: &str = "RPASS_MAX_SESSIONS_PER_USER";
+
+/// Environment variable to set maximum size of serialized record in bytes,
+/// e.g. `RPASS_MAX_RECORD_SIZE=1048576`. `0` means unlimited
+const MAX_RECORD_SIZE_ENV: &str = "RPASS_MAX_RECORD_SIZE";
+
+/// Maximum size of serialized record in bytes used if `MAX_RECORD_SIZE_ENV`
+/// isn't set
+const DEFAULT_MAX_RECORD_SIZE: usize = 64 * 1024;
+
+/// Time for which numbers of users and records reported by *metrics* command
+/// are reused
+const METRICS_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Environment variable to set number of previous versions kept for every
+/// record, e.g. `RPASS_RECORD_HISTORY_LIMIT=5`. `0` disables history, which is
+/// the default
+const RECORD_HISTORY_LIMIT_ENV: &str = "RPASS_RECORD_HISTORY_LIMIT";
+
+/// Environment variable to move deleted records to trash instead of permanent
+/// removal, e.g. `RPASS_SOFT_DELETE=true`. Disabled by default
+const SOFT_DELETE_ENV: &str = "RPASS_SOFT_DELETE";
+
+/// Environment variable to sync written records to disk before responding to
+/// client, e.g. `RPASS_DURABLE_WRITES=true`. Disabled by default
+const DURABLE_WRITES_ENV: &str = "RPASS_DURABLE_WRITES";
+
+/// Environment variable to set backend keeping records of every user: `files`
+/// (default) or `sqlite`. SQLite backend requires `sqlite` feature
+const STORAGE_BACKEND_ENV: &str = "RPASS_STORAGE_BACKEND";
+
+/// Command line flag enabling newline framing of messages for manual debugging
+/// with tools like `nc`. Regular clients can't connect to such server
+const TEXT_PROTOCOL_FLAG: &str = "--text-protocol";
+
+/// Command line flag to replace storage keys and re-encrypt all records
+/// instead of running the server, see [`Storage::rotate_storage_keys()`]
+const ROTATE_STORAGE_KEYS_FLAG: &str = "--rotate-storage-keys";
+
 pub type AsyncStorage = Arc<RwLock<Storage>>;
 pub type AsyncRequestDispatcher = Arc<RwLock<RequestDispatcher>>;
 
 fn main() -> Result<(), anyhow::Error> {
+    env_logger::Builder::from_env(Env::default().filter_or(LOG_ENV, DEFAULT_LOG_LEVEL)).init();
+
     let home_dir = dirs::home_dir()
         .ok_or_else(|| Error::new(ErrorKind::NotFound, "Can't open home directory"))?;
-    let path = home_dir.join(".rpass_storage");
+    let path = match std::env::var_os(STORAGE_ENV) {
+        Some(path) => std::path::PathBuf::from(path),
+        None => home_dir.join(".rpass_storage"),
+    };
+
+    let backend = match std::env::var(STORAGE_BACKEND_ENV) {
+        Ok(backend) => backend.parse()?,
+        Err(_) => storage::StorageBackend::default(),
+    };
 
-    let storage = Arc::new(RwLock::new(Storage::new(path)?));
+    let storage_builder = Storage::builder(path)
+        .history_limit(parse_env(RECORD_HISTORY_LIMIT_ENV)?.unwrap_or(0))
+        .soft_delete(parse_env(SOFT_DELETE_ENV)?.unwrap_or(false))
+        .durable(parse_env(DURABLE_WRITES_ENV)?.unwrap_or(false))
+        .backend(backend);
+    let mut storage = Storage::open(storage_builder)?;
+    if std::env::args()
+        .skip(1)
+        .any(|arg| arg == ROTATE_STORAGE_KEYS_FLAG)
+    {
+        storage.rotate_storage_keys(storage::Key::generate_pair)?;
+        log::info!("Storage keys are rotated, clients will get new public key on connect");
+        return Ok(());
+    }
+    let storage = Arc::new(RwLock::new(storage));
     let pub_key = {
         let storage_read = storage.read().unwrap();
         storage_read.pub_key().to_string()
     };
-    let request_dispatcher = build_request_dispatcher(storage);
+    let audit_log = Arc::new(AuditLog::open(home_dir.join(AUDIT_LOG_FILE))?);
+    let key_allowlist = match std::env::var_os(KEY_ALLOWLIST_ENV) {
+        Some(path) => {
+            log::info!("Only keys from {path:?} can register");
+            Some(Arc::new(KeyAllowlist::from_file(path)?))
+        }
+        None => None,
+    };
+    let max_record_size = match parse_env(MAX_RECORD_SIZE_ENV)? {
+        Some(0) => None,
+        Some(size) => Some(size),
+        None => Some(DEFAULT_MAX_RECORD_SIZE),
+    };
+    let max_records = parse_env(MAX_RECORDS_PER_USER_ENV)?.filter(|&limit| limit != 0);
+    let max_sessions = parse_env(MAX_SESSIONS_PER_USER_ENV)?.filter(|&limit| limit != 0);
+    let request_dispatcher = build_request_dispatcher(
+        storage,
+        audit_log,
+        key_allowlist,
+        max_record_size,
+        max_records,
+        max_sessions,
+    );
 
-    let server = Server::new("127.0.0.1:3747", pub_key, request_dispatcher)?;
-    server.run();
+    let mut server = build_server(pub_key, request_dispatcher)?;
+    server.set_login_rate_limit(LOGIN_ATTEMPTS, LOGIN_WINDOW);
+    server.set_idle_timeout(IDLE_TIMEOUT);
+    if std::env::args()
+        .skip(1)
+        .any(|arg| arg == TEXT_PROTOCOL_FLAG)
+    {
+        log::warn!("Text protocol is enabled, messages are delimited with newlines");
+        server.set_framing(Framing::TEXT);
+    }
+    Arc::new(server).run()?;
 
     Ok(())
 }
 
-fn build_request_dispatcher(storage: Arc<RwLock<Storage>>) -> AsyncRequestDispatcher {
+/// Parses value of `env` environment variable. Returns `None` if it isn't set
+///
+/// # Errors
+///
+/// Returns error if value isn't valid Unicode or can't be parsed
+fn parse_env<T>(env: &str) -> Result<Option<T>, anyhow::Error>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let value = match std::env::var(env) {
+        Ok(value) => value,
+        Err(std::env::VarError::NotPresent) => return Ok(None),
+        Err(err) => return Err(anyhow::Error::new(err).context(format!("invalid {env}"))),
+    };
+    value
+        .parse()
+        .map(Some)
+        .with_context(|| format!("invalid {env} value `{value}`"))
+}
+
+/// Creates server listening on Unix domain socket if `SOCKET_ENV` is set or
+/// on localhost `PORT` otherwise
+fn build_server(pub_key: String, dispatcher: AsyncRequestDispatcher) -> std::io::Result<Server> {
+    #[cfg(unix)]
+    if let Some(path) = std::env::var_os(SOCKET_ENV) {
+        let server = Server::new_unix(&path, pub_key, dispatcher)?;
+        log::info!("Listening on {}", std::path::Path::new(&path).display());
+        return Ok(server);
+    }
+
+    let listen_addrs = [
+        SocketAddr::from((Ipv4Addr::LOCALHOST, PORT)),
+        SocketAddr::from((Ipv6Addr::LOCALHOST, PORT)),
+    ];
+    let server = Server::new(&listen_addrs[..], pub_key, dispatcher)?;
+    for addr in server.local_addrs()? {
+        log::info!("Listening on {addr}");
+    }
+    Ok(server)
+}
+
+fn build_request_dispatcher(
+    storage: Arc<RwLock<Storage>>,
+    audit_log: Arc<AuditLog>,
+    key_allowlist: Option<Arc<KeyAllowlist>>,
+    max_record_size: Option<usize>,
+    max_records: Option<usize>,
+    max_sessions: Option<usize>,
+) -> AsyncRequestDispatcher {
     let request_dispatcher = AsyncRequestDispatcher::default();
 
     {
         let register_storage = storage.clone();
         let login_storage = storage.clone();
         let confirm_login_storage = storage.clone();
-        let delete_me_storage = storage;
+        let delete_me_storage = storage.clone();
+        let rename_me_storage = storage.clone();
+        let confirm_change_pub_key_storage = storage.clone();
+        let change_pub_key_allowlist = key_allowlist.clone();
+        let list_users_storage = storage.clone();
+        let metrics_storage = storage.clone();
+        let maintenance_storage = storage.clone();
+        let metrics_cache = callbacks::MetricsCache::new(Instant::now(), METRICS_CACHE_TTL);
+        let resume_storage = storage.clone();
+        let user_exists_storage = storage;
+        let register_audit_log = audit_log.clone();
+        let login_audit_log = audit_log.clone();
+        let confirm_login_audit_log = audit_log.clone();
+        let resume_audit_log = audit_log.clone();
+        let delete_me_audit_log = audit_log;
+        let session_tokens = Arc::new(SessionTokens::new(SESSION_TOKEN_TTL));
+        let quit_session_tokens = session_tokens.clone();
+        let issue_session_tokens = session_tokens.clone();
+        let resume_session_tokens = session_tokens.clone();
+        let rename_me_session_tokens = session_tokens.clone();
+        let confirm_change_pub_key_session_tokens = session_tokens.clone();
+        let delete_me_session_tokens = session_tokens;
+        let maintenance = Arc::new(Maintenance::default());
+        let maintenance_mode = maintenance.clone();
 
         let mut dispatcher_write = request_dispatcher.write().unwrap();
         dispatcher_write
-            .add_callback(Cow::from("register"), move |_, arg_iter| {
-                callbacks::register(register_storage.clone(), arg_iter)
+            .set_maintenance(maintenance)
+            .add_callback(Cow::from("ping"), move |_, _| callbacks::ping())
+            .add_callback(Cow::from("quit"), move |session, _| {
+                callbacks::quit(session, &quit_session_tokens)
+            })
+            .add_callback_with_connection(Cow::from("register"), move |_, connection, arg_iter| {
+                callbacks::register(
+                    register_storage.clone(),
+                    &register_audit_log,
+                    key_allowlist.as_deref(),
+                    connection,
+                    arg_iter,
+                )
             })
-            .add_callback(Cow::from("login"), move |session, arg_iter| {
-                callbacks::login(login_storage.clone(), session, arg_iter)
+            .add_callback_with_connection(
+                Cow::from("login"),
+                move |session, connection, arg_iter| {
+                    callbacks::login(
+                        login_storage.clone(),
+                        &login_audit_log,
+                        session,
+                        connection,
+                        arg_iter,
+                    )
+                },
+            )
+            .add_callback_with_connection(
+                Cow::from("confirm_login"),
+                move |session, connection, arg_iter| {
+                    callbacks::confirm_login(
+                        confirm_login_storage.clone(),
+                        &confirm_login_audit_log,
+                        session,
+                        connection,
+                        arg_iter,
+                        LOGIN_CONFIRMATION_EXPIRY,
+                        max_sessions,
+                    )
+                },
+            )
+            .add_callback_with_connection(Cow::from("delete_me"), move |session, connection, _| {
+                callbacks::delete_me(
+                    delete_me_storage.clone(),
+                    &delete_me_audit_log,
+                    &delete_me_session_tokens,
+                    session,
+                    connection,
+                )
             })
-            .add_callback(Cow::from("confirm_login"), move |session, arg_iter| {
-                callbacks::confirm_login(confirm_login_storage.clone(), session, arg_iter)
+            .add_callback(Cow::from("issue_session_token"), move |session, _| {
+                callbacks::issue_session_token(session, &issue_session_tokens)
             })
-            .add_callback(Cow::from("delete_me"), move |session, _| {
-                callbacks::delete_me(delete_me_storage.clone(), session)
+            .add_callback_with_connection(
+                Cow::from("resume"),
+                move |session, connection, arg_iter| {
+                    callbacks::resume(
+                        resume_storage.clone(),
+                        &resume_session_tokens,
+                        &resume_audit_log,
+                        session,
+                        connection,
+                        arg_iter,
+                        max_sessions,
+                    )
+                },
+            )
+            .add_callback(Cow::from("rename_me"), move |session, arg_iter| {
+                callbacks::rename_me(
+                    rename_me_storage.clone(),
+                    &rename_me_session_tokens,
+                    session,
+                    arg_iter,
+                )
             })
+            .add_callback(Cow::from("change_pub_key"), move |session, arg_iter| {
+                callbacks::change_pub_key(session, change_pub_key_allowlist.as_deref(), arg_iter)
+            })
+            .add_callback(
+                Cow::from("confirm_change_pub_key"),
+                move |session, arg_iter| {
+                    callbacks::confirm_change_pub_key(
+                        confirm_change_pub_key_storage.clone(),
+                        &confirm_change_pub_key_session_tokens,
+                        session,
+                        arg_iter,
+                    )
+                },
+            )
             .add_callback(Cow::from("new_record"), move |session, arg_iter| {
-                callbacks::new_record(session, arg_iter)
+                callbacks::new_record(session, arg_iter, max_records, max_record_size)
+            })
+            .add_callback(Cow::from("validate_record"), move |session, arg_iter| {
+                callbacks::validate_record(session, arg_iter, max_record_size)
+            })
+            .add_callback(Cow::from("patch_record"), move |session, arg_iter| {
+                callbacks::patch_record(session, arg_iter, max_record_size)
             })
             .add_callback(Cow::from("show_record"), move |session, arg_iter| {
                 callbacks::show_record(session, arg_iter)
             })
-            .add_callback(Cow::from("list_records"), move |session, _| {
-                callbacks::list_records(session)
+            .add_callback(Cow::from("exists_record"), move |session, arg_iter| {
+                callbacks::exists_record(session, arg_iter)
+            })
+            .add_callback(Cow::from("list_records"), move |session, arg_iter| {
+                callbacks::list_records(session, arg_iter)
             })
+            .add_streaming_callback(Cow::from("stream_records"), move |session, _, sink| {
+                callbacks::stream_records(session, sink)
+            })
+            .add_callback(Cow::from("count_records"), move |session, _| {
+                callbacks::count_records(session)
+            })
+            .add_callback(Cow::from("list_trash"), move |session, _| {
+                callbacks::list_trash(session)
+            })
+            .add_callback(Cow::from("restore_record"), move |session, arg_iter| {
+                callbacks::restore_record(session, arg_iter)
+            })
+            .add_callback(Cow::from("purge_trash"), move |session, _| {
+                callbacks::purge_trash(session)
+            })
+            .add_callback(Cow::from("get_record_history"), move |session, arg_iter| {
+                callbacks::get_record_history(session, arg_iter)
+            })
+            .add_callback(
+                Cow::from("restore_record_version"),
+                move |session, arg_iter| callbacks::restore_record_version(session, arg_iter),
+            )
             .add_callback(Cow::from("delete_record"), move |session, arg_iter| {
                 callbacks::delete_record(session, arg_iter)
+            })
+            .add_callback(Cow::from("delete_records"), move |session, arg_iter| {
+                callbacks::delete_records(session, arg_iter)
+            })
+            .add_callback(Cow::from("stats"), move |session, _| {
+                callbacks::stats(session)
+            })
+            .add_callback(Cow::from("list_users"), move |session, _| {
+                callbacks::list_users(list_users_storage.clone(), session)
+            })
+            .add_callback(Cow::from("metrics"), move |session, _| {
+                callbacks::metrics(metrics_storage.clone(), session, &metrics_cache)
+            })
+            .add_callback(Cow::from("maintenance"), move |session, arg_iter| {
+                callbacks::maintenance(
+                    maintenance_storage.clone(),
+                    session,
+                    &maintenance_mode,
+                    arg_iter,
+                )
+            })
+            .add_callback(Cow::from("user_exists"), move |session, arg_iter| {
+                callbacks::user_exists(user_exists_storage.clone(), session, arg_iter)
+            })
+            .add_callback(Cow::from("whoami"), move |session, _| {
+                callbacks::whoami(session)
+            });
+
+        dispatcher_write
+            .add_alias(Cow::from("ls"), Cow::from("list_records"))
+            .add_alias(Cow::from("rm"), Cow::from("delete_record"));
+        for command in [
+            "register",
+            "login",
+            "resume",
+            "rename_me",
+            "change_pub_key",
+            "confirm_change_pub_key",
+            "delete_me",
+            "new_record",
+            "patch_record",
+            "restore_record",
+            "purge_trash",
+            "restore_record_version",
+            "delete_record",
+            "delete_records",
+        ] {
+            dispatcher_write.restrict_in_maintenance(Cow::from(command));
+        }
+
+        let mut commands = dispatcher_write.commands();
+        commands.push(String::from("help"));
+        commands.push(String::from("version"));
+        commands.push(String::from(request_dispatcher::BATCH_COMMAND));
+        commands.push(String::from(rpass::compression::COMMAND));
+        commands.sort();
+        let aliases = dispatcher_write.aliases();
+        let help_commands = commands.clone();
+        dispatcher_write
+            .add_callback(Cow::from("help"), move |_, _| {
+                callbacks::help(&help_commands, &aliases)
+            })
+            .add_callback(Cow::from("version"), move |_, _| {
+                callbacks::version(&commands)
             });
     }
 
     request_dispatcher
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registered_commands() {
+        let request_dispatcher = build_request_dispatcher(
+            Arc::new(RwLock::new(Storage::default())),
+            Arc::new(AuditLog::disabled()),
+            None,
+            Some(DEFAULT_MAX_RECORD_SIZE),
+            None,
+            None,
+        );
+        let dispatcher = request_dispatcher.read().unwrap();
+
+        assert_eq!(
+            dispatcher.commands(),
+            [
+                "change_pub_key",
+                "confirm_change_pub_key",
+                "confirm_login",
+                "count_records",
+                "delete_me",
+                "delete_record",
+                "delete_records",
+                "exists_record",
+                "get_record_history",
+                "help",
+                "issue_session_token",
+                "list_records",
+                "list_trash",
+                "list_users",
+                "login",
+                "maintenance",
+                "metrics",
+                "new_record",
+                "patch_record",
+                "ping",
+                "purge_trash",
+                "quit",
+                "register",
+                "rename_me",
+                "restore_record",
+                "restore_record_version",
+                "resume",
+                "show_record",
+                "stats",
+                "stream_records",
+                "user_exists",
+                "validate_record",
+                "version",
+                "whoami",
+            ]
+        );
+        assert_eq!(
+            dispatcher.aliases(),
+            [
+                (String::from("ls"), String::from("list_records")),
+                (String::from("rm"), String::from("delete_record"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_env() {
+        const ENV: &str = "RPASS_TEST_PARSE_ENV";
+
+        assert_eq!(parse_env::<bool>(ENV).unwrap(), None);
+        std::env::set_var(ENV, "true");
+        assert_eq!(parse_env::<bool>(ENV).unwrap(), Some(true));
+        std::env::set_var(ENV, "yes");
+        let err = parse_env::<bool>(ENV).unwrap_err();
+        assert_eq!(err.to_string(), format!("invalid {ENV} value `yes`"));
+        std::env::remove_var(ENV);
+    }
+}
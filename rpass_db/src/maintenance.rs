@@ -0,0 +1,24 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Server maintenance mode
+///
+/// While enabled, commands marked with
+/// [`crate::request_dispatcher::RequestDispatcher::restrict_in_maintenance()`]
+/// are rejected, so new logins and changes of records are refused, while
+/// existing sessions can still read their records
+#[derive(Debug, Default)]
+pub struct Maintenance {
+    enabled: AtomicBool,
+}
+
+impl Maintenance {
+    /// Checks if maintenance mode is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    /// Enables or disables maintenance mode
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Limits number of attempts during sliding time window
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    max_attempts: usize,
+    window: Duration,
+    attempts: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    /// Creates new `RateLimiter` allowing `max_attempts` during every `window`
+    pub fn new(max_attempts: usize, window: Duration) -> Self {
+        RateLimiter {
+            max_attempts,
+            window,
+            attempts: VecDeque::with_capacity(max_attempts),
+        }
+    }
+
+    /// Registers attempt made at `now`
+    ///
+    /// Returns `false` if limit is exceeded. Rejected attempts aren't
+    /// registered
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        while let Some(&oldest) = self.attempts.front() {
+            if now.duration_since(oldest) < self.window {
+                break;
+            }
+            self.attempts.pop_front();
+        }
+
+        if self.attempts.len() >= self.max_attempts {
+            return false;
+        }
+
+        self.attempts.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_ATTEMPTS: usize = 3;
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_limit_exceeded() {
+        let mut limiter = RateLimiter::new(MAX_ATTEMPTS, WINDOW);
+        let now = Instant::now();
+
+        for _ in 0..MAX_ATTEMPTS {
+            assert!(limiter.try_acquire(now));
+        }
+        assert!(!limiter.try_acquire(now));
+        assert!(!limiter.try_acquire(now + WINDOW / 2));
+    }
+
+    #[test]
+    fn test_reset_after_window() {
+        let mut limiter = RateLimiter::new(MAX_ATTEMPTS, WINDOW);
+        let now = Instant::now();
+
+        for _ in 0..MAX_ATTEMPTS {
+            assert!(limiter.try_acquire(now));
+        }
+        assert!(!limiter.try_acquire(now));
+        assert!(limiter.try_acquire(now + WINDOW));
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let mut limiter = RateLimiter::new(2, WINDOW);
+        let now = Instant::now();
+
+        assert!(limiter.try_acquire(now));
+        assert!(limiter.try_acquire(now + WINDOW / 2));
+        assert!(!limiter.try_acquire(now + WINDOW / 2));
+        assert!(limiter.try_acquire(now + WINDOW));
+        assert!(!limiter.try_acquire(now + WINDOW));
+    }
+}
@@ -1,42 +1,97 @@
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::callbacks;
-use crate::session::Session;
+use crate::maintenance::Maintenance;
+use crate::session::{ConnectionInfo, Session};
 use regex::Regex;
 
 pub type ArgIter<'a> = &'a mut dyn Iterator<Item = String>;
 pub type Result<T> = std::result::Result<T, Error>;
 
-type Callback = dyn Fn(&mut Session, ArgIter) -> callbacks::Result<String> + Send + Sync;
+pub type Sink<'a> = &'a mut dyn FnMut(String);
+
+type Callback =
+    dyn Fn(&mut Session, &ConnectionInfo, ArgIter) -> callbacks::Result<String> + Send + Sync;
+type StreamingCallback = dyn Fn(&mut Session, ArgIter, Sink) -> callbacks::Result<()> + Send + Sync;
+type BeforeHook = dyn Fn(&str, &Session) + Send + Sync;
+type AfterHook = dyn Fn(&str, &Result<String>, Duration) + Send + Sync;
 
 mod error {
     use super::callbacks;
     use super::Cow;
+    use rpass::error::ErrorCode;
 
     #[derive(thiserror::Error, Debug)]
     pub enum Error {
         #[error("command wasn't provided")]
         NoCommandProvided,
 
-        #[error("undefined command `{0}`")]
-        UndefinedCommand(Cow<'static, str>),
+        #[error("undefined command `{command}`{}", did_you_mean(suggestion))]
+        UndefinedCommand {
+            command: Cow<'static, str>,
+            suggestion: Option<String>,
+        },
+
+        #[error("alias `{alias}` points to undefined command `{target}`")]
+        DanglingAlias {
+            alias: Cow<'static, str>,
+            target: Cow<'static, str>,
+        },
+
+        #[error("batch can't contain another batch")]
+        NestedBatch,
 
         #[error("callback error: {0}")]
         Callback(#[from] callbacks::Error),
     }
+
+    /// Formats `suggestion` as a hint for the error message
+    fn did_you_mean(suggestion: &Option<String>) -> String {
+        match suggestion {
+            Some(suggestion) => format!(", did you mean `{suggestion}`?"),
+            None => String::new(),
+        }
+    }
+
+    impl Error {
+        /// Gets machine-readable code of the error
+        pub fn code(&self) -> ErrorCode {
+            match self {
+                Self::NoCommandProvided => ErrorCode::NoCommandProvided,
+                Self::UndefinedCommand { .. } | Self::DanglingAlias { .. } => {
+                    ErrorCode::UndefinedCommand
+                }
+                Self::NestedBatch => ErrorCode::InvalidBatch,
+                Self::Callback(err) => err.code(),
+            }
+        }
+    }
 }
 
 pub use error::Error;
 
+/// Command to send multiple requests in one message. See [`RequestDispatcher::dispatch()`]
+pub const BATCH_COMMAND: &str = "batch";
+
 lazy_static! {
-    static ref ARGUMENTS_REGEX: Regex = Regex::new(r#"(?s)([^\s"]+|(?:".*?"))\s?+"#).unwrap();
+    static ref ARGUMENTS_REGEX: Regex =
+        Regex::new(r#"(?s)([^\s"]+|(?:"(?:[^"\\]|\\.)*"))\s?+"#).unwrap();
 }
 
 /// Dispatches requests to registered callbacks and returns response from them
 #[derive(Default)]
 pub struct RequestDispatcher {
     command_to_callback: HashMap<Cow<'static, str>, Box<Callback>>,
+    alias_to_command: HashMap<Cow<'static, str>, Cow<'static, str>>,
+    before_hooks: Vec<Box<BeforeHook>>,
+    after_hooks: Vec<Box<AfterHook>>,
+    case_insensitive: bool,
+    maintenance: Arc<Maintenance>,
+    restricted_in_maintenance: HashSet<Cow<'static, str>>,
+    command_to_streaming_callback: HashMap<Cow<'static, str>, Arc<StreamingCallback>>,
 }
 
 impl RequestDispatcher {
@@ -52,38 +107,915 @@ impl RequestDispatcher {
     where
         C: Fn(&mut Session, ArgIter) -> callbacks::Result<String> + Send + Sync + 'static,
     {
+        self.add_callback_with_connection(command, move |session, _, arg_iter| {
+            callback(session, arg_iter)
+        })
+    }
+
+    /// Same as [`RequestDispatcher::add_callback()`], but `callback` also receives
+    /// information about client connection
+    ///
+    /// Allows multiple adding with chaining
+    pub fn add_callback_with_connection<C>(
+        &mut self,
+        command: Cow<'static, str>,
+        callback: C,
+    ) -> &mut Self
+    where
+        C: Fn(&mut Session, &ConnectionInfo, ArgIter) -> callbacks::Result<String>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let command = self.normalize(command);
         self.command_to_callback.insert(command, Box::new(callback));
         self
     }
 
+    /// Add `alias` for the `target` command
+    ///
+    /// Commands always take precedence over aliases with the same name. Aliases are resolved
+    /// only once, so alias to another alias is treated as an alias to an undefined command.
+    /// Allows multiple adding with chaining
+    pub fn add_alias(&mut self, alias: Cow<'static, str>, target: Cow<'static, str>) -> &mut Self {
+        let (alias, target) = (self.normalize(alias), self.normalize(target));
+        self.alias_to_command.insert(alias, target);
+        self
+    }
+
+    /// Enables or disables case-insensitive matching of commands and aliases.
+    /// Disabled by default
+    ///
+    /// Only command is affected, arguments are passed to callbacks as is
+    pub fn set_case_insensitive(&mut self, case_insensitive: bool) -> &mut Self {
+        self.case_insensitive = case_insensitive;
+        if case_insensitive {
+            self.command_to_callback = self
+                .command_to_callback
+                .drain()
+                .map(|(command, callback)| (Cow::from(command.to_lowercase()), callback))
+                .collect();
+            self.alias_to_command = self
+                .alias_to_command
+                .drain()
+                .map(|(alias, target)| {
+                    (
+                        Cow::from(alias.to_lowercase()),
+                        Cow::from(target.to_lowercase()),
+                    )
+                })
+                .collect();
+            self.restricted_in_maintenance = self
+                .restricted_in_maintenance
+                .drain()
+                .map(|command| Cow::from(command.to_lowercase()))
+                .collect();
+            self.command_to_streaming_callback = self
+                .command_to_streaming_callback
+                .drain()
+                .map(|(command, callback)| (Cow::from(command.to_lowercase()), callback))
+                .collect();
+        }
+        self
+    }
+
+    /// Sets shared `maintenance` mode flag. See
+    /// [`RequestDispatcher::restrict_in_maintenance()`]
+    pub fn set_maintenance(&mut self, maintenance: Arc<Maintenance>) -> &mut Self {
+        self.maintenance = maintenance;
+        self
+    }
+
+    /// Makes `command` rejected with `Maintenance` error while maintenance mode
+    /// is enabled. Aliases to `command` are rejected too
+    ///
+    /// Allows multiple adding with chaining
+    pub fn restrict_in_maintenance(&mut self, command: Cow<'static, str>) -> &mut Self {
+        let command = self.normalize(command);
+        self.restricted_in_maintenance.insert(command);
+        self
+    }
+
+    /// Add streaming `callback` for `command`. It passes response lines to the sink
+    /// one by one instead of returning them all at once, so they can be sent as
+    /// separate messages followed by an empty one. See
+    /// [`RequestDispatcher::dispatch_streaming()`]
+    ///
+    /// [`RequestDispatcher::dispatch()`] and batch requests get lines joined with "\n".
+    /// Allows multiple adding with chaining
+    pub fn add_streaming_callback<C>(
+        &mut self,
+        command: Cow<'static, str>,
+        callback: C,
+    ) -> &mut Self
+    where
+        C: Fn(&mut Session, ArgIter, Sink) -> callbacks::Result<()> + Send + Sync + 'static,
+    {
+        let callback: Arc<StreamingCallback> = Arc::new(callback);
+        let collecting_callback = callback.clone();
+        self.add_callback(command.clone(), move |session, arg_iter| {
+            let mut lines = vec![];
+            collecting_callback(session, arg_iter, &mut |line| lines.push(line))?;
+            Ok(lines.join("\n"))
+        });
+
+        let command = self.normalize(command);
+        self.command_to_streaming_callback.insert(command, callback);
+        self
+    }
+
+    /// Checks if `request` invokes streaming callback, see
+    /// [`RequestDispatcher::add_streaming_callback()`]
+    pub fn is_streaming(&self, request: &str) -> bool {
+        split_request(request).next().is_some_and(|command| {
+            let command = self.resolve(command);
+            self.command_to_streaming_callback
+                .contains_key(command.as_str())
+        })
+    }
+
+    /// Add `hook` that will be invoked with command name and session before every dispatching
+    ///
+    /// Hooks are invoked in the order of adding. Allows multiple adding with chaining
+    pub fn add_before_hook<H>(&mut self, hook: H) -> &mut Self
+    where
+        H: Fn(&str, &Session) + Send + Sync + 'static,
+    {
+        self.before_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Add `hook` that will be invoked with command name, dispatching result and its duration
+    /// after every dispatching
+    ///
+    /// Hooks can only observe the result. They are invoked in the order of adding.
+    /// Allows multiple adding with chaining
+    pub fn add_after_hook<H>(&mut self, hook: H) -> &mut Self
+    where
+        H: Fn(&str, &Result<String>, Duration) + Send + Sync + 'static,
+    {
+        self.after_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Gets sorted list of all commands with registered callbacks
+    pub fn commands(&self) -> Vec<String> {
+        let mut commands: Vec<_> = self
+            .command_to_callback
+            .keys()
+            .map(|command| command.to_string())
+            .collect();
+        commands.sort();
+        commands
+    }
+
+    /// Gets list of all aliases with commands they point to sorted by alias
+    pub fn aliases(&self) -> Vec<(String, String)> {
+        let mut aliases: Vec<_> = self
+            .alias_to_command
+            .iter()
+            .map(|(alias, target)| (alias.to_string(), target.to_string()))
+            .collect();
+        aliases.sort();
+        aliases
+    }
+
+    /// Gets names of commands `request` would invoke if dispatched. Commands are
+    /// normalized and aliases are resolved. Batch request gives one command per
+    /// sub-request, see [`RequestDispatcher::dispatch()`]
+    pub fn request_commands(&self, request: &str) -> Vec<String> {
+        let Some(command) = split_request(request).next() else {
+            return vec![];
+        };
+        if self.normalize(Cow::from(command.clone())) != BATCH_COMMAND {
+            return vec![self.resolve(command)];
+        }
+
+        request
+            .lines()
+            .skip(1)
+            .filter_map(|sub_request| split_request(sub_request).next())
+            .map(|command| self.resolve(command))
+            .collect()
+    }
+
     /// Dispatches `request` to the associated callback and return response from it
     ///
+    /// Hooks are invoked around callback if `request` contains command, even if it's undefined
+    ///
+    /// If command is [`BATCH_COMMAND`], then every next line of `request` is dispatched as a
+    /// separate request. Responses are concatenated in the same order, each one prefixed with
+    /// its length in bytes and "\n". Errors are formatted with [`error_response()`], so one
+    /// failed request doesn't fail the whole batch
+    ///
     /// # Errors
     ///
     /// * `DispatchingError::NoCommandProvided` - if `request` doesn't contains command
     /// * `DispatchingError::UndefinedCommand` - if there isn't any callback for this command
+    /// * `DispatchingError::DanglingAlias` - if command is an alias to the command without
+    ///   callback
+    /// * `Maintenance` - if command is restricted while maintenance mode is enabled, see
+    ///   [`RequestDispatcher::restrict_in_maintenance()`]
     pub fn dispatch(&self, session: &mut Session, request: &str) -> Result<String> {
-        let mut iter = ARGUMENTS_REGEX
-            .captures_iter(request)
-            .map(|x| strip_quotes(&x[1]).to_owned());
+        self.dispatch_with_connection(session, &ConnectionInfo::default(), request)
+    }
+
+    /// Same as [`RequestDispatcher::dispatch()`], but passes information about client
+    /// `connection` to callbacks
+    ///
+    /// # Errors
+    ///
+    /// See [`RequestDispatcher::dispatch()`]
+    pub fn dispatch_with_connection(
+        &self,
+        session: &mut Session,
+        connection: &ConnectionInfo,
+        request: &str,
+    ) -> Result<String> {
+        self.dispatch_to(session, connection, request, None)
+    }
+
+    /// Same as [`RequestDispatcher::dispatch_with_connection()`], but streaming callback
+    /// passes response lines to `sink` and empty response is returned then. See
+    /// [`RequestDispatcher::add_streaming_callback()`]
+    ///
+    /// # Errors
+    ///
+    /// See [`RequestDispatcher::dispatch()`]. Some lines may be already passed to `sink`
+    /// if streaming callback fails
+    pub fn dispatch_streaming(
+        &self,
+        session: &mut Session,
+        connection: &ConnectionInfo,
+        request: &str,
+        sink: Sink,
+    ) -> Result<String> {
+        self.dispatch_to(session, connection, request, Some(sink))
+    }
+
+    /// Dispatches `request` to the streaming callback if there is `sink` and command has
+    /// one or to the regular callback otherwise
+    fn dispatch_to(
+        &self,
+        session: &mut Session,
+        connection: &ConnectionInfo,
+        request: &str,
+        sink: Option<Sink>,
+    ) -> Result<String> {
+        let mut iter = split_request(request);
         let command = match iter.next() {
-            Some(cmd) => Cow::from(cmd),
+            Some(cmd) => self.normalize(Cow::from(cmd)),
             None => return Err(Error::NoCommandProvided),
         };
+        if command == BATCH_COMMAND {
+            return Ok(self.dispatch_batch(session, connection, request));
+        }
+
+        for hook in &self.before_hooks {
+            hook(&command, session);
+        }
+
+        let start = Instant::now();
+        let res = self.find_callback(command.clone()).and_then(|callback| {
+            if self.maintenance.is_enabled() && self.is_restricted_in_maintenance(&command) {
+                return Err(callbacks::Error::Maintenance.into());
+            }
+            let streaming_callback = self
+                .command_to_streaming_callback
+                .get(self.resolve(command.to_string()).as_str());
+            match (streaming_callback, sink) {
+                (Some(streaming_callback), Some(sink)) => {
+                    streaming_callback(session, &mut iter, sink).map(|()| String::new())
+                }
+                _ => callback(session, connection, &mut iter),
+            }
+            .map_err(|err| err.into())
+        });
+        let duration = start.elapsed();
+
+        for hook in &self.after_hooks {
+            hook(&command, &res, duration);
+        }
+        res
+    }
+
+    /// Dispatches every line of `request` except the first one as a separate request.
+    /// See [`RequestDispatcher::dispatch()`] for response format
+    fn dispatch_batch(
+        &self,
+        session: &mut Session,
+        connection: &ConnectionInfo,
+        request: &str,
+    ) -> String {
+        let mut response = String::new();
+        for sub_request in request.lines().skip(1) {
+            let is_batch = split_request(sub_request)
+                .next()
+                .is_some_and(|command| self.normalize(Cow::from(command)) == BATCH_COMMAND);
+            let sub_response = match is_batch {
+                true => Err(Error::NestedBatch),
+                false => self.dispatch_with_connection(session, connection, sub_request),
+            }
+            .unwrap_or_else(|err| error_response(&err));
+
+            let sub_response = sub_response.trim_end_matches("\r\n");
+            response += &format!("{}\n{}", sub_response.len(), sub_response);
+        }
+        response
+    }
 
-        match self.command_to_callback.get(&command) {
-            Some(callback) => callback(session, &mut iter).map_err(|err| err.into()),
-            None => Err(Error::UndefinedCommand(command)),
+    /// Lowercases `command` if dispatcher is case-insensitive
+    fn normalize(&self, command: Cow<'static, str>) -> Cow<'static, str> {
+        match self.case_insensitive {
+            true => Cow::from(command.to_lowercase()),
+            false => command,
         }
     }
+
+    /// Normalizes `command` and resolves it if it's an alias
+    fn resolve(&self, command: String) -> String {
+        let command = self.normalize(Cow::from(command));
+        if self.command_to_callback.contains_key(&command) {
+            return command.into_owned();
+        }
+        match self.alias_to_command.get(&command) {
+            Some(target) => target.to_string(),
+            None => command.into_owned(),
+        }
+    }
+
+    /// Finds the most similar to `command` registered command or alias
+    ///
+    /// Returns `None` if there is no one close enough
+    fn suggest_command(&self, command: &str) -> Option<String> {
+        const MAX_DISTANCE: usize = 2;
+
+        self.command_to_callback
+            .keys()
+            .chain(self.alias_to_command.keys())
+            .map(|candidate| (levenshtein_distance(command, candidate), candidate))
+            .filter(|(distance, _)| *distance <= MAX_DISTANCE && *distance < command.len())
+            .min()
+            .map(|(_, candidate)| candidate.to_string())
+    }
+
+    /// Checks if `command` or command it's alias to is restricted in maintenance mode
+    fn is_restricted_in_maintenance(&self, command: &str) -> bool {
+        self.restricted_in_maintenance.contains(command)
+            || self
+                .alias_to_command
+                .get(command)
+                .is_some_and(|target| self.restricted_in_maintenance.contains(target))
+    }
+
+    /// Finds callback for `command` resolving aliases
+    ///
+    /// # Errors
+    ///
+    /// See [`RequestDispatcher::dispatch()`]
+    fn find_callback(&self, command: Cow<'static, str>) -> Result<&Callback> {
+        if let Some(callback) = self.command_to_callback.get(&command) {
+            return Ok(callback.as_ref());
+        }
+
+        let target = match self.alias_to_command.get(&command) {
+            Some(target) => target,
+            None => {
+                let suggestion = self.suggest_command(&command);
+                return Err(Error::UndefinedCommand {
+                    command,
+                    suggestion,
+                });
+            }
+        };
+        match self.command_to_callback.get(target) {
+            Some(callback) => Ok(callback.as_ref()),
+            None => Err(Error::DanglingAlias {
+                alias: command,
+                target: target.clone(),
+            }),
+        }
+    }
+}
+
+/// Computes Levenshtein distance between `a` and `b`
+fn levenshtein_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev_row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = prev_row[j] + usize::from(a_char != *b_char);
+            row.push(substitution.min(prev_row[j + 1] + 1).min(row[j] + 1));
+        }
+        prev_row = row;
+    }
+
+    prev_row[b.len()]
+}
+
+/// Formats `err` as response in `Error[<code>]: <message>` format
+pub fn error_response(err: &Error) -> String {
+    format!("Error[{}]: {}", err.code(), err)
+}
+
+/// Splits `request` into command and arguments. Quoted arguments may contain
+/// whitespaces and escaped `\"` and `\\`, they are returned without quotes
+/// and unescaped
+pub fn split_request(request: &str) -> impl Iterator<Item = String> + '_ {
+    ARGUMENTS_REGEX
+        .captures_iter(request)
+        .map(|x| strip_quotes(&x[1]))
 }
 
-/// Strips quotes `"` from start and end of `s`.
+/// Strips quotes `"` from start and end of `s` and unescapes `\"` and `\\`
+/// between them. Other escape sequences are left as is, so they reach
+/// callbacks unchanged.
 /// Deletes only one symbol from start and end if is is equal to `"`
-fn strip_quotes(s: &str) -> &str {
-    if s.starts_with('\"') && s.ends_with('\"') {
-        return s.strip_prefix('\"').unwrap().strip_suffix('\"').unwrap();
+fn strip_quotes(s: &str) -> String {
+    let quoted = match s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(quoted) => quoted,
+        None => return s.to_owned(),
+    };
+
+    let mut unescaped = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(&next)) if next == '\\' || next == '"' => {
+                unescaped.push(next);
+                chars.next();
+            }
+            (c, _) => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::{Authorized, Unauthorized};
+    use rpass::error::ErrorCode;
+    use rpass::record::Record;
+    use std::str::FromStr;
+    use std::sync::{Arc, RwLock};
+
+    #[test]
+    fn test_ping_in_all_states() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher.add_callback(Cow::from("ping"), |_, _| callbacks::ping());
+
+        let mut sessions = [
+            Session::Unauthorized(Unauthorized::default()),
+            Session::Authorized(Authorized::new(
+                String::from("test_user"),
+                Arc::new(RwLock::new(callbacks::storage::UserStorage::default())),
+            )),
+        ];
+        for session in &mut sessions {
+            assert_eq!(dispatcher.dispatch(session, "ping").unwrap(), "pong");
+        }
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher
+            .add_callback(Cow::from("version"), |_, _| callbacks::ping())
+            .add_callback(Cow::from("ping"), |_, _| callbacks::ping());
+
+        assert_eq!(dispatcher.commands(), ["ping", "version"]);
+    }
+
+    #[test]
+    fn test_aliases() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher
+            .add_callback(Cow::from("ping"), |_, _| callbacks::ping())
+            .add_alias(Cow::from("p"), Cow::from("ping"))
+            .add_alias(Cow::from("check"), Cow::from("ping"));
+
+        assert_eq!(dispatcher.commands(), ["ping"]);
+        assert_eq!(
+            dispatcher.aliases(),
+            [
+                (String::from("check"), String::from("ping")),
+                (String::from("p"), String::from("ping"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_undefined_command() {
+        let dispatcher = RequestDispatcher::new();
+
+        let err = dispatcher
+            .dispatch(&mut Session::default(), "ping")
+            .unwrap_err();
+        assert_eq!(err.code(), ErrorCode::UndefinedCommand);
+        assert!(matches!(
+            err,
+            Error::UndefinedCommand { command, suggestion: None } if command == "ping"
+        ));
+    }
+
+    #[test]
+    fn test_undefined_command_suggestion() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher
+            .add_callback(Cow::from("list_records"), |_, _| callbacks::ping())
+            .add_callback(Cow::from("list_users"), |_, _| callbacks::ping());
+
+        let err = dispatcher
+            .dispatch(&mut Session::default(), "lst_records")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "undefined command `lst_records`, did you mean `list_records`?"
+        );
+    }
+
+    #[test]
+    fn test_undefined_command_no_suggestion() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher.add_callback(Cow::from("list_records"), |_, _| callbacks::ping());
+
+        let err = dispatcher
+            .dispatch(&mut Session::default(), "completely_different")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "undefined command `completely_different`");
     }
 
-    s
+    #[test]
+    fn test_levenshtein_distance() {
+        assert_eq!(levenshtein_distance("", ""), 0);
+        assert_eq!(levenshtein_distance("ls", ""), 2);
+        assert_eq!(levenshtein_distance("kitten", "sitting"), 3);
+        assert_eq!(levenshtein_distance("lst_records", "list_records"), 1);
+    }
+
+    #[test]
+    fn test_alias() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher
+            .add_callback(Cow::from("ping"), |_, _| callbacks::ping())
+            .add_alias(Cow::from("p"), Cow::from("ping"));
+
+        assert_eq!(
+            dispatcher.dispatch(&mut Session::default(), "p").unwrap(),
+            "pong"
+        );
+    }
+
+    #[test]
+    fn test_command_takes_precedence_over_alias() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher
+            .add_callback(Cow::from("ping"), |_, _| callbacks::ping())
+            .add_callback(Cow::from("echo"), |_, arg_iter| Ok(arg_iter.collect()))
+            .add_alias(Cow::from("echo"), Cow::from("ping"));
+
+        assert_eq!(
+            dispatcher
+                .dispatch(&mut Session::default(), "echo hello")
+                .unwrap(),
+            "hello"
+        );
+    }
+
+    #[test]
+    fn test_dangling_alias() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher.add_alias(Cow::from("ls"), Cow::from("list_records"));
+
+        assert!(matches!(
+            dispatcher.dispatch(&mut Session::default(), "ls"),
+            Err(Error::DanglingAlias { alias, target })
+                if alias == "ls" && target == "list_records"
+        ));
+    }
+
+    #[test]
+    fn test_cyclic_aliases() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher
+            .add_alias(Cow::from("first"), Cow::from("second"))
+            .add_alias(Cow::from("second"), Cow::from("first"));
+
+        assert!(matches!(
+            dispatcher.dispatch(&mut Session::default(), "first"),
+            Err(Error::DanglingAlias { alias, target })
+                if alias == "first" && target == "second"
+        ));
+    }
+
+    #[test]
+    fn test_hooks_order() {
+        let events = Arc::new(RwLock::new(vec![]));
+        let mut dispatcher = build_dispatcher_with_hooks(events.clone());
+        dispatcher.add_callback(Cow::from("ping"), |_, _| callbacks::ping());
+
+        dispatcher
+            .dispatch(&mut Session::default(), "ping")
+            .unwrap();
+        assert_eq!(
+            *events.read().unwrap(),
+            [
+                "first before ping",
+                "second before ping",
+                "after ping: pong"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hooks_on_failure() {
+        let events = Arc::new(RwLock::new(vec![]));
+        let mut dispatcher = build_dispatcher_with_hooks(events.clone());
+        dispatcher.add_callback(Cow::from("fail"), |_, _| {
+            Err(callbacks::Error::UnacceptableRequestAtThisState)
+        });
+
+        assert!(dispatcher
+            .dispatch(&mut Session::default(), "fail")
+            .is_err());
+        assert_eq!(
+            *events.read().unwrap(),
+            [
+                "first before fail",
+                "second before fail",
+                "after fail: callback error: unacceptable request at this state"
+            ]
+        );
+    }
+
+    /// Builds dispatcher with two before hooks and one after hook writing to `events`
+    fn build_dispatcher_with_hooks(events: Arc<RwLock<Vec<String>>>) -> RequestDispatcher {
+        let mut dispatcher = RequestDispatcher::new();
+        let first_events = events.clone();
+        let second_events = events.clone();
+        dispatcher
+            .add_before_hook(move |command, _| {
+                first_events
+                    .write()
+                    .unwrap()
+                    .push(format!("first before {command}"))
+            })
+            .add_before_hook(move |command, _| {
+                second_events
+                    .write()
+                    .unwrap()
+                    .push(format!("second before {command}"))
+            })
+            .add_after_hook(move |command, res, _| {
+                let res = match res {
+                    Ok(response) => response.clone(),
+                    Err(err) => err.to_string(),
+                };
+                events
+                    .write()
+                    .unwrap()
+                    .push(format!("after {command}: {res}"))
+            });
+        dispatcher
+    }
+
+    #[test]
+    fn test_maintenance() {
+        let maintenance = Arc::new(Maintenance::default());
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher
+            .set_maintenance(maintenance.clone())
+            .add_callback(Cow::from("ping"), |_, _| callbacks::ping())
+            .add_callback(Cow::from("write"), |_, _| Ok(String::from("Ok")))
+            .add_alias(Cow::from("w"), Cow::from("write"))
+            .restrict_in_maintenance(Cow::from("write"));
+        let mut session = Session::default();
+
+        maintenance.set_enabled(true);
+        for request in ["write", "w"] {
+            let err = dispatcher.dispatch(&mut session, request).unwrap_err();
+            assert_eq!(err.code(), ErrorCode::Maintenance);
+        }
+        assert_eq!(dispatcher.dispatch(&mut session, "ping").unwrap(), "pong");
+
+        maintenance.set_enabled(false);
+        for request in ["write", "w"] {
+            assert_eq!(dispatcher.dispatch(&mut session, request).unwrap(), "Ok");
+        }
+    }
+
+    #[test]
+    fn test_streaming() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher
+            .set_case_insensitive(true)
+            .add_callback(Cow::from("ping"), |_, _| callbacks::ping())
+            .add_streaming_callback(Cow::from("List"), |_, _, sink| {
+                sink(String::from("a"));
+                sink(String::from("b"));
+                Ok(())
+            })
+            .add_alias(Cow::from("ls"), Cow::from("LIST"));
+
+        let mut session = Session::default();
+        let connection = ConnectionInfo::default();
+        for request in ["list", "LIST arg", "ls"] {
+            assert!(dispatcher.is_streaming(request), "{request}");
+
+            let mut lines = vec![];
+            let response = dispatcher
+                .dispatch_streaming(&mut session, &connection, request, &mut |line| {
+                    lines.push(line)
+                })
+                .unwrap();
+            assert_eq!(response, "", "{request}");
+            assert_eq!(lines, ["a", "b"], "{request}");
+        }
+        for request in ["ping", "batch\nlist", ""] {
+            assert!(!dispatcher.is_streaming(request), "{request}");
+        }
+
+        assert_eq!(dispatcher.dispatch(&mut session, "ls").unwrap(), "a\nb");
+        let response = dispatcher
+            .dispatch_streaming(&mut session, &connection, "ping", &mut |_| {
+                panic!("non-streaming callback uses sink")
+            })
+            .unwrap();
+        assert_eq!(response, "pong");
+    }
+
+    #[test]
+    fn test_batch() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher
+            .add_callback(Cow::from("ping"), |_, _| callbacks::ping())
+            .add_callback(Cow::from("multiline"), |_, _| Ok(String::from("a\nb\r\n")));
+
+        let response = dispatcher
+            .dispatch(&mut Session::default(), "batch\nping\nundefined\nmultiline")
+            .unwrap();
+        assert_eq!(
+            response,
+            "4\npong\
+             54\nError[UndefinedCommand]: undefined command `undefined`\
+             3\na\nb"
+        );
+    }
+
+    #[test]
+    fn test_request_commands() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher
+            .add_callback(Cow::from("login"), |_, _| callbacks::ping())
+            .add_callback(Cow::from("new_record"), |_, _| callbacks::ping())
+            .add_alias(Cow::from("signin"), Cow::from("login"))
+            .set_case_insensitive(true);
+
+        assert_eq!(dispatcher.request_commands("LOGIN user"), ["login"]);
+        assert_eq!(dispatcher.request_commands("signin user"), ["login"]);
+        assert_eq!(
+            dispatcher.request_commands("new_record \"example.com\" \"secret\nlogin user\""),
+            ["new_record"]
+        );
+        assert_eq!(
+            dispatcher.request_commands("batch\nLogin user\nping\nsignin user"),
+            ["login", "ping", "login"]
+        );
+        assert!(dispatcher.request_commands("").is_empty());
+    }
+
+    #[test]
+    fn test_empty_batch() {
+        let dispatcher = RequestDispatcher::new();
+        assert_eq!(
+            dispatcher
+                .dispatch(&mut Session::default(), "batch")
+                .unwrap(),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_nested_batch() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher.add_callback(Cow::from("ping"), |_, _| callbacks::ping());
+
+        let response = dispatcher
+            .dispatch(&mut Session::default(), "batch\nbatch ping\nping")
+            .unwrap();
+        assert_eq!(
+            response,
+            "54\nError[InvalidBatch]: batch can't contain another batch4\npong"
+        );
+    }
+
+    #[test]
+    fn test_case_sensitive_by_default() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher.add_callback(Cow::from("ping"), |_, _| callbacks::ping());
+
+        assert!(matches!(
+            dispatcher.dispatch(&mut Session::default(), "PING"),
+            Err(Error::UndefinedCommand { .. })
+        ));
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher
+            .add_callback(Cow::from("echo"), |_, arg_iter| {
+                Ok(arg_iter.collect::<Vec<_>>().join(" "))
+            })
+            .set_case_insensitive(true)
+            .add_alias(Cow::from("E"), Cow::from("ECHO"));
+
+        let mut session = Session::default();
+        assert_eq!(
+            dispatcher
+                .dispatch(&mut session, "EcHo Secret \"Resource Name\"")
+                .unwrap(),
+            "Secret Resource Name"
+        );
+        assert_eq!(
+            dispatcher.dispatch(&mut session, "e MiXeD").unwrap(),
+            "MiXeD"
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive_disabled_again() {
+        let mut dispatcher = RequestDispatcher::new();
+        dispatcher
+            .set_case_insensitive(true)
+            .add_callback(Cow::from("PING"), |_, _| callbacks::ping())
+            .set_case_insensitive(false);
+
+        let mut session = Session::default();
+        assert_eq!(dispatcher.dispatch(&mut session, "ping").unwrap(), "pong");
+        assert!(dispatcher.dispatch(&mut session, "PING").is_err());
+    }
+
+    #[test]
+    fn test_split_request() {
+        let request = concat!(
+            r#"new_record "My Bank" "pass\\\\word"#,
+            "\n",
+            r#"notes with \"quotes\" and \\x22escapes\\x22 \n""#
+        );
+        assert_eq!(
+            split_request(request).collect::<Vec<_>>(),
+            [
+                "new_record",
+                "My Bank",
+                concat!(
+                    r#"pass\\word"#,
+                    "\n",
+                    r#"notes with "quotes" and \x22escapes\x22 \n"#
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_request_unquoted() {
+        assert_eq!(
+            split_request(r"rename_me back\slash").collect::<Vec<_>>(),
+            ["rename_me", r"back\slash"]
+        );
+        assert_eq!(
+            split_request("show_record \"unterminated").collect::<Vec<_>>(),
+            ["show_record", "unterminated"]
+        );
+    }
+
+    #[test]
+    fn test_split_request_record_round_trip() {
+        let record = Record {
+            resource: String::new(),
+            password: String::from(r"pass\word"),
+            notes: String::from("notes with \"quotes\"\nand \\backslashes\\"),
+            encrypted_notes: false,
+        };
+        let escaped = record
+            .to_string()
+            .replace('\\', r"\\")
+            .replace('"', r#"\""#);
+        let request = format!("new_record \"example.com\" \"{escaped}\"");
+
+        let args = split_request(&request).collect::<Vec<_>>();
+        assert_eq!(args.len(), 3);
+        assert_eq!(Record::from_str(&args[2]).unwrap(), record);
+    }
+
+    #[test]
+    fn test_storage_error_code() {
+        let err = Error::from(callbacks::Error::from(
+            callbacks::storage::Error::UserDoesNotExist(String::from("test_user")),
+        ));
+        assert_eq!(err.code(), ErrorCode::UserDoesNotExist);
+    }
 }
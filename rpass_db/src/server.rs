@@ -1,176 +1,1438 @@
-use std::borrow::Cow;
-use std::io::{self, BufRead, BufReader, Write};
-use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::io;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::{
+    fs::FileTypeExt,
+    net::{UnixListener, UnixStream},
+};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub type Result<T> = io::Result<T>;
 
+/// Max size of the compressed request after inflating. Compressed requests
+/// exceeding it are rejected
+pub const MAX_REQUEST_SIZE: usize = 4 * 1024 * 1024;
+
+use crate::rate_limiter::RateLimiter;
+use crate::request_dispatcher;
+use crate::session::ConnectionInfo;
 use crate::AsyncRequestDispatcher;
 use crate::Session;
+use log::{debug, info, warn};
+use rpass::compression;
+use rpass::error::ErrorCode;
+use rpass::framing::Framing;
+use rpass::request_id;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Listener of incoming connections
+enum Listener {
+    Tcp(TcpListener),
+    /// Unix domain socket listener with path to the socket file
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+/// Listener of incoming connections registered in tokio runtime
+enum AsyncListener {
+    Tcp(tokio::net::TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
+}
+
+impl AsyncListener {
+    /// Registers copy of `listener` in the current tokio runtime
+    ///
+    /// # Errors
+    ///
+    /// Any error during listener cloning or registration
+    fn new(listener: &Listener) -> Result<Self> {
+        match listener {
+            Listener::Tcp(listener) => {
+                let listener = listener.try_clone()?;
+                listener.set_nonblocking(true)?;
+                Ok(Self::Tcp(tokio::net::TcpListener::from_std(listener)?))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => {
+                let listener = listener.try_clone()?;
+                listener.set_nonblocking(true)?;
+                Ok(Self::Unix(tokio::net::UnixListener::from_std(listener)?))
+            }
+        }
+    }
+}
 
 /// Server to handle clients requests
 ///
-/// Allocates a new thread for every new connection
+/// Can listen on several addresses at once, e.g. on IPv4 and IPv6 ones.
+/// Spawns a new tokio task for every listener and every new connection
 pub struct Server {
-    listener: TcpListener,
+    listeners: Vec<Listener>,
     pub_key: String,
     dispatcher: AsyncRequestDispatcher,
+    login_rate_limiter: Option<RateLimiter>,
+    idle_timeout: Option<Duration>,
+    framing: Framing,
+    /// Id of the last accepted connection
+    last_connection_id: AtomicU64,
 }
 
 impl Server {
-    /// End of transmission character
-    const EOT: u8 = 0x04;
+    /// Connection is closed if client sends nothing for this number of idle timeouts.
+    /// Gives client a chance to learn that its session expired
+    const IDLE_CONNECTION_FACTOR: u32 = 2;
+
+    /// Commands limited by `login_rate_limiter`
+    const LOGIN_COMMANDS: [&'static str; 2] = ["login", "confirm_login"];
+
+    /// Number of streamed response messages buffered before callback waits
+    /// for them to be written to the client
+    const RESPONSE_BUFFER_SIZE: usize = 32;
 
-    /// Creates new Server instance serving on `addr` with public key `pub_key`
-    /// and `dispatcher` to handle clients
+    /// Creates new Server instance serving on every address from `addrs` with
+    /// public key `pub_key` and `dispatcher` to handle clients
+    ///
+    /// Addresses which can't be bound are skipped with a log message
+    ///
+    /// # Errors
+    ///
+    /// Any error during `addrs` resolving or if no address can be bound
     pub fn new<A: ToSocketAddrs>(
-        addr: A,
+        addrs: A,
         pub_key: String,
         dispatcher: AsyncRequestDispatcher,
     ) -> Result<Self> {
+        let mut listeners = vec![];
+        let mut last_err = None;
+        for addr in addrs.to_socket_addrs()? {
+            match TcpListener::bind(addr) {
+                Ok(listener) => listeners.push(Listener::Tcp(listener)),
+                Err(err) => {
+                    warn!("Failed to listen on {addr}: {err}");
+                    last_err = Some(err);
+                }
+            }
+        }
+        if listeners.is_empty() {
+            return Err(last_err.unwrap_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "No addresses to listen on")
+            }));
+        }
+
         Ok(Server {
-            listener: TcpListener::bind(addr)?,
+            listeners,
             pub_key,
             dispatcher,
+            login_rate_limiter: None,
+            idle_timeout: None,
+            framing: Framing::default(),
+            last_connection_id: AtomicU64::new(0),
         })
     }
 
-    /// Runs server
-    pub fn run(&self) {
-        crossbeam_utils::thread::scope(|spawner| {
-            for stream_res in self.listener.incoming() {
-                let stream = match stream_res {
-                    Ok(connection) => connection,
-                    Err(err) => {
-                        println!("Failed to connect: {err}");
-                        break;
-                    }
-                };
+    /// Creates new Server instance serving on Unix domain socket at `path` with
+    /// public key `pub_key` and `dispatcher` to handle clients
+    ///
+    /// Socket file is accessible only by the owner and is removed when server is dropped.
+    /// Stale socket file left at `path` by the crashed server is replaced
+    ///
+    /// # Errors
+    ///
+    /// * AddrInUse - if another server is listening on `path`
+    /// * Any error during removing stale socket or binding new one
+    #[cfg(unix)]
+    pub fn new_unix<P: AsRef<Path>>(
+        path: P,
+        pub_key: String,
+        dispatcher: AsyncRequestDispatcher,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        remove_stale_socket(path)?;
+        let listener = Listener::Unix(bind_private(path)?, path.to_owned());
 
-                spawner.spawn(|_| self.handle_client(stream));
-            }
+        Ok(Server {
+            listeners: vec![listener],
+            pub_key,
+            dispatcher,
+            login_rate_limiter: None,
+            idle_timeout: None,
+            framing: Framing::default(),
+            last_connection_id: AtomicU64::new(0),
         })
-        .unwrap()
     }
 
-    /// Handles client `stream`
+    /// Gets TCP addresses server is listening on
     ///
     /// # Errors
     ///
-    /// See [`handle_requests()`]
-    fn handle_client(&self, mut stream: TcpStream) -> Result<()> {
-        let addr = match stream.peer_addr() {
-            Ok(peer_addr) => Cow::from(peer_addr.to_string()),
-            Err(_) => Cow::from("unknown"),
-        };
-        log_connection(&addr, ConnectionStatus::Connected);
+    /// See [`TcpListener::local_addr()`]
+    pub fn local_addrs(&self) -> Result<Vec<SocketAddr>> {
+        self.listeners
+            .iter()
+            .filter_map(|listener| match listener {
+                Listener::Tcp(listener) => Some(listener.local_addr()),
+                #[cfg(unix)]
+                Listener::Unix(..) => None,
+            })
+            .collect()
+    }
 
-        let res = self.handle_requests(&mut stream);
+    /// Limits login attempts of every connection to `max_attempts` per `window`.
+    /// Login attempts aren't limited by default
+    pub fn set_login_rate_limit(&mut self, max_attempts: usize, window: Duration) {
+        self.login_rate_limiter = Some(RateLimiter::new(max_attempts, window));
+    }
 
-        log_connection(&addr, ConnectionStatus::Disconnected);
-        res
+    /// Expires authorized sessions which have no requests for `timeout`.
+    /// Connections without requests for `IDLE_CONNECTION_FACTOR` timeouts are closed.
+    /// Sessions don't expire by default
+    pub fn set_idle_timeout(&mut self, timeout: Duration) {
+        self.idle_timeout = Some(timeout);
+    }
+
+    /// Frames messages with `framing`. [`Framing::EOT`] is used by default
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing;
     }
 
-    /// Handles requests from `stream` in cycle
+    /// Runs server blocking the current thread, see [`Server::run_async()`]
     ///
     /// # Errors
     ///
-    /// Any error caused by `stream` cloning, reading or writing
-    fn handle_requests(&self, stream: &mut TcpStream) -> Result<()> {
-        let mut reader = BufReader::new(stream.try_clone()?);
-        let mut session = Session::default();
+    /// Any error during runtime creation or listeners registration
+    pub fn run(self: Arc<Self>) -> Result<()> {
+        tokio::runtime::Runtime::new()?.block_on(self.run_async())
+    }
 
-        self.send_storage_key(stream)?;
+    /// Runs server in the current tokio runtime until all listeners fail
+    ///
+    /// Requests are dispatched on the blocking thread pool, cause callbacks
+    /// are synchronous and do file IO, see [`Server::handle_message_blocking()`]
+    ///
+    /// # Errors
+    ///
+    /// Any error during listeners registration
+    pub async fn run_async(self: Arc<Self>) -> Result<()> {
+        let mut accepting = tokio::task::JoinSet::new();
+        for listener in &self.listeners {
+            accepting.spawn(self.clone().accept(AsyncListener::new(listener)?));
+        }
+        while accepting.join_next().await.is_some() {}
+        Ok(())
+    }
 
+    /// Accepts connections from `listener` spawning a new task for every one
+    async fn accept(self: Arc<Self>, listener: AsyncListener) {
         loop {
-            let bytes = match Self::read_request_bytes(&mut reader) {
-                Ok(bytes) => bytes,
-                Err(err) => match err.kind() {
-                    io::ErrorKind::ConnectionAborted => return Ok(()),
-                    _ => return Err(err),
-                },
+            let server = self.clone();
+            let res = match &listener {
+                AsyncListener::Tcp(listener) => listener.accept().await.map(|(stream, addr)| {
+                    tokio::spawn(server.handle_client(stream, addr.to_string()));
+                }),
+                #[cfg(unix)]
+                AsyncListener::Unix(listener, ..) => listener.accept().await.map(|(stream, _)| {
+                    tokio::spawn(server.handle_client(stream, String::from("local client")));
+                }),
             };
-            let request = String::from_utf8(bytes);
+            if let Err(err) = res {
+                warn!("Failed to connect: {err}");
+                break;
+            }
+        }
+    }
+
+    /// Handles client `stream` from `peer_addr`
+    ///
+    /// # Errors
+    ///
+    /// See [`Server::handle_requests()`]
+    async fn handle_client<S: AsyncRead + AsyncWrite>(
+        self: Arc<Self>,
+        stream: S,
+        peer_addr: String,
+    ) -> Result<()> {
+        let connection = ConnectionInfo {
+            peer_addr,
+            id: self.last_connection_id.fetch_add(1, Ordering::Relaxed) + 1,
+        };
+        log_connection(&connection, ConnectionStatus::Connected);
+
+        let mut session = Session::default();
+        let res = self
+            .handle_requests(stream, &mut session, &connection)
+            .await;
 
-            let response = match request {
-                Ok(request) => {
-                    let request = request.trim();
-                    println!("request = \"{request}\"");
-                    self.dispatch_request(&mut session, request)
+        log_connection(&connection, ConnectionStatus::Disconnected(&session));
+        res
+    }
+
+    /// Handles requests from `stream` with `session` in cycle until client
+    /// terminates the connection or session is ended
+    ///
+    /// # Errors
+    ///
+    /// Any error caused by `stream` reading or writing
+    async fn handle_requests<S: AsyncRead + AsyncWrite>(
+        self: &Arc<Self>,
+        stream: S,
+        session: &mut Session,
+        connection: &ConnectionInfo,
+    ) -> Result<()> {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader);
+        let mut login_rate_limiter = self.login_rate_limiter.clone();
+        let mut compression = false;
+
+        let pub_key = self.pub_key.clone() + "\r\n";
+        writer
+            .write_all(&self.framing.frame(pub_key.as_bytes()))
+            .await?;
+
+        loop {
+            let read = self.framing.read_message_async(&mut reader);
+            let read_res = match self.idle_timeout {
+                Some(timeout) => {
+                    let idle_timeout = timeout * Self::IDLE_CONNECTION_FACTOR;
+                    match tokio::time::timeout(idle_timeout, read).await {
+                        Ok(read_res) => read_res,
+                        Err(_) => {
+                            info!("Closing idle connection");
+                            return Ok(());
+                        }
+                    }
                 }
-                Err(_) => "Error: request should be in UTF-8 format\r\n".to_owned(),
+                None => read.await,
+            };
+            let Some(bytes) = read_res? else {
+                return Ok(());
             };
 
-            stream.write_all(&Self::response_to_bytes(response))?;
+            self.handle_message_blocking(
+                bytes,
+                session,
+                connection,
+                &mut login_rate_limiter,
+                &mut compression,
+                &mut writer,
+            )
+            .await?;
+            if session.is_ended() {
+                return writer.shutdown().await.or_else(|err| match err.kind() {
+                    io::ErrorKind::NotConnected => Ok(()),
+                    _ => Err(err),
+                });
+            }
         }
     }
 
-    /// Sends storage pub key to the `stream`
+    /// Same as [`Server::handle_message()`], but runs it with
+    /// [`tokio::task::spawn_blocking()`], so slow callbacks don't stall other
+    /// connections served by the same runtime worker. Response messages are
+    /// written to `writer` as soon as they are sent
     ///
     /// # Errors
     ///
-    /// See [`TcpStream::write_all()`]
-    fn send_storage_key(&self, stream: &mut TcpStream) -> Result<()> {
-        let bytes = Self::response_to_bytes(self.pub_key.clone() + "\r\n");
-        stream.write_all(&bytes)
+    /// * Any error caused by `writer` writing
+    /// * Any error if message handling panicked. `session` is reset in this case
+    async fn handle_message_blocking<W: AsyncWrite + Unpin>(
+        self: &Arc<Self>,
+        bytes: Vec<u8>,
+        session: &mut Session,
+        connection: &ConnectionInfo,
+        login_rate_limiter: &mut Option<RateLimiter>,
+        compression: &mut bool,
+        writer: &mut W,
+    ) -> Result<()> {
+        let server = self.clone();
+        let mut owned_session = std::mem::take(session);
+        let connection = connection.clone();
+        let mut owned_limiter = login_rate_limiter.take();
+        let mut owned_compression = *compression;
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(Self::RESPONSE_BUFFER_SIZE);
+
+        let handling = tokio::task::spawn_blocking(move || {
+            server.handle_message(
+                bytes,
+                &mut owned_session,
+                &connection,
+                &mut owned_limiter,
+                &mut owned_compression,
+                &mut |response| {
+                    // Receiver is dropped only if connection is broken
+                    let _ = sender.blocking_send(response);
+                },
+            );
+            (owned_session, owned_limiter, owned_compression)
+        });
+        while let Some(response) = receiver.recv().await {
+            writer.write_all(&self.framing.frame(&response)).await?;
+        }
+
+        let (owned_session, owned_limiter, owned_compression) =
+            handling.await.map_err(io::Error::other)?;
+        *session = owned_session;
+        *login_rate_limiter = owned_limiter;
+        *compression = owned_compression;
+        Ok(())
     }
 
-    /// Dispatches `request` with `session` using `self.dispatcher`
+    /// Handles one message `bytes` from `connection` with `session`.
+    /// Bytes of the response messages are passed to `send` to be framed
+    /// separately. There are several of them only if response is streamed,
+    /// see [`Server::dispatch_streaming_request()`]
     ///
-    /// Returns response with "\r\n" at the end
-    fn dispatch_request(&self, session: &mut Session, request: &str) -> String {
-        let dispatcher_read = self.dispatcher.read().unwrap();
-        let mut response = match dispatcher_read.dispatch(session, request) {
-            Ok(response) => response,
-            Err(err) => format!("Error: {}\r\n", err),
+    /// Large responses are compressed after client sends [`compression::COMMAND`]
+    /// request, which sets `compression`. Compressed requests are accepted only
+    /// after that and are inflated up to [`MAX_REQUEST_SIZE`] bytes
+    ///
+    /// Request id is stripped before handling the request and echoed in every
+    /// response message, see [`request_id`]
+    fn handle_message(
+        &self,
+        bytes: Vec<u8>,
+        session: &mut Session,
+        connection: &ConnectionInfo,
+        login_rate_limiter: &mut Option<RateLimiter>,
+        compression: &mut bool,
+        send: &mut dyn FnMut(Vec<u8>),
+    ) {
+        // Compressed request before negotiation is rejected as not UTF-8 one
+        let bytes = match *compression {
+            true => compression::decompress_limited(bytes, MAX_REQUEST_SIZE),
+            false => Ok(bytes),
+        };
+        let request = match bytes.map(String::from_utf8) {
+            Ok(Ok(request)) => request,
+            Ok(Err(_)) => {
+                let response = format!(
+                    "Error[{}]: request should be in UTF-8 format\r\n",
+                    ErrorCode::InvalidRequestEncoding
+                );
+                return send(Self::encode_response(response, *compression));
+            }
+            Err(err) => {
+                let response = format!(
+                    "Error[{}]: can't decompress request: {err}\r\n",
+                    ErrorCode::InvalidRequestEncoding
+                );
+                return send(Self::encode_response(response, *compression));
+            }
         };
+        let (id, request) = request_id::split(request.trim());
+        let request = request.trim();
 
-        if !response.ends_with("\r\n") {
-            response += "\r\n";
+        if request == compression::COMMAND {
+            *compression = true;
+        }
+        let compression = *compression;
+        let mut send_response = |response: String| {
+            let response = match id {
+                Some(id) => request_id::tag(id, &response),
+                None => response,
+            };
+            send(Self::encode_response(response, compression));
+        };
+
+        match request {
+            compression::COMMAND => send_response(String::from("Ok\r\n")),
+            _ => self.handle_request(
+                request,
+                session,
+                connection,
+                login_rate_limiter,
+                &mut send_response,
+            ),
         }
-        response
     }
 
-    /// Reads bytes from `reader` until EOT byte is captured.
-    /// Returns bytes without EOT byte
-    fn read_request_bytes<R: BufRead>(mut reader: R) -> Result<Vec<u8>> {
-        let mut buf = vec![];
-        let size = reader.read_until(Self::EOT, &mut buf)?;
-        if size == 0 {
-            return Err(io::Error::new(
-                io::ErrorKind::ConnectionAborted,
-                "Client terminated the connection",
+    /// Gets bytes of `response`, compressing them if `compression` is enabled
+    fn encode_response(response: String, compression: bool) -> Vec<u8> {
+        let bytes = response.into_bytes();
+        match compression {
+            true => compression::compress(bytes),
+            false => bytes,
+        }
+    }
+
+    /// Handles one trimmed `request` without request id, see
+    /// [`Server::handle_message()`]. Responses with "\r\n" at the end are
+    /// passed to `send`
+    fn handle_request(
+        &self,
+        request: &str,
+        session: &mut Session,
+        connection: &ConnectionInfo,
+        login_rate_limiter: &mut Option<RateLimiter>,
+        send: &mut dyn FnMut(String),
+    ) {
+        debug!("{}", Self::request_log_message(request));
+        let rate_limited = match login_rate_limiter {
+            Some(limiter) => {
+                let now = Instant::now();
+                !(0..self.login_attempts(request)).all(|_| limiter.try_acquire(now))
+            }
+            None => false,
+        };
+        if rate_limited {
+            return send(format!(
+                "Error[{}]: too many login attempts, try again later\r\n",
+                ErrorCode::TooManyRequests
             ));
         }
-        buf.pop();
+        self.dispatch_streaming_request(session, connection, request, send)
+    }
 
-        Ok(buf)
+    /// Same as [`Server::dispatch_request()`], but every line passed by the
+    /// streaming callback is sent as a separate response. Successful streaming
+    /// is followed by an empty response, failed one ends with an error.
+    /// See [`RequestDispatcher::add_streaming_callback()`]
+    ///
+    /// [`RequestDispatcher::add_streaming_callback()`]: crate::request_dispatcher::RequestDispatcher::add_streaming_callback()
+    fn dispatch_streaming_request(
+        &self,
+        session: &mut Session,
+        connection: &ConnectionInfo,
+        request: &str,
+        send: &mut dyn FnMut(String),
+    ) {
+        let streaming = self.dispatcher.read().unwrap().is_streaming(request);
+        let res = self.dispatch_request(session, connection, request, &mut |line| {
+            // Empty line would be taken for the end of the stream
+            if !line.is_empty() {
+                send(line + "\r\n")
+            }
+        });
+        match res {
+            Ok(_) if streaming => send(String::from("\r\n")),
+            Ok(response) | Err(response) => send(response),
+        }
     }
 
-    /// Converts `response` to bytes with EOT byte at the end
-    fn response_to_bytes(mut response: String) -> Vec<u8> {
-        let mut bytes = Vec::with_capacity(response.len() + 1);
-        unsafe {
-            bytes.append(response.as_mut_vec());
+    /// Dispatches `request` with `session` from `connection` using `self.dispatcher`.
+    /// Streaming callback passes lines to `sink`, see
+    /// [`RequestDispatcher::dispatch_streaming()`]
+    ///
+    /// Authorized `session` is expired instead, if it was idle for too long.
+    ///
+    /// Returns response with "\r\n" at the end
+    ///
+    /// # Errors
+    ///
+    /// Formatted error response with "\r\n" at the end if dispatching failed
+    ///
+    /// [`RequestDispatcher::dispatch_streaming()`]: crate::request_dispatcher::RequestDispatcher::dispatch_streaming()
+    fn dispatch_request(
+        &self,
+        session: &mut Session,
+        connection: &ConnectionInfo,
+        request: &str,
+        sink: request_dispatcher::Sink,
+    ) -> std::result::Result<String, String> {
+        if let Some(timeout) = self.idle_timeout {
+            if session.expire_if_idle(Instant::now(), timeout) {
+                return Err(format!(
+                    "Error[{}]: session expired, please log in again\r\n",
+                    ErrorCode::SessionExpired
+                ));
+            }
+        }
+
+        let dispatcher_read = self.dispatcher.read().unwrap();
+        let with_line_end = |mut response: String| {
+            if !response.ends_with("\r\n") {
+                response += "\r\n";
+            }
+            response
+        };
+        dispatcher_read
+            .dispatch_streaming(session, connection, request, sink)
+            .map(with_line_end)
+            .map_err(|err| with_line_end(request_dispatcher::error_response(&err)))
+    }
+
+    /// Makes message to log `request` with. Only command and number of
+    /// arguments are logged, cause arguments may contain secrets
+    fn request_log_message(request: &str) -> String {
+        let mut tokens = request_dispatcher::split_request(request);
+        match tokens.next() {
+            Some(command) => format!(
+                "request = \"{command}\" with {} argument(s)",
+                tokens.count()
+            ),
+            None => String::from("empty request"),
+        }
+    }
+
+    /// Counts `LOGIN_COMMANDS` in `request` as dispatcher sees them, so
+    /// aliases and case-insensitive commands are counted too. Batch request
+    /// can contain several of them, one per line
+    fn login_attempts(&self, request: &str) -> usize {
+        self.dispatcher
+            .read()
+            .unwrap()
+            .request_commands(request)
+            .iter()
+            .filter(|command| Self::LOGIN_COMMANDS.contains(&command.as_str()))
+            .count()
+    }
+}
+
+impl Drop for Server {
+    /// Removes Unix domain socket files
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        for listener in &self.listeners {
+            if let Listener::Unix(_, path) = listener {
+                if let Err(err) = std::fs::remove_file(path) {
+                    warn!("Failed to remove socket file {}: {err}", path.display());
+                }
+            }
         }
-        bytes.push(Self::EOT);
-        bytes
     }
 }
 
 /// Status of connection with client
 ///
 /// Used to improve log_connection() usage code readability
-enum ConnectionStatus {
+enum ConnectionStatus<'a> {
     Connected,
-    Disconnected,
+    /// Connection is closed with session in the given state
+    Disconnected(&'a Session),
+}
+
+/// Removes socket file at `path` if nobody accepts connections on it, e.g.
+/// cause server crashed. Other files are left for binding to fail
+///
+/// # Errors
+///
+/// * AddrInUse - if some server accepts connections on `path`
+/// * Any error during checking or removing socket file
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => (),
+        Ok(_) => return Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    }
+
+    match UnixStream::connect(path) {
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} is used by another server", path.display()),
+        )),
+        Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+            info!("Removing stale socket file {}", path.display());
+            std::fs::remove_file(path)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Binds Unix domain socket at `path` accessible only by the owner
+///
+/// Umask is set before binding, so socket file is never accessible by others.
+/// It's process-wide, so the previous one is restored right after binding
+///
+/// # Errors
+///
+/// See [`UnixListener::bind()`]
+#[cfg(unix)]
+fn bind_private(path: &Path) -> Result<UnixListener> {
+    // SAFETY: umask() always succeeds and only changes file mode creation mask
+    let umask = unsafe { libc::umask(0o177) };
+    let res = UnixListener::bind(path);
+    // SAFETY: see above
+    unsafe { libc::umask(umask) };
+    res
+}
+
+/// Logs `status` of `connection`, see [`connection_log_message()`]
+fn log_connection(connection: &ConnectionInfo, status: ConnectionStatus) {
+    info!("{}", connection_log_message(connection, status));
+}
+
+/// Makes message about `status` of `connection`.
+/// If `status` is *ConnectionStatus::Connected* tells about successful
+/// connection. Else tells about disconnection and the user who was logged in
+/// at that moment, if any
+fn connection_log_message(connection: &ConnectionInfo, status: ConnectionStatus) -> String {
+    let ConnectionInfo { peer_addr, id } = connection;
+    match status {
+        ConnectionStatus::Connected => format!("Connected with {peer_addr} (connection #{id})"),
+        ConnectionStatus::Disconnected(session) => {
+            let user = match session.as_authorized() {
+                Some(authorized) => format!("user \"{}\"", authorized.username),
+                None => String::from("no user"),
+            };
+            format!("Connection with {peer_addr} closed (connection #{id}, {user})")
+        }
+    }
 }
 
-/// Logs status of connection with `peer_addr` to the stdout.
-/// If `connection` is *ConnectionStatus::Connected* prints info about
-/// successful connection. Else prints info about disconnection
-fn log_connection(peer_addr: &str, connection: ConnectionStatus) {
-    match connection {
-        ConnectionStatus::Connected => println!("Connected with {peer_addr}"),
-        ConnectionStatus::Disconnected => println!("Connection with {peer_addr} closed"),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit_log::AuditLog;
+    use crate::callbacks;
+    use crate::session_tokens::SessionTokens;
+    use std::borrow::Cow;
+    use std::io::{Read, Write};
+    use std::net::{Ipv4Addr, Ipv6Addr, TcpStream};
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+    use std::sync::{Arc, Mutex, RwLock};
+    use std::thread;
+
+    #[test]
+    fn test_listen_on_both_families() {
+        let addrs = [
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+            SocketAddr::from((Ipv6Addr::LOCALHOST, 0)),
+        ];
+        let server =
+            Arc::new(Server::new(&addrs[..], String::from("5:221"), Default::default()).unwrap());
+        let local_addrs = server.local_addrs().unwrap();
+        assert_eq!(local_addrs.len(), 2);
+        assert!(local_addrs[0].is_ipv4());
+        assert!(local_addrs[1].is_ipv6());
+
+        let server_clone = server.clone();
+        thread::spawn(move || server_clone.run());
+
+        for addr in local_addrs {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let mut buf = [0; 8];
+            stream.read_exact(&mut buf).unwrap();
+            assert_eq!(&buf, b"5:221\r\n\x04");
+        }
+    }
+
+    #[test]
+    fn test_quit() {
+        let dispatcher = AsyncRequestDispatcher::default();
+        dispatcher
+            .write()
+            .unwrap()
+            .add_callback(Cow::from("quit"), |session, _| {
+                callbacks::quit(session, &SessionTokens::new(Duration::ZERO))
+            });
+        let server = Arc::new(
+            Server::new((Ipv4Addr::LOCALHOST, 0), String::from("5:221"), dispatcher).unwrap(),
+        );
+        let addr = server.local_addrs().unwrap()[0];
+        let server_clone = server.clone();
+        thread::spawn(move || server_clone.run());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"quit\r\n\x04").unwrap();
+        let mut received = vec![];
+        stream.read_to_end(&mut received).unwrap();
+        assert_eq!(received, b"5:221\r\n\x04Bye\r\n\x04");
+    }
+
+    #[test]
+    fn test_compression() {
+        let dispatcher = AsyncRequestDispatcher::default();
+        dispatcher
+            .write()
+            .unwrap()
+            .add_callback(Cow::from("echo"), |_, arg_iter| {
+                Ok(arg_iter.collect::<Vec<_>>().join(" "))
+            })
+            .add_callback(Cow::from("quit"), |session, _| {
+                callbacks::quit(session, &SessionTokens::new(Duration::ZERO))
+            });
+        let server = Arc::new(
+            Server::new((Ipv4Addr::LOCALHOST, 0), String::from("5:221"), dispatcher).unwrap(),
+        );
+        let addr = server.local_addrs().unwrap()[0];
+        let server_clone = server.clone();
+        thread::spawn(move || server_clone.run());
+
+        let notes = "note ".repeat(1000);
+        let large_request = format!("echo {notes}\r\n");
+        let mut request_bytes = vec![];
+        let bomb =
+            compression::compress(format!("echo {}", "a".repeat(MAX_REQUEST_SIZE)).into_bytes());
+        for request in [
+            compression::compress(large_request.clone().into_bytes()),
+            large_request.as_bytes().to_vec(),
+            b"enable_compression\r\n".to_vec(),
+            compression::compress(large_request.clone().into_bytes()),
+            bomb,
+            b"echo small\r\n".to_vec(),
+            b"quit\r\n".to_vec(),
+        ] {
+            request_bytes.extend(Framing::EOT.frame(&request));
+        }
+
+        let stream = TcpStream::connect(addr).unwrap();
+        (&stream).write_all(&request_bytes).unwrap();
+        let mut reader = io::BufReader::new(stream);
+        let mut responses =
+            std::iter::from_fn(|| Framing::EOT.read_message(&mut reader).unwrap()).skip(1);
+
+        let expected_large = format!("{}\r\n", notes.trim_end()).into_bytes();
+        let not_negotiated = String::from_utf8(responses.next().unwrap()).unwrap();
+        assert!(not_negotiated.starts_with("Error[InvalidRequestEncoding]"));
+        assert_eq!(responses.next().unwrap(), expected_large);
+        assert_eq!(responses.next().unwrap(), b"Ok\r\n");
+        let compressed = responses.next().unwrap();
+        assert_eq!(compressed[0], compression::MARKER);
+        assert_eq!(compression::decompress(compressed).unwrap(), expected_large);
+        let too_large = String::from_utf8(responses.next().unwrap()).unwrap();
+        assert!(too_large.starts_with("Error[InvalidRequestEncoding]: can't decompress request"));
+        assert_eq!(responses.next().unwrap(), b"small\r\n");
+        assert_eq!(responses.next().unwrap(), b"Bye\r\n");
+    }
+
+    #[test]
+    fn test_slow_callback_doesnt_block_others() {
+        const DELAY: Duration = Duration::from_millis(500);
+
+        let dispatcher = AsyncRequestDispatcher::default();
+        dispatcher
+            .write()
+            .unwrap()
+            .add_callback(Cow::from("ping"), |_, _| callbacks::ping())
+            .add_callback(Cow::from("slow"), |_, _| {
+                thread::sleep(DELAY);
+                Ok(String::from("done"))
+            });
+        let server = Arc::new(
+            Server::new((Ipv4Addr::LOCALHOST, 0), String::from("5:221"), dispatcher).unwrap(),
+        );
+        let addr = server.local_addrs().unwrap()[0];
+        // Single worker thread is blocked by slow callback if it's dispatched in place
+        thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(server.run_async())
+        });
+
+        let connect = || {
+            let stream = TcpStream::connect(addr).unwrap();
+            let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+            Framing::EOT.read_message(&mut reader).unwrap();
+            (stream, reader)
+        };
+        let (slow_stream, mut slow_reader) = connect();
+        let (ping_stream, mut ping_reader) = connect();
+
+        let start = Instant::now();
+        (&slow_stream)
+            .write_all(&Framing::EOT.frame(b"slow\r\n"))
+            .unwrap();
+        thread::sleep(Duration::from_millis(50));
+        (&ping_stream)
+            .write_all(&Framing::EOT.frame(b"ping\r\n"))
+            .unwrap();
+
+        let pong = Framing::EOT
+            .read_message(&mut ping_reader)
+            .unwrap()
+            .unwrap();
+        assert_eq!(pong, b"pong\r\n");
+        assert!(start.elapsed() < DELAY);
+        let done = Framing::EOT
+            .read_message(&mut slow_reader)
+            .unwrap()
+            .unwrap();
+        assert_eq!(done, b"done\r\n");
+    }
+
+    #[test]
+    fn test_streaming() {
+        let dispatcher = AsyncRequestDispatcher::default();
+        dispatcher
+            .write()
+            .unwrap()
+            .add_streaming_callback(Cow::from("list"), |_, arg_iter, sink| {
+                arg_iter.for_each(sink);
+                Ok(())
+            })
+            .add_streaming_callback(Cow::from("fail"), |_, _, sink| {
+                sink(String::from("partial"));
+                Err(callbacks::Error::UnacceptableRequestAtThisState)
+            })
+            .add_callback(Cow::from("quit"), |session, _| {
+                callbacks::quit(session, &SessionTokens::new(Duration::ZERO))
+            });
+        let server = Arc::new(
+            Server::new((Ipv4Addr::LOCALHOST, 0), String::from("5:221"), dispatcher).unwrap(),
+        );
+        let addr = server.local_addrs().unwrap()[0];
+        let server_clone = server.clone();
+        thread::spawn(move || server_clone.run());
+
+        let stream = TcpStream::connect(addr).unwrap();
+        (&stream)
+            .write_all(b"list Error b c\r\n\x04list\r\n\x04fail\r\n\x04quit\r\n\x04")
+            .unwrap();
+        let mut reader = io::BufReader::new(stream);
+        let responses: Vec<_> =
+            std::iter::from_fn(|| Framing::EOT.read_message(&mut reader).unwrap())
+                .skip(1)
+                .map(|response| String::from_utf8(response).unwrap())
+                .collect();
+
+        assert_eq!(
+            responses[..7],
+            ["Error\r\n", "b\r\n", "c\r\n", "\r\n", "\r\n", "partial\r\n", "Error[UnacceptableRequestAtThisState]: callback error: unacceptable request at this state\r\n"]
+        );
+        assert_eq!(responses.len(), 8);
+    }
+
+    #[test]
+    fn test_request_ids() {
+        let dispatcher = AsyncRequestDispatcher::default();
+        dispatcher
+            .write()
+            .unwrap()
+            .add_streaming_callback(Cow::from("echo"), |_, arg_iter, sink| {
+                arg_iter.for_each(sink);
+                Ok(())
+            })
+            .add_callback(Cow::from("quit"), |session, _| {
+                callbacks::quit(session, &SessionTokens::new(Duration::ZERO))
+            });
+        let server = Arc::new(
+            Server::new((Ipv4Addr::LOCALHOST, 0), String::from("5:221"), dispatcher).unwrap(),
+        );
+        let addr = server.local_addrs().unwrap()[0];
+        let server_clone = server.clone();
+        thread::spawn(move || server_clone.run());
+
+        let stream = TcpStream::connect(addr).unwrap();
+        (&stream)
+            .write_all(
+                b"#1 echo a b\r\n\x04echo c\r\n\x04#req-2 unknown\r\n\x04#3\r\n\x04#4 quit\r\n\x04",
+            )
+            .unwrap();
+        let mut reader = io::BufReader::new(stream);
+        let responses: Vec<_> =
+            std::iter::from_fn(|| Framing::EOT.read_message(&mut reader).unwrap())
+                .skip(1)
+                .map(|response| String::from_utf8(response).unwrap())
+                .collect();
+
+        assert_eq!(
+            responses,
+            [
+                "#1 a\r\n",
+                "#1 b\r\n",
+                "#1 \r\n",
+                "c\r\n",
+                "\r\n",
+                "#req-2 Error[UndefinedCommand]: undefined command `unknown`\r\n",
+                "#3 Error[NoCommandProvided]: command wasn't provided\r\n",
+                "#4 Bye\r\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_text_protocol() {
+        let dispatcher = AsyncRequestDispatcher::default();
+        dispatcher
+            .write()
+            .unwrap()
+            .add_callback(Cow::from("ping"), |_, _| callbacks::ping())
+            .add_callback(Cow::from("quit"), |session, _| {
+                callbacks::quit(session, &SessionTokens::new(Duration::ZERO))
+            });
+        let mut server =
+            Server::new((Ipv4Addr::LOCALHOST, 0), String::from("5:221"), dispatcher).unwrap();
+        server.set_framing(Framing::TEXT);
+        let server = Arc::new(server);
+        let addr = server.local_addrs().unwrap()[0];
+        let server_clone = server.clone();
+        thread::spawn(move || server_clone.run());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"ping\nquit\r\n").unwrap();
+        let mut received = vec![];
+        stream.read_to_end(&mut received).unwrap();
+        assert_eq!(received, b"5:221\r\x05\n\npong\r\x05\n\nBye\r\x05\n\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket() {
+        let dir = std::env::temp_dir().join(format!("rpass-db-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rpass.sock");
+
+        let dispatcher = AsyncRequestDispatcher::default();
+        dispatcher
+            .write()
+            .unwrap()
+            .add_callback(Cow::from("quit"), |session, _| {
+                callbacks::quit(session, &SessionTokens::new(Duration::ZERO))
+            });
+        let server = Arc::new(Server::new_unix(&path, String::from("5:221"), dispatcher).unwrap());
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let server_clone = server.clone();
+        thread::spawn(move || server_clone.run());
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream.write_all(b"quit\r\n\x04").unwrap();
+        let mut received = vec![];
+        stream.read_to_end(&mut received).unwrap();
+        assert_eq!(received, b"5:221\r\n\x04Bye\r\n\x04");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket_removed_on_drop() {
+        let dir = std::env::temp_dir().join(format!("rpass-db-drop-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rpass.sock");
+
+        let server = Server::new_unix(&path, String::from("5:221"), Default::default()).unwrap();
+        assert!(path.exists());
+        drop(server);
+        assert!(!path.exists());
+
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket_stale() {
+        let dir = std::env::temp_dir().join(format!("rpass-db-stale-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rpass.sock");
+
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let server = Server::new_unix(&path, String::from("5:221"), Default::default()).unwrap();
+
+        let err = Server::new_unix(&path, String::from("5:221"), Default::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert!(path.exists());
+
+        drop(server);
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_malformed_requests() {
+        let dispatcher = AsyncRequestDispatcher::default();
+        dispatcher
+            .write()
+            .unwrap()
+            .add_callback(Cow::from("ping"), |_, _| callbacks::ping())
+            .add_callback(Cow::from("quit"), |session, _| {
+                callbacks::quit(session, &SessionTokens::new(Duration::ZERO))
+            });
+        let server = Arc::new(
+            Server::new((Ipv4Addr::LOCALHOST, 0), String::from("5:221"), dispatcher).unwrap(),
+        );
+        let addr = server.local_addrs().unwrap()[0];
+        let server_clone = server.clone();
+        thread::spawn(move || server_clone.run());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"\x04\xff\xfe\x04ping\r\n\x04quit\r\n\x04")
+            .unwrap();
+        let mut received = vec![];
+        stream.read_to_end(&mut received).unwrap();
+        assert_eq!(
+            String::from_utf8(received).unwrap(),
+            "5:221\r\n\x04\
+             Error[NoCommandProvided]: command wasn't provided\r\n\x04\
+             Error[InvalidRequestEncoding]: request should be in UTF-8 format\r\n\x04\
+             pong\r\n\x04\
+             Bye\r\n\x04"
+        );
+    }
+
+    #[test]
+    fn test_read_request() {
+        let mut reader = io::Cursor::new(b"ping\r\n\x04\x04".to_vec());
+        assert_eq!(
+            Framing::EOT.read_message(&mut reader).unwrap().unwrap(),
+            b"ping\r\n"
+        );
+        assert!(Framing::EOT
+            .read_message(&mut reader)
+            .unwrap()
+            .unwrap()
+            .is_empty());
+        assert!(Framing::EOT.read_message(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_request_partial_request() {
+        let reader = io::Cursor::new(b"pin".to_vec());
+        assert!(Framing::EOT.read_message(reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_request_escaped() {
+        let mut reader = io::Cursor::new(b"pass\x05\x04word\x05\x05\r\n\x04ping\x04".to_vec());
+        assert_eq!(
+            Framing::EOT.read_message(&mut reader).unwrap().unwrap(),
+            b"pass\x04word\x05\r\n"
+        );
+        assert_eq!(
+            Framing::EOT.read_message(&mut reader).unwrap().unwrap(),
+            b"ping"
+        );
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let response = String::from("pass\x04word\x05\x05\x04\r\n");
+        let bytes = Framing::EOT.frame(response.as_bytes());
+        assert_eq!(
+            bytes.iter().filter(|&&b| b == rpass::framing::EOT).count(),
+            3
+        );
+
+        let mut reader = io::Cursor::new(bytes);
+        assert_eq!(
+            Framing::EOT.read_message(&mut reader).unwrap().unwrap(),
+            response.as_bytes()
+        );
+        assert!(Framing::EOT.read_message(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_no_addresses() {
+        let addrs: [SocketAddr; 0] = [];
+        assert!(Server::new(&addrs[..], String::new(), Default::default()).is_err());
+    }
+
+    #[test]
+    fn test_request_log_message() {
+        let message =
+            Server::request_log_message("new_record \"My Bank\" \"secret password\nnotes\"");
+        assert_eq!(message, "request = \"new_record\" with 2 argument(s)");
+        assert!(!message.contains("secret"));
+
+        assert_eq!(
+            Server::request_log_message("show_record example.com"),
+            "request = \"show_record\" with 1 argument(s)"
+        );
+        assert_eq!(Server::request_log_message(""), "empty request");
+    }
+
+    #[test]
+    fn test_secrets_not_logged() {
+        const SECRETS: [&str; 3] = ["secret_key", "secret_confirmation", "secret_password"];
+
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(log::LevelFilter::Trace);
+        }
+        let dispatcher = AsyncRequestDispatcher::default();
+        {
+            let mut dispatcher_write = dispatcher.write().unwrap();
+            for command in ["register", "login", "confirm_login", "new_record"] {
+                dispatcher_write.add_callback(Cow::from(command), |_, _| Ok(String::from("Ok")));
+            }
+            dispatcher_write.add_callback(Cow::from("quit"), |session, _| {
+                callbacks::quit(session, &SessionTokens::new(Duration::ZERO))
+            });
+        }
+        let server = Arc::new(
+            Server::new((Ipv4Addr::LOCALHOST, 0), String::from("5:221"), dispatcher).unwrap(),
+        );
+        let addr = server.local_addrs().unwrap()[0];
+        let server_clone = server.clone();
+        thread::spawn(move || server_clone.run());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(
+                b"register secret_user secret_key\r\n\x04\
+                  login secret_user\r\n\x04\
+                  confirm_login secret_user secret_confirmation\r\n\x04\
+                  new_record example.com \"secret_password\nnotes\"\r\n\x04\
+                  batch\nconfirm_login secret_user secret_confirmation\r\n\x04\
+                  quit\r\n\x04",
+            )
+            .unwrap();
+        let mut received = vec![];
+        stream.read_to_end(&mut received).unwrap();
+
+        let messages = LOGGER.messages.lock().unwrap();
+        assert!(messages
+            .iter()
+            .any(|message| message.contains("\"confirm_login\"")));
+        for secret in SECRETS {
+            assert!(
+                !messages.iter().any(|message| message.contains(secret)),
+                "{secret} is logged"
+            );
+        }
+    }
+
+    /// Logger keeping all messages, so tests can check what is logged
+    struct CapturingLogger {
+        messages: Mutex<Vec<String>>,
+    }
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            self.messages
+                .lock()
+                .unwrap()
+                .push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger {
+        messages: Mutex::new(vec![]),
+    };
+
+    #[test]
+    fn test_disconnect_log_message() {
+        let connection = ConnectionInfo {
+            peer_addr: String::from("127.0.0.1:5000"),
+            id: 7,
+        };
+        let authorized = Session::Authorized(crate::session::Authorized::new(
+            String::from("test_user"),
+            Arc::new(std::sync::RwLock::new(Default::default())),
+        ));
+
+        assert_eq!(
+            connection_log_message(&connection, ConnectionStatus::Disconnected(&authorized)),
+            "Connection with 127.0.0.1:5000 closed (connection #7, user \"test_user\")"
+        );
+        for session in [Session::default(), Session::Ended] {
+            assert_eq!(
+                connection_log_message(&connection, ConnectionStatus::Disconnected(&session)),
+                "Connection with 127.0.0.1:5000 closed (connection #7, no user)"
+            );
+        }
+    }
+
+    #[test]
+    fn test_login_attempts() {
+        let dispatcher = AsyncRequestDispatcher::default();
+        dispatcher
+            .write()
+            .unwrap()
+            .add_callback(Cow::from("login"), |_, _| callbacks::ping())
+            .add_callback(Cow::from("confirm_login"), |_, _| callbacks::ping())
+            .add_alias(Cow::from("signin"), Cow::from("login"))
+            .set_case_insensitive(true);
+        let server =
+            Server::new((Ipv4Addr::LOCALHOST, 0), String::from("5:221"), dispatcher).unwrap();
+
+        assert_eq!(server.login_attempts("login user"), 1);
+        assert_eq!(server.login_attempts("LOGIN user"), 1);
+        assert_eq!(server.login_attempts("signin user"), 1);
+        assert_eq!(server.login_attempts("confirm_login abc"), 1);
+        assert_eq!(server.login_attempts("list_records"), 0);
+        assert_eq!(server.login_attempts("new_record login"), 0);
+        assert_eq!(
+            server.login_attempts("new_record \"example.com\" \"secret\nlogin user\""),
+            0
+        );
+        assert_eq!(server.login_attempts(""), 0);
+        assert_eq!(
+            server.login_attempts("batch\nlogin user\nping\nConfirm_Login abc"),
+            2
+        );
+    }
+
+    #[test]
+    fn test_callback_gets_connection() {
+        let dispatcher = AsyncRequestDispatcher::default();
+        dispatcher
+            .write()
+            .unwrap()
+            .add_callback_with_connection(Cow::from("peer"), |_, connection, _| {
+                Ok(format!("{} #{}", connection.peer_addr, connection.id))
+            })
+            .add_callback(Cow::from("quit"), |session, _| {
+                callbacks::quit(session, &SessionTokens::new(Duration::ZERO))
+            });
+        let server = Arc::new(
+            Server::new((Ipv4Addr::LOCALHOST, 0), String::from("5:221"), dispatcher).unwrap(),
+        );
+        let addr = server.local_addrs().unwrap()[0];
+        let server_clone = server.clone();
+        thread::spawn(move || server_clone.run());
+
+        let mut ids = vec![];
+        for _ in 0..2 {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(b"peer\r\n\x04quit\r\n\x04").unwrap();
+            let mut received = String::new();
+            stream.read_to_string(&mut received).unwrap();
+
+            let response = received.split('\x04').nth(1).unwrap();
+            let (peer_addr, id) = response.trim_end().split_once(" #").unwrap();
+            assert_eq!(peer_addr, stream.local_addr().unwrap().to_string());
+            ids.push(id.parse::<u64>().unwrap());
+        }
+        assert_ne!(ids[0], 0);
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[test]
+    fn test_idle_session_expired() {
+        let addrs = [SocketAddr::from((Ipv4Addr::LOCALHOST, 0))];
+        let dispatcher = crate::AsyncRequestDispatcher::default();
+        dispatcher
+            .write()
+            .unwrap()
+            .add_callback(Cow::from("ping"), |_, _| callbacks::ping());
+        let mut server = Server::new(&addrs[..], String::new(), dispatcher).unwrap();
+        let timeout = Duration::from_secs(60);
+        server.set_idle_timeout(timeout);
+
+        let mut authorized = crate::session::Authorized::new(
+            String::from("test_user"),
+            Arc::new(std::sync::RwLock::new(Default::default())),
+        );
+        authorized.last_activity = Instant::now()
+            .checked_sub(timeout + Duration::from_secs(1))
+            .unwrap();
+        let mut session = Session::Authorized(authorized);
+
+        let connection = ConnectionInfo::default();
+        assert_eq!(
+            server.dispatch_request(&mut session, &connection, "ping", &mut |_| ()),
+            Err(format!(
+                "Error[{}]: session expired, please log in again\r\n",
+                ErrorCode::SessionExpired
+            ))
+        );
+        assert!(session.is_unauthorized());
+        assert_eq!(
+            server.dispatch_request(&mut session, &connection, "ping", &mut |_| ()),
+            Ok(String::from("pong\r\n"))
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_run_async() {
+        const TEST_USER: &str = "test_user";
+        let (pub_key, sec_key) = crate::storage::Key::generate_pair();
+
+        let storage = crate::AsyncStorage::default();
+        {
+            let user_storage = Arc::new(RwLock::new(crate::storage::UserStorage::default()));
+            user_storage
+                .write()
+                .unwrap()
+                .expect_write_record()
+                .times(1)
+                .withf(|record| record.resource == "example.com" && record.password == "secret")
+                .returning(|_| Ok(()));
+            user_storage
+                .write()
+                .unwrap()
+                .expect_record_locks()
+                .returning(Arc::default);
+
+            let mut storage_write = storage.write().unwrap();
+            let user_pub_key = pub_key.clone();
+            storage_write
+                .expect_add_new_user()
+                .times(1)
+                .withf(move |username, key| username == TEST_USER && *key == user_pub_key)
+                .returning(|_, _| Ok(()));
+            let user_pub_key = pub_key.clone();
+            storage_write
+                .expect_get_user_pub_key()
+                .returning(move |_| Ok(user_pub_key.clone()));
+            storage_write.expect_sec_key().return_const(sec_key.clone());
+            storage_write
+                .expect_get_user_storage()
+                .returning(move |_| Ok(user_storage.clone()));
+        }
+
+        let dispatcher = AsyncRequestDispatcher::default();
+        {
+            let register_storage = storage.clone();
+            let login_storage = storage.clone();
+            let confirm_login_storage = storage;
+            dispatcher
+                .write()
+                .unwrap()
+                .add_callback_with_connection(Cow::from("register"), move |_, connection, args| {
+                    callbacks::register(
+                        register_storage.clone(),
+                        &AuditLog::disabled(),
+                        None,
+                        connection,
+                        args,
+                    )
+                })
+                .add_callback_with_connection(
+                    Cow::from("login"),
+                    move |session, connection, args| {
+                        callbacks::login(
+                            login_storage.clone(),
+                            &AuditLog::disabled(),
+                            session,
+                            connection,
+                            args,
+                        )
+                    },
+                )
+                .add_callback_with_connection(
+                    Cow::from("confirm_login"),
+                    move |session, connection, args| {
+                        callbacks::confirm_login(
+                            confirm_login_storage.clone(),
+                            &AuditLog::disabled(),
+                            session,
+                            connection,
+                            args,
+                            Duration::from_secs(30),
+                            None,
+                        )
+                    },
+                )
+                .add_callback(Cow::from("new_record"), |session, args| {
+                    callbacks::new_record(session, args, None, None)
+                });
+        }
+
+        let server = Arc::new(
+            Server::new((Ipv4Addr::LOCALHOST, 0), pub_key.to_string(), dispatcher).unwrap(),
+        );
+        let addr = server.local_addrs().unwrap()[0];
+        tokio::spawn(server.clone().run_async());
+
+        let mut session = rpass::session::Unauthorized::new(addr).await.unwrap();
+        session.register(TEST_USER, &pub_key).await.unwrap();
+        let mut session = session.login(TEST_USER, &sec_key).await.unwrap();
+        let record = rpass::record::Record {
+            resource: String::from("example.com"),
+            password: String::from("secret"),
+            notes: String::new(),
+            encrypted_notes: false,
+        };
+        session.add_record(&record).await.unwrap();
     }
 }
@@ -1,7 +1,10 @@
-use super::storage::UserStorage;
+use super::storage::{Key, UserStorage};
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use enum_as_inner::EnumAsInner;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
 
 /// Enum representing user session
 #[derive(EnumAsInner)]
@@ -11,17 +14,46 @@ pub enum Session {
 
     /// Authorized session
     Authorized(Authorized),
+
+    /// Session finished by client, connection should be closed
+    Ended,
 }
 
-#[derive(Default)]
 pub struct Unauthorized {
     pub username: String,
-    pub login_confirmation: String,
+    pub login_confirmation: Option<LoginConfirmation>,
+    /// Random identity of the connection. Login challenges are bound to it
+    pub connection_id: String,
 }
 
 pub struct Authorized {
     pub username: String,
     pub user_storage: Arc<RwLock<UserStorage>>,
+    pub pub_key_change: Option<PubKeyChange>,
+    /// Time of the last request
+    pub last_activity: Instant,
+    /// Token issued for or used to resume this session
+    pub session_token: Option<String>,
+}
+
+/// Information about client connection the session belongs to
+#[derive(Clone)]
+pub struct ConnectionInfo {
+    pub peer_addr: String,
+    /// Number of the connection assigned by server. `0` if unknown
+    pub id: u64,
+}
+
+/// Login confirmation waiting for client answer
+pub struct LoginConfirmation {
+    pub confirmation: String,
+    pub issued_at: Instant,
+}
+
+/// New user public key waiting for confirmation
+pub struct PubKeyChange {
+    pub pub_key: Key,
+    pub confirmation: String,
 }
 
 #[allow(dead_code)]
@@ -40,6 +72,26 @@ impl Session {
     pub fn is_authorized(&self) -> bool {
         matches!(self, Session::Authorized(_))
     }
+
+    /// Checks if session is ended
+    pub fn is_ended(&self) -> bool {
+        matches!(self, Session::Ended)
+    }
+
+    /// Resets authorized session to `Unauthorized` if it had no activity for more than
+    /// `timeout` before `now`. Otherwise registers activity at `now`
+    ///
+    /// Returns `true` if session was expired
+    pub fn expire_if_idle(&mut self, now: Instant, timeout: Duration) -> bool {
+        if let Session::Authorized(authorized) = self {
+            if now.saturating_duration_since(authorized.last_activity) > timeout {
+                *self = Session::default();
+                return true;
+            }
+            authorized.last_activity = now;
+        }
+        false
+    }
 }
 
 impl Unauthorized {
@@ -47,6 +99,51 @@ impl Unauthorized {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Binds `confirmation` to the connection, so it can't be replayed on
+    /// another one
+    pub fn bind_to_connection(&self, confirmation: &str) -> String {
+        format!("{}{confirmation}", self.connection_id)
+    }
+}
+
+impl Default for Unauthorized {
+    /// Creates `Unauthorized` with empty fields and newly generated `connection_id`
+    fn default() -> Self {
+        const CONNECTION_ID_LENGTH: usize = 16;
+        Unauthorized {
+            username: String::new(),
+            login_confirmation: None,
+            connection_id: thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(CONNECTION_ID_LENGTH)
+                .map(char::from)
+                .collect(),
+        }
+    }
+}
+
+impl Authorized {
+    /// Creates new `Authorized` for user with `username` and `user_storage`
+    pub fn new(username: String, user_storage: Arc<RwLock<UserStorage>>) -> Self {
+        Authorized {
+            username,
+            user_storage,
+            pub_key_change: None,
+            last_activity: Instant::now(),
+            session_token: None,
+        }
+    }
+}
+
+impl Default for ConnectionInfo {
+    /// Creates `ConnectionInfo` for connection with unknown peer
+    fn default() -> Self {
+        ConnectionInfo {
+            peer_addr: String::from("unknown"),
+            id: 0,
+        }
+    }
 }
 
 impl Default for Session {
@@ -54,3 +151,41 @@ impl Default for Session {
         Session::Unauthorized(Unauthorized::default())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_expire_if_idle() {
+        let mut session = build_authorized();
+        let start = session.as_authorized().unwrap().last_activity;
+
+        assert!(!session.expire_if_idle(start + TIMEOUT, TIMEOUT));
+        assert_eq!(
+            session.as_authorized().unwrap().last_activity,
+            start + TIMEOUT
+        );
+
+        assert!(session.expire_if_idle(start + TIMEOUT * 2 + Duration::from_secs(1), TIMEOUT));
+        assert!(session.is_unauthorized());
+    }
+
+    #[test]
+    fn test_expire_if_idle_unauthorized() {
+        let mut session = Session::default();
+        let now = Instant::now() + TIMEOUT * 10;
+
+        assert!(!session.expire_if_idle(now, TIMEOUT));
+        assert!(session.is_unauthorized());
+    }
+
+    fn build_authorized() -> Session {
+        Session::Authorized(Authorized::new(
+            String::from("test_user"),
+            Arc::new(RwLock::new(UserStorage::default())),
+        ))
+    }
+}
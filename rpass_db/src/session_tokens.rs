@@ -0,0 +1,138 @@
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Length of issued tokens
+const TOKEN_LENGTH: usize = 32;
+
+/// Reason why session token can't be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenError {
+    /// Token was never issued or was revoked
+    Unknown,
+    /// Token was issued too long ago
+    Expired,
+}
+
+/// Short-lived tokens allowing to resume authorized session on a new
+/// connection without logging in again
+pub struct SessionTokens {
+    ttl: Duration,
+    /// Username and issue time for every token
+    tokens: Mutex<HashMap<String, (String, Instant)>>,
+}
+
+impl SessionTokens {
+    /// Creates storage of tokens valid for `ttl` after issuing
+    pub fn new(ttl: Duration) -> Self {
+        SessionTokens {
+            ttl,
+            tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Issues new random token for user `username` at `now`.
+    /// Expired tokens of all users are forgotten
+    pub fn issue(&self, username: &str, now: Instant) -> String {
+        let token: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(TOKEN_LENGTH)
+            .map(char::from)
+            .collect();
+
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.retain(|_, (_, issued_at)| now.saturating_duration_since(*issued_at) <= self.ttl);
+        tokens.insert(token.clone(), (username.to_owned(), now));
+        token
+    }
+
+    /// Gets name of the user `token` was issued for, if it's still valid at `now`.
+    /// Expired token is forgotten
+    ///
+    /// # Errors
+    ///
+    /// * `Unknown` - if `token` wasn't issued or was revoked
+    /// * `Expired` - if `token` was issued more than ttl before `now`
+    pub fn resume(&self, token: &str, now: Instant) -> Result<String, TokenError> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let (username, issued_at) = tokens.get(token).ok_or(TokenError::Unknown)?;
+        if now.saturating_duration_since(*issued_at) > self.ttl {
+            tokens.remove(token);
+            return Err(TokenError::Expired);
+        }
+        Ok(username.clone())
+    }
+
+    /// Revokes `token`
+    pub fn revoke(&self, token: &str) {
+        self.tokens.lock().unwrap().remove(token);
+    }
+
+    /// Revokes all tokens of user `username`
+    pub fn revoke_user(&self, username: &str) {
+        self.tokens
+            .lock()
+            .unwrap()
+            .retain(|_, (token_username, _)| token_username != username);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(60);
+    const TEST_USER: &str = "test_user";
+
+    #[test]
+    fn test_resume() {
+        let tokens = SessionTokens::new(TTL);
+        let now = Instant::now();
+
+        let token = tokens.issue(TEST_USER, now);
+        assert_eq!(token.len(), TOKEN_LENGTH);
+        assert_eq!(tokens.resume(&token, now).unwrap(), TEST_USER);
+        assert_eq!(tokens.resume(&token, now + TTL).unwrap(), TEST_USER);
+        assert_ne!(tokens.issue(TEST_USER, now), token);
+    }
+
+    #[test]
+    fn test_expired() {
+        let tokens = SessionTokens::new(TTL);
+        let now = Instant::now();
+
+        let token = tokens.issue(TEST_USER, now);
+        let later = now + TTL + Duration::from_secs(1);
+        assert_eq!(tokens.resume(&token, later), Err(TokenError::Expired));
+        assert_eq!(tokens.resume(&token, later), Err(TokenError::Unknown));
+    }
+
+    #[test]
+    fn test_revoke() {
+        let tokens = SessionTokens::new(TTL);
+        let now = Instant::now();
+
+        let first = tokens.issue(TEST_USER, now);
+        let second = tokens.issue(TEST_USER, now);
+        let other = tokens.issue("other_user", now);
+
+        tokens.revoke(&first);
+        assert_eq!(tokens.resume(&first, now), Err(TokenError::Unknown));
+        assert!(tokens.resume(&second, now).is_ok());
+
+        tokens.revoke_user(TEST_USER);
+        assert_eq!(tokens.resume(&second, now), Err(TokenError::Unknown));
+        assert_eq!(tokens.resume(&other, now).unwrap(), "other_user");
+    }
+
+    #[test]
+    fn test_unknown() {
+        let tokens = SessionTokens::new(TTL);
+        assert_eq!(
+            tokens.resume("unknown", Instant::now()),
+            Err(TokenError::Unknown)
+        );
+    }
+}
@@ -1,16 +1,32 @@
+pub use builder::{KeyGenerator, StorageBackend, StorageBuilder};
+pub use cipher::Cipher;
 pub use error::Error;
+pub use record_locks::RecordLocks;
+use record_store::remove_dir_if_exists;
+pub use record_store::{FileStore, InMemoryStore, RecordStore};
 pub use rpass::key::{self, Key};
 pub use rpass::record::*;
+#[cfg(feature = "sqlite")]
+pub use sqlite_store::SqliteStore;
+pub use user_storage::UserStats;
 #[mockall_double::double]
 pub use user_storage::UserStorage;
 
+mod builder;
+mod cipher;
 mod error;
+mod filename;
+mod record_locks;
+mod record_store;
+#[cfg(feature = "sqlite")]
+mod sqlite_store;
 mod user_storage;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 use std::collections::HashMap;
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock, Weak};
 
@@ -18,36 +34,75 @@ use std::sync::{Arc, RwLock, Weak};
 use mockall::automock;
 
 const PUB_KEY_FILENAME: &str = "key.pub";
+const SEC_KEY_FILENAME: &str = "key.sec";
+const ADMINS_FILENAME: &str = "admins";
+const LOCK_FILENAME: &str = ".lock";
+/// Directory in storage where [`Storage::rotate_storage_keys()`] prepares
+/// re-encrypted copies of user directories and new keys
+const ROTATION_TMP_DIRNAME: &str = ".rotation.tmp";
+/// Directory with complete copies from [`ROTATION_TMP_DIRNAME`] waiting to be
+/// moved over the originals, see [`recover_key_rotation()`]
+const ROTATION_DIRNAME: &str = ".rotation";
 
 type WeakUserStorage = Weak<RwLock<UserStorage>>;
 
+/// Decrypted records of one user, see [`Storage::decrypt_user_records()`]
+#[derive(Clone)]
+enum DecryptedRecords {
+    /// Paths of record files relative to user directory with their
+    /// decrypted content
+    Files(Vec<(PathBuf, Vec<u8>)>),
+    /// Resource names and decrypted content of records in user database
+    #[cfg(feature = "sqlite")]
+    Sqlite(Vec<(String, Vec<u8>)>),
+}
+
 /// Record storage of all users
 pub struct Storage {
     path: PathBuf,
     pub_key: Key,
     sec_key: Key,
     username_to_user_storage: HashMap<String, WeakUserStorage>,
+    /// Owners of registered public keys. Built once at startup
+    pub_key_to_username: HashMap<Key, String>,
+    /// Number of previous versions kept for every record
+    history_limit: usize,
+    /// If deleted records should be moved to trash
+    soft_delete: bool,
+    /// If written records should be synced to disk
+    durable: bool,
+    /// Backend keeping records of every user
+    backend: StorageBackend,
+    /// Exclusively locked file preventing other instances from using the
+    /// same storage. Lock is released on drop
+    _lock_file: fs::File,
 }
 
 #[cfg_attr(test, automock, allow(dead_code))]
 impl Storage {
-    /// Initializes storage from given path to storage folder
+    /// Initializes storage from given path to storage folder, creating it
+    /// if needed. See [`Storage::builder()`] for more options
     ///
     /// # Errors
     ///
     /// Any possible error during file/directory opening/writing
     pub fn new<P: 'static + AsRef<Path>>(path: P) -> Result<Self> {
-        let real_path = path.as_ref();
-        Self::open_storage(real_path)?;
+        Self::builder(path).build()
+    }
 
-        let (pub_key, sec_key) = Self::read_keys(real_path)?;
+    /// Creates builder to configure storage at `path`
+    pub fn builder<P: 'static + AsRef<Path>>(path: P) -> StorageBuilder {
+        StorageBuilder::new(path)
+    }
 
-        Ok(Storage {
-            path: real_path.to_path_buf(),
-            pub_key,
-            sec_key,
-            username_to_user_storage: HashMap::new(),
-        })
+    /// Opens storage configured with `builder`. Same as [`StorageBuilder::build()`],
+    /// but can be mocked
+    ///
+    /// # Errors
+    ///
+    /// See [`StorageBuilder::build()`]
+    pub fn open(builder: StorageBuilder) -> Result<Self> {
+        builder.build()
     }
 
     /// Adds new user to the storage
@@ -57,14 +112,47 @@ impl Storage {
     ///
     /// # Errors
     ///
-    /// Any errors during creating folder and writing file
+    /// * KeyAlreadyInUse -- if `pub_key` is already registered by another user
+    /// * UserAlreadyExists -- if user folder already exists
+    /// * Io -- if any other error occurred during creating user folder or
+    ///   writing *key.pub* file
     pub fn add_new_user(&mut self, username: &str, pub_key: &Key) -> Result<()> {
-        let user_dir = self.path.join(username);
+        if self.is_pub_key_in_use(pub_key) {
+            return Err(Error::KeyAlreadyInUse);
+        }
+
+        let user_dir = self.user_dir(username);
         let pub_key_file = user_dir.join(PUB_KEY_FILENAME);
-        fs::create_dir(user_dir).map_err(|_| Error::UserAlreadyExists(username.to_owned()))?;
-        pub_key
-            .write_to_file(pub_key_file)
-            .map_err(|err| err.into())
+        fs::create_dir(user_dir).map_err(|err| match err.kind() {
+            io::ErrorKind::AlreadyExists => Error::UserAlreadyExists(username.to_owned()),
+            _ => Error::Io(err),
+        })?;
+        pub_key.write_to_file(pub_key_file)?;
+        self.pub_key_to_username
+            .insert(pub_key.clone(), username.to_owned());
+        Ok(())
+    }
+
+    /// Makes `pub_key` the indexed key of user `username` instead of the old
+    /// one. Key file itself is written with [`UserStorage::set_pub_key()`]
+    ///
+    /// # Errors
+    ///
+    /// * KeyAlreadyInUse -- if `pub_key` is already registered by another user
+    pub fn index_pub_key(&mut self, username: &str, pub_key: &Key) -> Result<()> {
+        let owned_by_other = self
+            .pub_key_to_username
+            .get(pub_key)
+            .is_some_and(|owner| owner != username);
+        if owned_by_other && self.is_pub_key_in_use(pub_key) {
+            return Err(Error::KeyAlreadyInUse);
+        }
+
+        self.pub_key_to_username
+            .retain(|_, owner| owner != username);
+        self.pub_key_to_username
+            .insert(pub_key.clone(), username.to_owned());
+        Ok(())
     }
 
     /// Deletes user's files and directory
@@ -73,7 +161,7 @@ impl Storage {
     /// # Errors
     ///
     /// * UnsupportedActionForMultiSession -- if there are some active sessions
-    /// of given user
+    ///   of given user
     /// * Io -- if any error occurred during [`std::fs::remove_dir_all()`]
     pub fn delete_user(&mut self, username: &str) -> Result<()> {
         if let Some(weak) = self.username_to_user_storage.get(username) {
@@ -83,7 +171,115 @@ impl Storage {
         };
 
         self.username_to_user_storage.remove(username);
-        fs::remove_dir_all(self.path.join(username)).map_err(|err| err.into())
+        fs::remove_dir_all(self.user_dir(username))?;
+        self.pub_key_to_username
+            .retain(|_, owner| owner != username);
+        Ok(())
+    }
+
+    /// Renames user `from` to `to`. Makes no `to` validation
+    ///
+    /// Record files are re-encrypted, cause encryption key depends on username.
+    /// All records are decrypted before user directory is renamed. If writing
+    /// re-encrypted records fails, they are written back with the old key and
+    /// directory is renamed back. There should be no any Arc on `from` user
+    /// storage
+    ///
+    /// # Errors
+    ///
+    /// * UnsupportedActionForMultiSession -- if there are some active sessions
+    ///   of user `from`
+    /// * UserDoesNotExist -- if there is no user `from`
+    /// * UserAlreadyExists -- if user `to` already exists
+    /// * CantDecrypt -- if some record file is corrupted
+    /// * Io -- if any error occurred during files re-encryption or
+    ///   [`std::fs::rename()`]
+    pub fn rename_user(&mut self, from: &str, to: &str) -> Result<()> {
+        if let Some(weak) = self.username_to_user_storage.get(from) {
+            if weak.strong_count() > 0 {
+                return Err(Error::UnsupportedActionForMultiSession);
+            }
+        };
+
+        let (from_dir, to_dir) = (self.user_dir(from), self.user_dir(to));
+        if !from_dir.is_dir() {
+            return Err(Error::UserDoesNotExist(from.to_owned()));
+        }
+        if to_dir.exists() {
+            return Err(Error::UserAlreadyExists(to.to_owned()));
+        }
+
+        let old_cipher = Cipher::new(&self.sec_key, from);
+        let records = self.decrypt_user_records(&from_dir, &old_cipher)?;
+        fs::rename(&from_dir, &to_dir)?;
+        let new_cipher = Cipher::new(&self.sec_key, to);
+        if let Err(err) = Self::write_user_records(new_cipher, &to_dir, records.clone()) {
+            Self::write_user_records(old_cipher, &to_dir, records)?;
+            fs::rename(to_dir, from_dir)?;
+            return Err(err);
+        }
+        self.username_to_user_storage.remove(from);
+        for owner in self.pub_key_to_username.values_mut() {
+            if owner == from {
+                *owner = to.to_owned();
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces storage keys with a new pair from `key_generator` and
+    /// re-encrypts record files of all users with the new secret key
+    ///
+    /// Should be done while server isn't running, cause clients read server
+    /// public key on connect. All records are decrypted first. Then
+    /// re-encrypted copies of user directories and new keys are prepared in
+    /// a staging directory, which is renamed to mark rotation complete and
+    /// moved over the originals. If process crashes, rotation is finished or
+    /// rolled back on the next storage opening, so records and keys are never
+    /// mixed
+    ///
+    /// # Errors
+    ///
+    /// * UnsupportedActionForMultiSession -- if some user has active sessions
+    /// * SameStorageKeys -- if `key_generator` returned current keys
+    /// * CantDecrypt -- if some record file is corrupted. Nothing is changed
+    ///   in this case
+    /// * Io -- if any error occurred during files reading or writing
+    pub fn rotate_storage_keys(&mut self, key_generator: KeyGenerator) -> Result<()> {
+        if self
+            .username_to_user_storage
+            .values()
+            .any(|weak| weak.strong_count() > 0)
+        {
+            return Err(Error::UnsupportedActionForMultiSession);
+        }
+
+        let (pub_key, sec_key) = key_generator();
+        if pub_key == self.pub_key || sec_key == self.sec_key {
+            return Err(Error::SameStorageKeys);
+        }
+
+        let mut decrypted = vec![];
+        for username in self.list_users()? {
+            let cipher = Cipher::new(&self.sec_key, &username);
+            let records = self.decrypt_user_records(&self.user_dir(&username), &cipher)?;
+            decrypted.push((username, records));
+        }
+
+        let staging_dir = self.path.join(ROTATION_TMP_DIRNAME);
+        remove_dir_if_exists(&staging_dir)?;
+        fs::create_dir(&staging_dir)?;
+        if let Err(err) = self.stage_rotation(&staging_dir, &pub_key, &sec_key, decrypted) {
+            remove_dir_if_exists(&staging_dir)?;
+            return Err(err);
+        }
+        fs::rename(staging_dir, self.path.join(ROTATION_DIRNAME))?;
+        recover_key_rotation(&self.path)?;
+
+        self.pub_key = pub_key;
+        self.sec_key = sec_key;
+        self.username_to_user_storage.clear();
+        Ok(())
     }
 
     /// Gets UserStorage struct for user with name `username`
@@ -98,27 +294,110 @@ impl Storage {
             }
         };
 
-        let user_dir_path = self.path.join(username);
-        let user_storage = Arc::new(RwLock::new(UserStorage::new(user_dir_path)?));
+        let user_dir_path = self.user_dir(username);
+        if !user_dir_path.is_dir() {
+            return Err(Error::UserDoesNotExist(user_dir_path.display().to_string()));
+        }
+        let store = self.open_store(username)?;
+        let user_storage = Arc::new(RwLock::new(UserStorage::new(user_dir_path, store)?));
         self.username_to_user_storage
             .insert(username.to_owned(), Arc::downgrade(&user_storage));
 
         Ok(user_storage)
     }
 
+    /// Gets number of active sessions of user with name `username`, i.e.
+    /// number of alive storages returned by [`Storage::get_user_storage()`]
+    pub fn session_count(&self, username: &str) -> usize {
+        self.username_to_user_storage
+            .get(username)
+            .map_or(0, Weak::strong_count)
+    }
+
     /// Reads and returns user public key
     ///
     /// # Errors
     ///
     /// Any error during file reading
     pub fn get_user_pub_key(&self, username: &str) -> Result<Key> {
-        let pub_key_file = self.path.join(username).join(PUB_KEY_FILENAME);
+        let pub_key_file = self.user_dir(username).join(PUB_KEY_FILENAME);
         if !pub_key_file.exists() {
             return Err(Error::UserDoesNotExist(username.to_owned()));
         }
         Key::from_file(pub_key_file).map_err(|err| err.into())
     }
 
+    /// Gets sorted list of names of all registered users
+    ///
+    /// Every directory in the storage root is treated as a user directory,
+    /// any other entries and directories with undecodable names are skipped
+    ///
+    /// # Errors
+    ///
+    /// Io - if can't read items in storage directory
+    pub fn list_users(&self) -> Result<Vec<String>> {
+        let mut usernames = vec![];
+        for entry_res in fs::read_dir(&self.path)? {
+            let entry = entry_res?;
+            if !entry.file_type()?.is_dir() || entry.file_name().to_string_lossy().starts_with('.')
+            {
+                continue;
+            }
+
+            if let Some(username) = filename::decode(&entry.file_name().to_string_lossy()) {
+                usernames.push(username);
+            }
+        }
+        usernames.sort();
+
+        Ok(usernames)
+    }
+
+    /// Gets number of registered users, see [`Storage::list_users()`]
+    ///
+    /// # Errors
+    ///
+    /// Io - if can't read items in storage directory
+    pub fn user_count(&self) -> Result<usize> {
+        Ok(self.list_users()?.len())
+    }
+
+    /// Gets total number of records of all users. Records aren't decrypted
+    ///
+    /// # Errors
+    ///
+    /// Any error during opening or reading record store of some user
+    pub fn record_count(&self) -> Result<usize> {
+        let mut count = 0;
+        for username in self.list_users()? {
+            count += self.open_store(&username)?.count_records()?;
+        }
+        Ok(count)
+    }
+
+    /// Checks if user with name `username` is registered
+    pub fn user_exists(&self, username: &str) -> bool {
+        self.user_dir(username).is_dir()
+    }
+
+    /// Checks if user with name `username` is an administrator
+    ///
+    /// Administrators are listed one per line in *admins* file in the storage
+    /// root. If there is no such file then nobody is an administrator
+    ///
+    /// # Errors
+    ///
+    /// Io - if *admins* file exists but can't be read
+    pub fn is_admin(&self, username: &str) -> Result<bool> {
+        let admins_file = self.path.join(ADMINS_FILENAME);
+        if !admins_file.exists() {
+            return Ok(false);
+        }
+
+        let admins = fs::read_to_string(admins_file)?;
+        Ok(admins.lines().any(|admin| admin.trim() == username))
+    }
+
     /// Gets storage public key
     pub fn pub_key(&self) -> &Key {
         &self.pub_key
@@ -129,48 +408,699 @@ impl Storage {
         &self.sec_key
     }
 
-    /// Open storage directory
+    /// Checks if `pub_key` belongs to some registered user
+    ///
+    /// Users can change their keys after registration, so index entry is
+    /// confirmed by the owner's *key.pub* file and removed if it's outdated
+    fn is_pub_key_in_use(&mut self, pub_key: &Key) -> bool {
+        let owner = match self.pub_key_to_username.get(pub_key) {
+            Some(owner) => owner,
+            None => return false,
+        };
+
+        match self.get_user_pub_key(owner) {
+            Ok(owner_pub_key) if owner_pub_key == *pub_key => true,
+            _ => {
+                self.pub_key_to_username.remove(pub_key);
+                false
+            }
+        }
+    }
+
+    /// Reads public keys of all registered users.
+    /// Users with unreadable keys are skipped
     ///
     /// # Errors
     ///
-    /// Any possible error during file/directory opening/writing
-    fn open_storage(path: &Path) -> Result<()> {
-        const DIRECTORY_MESSAGE_PREFIX: &str = "Rpass storage directory";
+    /// Io - if can't read items in storage directory
+    fn build_pub_key_index(&self) -> Result<HashMap<Key, String>> {
+        let mut index = HashMap::new();
+        for username in self.list_users()? {
+            match self.get_user_pub_key(&username) {
+                Ok(pub_key) => {
+                    if let Some(owner) = index.insert(pub_key, username.clone()) {
+                        log::warn!("Users {owner:?} and {username:?} share the same public key");
+                    }
+                }
+                Err(err) => log::warn!("Can't read public key of user {username:?}: {err}"),
+            }
+        }
+        Ok(index)
+    }
 
-        if !path.exists() {
-            println!("{DIRECTORY_MESSAGE_PREFIX} {path:?} does not exist. Creating...");
-            fs::create_dir(path)?;
-            return Self::init_keys(path);
-        } else if !path.is_dir() {
-            return Err(Error::StoragePathIsNotADirectory(path.to_owned()));
+    /// Gets path to directory of user `username`
+    fn user_dir(&self, username: &str) -> PathBuf {
+        self.path.join(filename::encode(username))
+    }
+
+    /// Opens record store of user `username` with storage backend
+    ///
+    /// # Errors
+    ///
+    /// Any error during store opening
+    fn open_store(&self, username: &str) -> Result<Box<dyn RecordStore>> {
+        let user_dir = self.user_dir(username);
+        let cipher = Cipher::new(&self.sec_key, username);
+        match self.backend {
+            StorageBackend::Files => Ok(Box::new(
+                FileStore::new(user_dir, cipher)
+                    .with_history_limit(self.history_limit)
+                    .with_trash(self.soft_delete)
+                    .with_durable(self.durable),
+            )),
+            #[cfg(feature = "sqlite")]
+            StorageBackend::Sqlite => Ok(Box::new(SqliteStore::open(user_dir, cipher)?)),
         }
+    }
 
-        println!("{DIRECTORY_MESSAGE_PREFIX} is {path:?}");
+    /// Fills `staging_dir` with copies of user directories with `decrypted`
+    /// records encrypted with `sec_key` and with new key files
+    ///
+    /// # Errors
+    ///
+    /// * Io -- if any error occurred during files copying or writing
+    fn stage_rotation(
+        &self,
+        staging_dir: &Path,
+        pub_key: &Key,
+        sec_key: &Key,
+        decrypted: Vec<(String, DecryptedRecords)>,
+    ) -> Result<()> {
+        for (username, records) in decrypted {
+            let staged_user_dir = staging_dir.join(filename::encode(&username));
+            copy_dir(&self.user_dir(&username), &staged_user_dir)?;
+            Self::write_user_records(Cipher::new(sec_key, &username), &staged_user_dir, records)?;
+        }
+        pub_key.write_to_file(staging_dir.join(PUB_KEY_FILENAME))?;
+        sec_key.write_to_file(staging_dir.join(SEC_KEY_FILENAME))?;
         Ok(())
     }
 
-    /// Creates public and secret keys and write them to the files *key.pub*
-    /// and *key.sec*
+    /// Decrypts all records in `user_dir` with `cipher`, see
+    /// [`Storage::decrypt_records()`] and [`SqliteStore::decrypt_all()`]
     ///
     /// # Errors
     ///
-    /// Any possible error during files writing
-    fn init_keys(path: &Path) -> Result<()> {
-        let (pub_key, sec_key) = Key::generate_pair();
-        pub_key.write_to_file(path.join("key.pub"))?;
-        sec_key
-            .write_to_file(path.join("key.sec"))
-            .map_err(|err| err.into())
+    /// * CantDecrypt -- if some record is corrupted
+    /// * Io -- if any error occurred during records reading
+    fn decrypt_user_records(&self, user_dir: &Path, cipher: &Cipher) -> Result<DecryptedRecords> {
+        match self.backend {
+            StorageBackend::Files => Ok(DecryptedRecords::Files(Self::decrypt_records(
+                user_dir, cipher,
+            )?)),
+            #[cfg(feature = "sqlite")]
+            StorageBackend::Sqlite => Ok(DecryptedRecords::Sqlite(
+                SqliteStore::open(user_dir, cipher.clone())?.decrypt_all()?,
+            )),
+        }
+    }
+
+    /// Writes `records` decrypted by [`Storage::decrypt_user_records()`] to
+    /// `user_dir` encrypting them with `cipher`. `user_dir` can be a copy of
+    /// the directory records were decrypted from
+    ///
+    /// # Errors
+    ///
+    /// * Io -- if any error occurred during records writing
+    fn write_user_records(
+        cipher: Cipher,
+        user_dir: &Path,
+        records: DecryptedRecords,
+    ) -> Result<()> {
+        match records {
+            DecryptedRecords::Files(files) => {
+                for (file, plaintext) in files {
+                    fs::write(user_dir.join(file), cipher.encrypt(&plaintext))?;
+                }
+                Ok(())
+            }
+            #[cfg(feature = "sqlite")]
+            DecryptedRecords::Sqlite(plaintexts) => {
+                SqliteStore::open(user_dir, cipher)?.write_plaintexts(plaintexts)
+            }
+        }
     }
 
-    /// Reads public and secret keys from files *key.pub* and *key.sec*
+    /// Decrypts all encrypted record files in `user_dir` and its
+    /// subdirectories with `cipher`. Plaintext record files are skipped
+    ///
+    /// Returns paths of files relative to `user_dir` with their decrypted content
     ///
     /// # Errors
     ///
-    /// Any possible error during files reading and keys constructing
-    fn read_keys(path: &Path) -> Result<(Key, Key)> {
-        let pub_key = Key::from_file(path.join("key.pub"))?;
-        let sec_key = Key::from_file(path.join("key.sec"))?;
-        Ok((pub_key, sec_key))
+    /// * CantDecrypt -- if some record file is corrupted
+    /// * Io -- if any error occurred during files reading
+    fn decrypt_records(user_dir: &Path, cipher: &Cipher) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+        let mut decrypted = vec![];
+        let mut dirs = vec![user_dir.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            for entry_res in fs::read_dir(dir)? {
+                let file = entry_res?.path();
+                if file.is_dir() {
+                    dirs.push(file);
+                    continue;
+                }
+                if !file.is_file() || file.ends_with(PUB_KEY_FILENAME) {
+                    continue;
+                }
+
+                let content = fs::read(&file)?;
+                if Cipher::is_encrypted(&content) {
+                    let relative = file.strip_prefix(user_dir).unwrap_or(&file).to_path_buf();
+                    decrypted.push((relative, cipher.decrypt(&content)?));
+                }
+            }
+        }
+        Ok(decrypted)
+    }
+}
+
+/// Finishes or rolls back [`Storage::rotate_storage_keys()`] interrupted by
+/// a crash. Incomplete staging directory is removed, complete one has its
+/// entries moved over the originals in `path`
+///
+/// # Errors
+///
+/// Io -- if any error occurred during files removing or renaming
+pub(crate) fn recover_key_rotation(path: &Path) -> Result<()> {
+    remove_dir_if_exists(&path.join(ROTATION_TMP_DIRNAME))?;
+
+    let rotation_dir = path.join(ROTATION_DIRNAME);
+    if !rotation_dir.is_dir() {
+        return Ok(());
+    }
+    for entry_res in fs::read_dir(&rotation_dir)? {
+        let entry = entry_res?;
+        let target = path.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            remove_dir_if_exists(&target)?;
+        }
+        fs::rename(entry.path(), target)?;
+    }
+    fs::remove_dir(rotation_dir)?;
+    Ok(())
+}
+
+/// Recursively copies directory `from` with all its content to `to`
+///
+/// # Errors
+///
+/// Io -- if any error occurred during files copying
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry_res in fs::read_dir(from)? {
+        let entry = entry_res?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_list_users() {
+        let path = build_storage_path("list_users");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        storage
+            .add_new_user("second_user", &build_pub_key(1))
+            .unwrap();
+        storage
+            .add_new_user("first_user", &build_pub_key(2))
+            .unwrap();
+        fs::write(path.join("stray_file"), "").unwrap();
+
+        assert_eq!(storage.list_users().unwrap(), ["first_user", "second_user"]);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_user_count() {
+        let path = build_storage_path("user_count");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        assert_eq!(storage.user_count().unwrap(), 0);
+
+        for n in 0..3 {
+            storage
+                .add_new_user(&format!("user_{n}"), &build_pub_key(n))
+                .unwrap();
+        }
+        fs::write(path.join("stray_file"), "").unwrap();
+        fs::write(path.join(ADMINS_FILENAME), "user_0\n").unwrap();
+
+        assert_eq!(storage.user_count().unwrap(), 3);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_record_count() {
+        let path = build_storage_path("record_count");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        for (n, resources) in [&["first.com", "second.com"][..], &["third.com"], &[]]
+            .into_iter()
+            .enumerate()
+        {
+            let username = format!("user_{n}");
+            storage
+                .add_new_user(&username, &build_pub_key(n as u32))
+                .unwrap();
+            let cipher = Cipher::new(storage.sec_key(), &username);
+            let mut store = FileStore::new(storage.user_dir(&username), cipher);
+            for resource in resources {
+                let record = Record {
+                    resource: resource.to_string(),
+                    ..Record::default()
+                };
+                store.write_record(&record).unwrap();
+            }
+        }
+
+        assert_eq!(storage.record_count().unwrap(), 3);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_unicode_username() {
+        let path = build_storage_path("unicode_username");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        let (pub_key, _) = Key::generate_pair();
+        storage.add_new_user("Борщ", &pub_key).unwrap();
+
+        assert_eq!(storage.get_user_pub_key("Борщ").unwrap(), pub_key);
+        assert_eq!(storage.list_users().unwrap(), ["Борщ"]);
+        storage.delete_user("Борщ").unwrap();
+        assert!(storage.list_users().unwrap().is_empty());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_rename_user() {
+        let path = build_storage_path("rename_user");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        let (pub_key, _) = Key::generate_pair();
+        storage.add_new_user("old_name", &pub_key).unwrap();
+        let record_file = path.join("old_name").join("example.com");
+        let old_cipher = Cipher::new(storage.sec_key(), "old_name");
+        fs::write(&record_file, old_cipher.encrypt(b"secret\nnotes")).unwrap();
+        let history_dir = path.join("old_name").join(".history").join("example.com");
+        fs::create_dir_all(&history_dir).unwrap();
+        fs::write(history_dir.join("1"), old_cipher.encrypt(b"old\nnotes")).unwrap();
+
+        storage.rename_user("old_name", "new_name").unwrap();
+
+        assert_eq!(storage.list_users().unwrap(), ["new_name"]);
+        assert_eq!(storage.get_user_pub_key("new_name").unwrap(), pub_key);
+        let content = fs::read(path.join("new_name").join("example.com")).unwrap();
+        let new_cipher = Cipher::new(storage.sec_key(), "new_name");
+        assert_eq!(new_cipher.decrypt(&content).unwrap(), b"secret\nnotes");
+        let history_content = fs::read(
+            path.join("new_name")
+                .join(".history")
+                .join("example.com")
+                .join("1"),
+        )
+        .unwrap();
+        assert_eq!(new_cipher.decrypt(&history_content).unwrap(), b"old\nnotes");
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_rename_user_corrupted_record() {
+        let path = build_storage_path("rename_user_corrupted_record");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        storage.add_new_user("old_name", &build_pub_key(1)).unwrap();
+        let old_cipher = Cipher::new(storage.sec_key(), "old_name");
+        let record_file = path.join("old_name").join("example.com");
+        fs::write(&record_file, old_cipher.encrypt(b"secret\nnotes")).unwrap();
+        let mut corrupted = Cipher::new(storage.sec_key(), "other").encrypt(b"secret\nnotes");
+        corrupted.push(0);
+        fs::write(path.join("old_name").join("corrupted.com"), corrupted).unwrap();
+
+        assert!(matches!(
+            storage.rename_user("old_name", "new_name"),
+            Err(Error::CantDecrypt)
+        ));
+        assert_eq!(storage.list_users().unwrap(), ["old_name"]);
+        let content = fs::read(record_file).unwrap();
+        assert_eq!(old_cipher.decrypt(&content).unwrap(), b"secret\nnotes");
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_storage_keys() {
+        let path = build_storage_path("rotate_storage_keys");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        storage.add_new_user("user", &build_pub_key(1)).unwrap();
+        let record_file = path.join("user").join("example.com");
+        let old_cipher = Cipher::new(storage.sec_key(), "user");
+        fs::write(&record_file, old_cipher.encrypt(b"secret\nnotes")).unwrap();
+        let history_dir = path.join("user").join(".history").join("example.com");
+        fs::create_dir_all(&history_dir).unwrap();
+        fs::write(history_dir.join("1"), old_cipher.encrypt(b"old\nnotes")).unwrap();
+        let old_sec_key = storage.sec_key().clone();
+
+        storage
+            .rotate_storage_keys(|| (build_pub_key(2), build_pub_key(3)))
+            .unwrap();
+
+        assert_eq!(storage.pub_key(), &build_pub_key(2));
+        assert_eq!(storage.sec_key(), &build_pub_key(3));
+        assert_ne!(storage.sec_key(), &old_sec_key);
+        let new_cipher = Cipher::new(storage.sec_key(), "user");
+        let content = fs::read(&record_file).unwrap();
+        assert_eq!(new_cipher.decrypt(&content).unwrap(), b"secret\nnotes");
+        let history_content = fs::read(history_dir.join("1")).unwrap();
+        assert_eq!(new_cipher.decrypt(&history_content).unwrap(), b"old\nnotes");
+        drop(storage);
+
+        let reopened = Storage::new(path.clone()).unwrap();
+        assert_eq!(reopened.pub_key(), &build_pub_key(2));
+        assert_eq!(reopened.sec_key(), &build_pub_key(3));
+        assert!(!path.join(ROTATION_TMP_DIRNAME).exists());
+        assert!(!path.join(ROTATION_DIRNAME).exists());
+        assert_eq!(reopened.list_users().unwrap(), ["user"]);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_storage_keys_same_keys() {
+        let path = build_storage_path("rotate_storage_keys_same_keys");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        storage.pub_key = build_pub_key(2);
+        storage.sec_key = build_pub_key(3);
+
+        assert!(matches!(
+            storage.rotate_storage_keys(|| (build_pub_key(2), build_pub_key(4))),
+            Err(Error::SameStorageKeys)
+        ));
+        assert!(matches!(
+            storage.rotate_storage_keys(|| (build_pub_key(4), build_pub_key(3))),
+            Err(Error::SameStorageKeys)
+        ));
+        assert_eq!(storage.pub_key(), &build_pub_key(2));
+        assert_eq!(storage.sec_key(), &build_pub_key(3));
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_recover_interrupted_key_rotation() {
+        let path = build_storage_path("recover_interrupted_key_rotation");
+        let storage = Storage::new(path.clone()).unwrap();
+        let old_pub_key = storage.pub_key().clone();
+        drop(storage);
+        fs::create_dir_all(path.join(ROTATION_TMP_DIRNAME).join("user")).unwrap();
+        build_pub_key(2)
+            .write_to_file(path.join(ROTATION_TMP_DIRNAME).join(PUB_KEY_FILENAME))
+            .unwrap();
+
+        let storage = Storage::new(path.clone()).unwrap();
+        assert_eq!(storage.pub_key(), &old_pub_key);
+        assert!(!path.join(ROTATION_TMP_DIRNAME).exists());
+        assert!(storage.list_users().unwrap().is_empty());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_recover_complete_key_rotation() {
+        let path = build_storage_path("recover_complete_key_rotation");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        storage.add_new_user("user", &build_pub_key(1)).unwrap();
+        fs::write(path.join("user").join("example.com"), b"old").unwrap();
+        drop(storage);
+        let rotation_dir = path.join(ROTATION_DIRNAME);
+        fs::create_dir_all(rotation_dir.join("user")).unwrap();
+        fs::write(rotation_dir.join("user").join("example.com"), b"new").unwrap();
+        build_pub_key(2)
+            .write_to_file(rotation_dir.join(PUB_KEY_FILENAME))
+            .unwrap();
+        build_pub_key(3)
+            .write_to_file(rotation_dir.join(SEC_KEY_FILENAME))
+            .unwrap();
+
+        let storage = Storage::new(path.clone()).unwrap();
+        assert_eq!(storage.pub_key(), &build_pub_key(2));
+        assert_eq!(storage.sec_key(), &build_pub_key(3));
+        assert_eq!(
+            fs::read(path.join("user").join("example.com")).unwrap(),
+            b"new"
+        );
+        assert!(!path.join("user").join(PUB_KEY_FILENAME).exists());
+        assert!(!rotation_dir.exists());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_backend() {
+        let path = build_storage_path("sqlite_backend");
+        let mut storage = Storage::builder(path.clone())
+            .backend(StorageBackend::Sqlite)
+            .build()
+            .unwrap();
+        storage.add_new_user("old_name", &build_pub_key(1)).unwrap();
+        let record = Record {
+            resource: String::from("example.com"),
+            password: String::from("secret"),
+            ..Record::default()
+        };
+        storage
+            .open_store("old_name")
+            .unwrap()
+            .write_record(&record)
+            .unwrap();
+        assert_eq!(storage.record_count().unwrap(), 1);
+        assert_eq!(storage.list_users().unwrap(), ["old_name"]);
+
+        storage.rename_user("old_name", "new_name").unwrap();
+        let store = storage.open_store("new_name").unwrap();
+        assert_eq!(store.get_record("example.com").unwrap(), record);
+        drop(store);
+
+        storage
+            .rotate_storage_keys(|| (build_pub_key(2), build_pub_key(3)))
+            .unwrap();
+        let store = storage.open_store("new_name").unwrap();
+        assert_eq!(store.get_record("example.com").unwrap(), record);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_storage_keys_with_active_session() {
+        let path = build_storage_path("rotate_storage_keys_with_active_session");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        storage.add_new_user("user", &build_pub_key(1)).unwrap();
+        let user_storage = Arc::new(RwLock::new(UserStorage::default()));
+        storage
+            .username_to_user_storage
+            .insert(String::from("user"), Arc::downgrade(&user_storage));
+        let pub_key = storage.pub_key().clone();
+
+        assert!(matches!(
+            storage.rotate_storage_keys(|| (build_pub_key(2), build_pub_key(3))),
+            Err(Error::UnsupportedActionForMultiSession)
+        ));
+        assert_eq!(storage.pub_key(), &pub_key);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_rename_user_collision() {
+        let path = build_storage_path("rename_user_collision");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        storage.add_new_user("first", &build_pub_key(1)).unwrap();
+        storage.add_new_user("second", &build_pub_key(2)).unwrap();
+
+        assert!(matches!(
+            storage.rename_user("first", "second"),
+            Err(Error::UserAlreadyExists(name)) if name == "second"
+        ));
+        assert!(matches!(
+            storage.rename_user("third", "fourth"),
+            Err(Error::UserDoesNotExist(name)) if name == "third"
+        ));
+        assert_eq!(storage.list_users().unwrap(), ["first", "second"]);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_rename_user_with_active_session() {
+        let path = build_storage_path("rename_user_with_active_session");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        let (pub_key, _) = Key::generate_pair();
+        storage.add_new_user("user", &pub_key).unwrap();
+        let user_storage = Arc::new(RwLock::new(UserStorage::default()));
+        storage
+            .username_to_user_storage
+            .insert(String::from("user"), Arc::downgrade(&user_storage));
+
+        assert!(matches!(
+            storage.rename_user("user", "new_user"),
+            Err(Error::UnsupportedActionForMultiSession)
+        ));
+        assert_eq!(storage.list_users().unwrap(), ["user"]);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_duplicate_pub_key() {
+        let path = build_storage_path("duplicate_pub_key");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        let (pub_key, _) = Key::generate_pair();
+        storage.add_new_user("first", &pub_key).unwrap();
+
+        assert!(matches!(
+            storage.add_new_user("second", &pub_key),
+            Err(Error::KeyAlreadyInUse)
+        ));
+        storage.add_new_user("second", &build_pub_key(2)).unwrap();
+        assert_eq!(storage.list_users().unwrap(), ["first", "second"]);
+        drop(storage);
+
+        let mut reopened = Storage::new(path.clone()).unwrap();
+        assert!(matches!(
+            reopened.add_new_user("third", &pub_key),
+            Err(Error::KeyAlreadyInUse)
+        ));
+
+        assert!(reopened.user_exists("first"));
+        reopened.delete_user("first").unwrap();
+        assert!(!reopened.user_exists("first"));
+        reopened.add_new_user("third", &pub_key).unwrap();
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_register_two_users() {
+        let path = build_storage_path("register_two_users");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        storage
+            .add_new_user("first", &Key::generate_pair().0)
+            .unwrap();
+        storage
+            .add_new_user("second", &Key::generate_pair().0)
+            .unwrap();
+        assert_eq!(storage.list_users().unwrap(), ["first", "second"]);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_index_pub_key() {
+        let path = build_storage_path("index_pub_key");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        storage.add_new_user("first", &build_pub_key(1)).unwrap();
+        storage.add_new_user("second", &build_pub_key(2)).unwrap();
+
+        assert!(matches!(
+            storage.index_pub_key("first", &build_pub_key(2)),
+            Err(Error::KeyAlreadyInUse)
+        ));
+        storage.index_pub_key("first", &build_pub_key(3)).unwrap();
+        build_pub_key(3)
+            .write_to_file(path.join("first").join(PUB_KEY_FILENAME))
+            .unwrap();
+
+        assert!(matches!(
+            storage.add_new_user("third", &build_pub_key(3)),
+            Err(Error::KeyAlreadyInUse)
+        ));
+        storage.add_new_user("third", &build_pub_key(1)).unwrap();
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_session_count() {
+        let path = build_storage_path("session_count");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        storage.add_new_user("user", &build_pub_key(1)).unwrap();
+        assert_eq!(storage.session_count("user"), 0);
+
+        let user_storage = Arc::new(RwLock::new(UserStorage::default()));
+        storage
+            .username_to_user_storage
+            .insert(String::from("user"), Arc::downgrade(&user_storage));
+        let other_session = user_storage.clone();
+        assert_eq!(storage.session_count("user"), 2);
+        assert_eq!(storage.session_count("other"), 0);
+
+        drop(other_session);
+        assert_eq!(storage.session_count("user"), 1);
+        drop(user_storage);
+        assert_eq!(storage.session_count("user"), 0);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_add_existing_user() {
+        let path = build_storage_path("add_existing_user");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        storage.add_new_user("user", &build_pub_key(1)).unwrap();
+
+        assert!(matches!(
+            storage.add_new_user("user", &build_pub_key(2)),
+            Err(Error::UserAlreadyExists(name)) if name == "user"
+        ));
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_add_new_user_io_error() {
+        let path = build_storage_path("add_new_user_io_error");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        fs::remove_dir_all(&path).unwrap();
+
+        assert!(matches!(
+            storage.add_new_user("user", &build_pub_key(1)),
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn test_pub_key_index_follows_rename() {
+        let path = build_storage_path("pub_key_index_follows_rename");
+        let mut storage = Storage::new(path.clone()).unwrap();
+        let (pub_key, _) = Key::generate_pair();
+        storage.add_new_user("old_name", &pub_key).unwrap();
+        storage.rename_user("old_name", "new_name").unwrap();
+
+        assert!(matches!(
+            storage.add_new_user("other", &pub_key),
+            Err(Error::KeyAlreadyInUse)
+        ));
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_is_admin() {
+        let path = build_storage_path("is_admin");
+        let storage = Storage::new(path.clone()).unwrap();
+        assert!(!storage.is_admin("admin").unwrap());
+
+        fs::write(path.join(ADMINS_FILENAME), "admin\nother_admin\n").unwrap();
+        assert!(storage.is_admin("admin").unwrap());
+        assert!(storage.is_admin("other_admin").unwrap());
+        assert!(!storage.is_admin("user").unwrap());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    /// Builds public key unique for `n`
+    fn build_pub_key(n: u32) -> Key {
+        Key::from_str(&format!("{n}:221")).unwrap()
+    }
+
+    /// Builds path to not yet existing storage directory unique for test `name`
+    fn build_storage_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("rpass_db_{name}_{}", std::process::id()));
+        if path.exists() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        path
     }
 }
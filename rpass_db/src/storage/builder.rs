@@ -0,0 +1,531 @@
+use super::{recover_key_rotation, Error, Key, Result, Storage, LOCK_FILENAME};
+use fs2::FileExt;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Function generating storage public and secret keys
+pub type KeyGenerator = fn() -> (Key, Key);
+
+/// Backend keeping records of every user in user directory
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StorageBackend {
+    /// Every record in a separate file, see [`FileStore`](super::FileStore)
+    #[default]
+    Files,
+    /// All records of a user in one SQLite database, see
+    /// [`SqliteStore`](super::SqliteStore). History and trash aren't supported
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+impl FromStr for StorageBackend {
+    type Err = Error;
+
+    /// Parses backend name: `files` or `sqlite`
+    ///
+    /// # Errors
+    ///
+    /// * `InvalidConfig` - if name is unknown or backend isn't compiled in
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "files" => Ok(Self::Files),
+            #[cfg(feature = "sqlite")]
+            "sqlite" => Ok(Self::Sqlite),
+            unknown => Err(Error::InvalidConfig(format!(
+                "unknown storage backend `{unknown}`"
+            ))),
+        }
+    }
+}
+
+/// Builder to configure [`Storage`] before opening it
+///
+/// # Example
+///
+/// ```ignore
+/// let storage = Storage::builder("/var/lib/rpass")
+///     .create_if_missing(false)
+///     .build()?;
+/// ```
+pub struct StorageBuilder {
+    path: PathBuf,
+    create_if_missing: bool,
+    key_generator: KeyGenerator,
+    history_limit: usize,
+    soft_delete: bool,
+    durable: bool,
+    backend: StorageBackend,
+}
+
+impl StorageBuilder {
+    /// Creates builder for storage at `path`.
+    ///
+    /// By default missing storage directory is created with keys
+    /// from [`Key::generate_pair()`], records are kept in files, records
+    /// history is disabled, records are deleted permanently and written
+    /// without syncing to disk
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        StorageBuilder {
+            path: path.as_ref().to_path_buf(),
+            create_if_missing: true,
+            key_generator: Key::generate_pair,
+            history_limit: 0,
+            soft_delete: false,
+            durable: false,
+            backend: StorageBackend::default(),
+        }
+    }
+
+    /// Sets if storage directory should be created when it doesn't exist
+    pub fn create_if_missing(mut self, create_if_missing: bool) -> Self {
+        self.create_if_missing = create_if_missing;
+        self
+    }
+
+    /// Sets function generating keys of a newly created storage
+    pub fn key_generator(mut self, key_generator: KeyGenerator) -> Self {
+        self.key_generator = key_generator;
+        self
+    }
+
+    /// Sets number of previous versions kept for every record.
+    /// `0` disables history, see [`FileStore`](super::FileStore)
+    pub fn history_limit(mut self, history_limit: usize) -> Self {
+        self.history_limit = history_limit;
+        self
+    }
+
+    /// Sets if deleted records should be moved to trash instead of permanent
+    /// removal, see [`FileStore`](super::FileStore)
+    pub fn soft_delete(mut self, soft_delete: bool) -> Self {
+        self.soft_delete = soft_delete;
+        self
+    }
+
+    /// Sets if written records should be synced to disk before write returns,
+    /// see [`FileStore`](super::FileStore)
+    pub fn durable(mut self, durable: bool) -> Self {
+        self.durable = durable;
+        self
+    }
+
+    /// Sets backend keeping records of every user. Storage should be opened
+    /// with the same backend it was created with, records aren't migrated
+    pub fn backend(mut self, backend: StorageBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Opens storage, creating it if needed and allowed
+    ///
+    /// # Errors
+    ///
+    /// * `StoragePathIsNotADirectory` - if storage path exists, but isn't a directory
+    ///   or a symlink to a directory. Dangling symlink is refused too
+    /// * `StorageLocked` - if storage is used by another instance
+    /// * `StorageKeysMissing` - if storage directory exists, but some key files are
+    ///   missing and can't be safely regenerated
+    /// * `InvalidConfig` - if backend doesn't support records history or trash,
+    ///   but they are enabled
+    /// * `Io` - if storage directory doesn't exist and creation isn't allowed
+    /// * Any possible error during file/directory opening/writing
+    pub fn build(self) -> Result<Storage> {
+        self.check_backend_options()?;
+        let lock_file = self.open_storage()?;
+
+        let (pub_key, sec_key) = Self::read_keys(&self.path)?;
+
+        let mut storage = Storage {
+            path: self.path,
+            pub_key,
+            sec_key,
+            username_to_user_storage: HashMap::new(),
+            pub_key_to_username: HashMap::new(),
+            history_limit: self.history_limit,
+            soft_delete: self.soft_delete,
+            durable: self.durable,
+            backend: self.backend,
+            _lock_file: lock_file,
+        };
+        storage.pub_key_to_username = storage.build_pub_key_index()?;
+        Ok(storage)
+    }
+
+    /// Checks that selected backend supports enabled options
+    ///
+    /// # Errors
+    ///
+    /// * `InvalidConfig` - if SQLite backend is used with records history or trash
+    fn check_backend_options(&self) -> Result<()> {
+        #[cfg(feature = "sqlite")]
+        if self.backend == StorageBackend::Sqlite && (self.history_limit > 0 || self.soft_delete) {
+            return Err(Error::InvalidConfig(String::from(
+                "SQLite backend doesn't support records history and trash",
+            )));
+        }
+        Ok(())
+    }
+
+    /// Open storage directory and locks it, see [`StorageBuilder::lock()`].
+    /// Key rotation interrupted by a crash is finished or rolled back here
+    ///
+    /// Returns locked file
+    ///
+    /// # Errors
+    ///
+    /// Any possible error during file/directory opening/writing
+    fn open_storage(&self) -> Result<fs::File> {
+        const DIRECTORY_MESSAGE_PREFIX: &str = "Rpass storage directory";
+        let path = &self.path;
+
+        // Dangling symlink doesn't exist, but storage can't be created there
+        let is_dangling_symlink = !path.exists() && path.symlink_metadata().is_ok();
+        if is_dangling_symlink || (path.exists() && !path.is_dir()) {
+            return Err(Error::StoragePathIsNotADirectory(path.to_owned()));
+        }
+
+        if !path.exists() {
+            if !self.create_if_missing {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{DIRECTORY_MESSAGE_PREFIX} {path:?} does not exist"),
+                )
+                .into());
+            }
+
+            log::info!("{DIRECTORY_MESSAGE_PREFIX} {path:?} does not exist. Creating...");
+            fs::create_dir_all(path)?;
+            let lock_file = self.lock()?;
+            self.init_keys()?;
+            return Ok(lock_file);
+        }
+
+        log::info!("{DIRECTORY_MESSAGE_PREFIX} is {path:?}");
+        let lock_file = self.lock()?;
+        recover_key_rotation(path)?;
+        self.check_keys()?;
+        Ok(lock_file)
+    }
+
+    /// Takes exclusive advisory lock on the *.lock* file in storage directory,
+    /// so only one server instance can use it at once
+    ///
+    /// Returns locked file. Lock is held until it's closed
+    ///
+    /// # Errors
+    ///
+    /// * `StorageLocked` - if lock is held by another instance
+    /// * `Io` - if can't open lock file
+    fn lock(&self) -> Result<fs::File> {
+        let lock_file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.path.join(LOCK_FILENAME))?;
+        lock_file
+            .try_lock_exclusive()
+            .map_err(|_| Error::StorageLocked(self.path.clone()))?;
+        Ok(lock_file)
+    }
+
+    /// Checks that both key files exist in the storage directory.
+    ///
+    /// If both are missing and there are no users, storage initialization was
+    /// interrupted, so keys are generated again. Otherwise regenerating keys
+    /// would make existing user data undecryptable, so error is returned
+    ///
+    /// # Errors
+    ///
+    /// * `StorageKeysMissing` - if some key files are missing and can't be regenerated
+    /// * Any possible error during directory reading or files writing
+    fn check_keys(&self) -> Result<()> {
+        let pub_key_exists = self.path.join("key.pub").exists();
+        let sec_key_exists = self.path.join("key.sec").exists();
+
+        match (pub_key_exists, sec_key_exists) {
+            (true, true) => Ok(()),
+            (false, false) if !self.has_users()? => {
+                log::warn!(
+                    "Storage keys are missing in {:?}, but there are no users. \
+                     Generating new keys...",
+                    self.path
+                );
+                self.init_keys()
+            }
+            _ => {
+                log::error!(
+                    "Storage keys are missing in {:?}. Regenerating them would make \
+                     existing user data undecryptable",
+                    self.path
+                );
+                Err(Error::StorageKeysMissing(self.path.clone()))
+            }
+        }
+    }
+
+    /// Checks if storage directory contains any user directory
+    ///
+    /// # Errors
+    ///
+    /// Any possible error during directory reading
+    fn has_users(&self) -> Result<bool> {
+        for entry_res in fs::read_dir(&self.path)? {
+            if entry_res?.path().is_dir() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Creates public and secret keys and write them to the files *key.pub*
+    /// and *key.sec*
+    ///
+    /// # Errors
+    ///
+    /// Any possible error during files writing
+    fn init_keys(&self) -> Result<()> {
+        let (pub_key, sec_key) = (self.key_generator)();
+        pub_key.write_to_file(self.path.join("key.pub"))?;
+        sec_key
+            .write_to_file(self.path.join("key.sec"))
+            .map_err(|err| err.into())
+    }
+
+    /// Reads public and secret keys from files *key.pub* and *key.sec*
+    ///
+    /// # Errors
+    ///
+    /// Any possible error during files reading and keys constructing
+    fn read_keys(path: &Path) -> Result<(Key, Key)> {
+        let pub_key = Self::read_key(&path.join("key.pub"))?;
+        let sec_key = Self::read_key(&path.join("key.sec"))?;
+        Ok((pub_key, sec_key))
+    }
+
+    /// Reads key from file by `path`. Key in legacy byte format is rewritten
+    /// in text format, see [`Key::from_any()`]
+    ///
+    /// # Errors
+    ///
+    /// Any possible error during file reading/writing and key constructing
+    fn read_key(path: &Path) -> Result<Key> {
+        let content = fs::read(path)?;
+        let key = Key::from_any(&content)?;
+        if key.to_string().as_bytes() != content {
+            log::warn!("Migrating key {path:?} from legacy byte format to text format");
+            key.write_to_file(path)?;
+        }
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_arbitrary_path() {
+        let path = build_storage_path("arbitrary_path").join("nested");
+        let storage = StorageBuilder::new(&path)
+            .key_generator(|| {
+                (
+                    Key::from_str("7:221").unwrap(),
+                    Key::from_str("103:221").unwrap(),
+                )
+            })
+            .build()
+            .unwrap();
+
+        assert_eq!(storage.pub_key().to_string(), "7:221");
+        assert_eq!(storage.sec_key().to_string(), "103:221");
+        assert_eq!(fs::read_to_string(path.join("key.pub")).unwrap(), "7:221");
+        assert_eq!(fs::read_to_string(path.join("key.sec")).unwrap(), "103:221");
+        drop(storage);
+
+        let reopened = Storage::builder(path.clone())
+            .create_if_missing(false)
+            .build()
+            .unwrap();
+        assert_eq!(reopened.pub_key().to_string(), "7:221");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_missing_path_without_creation() {
+        let path = build_storage_path("missing_path_without_creation");
+
+        let res = StorageBuilder::new(&path).create_if_missing(false).build();
+        assert!(matches!(res, Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_path_is_file() {
+        let path = build_storage_path("path_is_file");
+        fs::write(&path, "").unwrap();
+
+        let res = StorageBuilder::new(&path).build();
+        assert!(
+            matches!(&res, Err(Error::StoragePathIsNotADirectory(err_path)) if *err_path == path)
+        );
+        let message = res.err().unwrap().to_string();
+        assert!(message.contains(&path.display().to_string()), "{message}");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_path_is_symlink() {
+        use std::os::unix::fs::symlink;
+
+        let path = build_storage_path("path_is_symlink");
+        let target = build_storage_path("path_is_symlink_target");
+
+        fs::write(&target, "").unwrap();
+        symlink(&target, &path).unwrap();
+        let res = StorageBuilder::new(&path).build();
+        assert!(matches!(res, Err(Error::StoragePathIsNotADirectory(_))));
+
+        fs::remove_file(&target).unwrap();
+        let res = StorageBuilder::new(&path).build();
+        assert!(matches!(res, Err(Error::StoragePathIsNotADirectory(_))));
+        assert!(!target.exists());
+
+        fs::create_dir(&target).unwrap();
+        StorageBuilder::new(&path).build().unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_dir_all(target).unwrap();
+    }
+
+    #[test]
+    fn test_locked() {
+        let path = build_storage_path("locked");
+        let storage = Storage::new(path.clone()).unwrap();
+
+        let res = Storage::new(path.clone());
+        assert!(matches!(res, Err(Error::StorageLocked(p)) if p == path));
+
+        drop(storage);
+        Storage::new(path.clone()).unwrap();
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_legacy_keys_migration() {
+        let path = build_storage_path("legacy_keys_migration");
+        fs::create_dir(&path).unwrap();
+        let (pub_key, sec_key) = Key::generate_pair();
+        fs::write(path.join("key.pub"), pub_key.as_bytes()).unwrap();
+        fs::write(path.join("key.sec"), sec_key.as_bytes()).unwrap();
+
+        let storage = StorageBuilder::new(&path).build().unwrap();
+        assert_eq!(storage.pub_key(), &pub_key);
+        assert_eq!(storage.sec_key(), &sec_key);
+        assert_eq!(Key::from_file(path.join("key.pub")).unwrap(), pub_key);
+        assert_eq!(Key::from_file(path.join("key.sec")).unwrap(), sec_key);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_complete_dir() {
+        let path = build_storage_path("complete_dir");
+        let pub_key = StorageBuilder::new(&path)
+            .build()
+            .unwrap()
+            .pub_key()
+            .clone();
+
+        let storage = StorageBuilder::new(&path)
+            .key_generator(|| panic!("keys shouldn't be generated"))
+            .build()
+            .unwrap();
+        assert_eq!(storage.pub_key(), &pub_key);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_both_keys_missing_without_users() {
+        let path = build_storage_path("both_keys_missing_without_users");
+        fs::create_dir(&path).unwrap();
+
+        let storage = StorageBuilder::new(&path).build().unwrap();
+        assert_eq!(
+            storage.pub_key(),
+            &Key::from_file(path.join("key.pub")).unwrap()
+        );
+        assert_eq!(
+            storage.sec_key(),
+            &Key::from_file(path.join("key.sec")).unwrap()
+        );
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_both_keys_missing_with_users() {
+        let path = build_storage_path("both_keys_missing_with_users");
+        fs::create_dir_all(path.join("user")).unwrap();
+
+        let res = StorageBuilder::new(&path).build();
+        assert!(matches!(res, Err(Error::StorageKeysMissing(p)) if p == path));
+        assert!(!path.join("key.pub").exists());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_one_key_missing() {
+        for key_file in ["key.pub", "key.sec"] {
+            let path = build_storage_path("one_key_missing");
+            StorageBuilder::new(&path).build().unwrap();
+            fs::remove_file(path.join(key_file)).unwrap();
+
+            let res = StorageBuilder::new(&path).build();
+            assert!(matches!(res, Err(Error::StorageKeysMissing(_))));
+            assert!(!path.join(key_file).exists());
+            fs::remove_dir_all(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_backend_from_str() {
+        assert_eq!(
+            StorageBackend::from_str("files").unwrap(),
+            StorageBackend::Files
+        );
+        #[cfg(feature = "sqlite")]
+        assert_eq!(
+            StorageBackend::from_str("sqlite").unwrap(),
+            StorageBackend::Sqlite
+        );
+        assert!(matches!(
+            StorageBackend::from_str("Files"),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_unsupported_options() {
+        let path = build_storage_path("sqlite_unsupported_options");
+        for builder in [
+            StorageBuilder::new(&path).history_limit(1),
+            StorageBuilder::new(&path).soft_delete(true),
+        ] {
+            let res = builder.backend(StorageBackend::Sqlite).build();
+            assert!(matches!(res, Err(Error::InvalidConfig(_))));
+        }
+        assert!(!path.exists());
+    }
+
+    /// Builds path to not yet existing storage directory unique for test `name`
+    fn build_storage_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("rpass_db_builder_{name}_{}", std::process::id()));
+        if path.exists() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        path
+    }
+}
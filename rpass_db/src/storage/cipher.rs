@@ -0,0 +1,108 @@
+use super::{Error, Key, Result};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use sha2::{Digest, Sha256};
+
+/// Prefix of every encrypted file. Used to distinguish encrypted files from
+/// plaintext ones written by older versions
+const MAGIC: &[u8] = b"RPASS-ENC1\n";
+
+/// Length of nonce stored right after [`MAGIC`]
+const NONCE_LENGTH: usize = 12;
+
+/// Symmetric cipher for at-rest encryption of user files
+///
+/// Key is derived from storage secret key and username, so every user has his
+/// own key and no additional key files are needed
+#[derive(Clone)]
+pub struct Cipher {
+    cipher: ChaCha20Poly1305,
+}
+
+impl Cipher {
+    /// Creates cipher for user `username` deriving its key from `storage_sec_key`
+    pub fn new(storage_sec_key: &Key, username: &str) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(storage_sec_key.as_bytes());
+        hasher.update(username.as_bytes());
+
+        Cipher {
+            cipher: ChaCha20Poly1305::new(&hasher.finalize()),
+        }
+    }
+
+    /// Encrypts `plaintext` with random nonce
+    ///
+    /// # Panics
+    ///
+    /// Panics if `plaintext` is too large to be encrypted, which never happens
+    /// for sizes fitting into memory
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, plaintext).unwrap();
+
+        [MAGIC, nonce.as_slice(), &ciphertext].concat()
+    }
+
+    /// Decrypts `data` previously encrypted with [`Cipher::encrypt()`]
+    ///
+    /// # Errors
+    ///
+    /// * CantDecrypt - if `data` is malformed or was encrypted with another key
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let data = data.strip_prefix(MAGIC).ok_or(Error::CantDecrypt)?;
+        if data.len() < NONCE_LENGTH {
+            return Err(Error::CantDecrypt);
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| Error::CantDecrypt)
+    }
+
+    /// Checks if `data` looks like produced by [`Cipher::encrypt()`]
+    pub fn is_encrypted(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_USER: &str = "test_user";
+    const PLAINTEXT: &[u8] = b"secret\nnotes";
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let (_, sec_key) = Key::generate_pair();
+        let cipher = Cipher::new(&sec_key, TEST_USER);
+
+        let encrypted = cipher.encrypt(PLAINTEXT);
+        assert!(Cipher::is_encrypted(&encrypted));
+        assert_ne!(encrypted, PLAINTEXT);
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), PLAINTEXT);
+    }
+
+    #[test]
+    fn test_another_user() {
+        let (_, sec_key) = Key::generate_pair();
+        let encrypted = Cipher::new(&sec_key, TEST_USER).encrypt(PLAINTEXT);
+
+        assert!(matches!(
+            Cipher::new(&sec_key, "another_user").decrypt(&encrypted),
+            Err(Error::CantDecrypt)
+        ));
+    }
+
+    #[test]
+    fn test_plaintext() {
+        let (_, sec_key) = Key::generate_pair();
+        let cipher = Cipher::new(&sec_key, TEST_USER);
+
+        assert!(!Cipher::is_encrypted(PLAINTEXT));
+        assert!(matches!(cipher.decrypt(PLAINTEXT), Err(Error::CantDecrypt)));
+        assert!(matches!(cipher.decrypt(MAGIC), Err(Error::CantDecrypt)));
+    }
+}
@@ -1,4 +1,5 @@
 use super::{key, ParseRecordError};
+use rpass::error::ErrorCode;
 
 use std::io;
 use std::path::PathBuf;
@@ -11,12 +12,33 @@ pub enum Error {
     #[error("Storage path {0} is not a directory")]
     StoragePathIsNotADirectory(PathBuf),
 
+    #[error("Storage {0} is locked by another server instance")]
+    StorageLocked(PathBuf),
+
+    #[error("invalid storage configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Storage keys are missing in {0}, restore key.pub and key.sec from backup")]
+    StorageKeysMissing(PathBuf),
+
+    #[error("new storage keys are the same as the current ones")]
+    SameStorageKeys,
+
     #[error("user {0} already exists")]
     UserAlreadyExists(String),
 
     #[error("user {0} doesn't exist")]
     UserDoesNotExist(String),
 
+    #[error("public key is already in use by another user")]
+    KeyAlreadyInUse,
+
+    #[error("record {0} doesn't exist")]
+    RecordNotFound(String),
+
+    #[error("can't decrypt file")]
+    CantDecrypt,
+
     #[error("record parsing error: {0}")]
     CantParseRecord(#[from] ParseRecordError),
 
@@ -25,4 +47,32 @@ pub enum Error {
 
     #[error("key error: {0}")]
     Key(#[from] key::Error),
+
+    #[cfg(feature = "sqlite")]
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+impl Error {
+    /// Gets machine-readable code of the error
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Io(_) => ErrorCode::Io,
+            Self::StoragePathIsNotADirectory(_) => ErrorCode::StoragePathIsNotADirectory,
+            Self::StorageLocked(_) => ErrorCode::StorageLocked,
+            Self::InvalidConfig(_) => ErrorCode::InvalidStorageConfig,
+            Self::StorageKeysMissing(_) => ErrorCode::StorageKey,
+            Self::SameStorageKeys => ErrorCode::StorageKey,
+            Self::UserAlreadyExists(_) => ErrorCode::UserAlreadyExists,
+            Self::UserDoesNotExist(_) => ErrorCode::UserDoesNotExist,
+            Self::KeyAlreadyInUse => ErrorCode::KeyAlreadyInUse,
+            Self::RecordNotFound(_) => ErrorCode::RecordNotFound,
+            Self::CantDecrypt => ErrorCode::CantDecrypt,
+            Self::CantParseRecord(_) => ErrorCode::CantParseRecord,
+            Self::UnsupportedActionForMultiSession => ErrorCode::UnsupportedActionForMultiSession,
+            Self::Key(_) => ErrorCode::StorageKey,
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(_) => ErrorCode::Io,
+        }
+    }
 }
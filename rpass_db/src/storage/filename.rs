@@ -0,0 +1,88 @@
+use rpass::name;
+
+/// Encodes `name` into a string safe to be used as a filename on any platform
+///
+/// ASCII alphanumeric characters and `.`, `@`, `_`, `-`, ` ` are kept as is.
+/// Every byte of other characters is percent-encoded, so names valid in
+/// older versions are encoded to themselves.
+///
+/// On Windows the first byte of names reserved by OS is percent-encoded too,
+/// see [`name::is_os_reserved()`]. Such files couldn't be created there before,
+/// so it doesn't break existing storages
+pub fn encode(name: &str) -> String {
+    let escape_first = cfg!(windows) && name::is_os_reserved(name);
+    let mut encoded = String::with_capacity(name.len());
+    for (i, byte) in name.bytes().enumerate() {
+        match byte {
+            b if (i > 0 || !escape_first)
+                && (b.is_ascii_alphanumeric() || b".@_- ".contains(&b)) =>
+            {
+                encoded.push(b as char)
+            }
+            b => encoded.push_str(&format!("%{b:02X}")),
+        }
+    }
+    encoded
+}
+
+/// Decodes `filename` produced by [`encode()`]
+///
+/// Returns `None` if `filename` isn't a valid result of [`encode()`]
+pub fn decode(filename: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(filename.len());
+    let mut rest = filename.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_is_not_changed() {
+        assert_eq!(encode("user_404@example.com"), "user_404@example.com");
+        assert_eq!(encode("My Bank"), "My Bank");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for name in ["Борщ", "🏦 bank", "100%", "a/b\\c"] {
+            let encoded = encode(name);
+            assert!(!encoded.contains(['/', '\\']));
+            assert!(encoded.is_ascii());
+            assert_eq!(decode(&encoded).unwrap(), name);
+        }
+        assert_eq!(encode("Борщ"), "%D0%91%D0%BE%D1%80%D1%89");
+    }
+
+    #[test]
+    fn test_os_reserved() {
+        for name in ["nul", "CON.txt", "com1.example.com"] {
+            let encoded = encode(name);
+            if cfg!(windows) {
+                assert!(encoded.starts_with('%'), "{name}");
+            } else {
+                assert_eq!(encoded, name);
+            }
+            assert_eq!(decode(&encoded).unwrap(), name);
+        }
+        assert_eq!(encode("console"), "console");
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(decode("%"), None);
+        assert_eq!(decode("%Z1"), None);
+        assert_eq!(decode("%FF"), None);
+    }
+}
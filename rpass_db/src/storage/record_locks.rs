@@ -0,0 +1,78 @@
+use std::collections::HashSet;
+use std::sync::{Condvar, Mutex};
+
+/// Advisory locks on user records
+///
+/// Used to make read-modify-write sequences on one record atomic across
+/// sessions of the same user, while other records stay accessible
+#[derive(Default)]
+pub struct RecordLocks {
+    locked: Mutex<HashSet<String>>,
+    unlocked: Condvar,
+}
+
+impl RecordLocks {
+    /// Runs `f` holding lock on `resource`. Blocks until lock is released if
+    /// it's held by someone else
+    ///
+    /// Lock is released even if `f` panics
+    pub fn with_record_lock<R>(&self, resource: &str, f: impl FnOnce() -> R) -> R {
+        let _guard = self.lock(resource);
+        f()
+    }
+
+    /// Locks `resource` until returned guard is dropped
+    fn lock(&self, resource: &str) -> RecordLockGuard<'_> {
+        let mut locked = self.locked.lock().unwrap();
+        while locked.contains(resource) {
+            locked = self.unlocked.wait(locked).unwrap();
+        }
+        locked.insert(resource.to_owned());
+
+        RecordLockGuard {
+            locks: self,
+            resource: resource.to_owned(),
+        }
+    }
+}
+
+/// Releases lock on `resource` when dropped
+struct RecordLockGuard<'a> {
+    locks: &'a RecordLocks,
+    resource: String,
+}
+
+impl Drop for RecordLockGuard<'_> {
+    fn drop(&mut self) {
+        let mut locked = self.locks.locked.lock().unwrap();
+        locked.remove(&self.resource);
+        self.locks.unlocked.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_other_resource_is_not_locked() {
+        let locks = RecordLocks::default();
+
+        let res = locks.with_record_lock("first", || locks.with_record_lock("second", || 42));
+        assert_eq!(res, 42);
+    }
+
+    #[test]
+    fn test_released_after_panic() {
+        let locks = Arc::new(RecordLocks::default());
+
+        let locks_clone = locks.clone();
+        let res =
+            thread::spawn(move || locks_clone.with_record_lock("resource", || panic!())).join();
+        assert!(res.is_err());
+
+        assert!(locks.with_record_lock("resource", || true));
+    }
+}
@@ -0,0 +1,1026 @@
+use super::{filename, Cipher, Error, Record, RecordVersion, Result, SortOrder, UserStats};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the directory in user directory with previous versions of records
+pub const HISTORY_DIRNAME: &str = ".history";
+
+/// Name of the directory in user directory with soft-deleted records
+pub const TRASH_DIRNAME: &str = ".trash";
+
+/// Name of the directory in user directory with records being written in
+/// durable mode or migrated to encrypted format
+pub const TMP_DIRNAME: &str = ".tmp";
+
+/// Backend keeping records of one user
+pub trait RecordStore: Send + Sync {
+    /// Writes `record` replacing existing record about the same resource
+    ///
+    /// # Errors
+    ///
+    /// Any backend error during record writing
+    fn write_record(&mut self, record: &Record) -> Result<()>;
+
+    /// Gets record about `resource`
+    ///
+    /// # Errors
+    ///
+    /// * RecordNotFound - if there is no such record
+    /// * Io - if backend can't read record
+    /// * CantDecrypt - if stored record is corrupted
+    /// * CantParseRecord - if can't parse stored record
+    fn get_record(&self, resource: &str) -> Result<Record>;
+
+    /// Checks if record about `resource` exists without reading it
+    ///
+    /// # Errors
+    ///
+    /// Any backend error during presence check
+    fn record_exists(&self, resource: &str) -> Result<bool> {
+        Ok(self.list_records()?.iter().any(|name| name == resource))
+    }
+
+    /// Gets sorted list of names of all records
+    ///
+    /// # Errors
+    ///
+    /// Any backend error during records listing
+    fn list_records(&self) -> Result<Vec<String>>;
+
+    /// Calls `visitor` with name of every record in the same order as
+    /// [`RecordStore::list_records()`], so backend doesn't have to collect all
+    /// names first
+    ///
+    /// # Errors
+    ///
+    /// Any backend error during records listing
+    fn visit_records(&self, visitor: &mut dyn FnMut(String)) -> Result<()> {
+        self.list_records()?.into_iter().for_each(visitor);
+        Ok(())
+    }
+
+    /// Gets number of records
+    ///
+    /// # Errors
+    ///
+    /// Any backend error during records listing
+    fn count_records(&self) -> Result<usize> {
+        Ok(self.list_records()?.len())
+    }
+
+    /// Gets list of names of all records sorted in `order`. Records with equal
+    /// timestamps are sorted by name
+    ///
+    /// # Errors
+    ///
+    /// Any backend error during records listing or reading their timestamps
+    fn list_records_sorted(&self, order: SortOrder) -> Result<Vec<String>>;
+
+    /// Deletes record about `resource`
+    ///
+    /// # Errors
+    ///
+    /// * Io - if there is no such record or backend can't delete it
+    fn delete_record(&mut self, resource: &str) -> Result<()>;
+
+    /// Deletes records about all `resources` or none of them
+    ///
+    /// Default implementation checks that all records exist before deleting
+    /// them one by one, so backend error in the middle leaves some records
+    /// deleted
+    ///
+    /// # Errors
+    ///
+    /// * Io - if there is no record about some resource or backend can't delete it
+    fn delete_records(&mut self, resources: &[String]) -> Result<()> {
+        for resource in resources {
+            if !self.record_exists(resource)? {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("record {resource:?} doesn't exist"),
+                )
+                .into());
+            }
+        }
+        for resource in resources {
+            self.delete_record(resource)?;
+        }
+        Ok(())
+    }
+
+    /// Gets number of records and their total size
+    ///
+    /// # Errors
+    ///
+    /// Any backend error during records listing
+    fn stats(&self) -> Result<UserStats>;
+
+    /// Gets previous versions of record about `resource`, oldest first.
+    /// Stores not keeping history return empty list
+    ///
+    /// # Errors
+    ///
+    /// Any backend error during versions reading
+    fn get_record_history(&self, _resource: &str) -> Result<Vec<RecordVersion>> {
+        Ok(vec![])
+    }
+
+    /// Gets sorted list of names of records in trash.
+    /// Stores deleting records permanently return empty list
+    ///
+    /// # Errors
+    ///
+    /// Any backend error during trash listing
+    fn list_trash(&self) -> Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    /// Moves record about `resource` from trash back to records
+    ///
+    /// # Errors
+    ///
+    /// * Io - if there is no such record in trash, record with the same name
+    ///   already exists or backend can't move it
+    fn restore_record(&mut self, resource: &str) -> Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("record {resource:?} isn't in trash"),
+        )
+        .into())
+    }
+
+    /// Permanently deletes all records in trash
+    ///
+    /// # Errors
+    ///
+    /// Any backend error during records deleting
+    fn purge_trash(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Record store keeping every record in a separate file in user directory
+///
+/// Record files are encrypted at rest with `cipher`. Percent-encoded resource
+/// names are used as filenames, so they are not encrypted
+///
+/// If history is enabled, replaced versions of record are kept in
+/// *.history/\<resource\>/\<version\>* files. If trash is enabled, deleted
+/// records are moved to *.trash* directory. Only the last deleted record with
+/// the same name is kept there
+///
+/// If durable mode is enabled, record is written to *.tmp* directory first,
+/// synced to disk and then atomically renamed over the record file
+pub struct FileStore {
+    path: PathBuf,
+    cipher: Cipher,
+    history_limit: usize,
+    trash: bool,
+    durable: bool,
+}
+
+impl FileStore {
+    /// Creates store of records in directory at `path` encrypted with `cipher`.
+    /// History, trash and durable mode are disabled
+    pub fn new<P: AsRef<Path>>(path: P, cipher: Cipher) -> Self {
+        FileStore {
+            path: path.as_ref().to_path_buf(),
+            cipher,
+            history_limit: 0,
+            trash: false,
+            durable: false,
+        }
+    }
+
+    /// Gets name of the record stored in `file`. `None` if it isn't a record
+    /// file, e.g. user public key or file with name not made by [`filename::encode()`]
+    fn record_name(file: &Path) -> Option<String> {
+        if !file.is_file() {
+            return None;
+        }
+        match file.file_name() {
+            Some(filename) if filename != "key.pub" => {
+                filename::decode(&filename.to_string_lossy())
+            }
+            _ => None,
+        }
+    }
+
+    /// Sets number of previous versions kept for every record. `0` disables history
+    pub fn with_history_limit(mut self, history_limit: usize) -> Self {
+        self.history_limit = history_limit;
+        self
+    }
+
+    /// Sets if deleted records should be moved to trash instead of permanent removal
+    pub fn with_trash(mut self, trash: bool) -> Self {
+        self.trash = trash;
+        self
+    }
+
+    /// Sets if written records should be synced to disk before write returns.
+    /// Slows down writes, but survives power loss
+    pub fn with_durable(mut self, durable: bool) -> Self {
+        self.durable = durable;
+        self
+    }
+
+    /// Gets path to file of record about `resource`
+    fn record_file(&self, resource: &str) -> PathBuf {
+        self.path.join(filename::encode(resource))
+    }
+
+    /// Gets path to file of record about `resource` in trash
+    fn trash_file(&self, resource: &str) -> PathBuf {
+        self.path
+            .join(TRASH_DIRNAME)
+            .join(filename::encode(resource))
+    }
+
+    /// Gets path to directory with previous versions of record about `resource`
+    fn history_dir(&self, resource: &str) -> PathBuf {
+        self.path
+            .join(HISTORY_DIRNAME)
+            .join(filename::encode(resource))
+    }
+
+    /// Writes `content` to file of record about `resource`
+    ///
+    /// In durable mode file is replaced atomically, see [`FileStore::replace_file()`]
+    ///
+    /// # Errors
+    ///
+    /// Any error during files writing, syncing or renaming
+    fn write_record_file(&self, resource: &str, content: &[u8]) -> Result<()> {
+        let record_file = self.record_file(resource);
+        if !self.durable {
+            return fs::write(record_file, content).map_err(|err| err.into());
+        }
+        self.replace_file(&record_file, content)
+    }
+
+    /// Replaces `file` with `content`, so that `file` is never seen partially written
+    ///
+    /// Content goes to a uniquely named file in *.tmp* directory, which is synced and
+    /// renamed over `file`. Directory of `file` is synced after that, so the rename
+    /// isn't lost either
+    ///
+    /// # Errors
+    ///
+    /// Any error during files writing, syncing or renaming
+    fn replace_file(&self, file: &Path, content: &[u8]) -> Result<()> {
+        static TMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let tmp_dir = self.path.join(TMP_DIRNAME);
+        fs::create_dir_all(&tmp_dir)?;
+        let tmp_file = tmp_dir.join(TMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed).to_string());
+        let written = fs::File::create(&tmp_file).and_then(|mut tmp| {
+            tmp.write_all(content)?;
+            tmp.sync_all()?;
+            fs::rename(&tmp_file, file)
+        });
+        if let Err(err) = written {
+            let _ = fs::remove_file(&tmp_file);
+            return Err(err.into());
+        }
+        sync_dir(file.parent().unwrap_or(&self.path))
+    }
+
+    /// Reads record about `resource` from `file`
+    ///
+    /// Plaintext record files written before at-rest encryption was introduced
+    /// are encrypted on first successful read. File is replaced atomically, so
+    /// concurrent readers see either the old or the new content
+    ///
+    /// # Errors
+    ///
+    /// * Io - if can't read or write `file`
+    /// * CantDecrypt - if `file` is corrupted
+    /// * CantParseRecord - if can't parse record
+    fn read_record_file(&self, file: &Path, resource: &str) -> Result<Record> {
+        let content = fs::read(file)?;
+
+        let encrypted = Cipher::is_encrypted(&content);
+        let plaintext = if encrypted {
+            self.cipher.decrypt(&content)?
+        } else {
+            content
+        };
+
+        let record_str = std::str::from_utf8(&plaintext).map_err(|_| Error::CantDecrypt)?;
+        let record = Record {
+            resource: resource.to_owned(),
+            ..Record::from_str(record_str)?
+        };
+        if !encrypted {
+            self.replace_file(file, &self.cipher.encrypt(&plaintext))?;
+        }
+        Ok(record)
+    }
+
+    /// Gets sorted ids of previous versions of record about `resource`
+    ///
+    /// # Errors
+    ///
+    /// Any error during history directory reading
+    fn history_versions(&self, resource: &str) -> Result<Vec<u64>> {
+        let history_dir = self.history_dir(resource);
+        if !history_dir.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut versions = vec![];
+        for entry_res in fs::read_dir(history_dir)? {
+            if let Some(version) = entry_res?.file_name().to_str().and_then(|s| s.parse().ok()) {
+                versions.push(version);
+            }
+        }
+        versions.sort_unstable();
+        Ok(versions)
+    }
+
+    /// Copies current record about `resource` to history if there is one,
+    /// evicting the oldest versions above the limit
+    ///
+    /// # Errors
+    ///
+    /// Any error during files copying or removing
+    fn save_to_history(&self, resource: &str) -> Result<()> {
+        let record_file = self.record_file(resource);
+        if self.history_limit == 0 || !record_file.is_file() {
+            return Ok(());
+        }
+
+        let history_dir = self.history_dir(resource);
+        fs::create_dir_all(&history_dir)?;
+        let mut versions = self.history_versions(resource)?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let version = match versions.last() {
+            Some(&last) if last >= now => last + 1,
+            _ => now,
+        };
+        fs::copy(record_file, history_dir.join(version.to_string()))?;
+        versions.push(version);
+
+        let excess = versions.len().saturating_sub(self.history_limit);
+        for old_version in &versions[..excess] {
+            fs::remove_file(history_dir.join(old_version.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+impl RecordStore for FileStore {
+    /// Current version is copied to history first if history is enabled
+    fn write_record(&mut self, record: &Record) -> Result<()> {
+        self.save_to_history(&record.resource)?;
+
+        let encrypted = self.cipher.encrypt(record.to_versioned_string().as_bytes());
+        self.write_record_file(&record.resource, &encrypted)
+    }
+
+    /// Plaintext record files written before at-rest encryption was introduced
+    /// are encrypted on first read
+    fn get_record(&self, resource: &str) -> Result<Record> {
+        let record_file = self.record_file(resource);
+        if !record_file.exists() {
+            return Err(Error::RecordNotFound(resource.to_owned()));
+        }
+        self.read_record_file(&record_file, resource)
+    }
+
+    /// Checks only presence of the record file, so corrupted records exist too
+    fn record_exists(&self, resource: &str) -> Result<bool> {
+        Ok(self.record_file(resource).is_file())
+    }
+
+    fn list_records(&self) -> Result<Vec<String>> {
+        let mut records_names = vec![];
+        for entry_res in fs::read_dir(self.path.clone())? {
+            if let Some(name) = Self::record_name(&entry_res?.path()) {
+                records_names.push(name)
+            }
+        }
+        records_names.sort();
+
+        Ok(records_names)
+    }
+
+    /// Counts record files without collecting their names
+    fn count_records(&self) -> Result<usize> {
+        let mut count = 0;
+        for entry_res in fs::read_dir(&self.path)? {
+            let file = entry_res?.path();
+            let is_record = file.is_file()
+                && file.file_name().is_some_and(|filename| {
+                    filename != "key.pub" && filename::decode(&filename.to_string_lossy()).is_some()
+                });
+            if is_record {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Timestamp orders read metadata of every record file, so they cost one
+    /// extra filesystem call per record. Creation time falls back to
+    /// modification time on platforms not supporting it
+    fn list_records_sorted(&self, order: SortOrder) -> Result<Vec<String>> {
+        let mut records_names = self.list_records()?;
+        match order {
+            SortOrder::Name => Ok(records_names),
+            SortOrder::NameDesc => {
+                records_names.reverse();
+                Ok(records_names)
+            }
+            SortOrder::Updated | SortOrder::Created => {
+                let mut timed_names = Vec::with_capacity(records_names.len());
+                for name in records_names {
+                    let metadata = fs::metadata(self.record_file(&name))?;
+                    let modified = metadata.modified()?;
+                    let time = match order {
+                        SortOrder::Created => metadata.created().unwrap_or(modified),
+                        _ => modified,
+                    };
+                    timed_names.push((time, name));
+                }
+                Ok(sort_recent_first(timed_names))
+            }
+        }
+    }
+
+    /// If trash is enabled, record is moved there keeping its history.
+    /// Otherwise history of the record is deleted too
+    fn delete_record(&mut self, resource: &str) -> Result<()> {
+        let record_file = self.record_file(resource);
+        if self.trash {
+            if !record_file.is_file() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("record {resource:?} doesn't exist"),
+                )
+                .into());
+            }
+            fs::create_dir_all(self.path.join(TRASH_DIRNAME))?;
+            return fs::rename(record_file, self.trash_file(resource)).map_err(|err| err.into());
+        }
+
+        fs::remove_file(record_file)?;
+        remove_dir_if_exists(&self.history_dir(resource))
+    }
+
+    /// Size is the total size of record files. Entries which can't be read are skipped
+    fn stats(&self) -> Result<UserStats> {
+        let mut stats = UserStats::default();
+        for entry_res in fs::read_dir(&self.path)? {
+            // Records may be deleted concurrently, so they are skipped too
+            let Ok(entry) = entry_res else { continue };
+            let file = entry.path();
+            if Self::record_name(&file).is_none() {
+                continue;
+            }
+            let Ok(metadata) = fs::metadata(file) else {
+                continue;
+            };
+
+            stats.records += 1;
+            stats.bytes += metadata.len();
+        }
+
+        Ok(stats)
+    }
+
+    fn get_record_history(&self, resource: &str) -> Result<Vec<RecordVersion>> {
+        let history_dir = self.history_dir(resource);
+        self.history_versions(resource)?
+            .into_iter()
+            .map(|version| {
+                let record =
+                    self.read_record_file(&history_dir.join(version.to_string()), resource)?;
+                Ok(RecordVersion { version, record })
+            })
+            .collect()
+    }
+
+    fn list_trash(&self) -> Result<Vec<String>> {
+        let trash_dir = self.path.join(TRASH_DIRNAME);
+        if !trash_dir.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut records_names = vec![];
+        for entry_res in fs::read_dir(trash_dir)? {
+            if let Some(name) = filename::decode(&entry_res?.file_name().to_string_lossy()) {
+                records_names.push(name);
+            }
+        }
+        records_names.sort();
+        Ok(records_names)
+    }
+
+    fn restore_record(&mut self, resource: &str) -> Result<()> {
+        let trash_file = self.trash_file(resource);
+        if !trash_file.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("record {resource:?} isn't in trash"),
+            )
+            .into());
+        }
+        let record_file = self.record_file(resource);
+        if record_file.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("record {resource:?} already exists"),
+            )
+            .into());
+        }
+
+        fs::rename(trash_file, record_file).map_err(|err| err.into())
+    }
+
+    /// History of purged records is deleted too, unless record with the same
+    /// name was created again
+    fn purge_trash(&mut self) -> Result<()> {
+        for resource in self.list_trash()? {
+            if !self.record_file(&resource).exists() {
+                remove_dir_if_exists(&self.history_dir(&resource))?;
+            }
+        }
+        remove_dir_if_exists(&self.path.join(TRASH_DIRNAME))
+    }
+}
+
+/// Record store keeping records in memory. Records are lost when store is dropped
+///
+/// Creation and update timestamps are logical: they only reflect order of writes
+#[derive(Default)]
+pub struct InMemoryStore {
+    records: HashMap<String, StoredRecord>,
+    clock: u64,
+}
+
+/// Record with its logical timestamps
+struct StoredRecord {
+    record: Record,
+    created: u64,
+    updated: u64,
+}
+
+impl InMemoryStore {
+    /// Creates empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds error about missing record about `resource`, same as file store returns
+    fn not_found(resource: &str) -> Error {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("record {resource:?} doesn't exist"),
+        )
+        .into()
+    }
+}
+
+impl RecordStore for InMemoryStore {
+    fn write_record(&mut self, record: &Record) -> Result<()> {
+        self.clock += 1;
+        let created = self
+            .records
+            .get(&record.resource)
+            .map_or(self.clock, |stored| stored.created);
+        self.records.insert(
+            record.resource.clone(),
+            StoredRecord {
+                record: record.clone(),
+                created,
+                updated: self.clock,
+            },
+        );
+        Ok(())
+    }
+
+    fn get_record(&self, resource: &str) -> Result<Record> {
+        self.records
+            .get(resource)
+            .map(|stored| stored.record.clone())
+            .ok_or_else(|| Error::RecordNotFound(resource.to_owned()))
+    }
+
+    fn record_exists(&self, resource: &str) -> Result<bool> {
+        Ok(self.records.contains_key(resource))
+    }
+
+    fn list_records(&self) -> Result<Vec<String>> {
+        let mut records_names: Vec<_> = self.records.keys().cloned().collect();
+        records_names.sort();
+        Ok(records_names)
+    }
+
+    fn count_records(&self) -> Result<usize> {
+        Ok(self.records.len())
+    }
+
+    fn list_records_sorted(&self, order: SortOrder) -> Result<Vec<String>> {
+        let timed_names = |time: fn(&StoredRecord) -> u64| {
+            self.records
+                .iter()
+                .map(|(name, stored)| (time(stored), name.clone()))
+                .collect()
+        };
+        match order {
+            SortOrder::Name => self.list_records(),
+            SortOrder::NameDesc => {
+                let mut records_names = self.list_records()?;
+                records_names.reverse();
+                Ok(records_names)
+            }
+            SortOrder::Updated => Ok(sort_recent_first(timed_names(|stored| stored.updated))),
+            SortOrder::Created => Ok(sort_recent_first(timed_names(|stored| stored.created))),
+        }
+    }
+
+    fn delete_record(&mut self, resource: &str) -> Result<()> {
+        self.records
+            .remove(resource)
+            .map(|_| ())
+            .ok_or_else(|| Self::not_found(resource))
+    }
+
+    /// Size is the total size of records in their text form
+    fn stats(&self) -> Result<UserStats> {
+        Ok(UserStats {
+            records: self.records.len(),
+            bytes: self
+                .records
+                .values()
+                .map(|stored| stored.record.to_string().len() as u64)
+                .sum(),
+        })
+    }
+}
+
+/// Removes directory at `path` with all its content. Missing directory isn't an error
+///
+/// # Errors
+///
+/// Any error during directory removing
+pub(super) fn remove_dir_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_dir_all(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Syncs directory at `path` to disk, so renames in it are durable.
+/// Does nothing on platforms which can't open directories as files
+///
+/// # Errors
+///
+/// Any error during directory opening or syncing
+fn sync_dir(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    fs::File::open(path)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Sorts names by their timestamps, most recent first. Names with equal
+/// timestamps are sorted alphabetically
+pub(super) fn sort_recent_first<T: Ord>(mut timed_names: Vec<(T, String)>) -> Vec<String> {
+    timed_names.sort_by(|(lhs_time, lhs_name), (rhs_time, rhs_name)| {
+        rhs_time.cmp(lhs_time).then_with(|| lhs_name.cmp(rhs_name))
+    });
+    timed_names.into_iter().map(|(_, name)| name).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_in_memory_store() {
+        let mut store = InMemoryStore::new();
+        let record = Record {
+            resource: String::from("example.com"),
+            password: String::from("secret"),
+            notes: String::from("notes"),
+            encrypted_notes: false,
+        };
+
+        store.write_record(&record).unwrap();
+        store
+            .write_record(&Record {
+                resource: String::from("another.com"),
+                ..record.clone()
+            })
+            .unwrap();
+        assert_eq!(store.get_record("example.com").unwrap(), record);
+        assert_eq!(
+            store.list_records().unwrap(),
+            ["another.com", "example.com"]
+        );
+        assert_eq!(
+            store.stats().unwrap(),
+            UserStats {
+                records: 2,
+                bytes: 2 * record.to_string().len() as u64
+            }
+        );
+
+        store.delete_record("example.com").unwrap();
+        assert!(matches!(
+            store.get_record("example.com"),
+            Err(Error::RecordNotFound(resource)) if resource == "example.com"
+        ));
+        assert!(matches!(
+            store.delete_record("example.com"),
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound
+        ));
+        assert_eq!(store.list_records().unwrap(), ["another.com"]);
+    }
+
+    #[test]
+    fn test_delete_records() {
+        let mut store = InMemoryStore::new();
+        write_fixture(&mut store);
+
+        let res = store.delete_records(&[String::from("a.com"), String::from("missing.com")]);
+        assert!(matches!(res, Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound));
+        assert_eq!(store.count_records().unwrap(), 3);
+
+        store
+            .delete_records(&[String::from("a.com"), String::from("c.com")])
+            .unwrap();
+        assert_eq!(store.list_records().unwrap(), ["b.com"]);
+    }
+
+    #[test]
+    fn test_in_memory_store_sorted() {
+        let mut store = InMemoryStore::new();
+        write_fixture(&mut store);
+
+        assert_eq!(
+            store.list_records_sorted(SortOrder::Name).unwrap(),
+            ["a.com", "b.com", "c.com"]
+        );
+        assert_eq!(
+            store.list_records_sorted(SortOrder::NameDesc).unwrap(),
+            ["c.com", "b.com", "a.com"]
+        );
+        assert_eq!(
+            store.list_records_sorted(SortOrder::Updated).unwrap(),
+            ["a.com", "c.com", "b.com"]
+        );
+        assert_eq!(
+            store.list_records_sorted(SortOrder::Created).unwrap(),
+            ["c.com", "a.com", "b.com"]
+        );
+    }
+
+    #[test]
+    fn test_file_store_sorted() {
+        let path =
+            env::temp_dir().join(format!("rpass_db_file_store_sorted_{}", std::process::id()));
+        if path.exists() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        fs::create_dir(&path).unwrap();
+        let (_, sec_key) = rpass::key::Key::generate_pair();
+        let mut store = FileStore::new(&path, Cipher::new(&sec_key, "test_user"));
+        write_fixture(&mut store);
+
+        // Filesystem timestamps are too coarse to rely on write order
+        let base = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        for (secs, name) in [(3, "a.com"), (1, "b.com"), (2, "c.com")] {
+            fs::File::options()
+                .write(true)
+                .open(path.join(name))
+                .unwrap()
+                .set_modified(base + Duration::from_secs(secs))
+                .unwrap();
+        }
+
+        assert_eq!(
+            store.list_records_sorted(SortOrder::Name).unwrap(),
+            ["a.com", "b.com", "c.com"]
+        );
+        assert_eq!(
+            store.list_records_sorted(SortOrder::NameDesc).unwrap(),
+            ["c.com", "b.com", "a.com"]
+        );
+        assert_eq!(
+            store.list_records_sorted(SortOrder::Updated).unwrap(),
+            ["a.com", "c.com", "b.com"]
+        );
+        assert_eq!(
+            store.list_records_sorted(SortOrder::Created).unwrap().len(),
+            3
+        );
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_file_store_get_record_errors() {
+        let path = env::temp_dir().join(format!(
+            "rpass_db_file_store_get_record_errors_{}",
+            std::process::id()
+        ));
+        if path.exists() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        fs::create_dir(&path).unwrap();
+        let (_, sec_key) = rpass::key::Key::generate_pair();
+        let store = FileStore::new(&path, Cipher::new(&sec_key, "test_user"));
+
+        assert!(matches!(
+            store.get_record("missing.com"),
+            Err(Error::RecordNotFound(resource)) if resource == "missing.com"
+        ));
+
+        fs::create_dir(path.join("unreadable.com")).unwrap();
+        assert!(matches!(
+            store.get_record("unreadable.com"),
+            Err(Error::Io(_))
+        ));
+
+        fs::write(path.join("malformed.com"), "no notes line").unwrap();
+        assert!(matches!(
+            store.get_record("malformed.com"),
+            Err(Error::CantParseRecord(_))
+        ));
+        assert_eq!(
+            fs::read_to_string(path.join("malformed.com")).unwrap(),
+            "no notes line"
+        );
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_file_store_writes_format_version() {
+        let path = env::temp_dir().join(format!(
+            "rpass_db_file_store_writes_format_version_{}",
+            std::process::id()
+        ));
+        if path.exists() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        fs::create_dir(&path).unwrap();
+        let (_, sec_key) = rpass::key::Key::generate_pair();
+        let cipher = Cipher::new(&sec_key, "test_user");
+        let mut store = FileStore::new(&path, cipher.clone());
+        let record = build_record("example.com");
+
+        store.write_record(&record).unwrap();
+        let content = cipher
+            .decrypt(&fs::read(path.join("example.com")).unwrap())
+            .unwrap();
+        assert_eq!(content, record.to_versioned_string().as_bytes());
+        assert!(content.starts_with(b"v2\n"));
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_file_store_durable() {
+        let path = env::temp_dir().join(format!(
+            "rpass_db_file_store_durable_{}",
+            std::process::id()
+        ));
+        if path.exists() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        fs::create_dir(&path).unwrap();
+        let (_, sec_key) = rpass::key::Key::generate_pair();
+        let mut store = FileStore::new(&path, Cipher::new(&sec_key, "test_user"))
+            .with_history_limit(1)
+            .with_durable(true);
+        let record = build_record("example.com");
+
+        store.write_record(&record).unwrap();
+        let updated = Record {
+            password: String::from("new secret"),
+            ..record.clone()
+        };
+        store.write_record(&updated).unwrap();
+
+        assert_eq!(store.get_record("example.com").unwrap(), updated);
+        assert_eq!(store.get_record_history("example.com").unwrap().len(), 1);
+        assert_eq!(store.list_records().unwrap(), ["example.com"]);
+        assert_eq!(store.stats().unwrap().records, 1);
+        assert_eq!(fs::read_dir(path.join(TMP_DIRNAME)).unwrap().count(), 0);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_file_store_plaintext_migration() {
+        let path = env::temp_dir().join(format!(
+            "rpass_db_file_store_plaintext_migration_{}",
+            std::process::id()
+        ));
+        if path.exists() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        fs::create_dir(&path).unwrap();
+        let (_, sec_key) = rpass::key::Key::generate_pair();
+        let store = FileStore::new(&path, Cipher::new(&sec_key, "test_user"));
+        let record = build_record("example.com");
+        fs::write(path.join("example.com"), record.to_string()).unwrap();
+
+        assert_eq!(store.get_record("example.com").unwrap(), record);
+        assert!(Cipher::is_encrypted(
+            &fs::read(path.join("example.com")).unwrap()
+        ));
+        assert_eq!(fs::read_dir(path.join(TMP_DIRNAME)).unwrap().count(), 0);
+        assert_eq!(store.get_record("example.com").unwrap(), record);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_file_store_stats_skip_non_records() {
+        let path =
+            env::temp_dir().join(format!("rpass_db_file_store_stats_{}", std::process::id()));
+        if path.exists() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        fs::create_dir(&path).unwrap();
+        let (_, sec_key) = rpass::key::Key::generate_pair();
+        let mut store = FileStore::new(&path, Cipher::new(&sec_key, "test_user"));
+        store.write_record(&build_record("example.com")).unwrap();
+        fs::write(path.join("key.pub"), "key").unwrap();
+        fs::write(path.join("invalid%zz"), "not a record").unwrap();
+
+        assert_eq!(store.list_records().unwrap(), ["example.com"]);
+        let stats = store.stats().unwrap();
+        assert_eq!(stats.records, 1);
+        assert_eq!(
+            stats.bytes,
+            fs::metadata(path.join("example.com")).unwrap().len()
+        );
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_file_store_stats_concurrent_delete() {
+        let path = env::temp_dir().join(format!(
+            "rpass_db_file_store_stats_concurrent_delete_{}",
+            std::process::id()
+        ));
+        if path.exists() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        fs::create_dir(&path).unwrap();
+        let (_, sec_key) = rpass::key::Key::generate_pair();
+        let mut store = FileStore::new(&path, Cipher::new(&sec_key, "test_user"));
+        store.write_record(&build_record("example.com")).unwrap();
+
+        let racy_files: Vec<_> = (0..100)
+            .map(|i| path.join(format!("racy{i}.com")))
+            .collect();
+        let deleter = std::thread::spawn(move || {
+            for _ in 0..100 {
+                for file in &racy_files {
+                    fs::write(file, "racy").unwrap();
+                }
+                for file in &racy_files {
+                    fs::remove_file(file).unwrap();
+                }
+            }
+        });
+        while !deleter.is_finished() {
+            assert!(store.stats().unwrap().records >= 1);
+        }
+        deleter.join().unwrap();
+        assert_eq!(store.stats().unwrap().records, 1);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    /// Writes *b.com*, *a.com* and *c.com* records, then updates *a.com*
+    fn write_fixture(store: &mut dyn RecordStore) {
+        for name in ["b.com", "a.com", "c.com"] {
+            store.write_record(&build_record(name)).unwrap();
+        }
+        store.write_record(&build_record("a.com")).unwrap();
+    }
+
+    fn build_record(resource: &str) -> Record {
+        Record {
+            resource: resource.to_owned(),
+            password: String::from("secret"),
+            notes: String::new(),
+            encrypted_notes: false,
+        }
+    }
+}
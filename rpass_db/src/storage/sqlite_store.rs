@@ -0,0 +1,392 @@
+use super::record_store::{sort_recent_first, RecordStore};
+use super::{Cipher, Error, Record, Result, SortOrder, UserStats};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the database file in user directory
+pub const SQLITE_FILENAME: &str = ".records.sqlite3";
+
+/// Record store keeping all records of a user in one SQLite database in user
+/// directory
+///
+/// Records are encrypted at rest with `cipher` like in
+/// [`FileStore`](super::FileStore), resource names are not encrypted.
+/// History and trash aren't supported, so deleted records are removed
+/// permanently. Batch operations run in a single transaction
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+    cipher: Cipher,
+}
+
+impl SqliteStore {
+    /// Opens store in directory at `path` encrypted with `cipher`, creating
+    /// database if needed
+    ///
+    /// # Errors
+    ///
+    /// * Sqlite - if can't open or initialize database
+    pub fn open<P: AsRef<Path>>(path: P, cipher: Cipher) -> Result<Self> {
+        let connection = Connection::open(path.as_ref().join(SQLITE_FILENAME))?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS records (
+                resource TEXT PRIMARY KEY NOT NULL,
+                data BLOB NOT NULL,
+                created INTEGER NOT NULL,
+                updated INTEGER NOT NULL
+            )",
+        )?;
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+            cipher,
+        })
+    }
+
+    /// Decrypts all records, e.g. to re-encrypt them with another cipher.
+    /// Returns resource names with decrypted record content
+    ///
+    /// # Errors
+    ///
+    /// * CantDecrypt - if some record is corrupted
+    /// * Sqlite - if can't read records
+    pub fn decrypt_all(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare("SELECT resource, data FROM records")?;
+        let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut decrypted = vec![];
+        for row in rows {
+            let (resource, data): (String, Vec<u8>) = row?;
+            decrypted.push((resource, self.cipher.decrypt(&data)?));
+        }
+        Ok(decrypted)
+    }
+
+    /// Replaces content of records with `plaintexts` encrypted with store
+    /// cipher in one transaction. Timestamps are kept
+    ///
+    /// # Errors
+    ///
+    /// * Sqlite - if can't write records. Nothing is changed in this case
+    pub fn write_plaintexts(&mut self, plaintexts: Vec<(String, Vec<u8>)>) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        for (resource, plaintext) in plaintexts {
+            transaction.execute(
+                "UPDATE records SET data = ?1 WHERE resource = ?2",
+                params![self.cipher.encrypt(&plaintext), resource],
+            )?;
+        }
+        transaction.commit().map_err(|err| err.into())
+    }
+
+    /// Locks database connection
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap()
+    }
+
+    /// Builds error about missing record about `resource`, same as file store returns
+    fn not_found(resource: &str) -> Error {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("record {resource:?} doesn't exist"),
+        )
+        .into()
+    }
+
+    /// Lists names of records ordered by `order_by` SQL expression
+    ///
+    /// # Errors
+    ///
+    /// * Sqlite - if can't read records
+    fn list_ordered(&self, order_by: &str) -> Result<Vec<String>> {
+        let connection = self.connection();
+        let mut statement =
+            connection.prepare(&format!("SELECT resource FROM records ORDER BY {order_by}"))?;
+        let names = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(names)
+    }
+}
+
+impl RecordStore for SqliteStore {
+    fn write_record(&mut self, record: &Record) -> Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64;
+        let encrypted = self.cipher.encrypt(record.to_versioned_string().as_bytes());
+        self.connection().execute(
+            "INSERT INTO records (resource, data, created, updated) VALUES (?1, ?2, ?3, ?3)
+             ON CONFLICT (resource) DO UPDATE SET data = excluded.data, updated = excluded.updated",
+            params![record.resource, encrypted, now],
+        )?;
+        Ok(())
+    }
+
+    fn get_record(&self, resource: &str) -> Result<Record> {
+        let data: Vec<u8> = self
+            .connection()
+            .query_row(
+                "SELECT data FROM records WHERE resource = ?1",
+                [resource],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| Error::RecordNotFound(resource.to_owned()))?;
+
+        let plaintext = self.cipher.decrypt(&data)?;
+        let record_str = String::from_utf8(plaintext).map_err(|_| Error::CantDecrypt)?;
+        Ok(Record {
+            resource: resource.to_owned(),
+            ..Record::from_str(&record_str)?
+        })
+    }
+
+    fn record_exists(&self, resource: &str) -> Result<bool> {
+        let exists = self.connection().query_row(
+            "SELECT EXISTS (SELECT 1 FROM records WHERE resource = ?1)",
+            [resource],
+            |row| row.get(0),
+        )?;
+        Ok(exists)
+    }
+
+    fn list_records(&self) -> Result<Vec<String>> {
+        self.list_ordered("resource")
+    }
+
+    fn visit_records(&self, visitor: &mut dyn FnMut(String)) -> Result<()> {
+        let connection = self.connection();
+        let mut statement = connection.prepare("SELECT resource FROM records ORDER BY resource")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            visitor(row.get(0)?);
+        }
+        Ok(())
+    }
+
+    fn count_records(&self) -> Result<usize> {
+        let count: i64 =
+            self.connection()
+                .query_row("SELECT COUNT(*) FROM records", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    /// Timestamps have millisecond precision, so records written in the same
+    /// millisecond are sorted by name
+    fn list_records_sorted(&self, order: SortOrder) -> Result<Vec<String>> {
+        let timed_names = |column: &str| -> Result<Vec<String>> {
+            let connection = self.connection();
+            let mut statement =
+                connection.prepare(&format!("SELECT {column}, resource FROM records"))?;
+            let timed_names = statement
+                .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(sort_recent_first(timed_names))
+        };
+        match order {
+            SortOrder::Name => self.list_records(),
+            SortOrder::NameDesc => self.list_ordered("resource DESC"),
+            SortOrder::Updated => timed_names("updated"),
+            SortOrder::Created => timed_names("created"),
+        }
+    }
+
+    fn delete_record(&mut self, resource: &str) -> Result<()> {
+        let deleted = self
+            .connection()
+            .execute("DELETE FROM records WHERE resource = ?1", [resource])?;
+        if deleted == 0 {
+            return Err(Self::not_found(resource));
+        }
+        Ok(())
+    }
+
+    /// All records are deleted in one transaction, which is rolled back if
+    /// some record doesn't exist
+    fn delete_records(&mut self, resources: &[String]) -> Result<()> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        for resource in resources {
+            let deleted =
+                transaction.execute("DELETE FROM records WHERE resource = ?1", [resource])?;
+            if deleted == 0 {
+                return Err(Self::not_found(resource));
+            }
+        }
+        transaction.commit().map_err(|err| err.into())
+    }
+
+    /// Size is the total size of encrypted records
+    fn stats(&self) -> Result<UserStats> {
+        let (records, bytes): (i64, i64) = self.connection().query_row(
+            "SELECT COUNT(*), COALESCE(SUM(LENGTH(data)), 0) FROM records",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok(UserStats {
+            records: records as usize,
+            bytes: bytes as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_records() {
+        let path = build_store_dir("records");
+        let mut store = build_store(&path);
+        let record = Record {
+            resource: String::from("example.com"),
+            password: String::from("secret"),
+            notes: String::from("notes"),
+            encrypted_notes: false,
+        };
+
+        store.write_record(&record).unwrap();
+        store
+            .write_record(&Record {
+                resource: String::from("another.com"),
+                ..record.clone()
+            })
+            .unwrap();
+        assert_eq!(store.get_record("example.com").unwrap(), record);
+        assert!(store.record_exists("example.com").unwrap());
+        assert_eq!(
+            store.list_records().unwrap(),
+            ["another.com", "example.com"]
+        );
+        assert_eq!(store.count_records().unwrap(), 2);
+        assert_eq!(store.stats().unwrap().records, 2);
+        let mut visited = vec![];
+        store
+            .visit_records(&mut |resource| visited.push(resource))
+            .unwrap();
+        assert_eq!(visited, ["another.com", "example.com"]);
+
+        let updated = Record {
+            password: String::from("new secret"),
+            ..record.clone()
+        };
+        store.write_record(&updated).unwrap();
+        assert_eq!(store.get_record("example.com").unwrap(), updated);
+        assert_eq!(store.count_records().unwrap(), 2);
+
+        store.delete_record("example.com").unwrap();
+        assert!(matches!(
+            store.get_record("example.com"),
+            Err(Error::RecordNotFound(resource)) if resource == "example.com"
+        ));
+        assert!(matches!(
+            store.delete_record("example.com"),
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound
+        ));
+        assert!(!store.record_exists("example.com").unwrap());
+        assert_eq!(store.list_records().unwrap(), ["another.com"]);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_persistence() {
+        let path = build_store_dir("persistence");
+        let record = Record {
+            resource: String::from("example.com"),
+            password: String::from("secret"),
+            ..Record::default()
+        };
+        build_store(&path).write_record(&record).unwrap();
+
+        let raw = fs::read(path.join(SQLITE_FILENAME)).unwrap();
+        assert!(!raw
+            .windows(record.password.len())
+            .any(|window| window == record.password.as_bytes()));
+        assert_eq!(
+            build_store(&path).get_record("example.com").unwrap(),
+            record
+        );
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_delete_records_transactional() {
+        let path = build_store_dir("delete_records_transactional");
+        let mut store = build_store(&path);
+        for resource in ["a.com", "b.com"] {
+            store
+                .write_record(&Record {
+                    resource: String::from(resource),
+                    ..Record::default()
+                })
+                .unwrap();
+        }
+
+        let res = store.delete_records(&[String::from("a.com"), String::from("missing.com")]);
+        assert!(matches!(res, Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound));
+        assert_eq!(store.list_records().unwrap(), ["a.com", "b.com"]);
+
+        store
+            .delete_records(&[String::from("a.com"), String::from("b.com")])
+            .unwrap();
+        assert_eq!(store.count_records().unwrap(), 0);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_reencrypt() {
+        let path = build_store_dir("reencrypt");
+        let record = Record {
+            resource: String::from("example.com"),
+            password: String::from("secret"),
+            ..Record::default()
+        };
+        build_store(&path).write_record(&record).unwrap();
+
+        let plaintexts = build_store(&path).decrypt_all().unwrap();
+        assert_eq!(
+            plaintexts,
+            [(
+                record.resource.clone(),
+                record.to_versioned_string().into_bytes()
+            )]
+        );
+        let (_, sec_key) = rpass::key::Key::generate_pair();
+        let new_cipher = Cipher::new(&sec_key, "renamed_user");
+        let mut store = SqliteStore::open(&path, new_cipher).unwrap();
+        store.write_plaintexts(plaintexts).unwrap();
+
+        assert_eq!(store.get_record("example.com").unwrap(), record);
+        assert!(matches!(
+            build_store(&path).get_record("example.com"),
+            Err(Error::CantDecrypt)
+        ));
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    /// Opens store in `path` with cipher of the test user. Key is fixed, so
+    /// stores opened in the same `path` can read each other's records
+    fn build_store(path: &Path) -> SqliteStore {
+        let sec_key = rpass::key::Key::from_str("5:221").unwrap();
+        SqliteStore::open(path, Cipher::new(&sec_key, "test_user")).unwrap()
+    }
+
+    /// Creates empty directory unique for test `name`
+    fn build_store_dir(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("rpass_db_sqlite_{name}_{}", std::process::id()));
+        if path.exists() {
+            fs::remove_dir_all(&path).unwrap();
+        }
+        fs::create_dir(&path).unwrap();
+        path
+    }
+}
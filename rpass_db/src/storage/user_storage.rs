@@ -1,102 +1,665 @@
-use super::{Error, Key, Record, Result};
-use std::fs;
+use super::record_store::{InMemoryStore, RecordStore};
+use super::{Error, Key, Record, RecordLocks, RecordVersion, Result, SortOrder};
+use std::io;
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
-use std::string::ToString;
+use std::sync::Arc;
 
 #[cfg(test)]
 use mockall::automock;
 
+/// Disk usage statistics of user storage
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UserStats {
+    /// Number of records
+    pub records: usize,
+    /// Total size of record files in bytes
+    pub bytes: u64,
+}
+
 /// Password storage of concrete user
+///
+/// Records are kept in pluggable [`RecordStore`]
 pub struct UserStorage {
-    path: PathBuf,
+    /// File to persist public key to. `None` if key is kept only in memory
+    key_file: Option<PathBuf>,
     pub_key: Key,
+    store: Box<dyn RecordStore>,
+    record_locks: Arc<RecordLocks>,
 }
 
 #[cfg_attr(test, automock, allow(dead_code))]
 impl UserStorage {
-    /// Initializes UserDir from given `path`
+    /// Initializes UserDir from given `path`. Records will be kept in `store`,
+    /// which should be opened in the same directory
     ///
     /// # Errors
     ///
     /// * UserDoesNotExists - if `path` does not exist or isn't a directory
     /// * Io - if can't read key from *path/key.pub* file
-    pub(super) fn new<P: 'static + AsRef<Path>>(path: P) -> Result<Self> {
+    pub(super) fn new<P: 'static + AsRef<Path>>(
+        path: P,
+        store: Box<dyn RecordStore>,
+    ) -> Result<Self> {
         let real_path = path.as_ref();
         if !real_path.exists() || !real_path.is_dir() {
             return Err(Error::UserDoesNotExist(real_path.display().to_string()));
         }
 
-        let pub_key = Key::from_file(real_path.join("key.pub"))?;
+        let key_file = real_path.join("key.pub");
+        let pub_key = Key::from_file(&key_file)?;
         Ok(UserStorage {
-            path: real_path.to_path_buf(),
+            key_file: Some(key_file),
             pub_key,
+            store,
+            record_locks: Arc::default(),
         })
     }
 
+    /// Creates user storage with `pub_key` keeping everything in memory,
+    /// see [`InMemoryStore`]
+    pub fn in_memory(pub_key: Key) -> Self {
+        UserStorage {
+            key_file: None,
+            pub_key,
+            store: Box::new(InMemoryStore::new()),
+            record_locks: Arc::default(),
+        }
+    }
+
     /// Gets user pub key
     pub fn pub_key(&self) -> &Key {
         &self.pub_key
     }
 
-    /// Writes `record` into user's directory with filename `record.resource`
+    /// Gets advisory locks on user records
+    ///
+    /// Locks are shared between all sessions of the user. Take them before
+    /// read-modify-write sequences on a record without holding the lock on
+    /// `self`:
+    ///
+    /// ```ignore
+    /// let record_locks = user_storage.read().unwrap().record_locks();
+    /// record_locks.with_record_lock(resource, || {
+    ///     let record = user_storage.read().unwrap().get_record(resource)?;
+    ///     user_storage.write().unwrap().write_record(&modify(record))
+    /// })
+    /// ```
+    pub fn record_locks(&self) -> Arc<RecordLocks> {
+        self.record_locks.clone()
+    }
+
+    /// Replaces user pub key with `pub_key` overwriting *key.pub* file if any
     ///
     /// # Errors
     ///
-    /// * Io - if some error occurred during record writing
+    /// * Key - if some error occurred during key writing
+    pub fn set_pub_key(&mut self, pub_key: Key) -> Result<()> {
+        if let Some(key_file) = &self.key_file {
+            pub_key.write_to_file(key_file)?;
+        }
+        self.pub_key = pub_key;
+        Ok(())
+    }
+
+    /// Writes `record` with `record.resource` name
+    ///
+    /// # Errors
+    ///
+    /// See [`RecordStore::write_record()`]
     pub fn write_record(&mut self, record: &Record) -> Result<()> {
-        let record_file = self.path.join(&record.resource);
-        fs::write(record_file, record.to_string()).map_err(|err| err.into())
+        self.store.write_record(record)
     }
 
     /// Deletes record about `resource`
     ///
     /// # Errors
     ///
-    /// * Io - if some error occurred during file removing
+    /// See [`RecordStore::delete_record()`]
     pub fn delete_record(&mut self, resource: &str) -> Result<()> {
-        let record_file = self.path.join(resource);
-        fs::remove_file(record_file).map_err(|err| err.into())
+        self.store.delete_record(resource)
+    }
+
+    /// Deletes records about all `resources` or none of them
+    ///
+    /// # Errors
+    ///
+    /// See [`RecordStore::delete_records()`]
+    pub fn delete_records(&mut self, resources: &[String]) -> Result<()> {
+        self.store.delete_records(resources)
     }
 
     /// Gets record about `resource`
     ///
     /// # Errors
     ///
-    /// * Io - if some error occurred during record file reading
-    /// * CantParseRecord - if can't parse record
+    /// See [`RecordStore::get_record()`]
     pub fn get_record(&self, resource: &str) -> Result<Record> {
-        let record_file = self.path.join(resource);
-        let record_str = fs::read_to_string(record_file)?;
-        Ok(Record {
-            resource: resource.to_owned(),
-            ..Record::from_str(&record_str)?
-        })
+        self.store.get_record(resource)
+    }
+
+    /// Checks if record about `resource` exists
+    ///
+    /// # Errors
+    ///
+    /// See [`RecordStore::record_exists()`]
+    pub fn record_exists(&self, resource: &str) -> Result<bool> {
+        self.store.record_exists(resource)
     }
 
     /// Gets list of names of all records
     ///
     /// # Errors
     ///
-    /// Io - if can't read items in user directory
+    /// See [`RecordStore::list_records()`]
     pub fn list_records(&self) -> Result<Vec<String>> {
-        let mut records_names = vec![];
-        for entry_res in fs::read_dir(self.path.clone())? {
-            let entry = entry_res?;
-            let file = entry.path();
-            if !file.is_file() {
-                continue;
-            }
+        self.store.list_records()
+    }
+
+    /// Calls `visitor` with name of every record in alphabetical order
+    ///
+    /// # Errors
+    ///
+    /// See [`RecordStore::visit_records()`]
+    pub fn visit_records(&self, visitor: &mut dyn FnMut(String)) -> Result<()> {
+        self.store.visit_records(visitor)
+    }
+
+    /// Gets list of names of all records sorted in `order`
+    ///
+    /// # Errors
+    ///
+    /// See [`RecordStore::list_records_sorted()`]
+    pub fn list_records_sorted(&self, order: SortOrder) -> Result<Vec<String>> {
+        self.store.list_records_sorted(order)
+    }
+
+    /// Gets number of records
+    ///
+    /// # Errors
+    ///
+    /// See [`RecordStore::count_records()`]
+    pub fn count_records(&self) -> Result<usize> {
+        self.store.count_records()
+    }
+
+    /// Gets number of records and their total size
+    ///
+    /// # Errors
+    ///
+    /// See [`RecordStore::stats()`]
+    pub fn stats(&self) -> Result<UserStats> {
+        self.store.stats()
+    }
+
+    /// Gets sorted list of names of deleted records in trash
+    ///
+    /// # Errors
+    ///
+    /// See [`RecordStore::list_trash()`]
+    pub fn list_trash(&self) -> Result<Vec<String>> {
+        self.store.list_trash()
+    }
+
+    /// Moves record about `resource` from trash back to records
+    ///
+    /// # Errors
+    ///
+    /// See [`RecordStore::restore_record()`]
+    pub fn restore_record(&mut self, resource: &str) -> Result<()> {
+        self.store.restore_record(resource)
+    }
+
+    /// Permanently deletes all records in trash
+    ///
+    /// # Errors
+    ///
+    /// See [`RecordStore::purge_trash()`]
+    pub fn purge_trash(&mut self) -> Result<()> {
+        self.store.purge_trash()
+    }
+
+    /// Gets previous versions of record about `resource`, oldest first
+    ///
+    /// # Errors
+    ///
+    /// See [`RecordStore::get_record_history()`]
+    pub fn get_record_history(&self, resource: &str) -> Result<Vec<RecordVersion>> {
+        self.store.get_record_history(resource)
+    }
+
+    /// Replaces record about `resource` with its previous `version`.
+    /// Replaced content is saved to history as any other overwrite
+    ///
+    /// # Errors
+    ///
+    /// * Io - if there is no such version
+    /// * See [`RecordStore::get_record_history()`] and [`RecordStore::write_record()`]
+    pub fn restore_record_version(&mut self, resource: &str, version: u64) -> Result<()> {
+        let record_version = self
+            .store
+            .get_record_history(resource)?
+            .into_iter()
+            .find(|record_version| record_version.version == version)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("version {version} of record {resource:?} doesn't exist"),
+                )
+            })?;
+        self.store.write_record(&record_version.record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::record_store::FileStore;
+    use super::super::Cipher;
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::sync::RwLock;
+    use std::thread;
+
+    #[test]
+    fn test_write_and_get_record() {
+        let path = build_user_dir("write_and_get_record");
+        let mut user_storage = build_user_storage(path.clone());
+        let record = build_record();
+
+        user_storage.write_record(&record).unwrap();
+
+        let content = fs::read(path.join(&record.resource)).unwrap();
+        assert_ne!(content, record.to_string().as_bytes());
+        assert_eq!(user_storage.get_record(&record.resource).unwrap(), record);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_plaintext_record_migration() {
+        let path = build_user_dir("plaintext_record_migration");
+        let user_storage = build_user_storage(path.clone());
+        let record = build_record();
+        let record_file = path.join(&record.resource);
+        fs::write(&record_file, record.to_string()).unwrap();
+
+        assert_eq!(user_storage.get_record(&record.resource).unwrap(), record);
+        assert!(Cipher::is_encrypted(&fs::read(&record_file).unwrap()));
+        assert_eq!(user_storage.get_record(&record.resource).unwrap(), record);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_unicode_resources() {
+        let path = build_user_dir("unicode_resources");
+        let mut user_storage = build_user_storage(path.clone());
+        let resources = ["Почта", "🏦 bank", "example.com"];
+        for resource in resources {
+            let record = Record {
+                resource: resource.to_owned(),
+                ..build_record()
+            };
+            user_storage.write_record(&record).unwrap();
+            assert_eq!(user_storage.get_record(resource).unwrap(), record);
+        }
 
-            match file.file_name() {
-                Some(filename) if filename != "key.pub" => {
-                    records_names.push(filename.to_string_lossy().into_owned())
-                }
-                _ => (),
+        let mut expected = resources.map(String::from).to_vec();
+        expected.sort();
+        assert_eq!(user_storage.list_records().unwrap(), expected);
+
+        user_storage.delete_record("Почта").unwrap();
+        assert_eq!(user_storage.list_records().unwrap().len(), 2);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_stats() {
+        let path = build_user_dir("stats");
+        let mut user_storage = build_user_storage(path.clone());
+        assert_eq!(user_storage.stats().unwrap(), UserStats::default());
+
+        let record = build_record();
+        user_storage.write_record(&record).unwrap();
+        user_storage
+            .write_record(&Record {
+                resource: String::from("another.com"),
+                ..build_record()
+            })
+            .unwrap();
+        fs::create_dir(path.join("not_a_record")).unwrap();
+
+        let record_size = fs::metadata(path.join(&record.resource)).unwrap().len();
+        assert_eq!(
+            user_storage.stats().unwrap(),
+            UserStats {
+                records: 2,
+                bytes: 2 * record_size
             }
+        );
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_count_records() {
+        let path = build_user_dir("count_records");
+        let mut user_storage = build_user_storage(path.clone());
+        assert_eq!(user_storage.count_records().unwrap(), 0);
+
+        user_storage.write_record(&build_record()).unwrap();
+        assert_eq!(user_storage.count_records().unwrap(), 1);
+
+        for resource in ["another.com", "My Bank"] {
+            user_storage
+                .write_record(&Record {
+                    resource: String::from(resource),
+                    ..build_record()
+                })
+                .unwrap();
+        }
+        fs::create_dir(path.join("not_a_record")).unwrap();
+        assert_eq!(user_storage.count_records().unwrap(), 3);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_record_exists() {
+        let path = build_user_dir("record_exists");
+        let mut user_storage = build_user_storage(path.clone());
+        let record = build_record();
+        assert!(!user_storage.record_exists(&record.resource).unwrap());
+
+        user_storage.write_record(&record).unwrap();
+        assert!(user_storage.record_exists(&record.resource).unwrap());
+        assert!(!user_storage.record_exists("another.com").unwrap());
+
+        user_storage.delete_record(&record.resource).unwrap();
+        assert!(!user_storage.record_exists(&record.resource).unwrap());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_updates() {
+        const UPDATES_PER_THREAD: u32 = 50;
+
+        let path = build_user_dir("concurrent_updates");
+        let user_storage = Arc::new(RwLock::new(build_user_storage(path.clone())));
+        let mut record = build_record();
+        record.password = 0.to_string();
+        user_storage.write().unwrap().write_record(&record).unwrap();
+
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                let user_storage = user_storage.clone();
+                let resource = record.resource.clone();
+                thread::spawn(move || {
+                    let record_locks = user_storage.read().unwrap().record_locks();
+                    for _ in 0..UPDATES_PER_THREAD {
+                        record_locks.with_record_lock(&resource, || {
+                            let mut record =
+                                user_storage.read().unwrap().get_record(&resource).unwrap();
+                            let counter: u32 = record.password.parse().unwrap();
+                            thread::yield_now();
+                            record.password = (counter + 1).to_string();
+                            user_storage.write().unwrap().write_record(&record).unwrap();
+                        });
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let record = user_storage
+            .read()
+            .unwrap()
+            .get_record(&record.resource)
+            .unwrap();
+        assert_eq!(record.password, (2 * UPDATES_PER_THREAD).to_string());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_in_memory() {
+        let (pub_key, sec_key) = Key::generate_pair();
+        let mut user_storage = UserStorage::in_memory(pub_key);
+        let record = build_record();
+
+        user_storage.write_record(&record).unwrap();
+        assert_eq!(user_storage.get_record(&record.resource).unwrap(), record);
+        assert_eq!(
+            user_storage.list_records().unwrap(),
+            [record.resource.as_str()]
+        );
+
+        user_storage.set_pub_key(sec_key.clone()).unwrap();
+        assert_eq!(user_storage.pub_key(), &sec_key);
+    }
+
+    #[test]
+    fn test_history_accumulation() {
+        let path = build_user_dir("history_accumulation");
+        let mut user_storage =
+            build_user_storage_with(path.clone(), |store| store.with_history_limit(5));
+        let resource = build_record().resource;
+        assert!(user_storage
+            .get_record_history(&resource)
+            .unwrap()
+            .is_empty());
+
+        write_passwords(&mut user_storage, &["first", "second", "third"]);
+
+        let history = user_storage.get_record_history(&resource).unwrap();
+        let passwords: Vec<_> = history.iter().map(|v| v.record.password.as_str()).collect();
+        assert_eq!(passwords, ["first", "second"]);
+        assert!(history[0].version < history[1].version);
+        assert!(history.iter().all(|v| v.record.resource == resource));
+        assert_eq!(
+            user_storage.get_record(&resource).unwrap().password,
+            "third"
+        );
+        assert_eq!(user_storage.list_records().unwrap(), [resource.as_str()]);
+        assert_eq!(user_storage.stats().unwrap().records, 1);
+
+        user_storage.delete_record(&resource).unwrap();
+        assert!(user_storage
+            .get_record_history(&resource)
+            .unwrap()
+            .is_empty());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_history_limit() {
+        let path = build_user_dir("history_limit");
+        let mut user_storage =
+            build_user_storage_with(path.clone(), |store| store.with_history_limit(2));
+
+        write_passwords(&mut user_storage, &["first", "second", "third", "fourth"]);
+
+        let passwords: Vec<_> = user_storage
+            .get_record_history(&build_record().resource)
+            .unwrap()
+            .into_iter()
+            .map(|v| v.record.password)
+            .collect();
+        assert_eq!(passwords, ["second", "third"]);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_history_disabled() {
+        let path = build_user_dir("history_disabled");
+        let mut user_storage = build_user_storage(path.clone());
+
+        write_passwords(&mut user_storage, &["first", "second"]);
+
+        assert!(user_storage
+            .get_record_history(&build_record().resource)
+            .unwrap()
+            .is_empty());
+        assert!(!path.join(".history").exists());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_restore_record_version() {
+        let path = build_user_dir("restore_record_version");
+        let mut user_storage =
+            build_user_storage_with(path.clone(), |store| store.with_history_limit(5));
+        let resource = build_record().resource;
+        write_passwords(&mut user_storage, &["first", "second"]);
+        let first_version = user_storage.get_record_history(&resource).unwrap()[0].version;
+
+        user_storage
+            .restore_record_version(&resource, first_version)
+            .unwrap();
+
+        assert_eq!(
+            user_storage.get_record(&resource).unwrap().password,
+            "first"
+        );
+        let passwords: Vec<_> = user_storage
+            .get_record_history(&resource)
+            .unwrap()
+            .into_iter()
+            .map(|v| v.record.password)
+            .collect();
+        assert_eq!(passwords, ["first", "second"]);
+        assert!(matches!(
+            user_storage.restore_record_version(&resource, 0),
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound
+        ));
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_trash_restore() {
+        let path = build_user_dir("trash_restore");
+        let mut user_storage =
+            build_user_storage_with(path.clone(), |store| store.with_trash(true));
+        let record = build_record();
+        user_storage.write_record(&record).unwrap();
+
+        user_storage.delete_record(&record.resource).unwrap();
+        assert!(user_storage.list_records().unwrap().is_empty());
+        assert_eq!(user_storage.stats().unwrap(), UserStats::default());
+        assert!(user_storage.get_record(&record.resource).is_err());
+        assert_eq!(
+            user_storage.list_trash().unwrap(),
+            [record.resource.as_str()]
+        );
+
+        user_storage.restore_record(&record.resource).unwrap();
+        assert_eq!(user_storage.get_record(&record.resource).unwrap(), record);
+        assert!(user_storage.list_trash().unwrap().is_empty());
+        assert!(matches!(
+            user_storage.restore_record(&record.resource),
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound
+        ));
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_trash_restore_existing() {
+        let path = build_user_dir("trash_restore_existing");
+        let mut user_storage =
+            build_user_storage_with(path.clone(), |store| store.with_trash(true));
+        let record = build_record();
+        user_storage.write_record(&record).unwrap();
+        user_storage.delete_record(&record.resource).unwrap();
+        user_storage.write_record(&record).unwrap();
+
+        assert!(matches!(
+            user_storage.restore_record(&record.resource),
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::AlreadyExists
+        ));
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_trash_purge() {
+        let path = build_user_dir("trash_purge");
+        let mut user_storage = build_user_storage_with(path.clone(), |store| {
+            store.with_trash(true).with_history_limit(5)
+        });
+        write_passwords(&mut user_storage, &["first", "second"]);
+        let resource = build_record().resource;
+
+        user_storage.delete_record(&resource).unwrap();
+        assert_eq!(user_storage.get_record_history(&resource).unwrap().len(), 1);
+        user_storage.purge_trash().unwrap();
+
+        assert!(user_storage.list_trash().unwrap().is_empty());
+        assert!(user_storage
+            .get_record_history(&resource)
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            user_storage.restore_record(&resource),
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound
+        ));
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_hard_delete() {
+        let path = build_user_dir("hard_delete");
+        let mut user_storage = build_user_storage(path.clone());
+        let record = build_record();
+        user_storage.write_record(&record).unwrap();
+
+        user_storage.delete_record(&record.resource).unwrap();
+        assert!(user_storage.list_trash().unwrap().is_empty());
+        assert!(!path.join(".trash").exists());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    /// Creates user directory unique for test `name` with *key.pub* file
+    fn build_user_dir(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("rpass_db_{name}_{}", std::process::id()));
+        if path.exists() {
+            fs::remove_dir_all(&path).unwrap();
         }
-        records_names.sort();
+        fs::create_dir(&path).unwrap();
+        Key::generate_pair()
+            .0
+            .write_to_file(path.join("key.pub"))
+            .unwrap();
+        path
+    }
+
+    fn build_user_storage(path: PathBuf) -> UserStorage {
+        build_user_storage_with(path, |store| store)
+    }
+
+    /// Builds user storage at `path` with file store modified by `configure`
+    fn build_user_storage_with(
+        path: PathBuf,
+        configure: impl FnOnce(FileStore) -> FileStore,
+    ) -> UserStorage {
+        let (_, sec_key) = Key::generate_pair();
+        let store = configure(FileStore::new(&path, Cipher::new(&sec_key, "test_user")));
+        UserStorage::new(path, Box::new(store)).unwrap()
+    }
 
-        Ok(records_names)
+    /// Writes record from [`build_record()`] with every password from `passwords`
+    fn write_passwords(user_storage: &mut UserStorage, passwords: &[&str]) {
+        for password in passwords {
+            user_storage
+                .write_record(&Record {
+                    password: password.to_string(),
+                    ..build_record()
+                })
+                .unwrap();
+        }
+    }
+
+    fn build_record() -> Record {
+        Record {
+            resource: String::from("example.com"),
+            password: String::from("secret"),
+            notes: String::from("notes"),
+            encrypted_notes: false,
+        }
     }
 }